@@ -1,328 +1,1122 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
 use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::sync::Arc;
+#[cfg(feature = "rc")]
+use std::rc::{Rc as Shared, Weak};
+#[cfg(not(feature = "rc"))]
+use std::sync::{Arc as Shared, Weak};
+
+#[cfg(all(feature = "rayon", feature = "rc"))]
+compile_error!("the rayon feature needs nodes shared through Arc: it cannot be combined with the rc feature");
+
+mod affixes;
+#[cfg(feature = "allocator_api")]
+mod alloc_leaf;
+mod brackets;
+mod bulk;
+mod bytes;
+mod checkpoint;
+mod chunks;
+mod clipboard;
+#[cfg(feature = "lz4_flex")]
+mod cold;
+mod column;
+mod config;
+mod damage;
+mod delta;
+mod diagnostics;
+mod diff;
+mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod folding;
+mod fuzzy;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+mod graphemes;
+mod history;
+mod incremental;
+mod indent;
+mod inline;
+mod intern;
+mod journal;
+mod leaf_cache;
+mod line_ops;
+mod lines;
+mod loader;
+mod lsp;
+mod metrics;
+mod mutable;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod paragraphs;
+mod patch;
+mod piece;
+mod pool;
+mod provenance;
+#[cfg(feature = "pyo3")]
+mod python;
+mod rebalance;
+mod rope;
+mod search;
+mod selection;
+#[cfg(feature = "shadow")]
+mod shadow;
+mod slice;
+mod stats;
+#[cfg(feature = "proptest")]
+pub mod testing;
+mod tree;
+mod wal;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod wildcard;
+mod words;
+mod wrap;
+
+#[cfg(feature = "allocator_api")]
+pub use alloc_leaf::create_immutable_text_in;
+pub use bytes::ImmutableBytes;
+pub use checkpoint::CheckpointStore;
+pub use chunks::ChunksIn;
+pub use clipboard::{ClipboardOptions, LineEnding, TrailingNewline};
+pub use config::{ChunkPolicy, TextConfig};
+pub use damage::Damage;
+pub use delta::{Bias, Delta, Edit};
+pub use diagnostics::{versions_to_dot, InvariantViolation, MemoryUsage, TreeStats};
+pub use diff::Granularity;
+pub use error::TextError;
+pub use folding::{FoldingRange, IndentFolding};
+pub use fuzzy::FuzzyMatch;
+pub use history::{CoalescePolicy, EditKind, Eviction, History};
+pub use incremental::IncrementalSearch;
+pub use indent::{IndentStyle, Indentation};
+pub use intern::LeafInterner;
+pub use journal::{read_journal, Journal};
+pub use loader::{LoadError, LoadProgress};
+pub use lsp::{LspRange, Position, TextEdit};
+pub use mutable::MutableText;
+#[cfg(feature = "rayon")]
+pub use parallel::ParChunks;
+pub use paragraphs::Paragraphs;
+pub use patch::{Hunk, HunkLine, HunkResult, Patch, PatchParseError};
+pub use piece::open_immutable_text;
+pub use pool::NodePool;
+pub use provenance::Provenance;
+pub use rope::{Rope, RopeChunks, RopeIter};
+pub use search::SearchOptions;
+pub use selection::{Selection, SelectionSet};
+pub use slice::{LinesWithRanges, TextSlice};
+pub use stats::TextStats;
+pub use wal::WalDocument;
+#[cfg(feature = "wasm")]
+pub use wasm::{WasmEdit, WasmText};
+pub use words::Words;
+
+use inline::{InlineLeaf, INLINE_CAPACITY};
+use leaf_cache::LeafCache;
+use metrics::LineMetrics;
+use mutable::GapLeafNode;
+use pool::PoolClass;
+use search::find_byte;
+use tree::{balanced_node, collect_leaves, concat_nodes, node_of, TreeLink};
 
 // <summary>Holds the default size for primitive blocks of characters.</summary>
-const BLOCK_SIZE : i32 = 1 << 6;
+const BLOCK_SIZE : usize = 1 << 6;
 
-/// <summary>Holds the mask used to ensure a block boundary cesures.</summary>
-const BLOCK_MASK : i32 = !(BLOCK_SIZE - 1);
+/// <summary>
+/// Holds the maximum depth of a tree; deeper trees are rebuilt balanced so that
+/// the walks bounded by the depth (sub_node, stats) cannot exhaust the stack.
+/// </summary>
+const MAX_DEPTH : usize = 48;
+
+/// <summary>
+/// The bounds nodes must satisfy to be shared: nodes are held by <code>Arc</code> so
+/// that texts are Send + Sync and snapshots can be handed to other threads, unless the
+/// "rc" feature swaps in <code>Rc</code>, cheaper for single-threaded callers.
+/// </summary>
+#[cfg(not(feature = "rc"))]
+trait SharedBounds : Send + Sync {}
+#[cfg(not(feature = "rc"))]
+impl<T : Send + Sync + ?Sized> SharedBounds for T {}
+#[cfg(feature = "rc")]
+trait SharedBounds {}
+#[cfg(feature = "rc")]
+impl<T : ?Sized> SharedBounds for T {}
 
-trait Node {
+trait Node : SharedBounds {
 	fn length(&self) -> usize;
-	fn sub_node(&self, start: usize, end: usize) -> Node;
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node>;
 	fn get_char_at(&self, offset : usize) -> char;
 	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize);
+	fn metrics(&self) -> &LineMetrics;
+
+	/// <summary>Returns the heap bytes held by this node itself, excluding its children.</summary>
+	fn allocated_bytes(&self) -> usize;
+
+	/// <summary>Returns the number of composite levels below this node, 0 for leaves.</summary>
+	fn depth(&self) -> usize {
+		0
+	}
+
+	/// <summary>Returns this node as a composite, or None for leaves.</summary>
+	fn as_composite(&self) -> Option<&CompositeNode> {
+		None
+	}
+
+	fn as_composite_mut(&mut self) -> Option<&mut CompositeNode> {
+		None
+	}
+
+	/// <summary>Returns the free list of the node pool this node goes to once unreferenced, None if it is not pooled.</summary>
+	fn pool_class(&self) -> Option<PoolClass> {
+		None
+	}
+
+	/// <summary>Replaces the characters of a leaf taken from the node pool, reusing its buffer where it can.</summary>
+	fn refill(&mut self, _data : Vec<char>) {
+		unreachable!("only pooled leaves are refilled")
+	}
+
+	/// <summary>
+	/// Replaces <code>removed</code> characters at <code>start</code> with the given ones
+	/// in place, if this is a leaf the chunk policy lets grow that much.
+	/// </summary>
+	/// <returns>false if the node was left unchanged.</returns>
+	fn splice_chars(&mut self, _start : usize, _removed : usize, _data : &[char], _config : &TextConfig) -> bool {
+		false
+	}
+
+	/// <summary>Returns the offset of the first occurrence of the character at or after <code>start</code> in this leaf.</summary>
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		(start..self.length()).find(|&i| self.get_char_at(i) == c)
+	}
+
+	/// <summary>Returns whether this leaf is a gap buffer materialized by <code>MutableText</code>.</summary>
+	fn is_gap_buffer(&self) -> bool {
+		false
+	}
+
+	/// <summary>Returns whether this leaf is a piece of a shared buffer, sliced without copying and so never chunked.</summary>
+	fn is_piece(&self) -> bool {
+		false
+	}
+
+	/// <summary>
+	/// Returns the characters of a leaf decoded on access, such as a cold leaf, so that
+	/// walks over it decode them once rather than for every character; None for the
+	/// leaves read in place.
+	/// </summary>
+	fn decoded(&self) -> Option<Arc<[char]>> {
+		None
+	}
+
+	/// <summary>Returns the characters of this leaf as a string slice, if it stores them as UTF-8.</summary>
+	fn as_utf8(&self) -> Option<&str> {
+		None
+	}
+
+	/// <summary>Returns the offset in the UTF-8 encoding of this leaf of the character at <code>index</code>, the length of the encoding past the end.</summary>
+	fn utf8_offset(&self, index : usize) -> usize {
+		if self.metrics().bytes == self.length() {
+			return index; // ASCII
+		}
+		if let Some(text) = self.as_utf8() {
+			return text.char_indices().nth(index).map_or(text.len(), |(offset, _)| offset);
+		}
+		(0..index).map(|i| self.get_char_at(i).len_utf8()).sum()
+	}
+
+	/// <summary>
+	/// Returns the index of the character of this leaf whose UTF-8 encoding holds the
+	/// byte at <code>byte_offset</code>, which must be within the encoding, and the offset it starts at.
+	/// </summary>
+	fn char_at_utf8(&self, byte_offset : usize) -> (usize, usize) {
+		if self.metrics().bytes == self.length() {
+			return (byte_offset, byte_offset); // ASCII
+		}
+		let mut offset = 0;
+		for index in 0..self.length() {
+			let next = offset + self.get_char_at(index).len_utf8();
+			if next > byte_offset {
+				return (index, offset);
+			}
+			offset = next;
+		}
+		unreachable!("byte offset past the end of the leaf")
+	}
+
+	/// <summary>Returns the bytes of a shared buffer this leaf keeps alive without covering them, as a view on a larger buffer.</summary>
+	fn retained_bytes(&self) -> usize {
+		0
+	}
+
+	/// <summary>Returns whether this leaf holds a compressed block, decompressed when sliced and so never chunked.</summary>
+	fn is_cold(&self) -> bool {
+		false
+	}
 }
 
 struct WideLeafNode {
-	data:Vec<char>
+	data:Vec<char>,
+	metrics : LineMetrics
+}
+
+impl WideLeafNode {
+	fn new(data : Vec<char>) -> WideLeafNode {
+		WideLeafNode {
+			metrics : LineMetrics::of_chars(&data),
+			data
+		}
+	}
 }
 
 impl Node for WideLeafNode {
 	fn length(&self) -> usize {
 		self.data.len()
 	}
-	
-	fn sub_node<'a>(&self, start: usize, end: usize) -> Node {
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
 		if start == 0 && end == self.length() {
 			return self;
 		}
-		let mut vec = Vec::with_capacity(end - start);
-		let mut i = 0;
-		for j in start..end {
-			vec[i] = self.data[j];
-			i = i + 1;
-		}
-		let mut result = WideLeafNode {
-			data : vec
-		};
-
-		result
+		create_leaf_node(self.data[start..end].to_vec(), config)
 	}
-	
+
 	fn get_char_at(&self, offset : usize) -> char {
 		self.data[offset]
 	}
 
 	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
-		for i in 0..count {
-			destination[destination_index + i] = self.data[source_index +i];
+		destination[destination_index..destination_index + count].copy_from_slice(&self.data[source_index..source_index + count]);
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<WideLeafNode>() + self.data.capacity() * mem::size_of::<char>()
+	}
+
+	fn splice_chars(&mut self, start : usize, removed : usize, data : &[char], config : &TextConfig) -> bool {
+		if !splices(self.data.len(), removed, data.len(), config) {
+			return false;
 		}
+		if start == self.data.len() {
+			self.data.extend_from_slice(data);
+			self.metrics = self.metrics.combine(&LineMetrics::of_chars(data));
+		} else {
+			self.data.splice(start..start + removed, data.iter().copied());
+			self.metrics = LineMetrics::of_chars(&self.data);
+		}
+		true
 	}
-}
 
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		self.data[start..].iter().position(|&x| x == c).map(|i| start + i)
+	}
 
+	fn pool_class(&self) -> Option<PoolClass> {
+		Some(PoolClass::Wide)
+	}
 
-/*
-func (this WideLeafNode) CopyTo(sourceIndex int, destination []rune, destinationIndex int, count int) {
-	copy(destination[destinationIndex:], this.data[sourceIndex:count])
+	fn refill(&mut self, data : Vec<char>) {
+		*self = WideLeafNode::new(data);
+	}
 }
 
-type CompositeNode struct {
-	count int
-	head Node
-	tail Node
+/// <summary>A leaf of ASCII characters, stored one byte per character.</summary>
+struct Leaf8BitNode {
+	data : Vec<u8>,
+	metrics : LineMetrics
 }
 
-func (this CompositeNode) Length() int {
-	return this.count
+impl Leaf8BitNode {
+	fn new(data : &[char]) -> Leaf8BitNode {
+		let data : Vec<u8> = data.iter().map(|&c| c as u8).collect();
+		Leaf8BitNode {
+			metrics : LineMetrics::of_ascii(&data),
+			data
+		}
+	}
 }
 
-func (this CompositeNode) SubNode(start int, end int) Node {
-	var cesure = this.head.Length()
-	if end <= cesure {
-		return this.head.SubNode (start, end)
+impl Node for Leaf8BitNode {
+	fn length(&self) -> usize {
+		self.data.len()
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, _config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		let data : Vec<char> = self.data[start..end].iter().map(|&b| b as char).collect();
+		pool::leaf(PoolClass::Ascii, data, |data| Shared::new(Leaf8BitNode::new(&data)))
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		self.data[offset] as char
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		let source = &self.data[source_index..source_index + count];
+		for (target, &b) in destination[destination_index..destination_index + count].iter_mut().zip(source) {
+			*target = b as char;
+		}
 	}
-	if start >= cesure {
-		return this.tail.SubNode (start - cesure, end - cesure)
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
 	}
-	if start == 0 && end == this.count {
-		return this
+
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<Leaf8BitNode>() + self.data.capacity()
 	}
-	// Overlaps head and tail.
-	return ConcatNodes (this.head.SubNode (start, cesure), this.tail.SubNode (0, end - cesure))
+
+	fn splice_chars(&mut self, start : usize, removed : usize, data : &[char], config : &TextConfig) -> bool {
+		if !splices(self.data.len(), removed, data.len(), config) || !data.iter().all(char::is_ascii) {
+			return false;
+		}
+		if start == self.data.len() {
+			self.data.extend(data.iter().map(|&c| c as u8));
+			self.metrics = self.metrics.combine(&LineMetrics::of_chars(data));
+		} else {
+			self.data.splice(start..start + removed, data.iter().map(|&c| c as u8));
+			self.metrics = LineMetrics::of_ascii(&self.data);
+		}
+		true
+	}
+
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		if !c.is_ascii() {
+			return None;
+		}
+		find_byte(c as u8, &self.data[start..]).map(|i| start + i)
+	}
+
+	fn as_utf8(&self) -> Option<&str> {
+		// ASCII bytes are UTF-8.
+		Some(unsafe { std::str::from_utf8_unchecked(&self.data) })
+	}
+
+	fn pool_class(&self) -> Option<PoolClass> {
+		Some(PoolClass::Ascii)
+	}
+
+	fn refill(&mut self, data : Vec<char>) {
+		self.data.clear();
+		self.data.extend(data.iter().map(|&c| c as u8));
+		self.metrics = LineMetrics::of_ascii(&self.data);
+	}
+}
+
+#[derive(Clone)]
+struct CompositeNode {
+	count : usize,
+	depth : usize,
+	metrics : LineMetrics,
+	head : Shared<dyn Node>,
+	tail : Shared<dyn Node>
 }
 
-func (this CompositeNode) GetCharAt(index int) rune {
-	var headLength = this.head.Length()
-	if index < headLength {
-		return this.head.GetCharAt(index)
+impl CompositeNode {
+	fn new(head : Shared<dyn Node>, tail : Shared<dyn Node>) -> CompositeNode {
+		CompositeNode {
+			count : head.length() + tail.length(),
+			depth : 1 + head.depth().max(tail.depth()),
+			metrics : head.metrics().combine(tail.metrics()),
+			head,
+			tail
+		}
 	}
-	return this.tail.GetCharAt(index - headLength)
+
 }
 
-func (this CompositeNode) CopyTo(sourceIndex int, destination []rune, destinationIndex int, count int) {
-	var cesure = this.head.Length ()
-	if sourceIndex + count <= cesure {
-		this.head.CopyTo (sourceIndex, destination, destinationIndex, count)
-		return
+impl Node for CompositeNode {
+	fn length(&self) -> usize {
+		self.count
 	}
-	if (sourceIndex >= cesure) {
-		this.tail.CopyTo (sourceIndex - cesure, destination, destinationIndex, count)
-		return
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
+		let cesure = self.head.length();
+		if end <= cesure {
+			return self.head.clone().sub_node(start, end, config);
+		}
+		if start >= cesure {
+			return self.tail.clone().sub_node(start - cesure, end - cesure, config);
+		}
+		if start == 0 && end == self.count {
+			return self;
+		}
+		// Overlaps head and tail.
+		concat_nodes(self.head.clone().sub_node(start, cesure, config), self.tail.clone().sub_node(0, end - cesure, config), config)
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		let mut node : &dyn Node = self;
+		let mut index = offset;
+		while let Some(composite) = node.as_composite() {
+			let head_length = composite.head.length();
+			if index < head_length {
+				node = &*composite.head;
+			} else {
+				index -= head_length;
+				node = &*composite.tail;
+			}
+		}
+		node.get_char_at(index)
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		// Visits the overlapped subtrees with an explicit stack rather than recursing.
+		let mut stack : Vec<(&dyn Node, usize, usize, usize)> = vec![(self, source_index, destination_index, count)];
+		while let Some((node, source_index, destination_index, count)) = stack.pop() {
+			let composite = match node.as_composite() {
+				Some(composite) => composite,
+				None => {
+					node.copy_to(source_index, destination, destination_index, count);
+					continue;
+				}
+			};
+			let cesure = composite.head.length();
+			if source_index + count <= cesure {
+				stack.push((&*composite.head, source_index, destination_index, count));
+			} else if source_index >= cesure {
+				stack.push((&*composite.tail, source_index - cesure, destination_index, count));
+			} else {
+				// Overlaps head and tail.
+				let head_chunk_size = cesure - source_index;
+				stack.push((&*composite.tail, 0, destination_index + head_chunk_size, count - head_chunk_size));
+				stack.push((&*composite.head, source_index, destination_index, head_chunk_size));
+			}
+		}
+	}
+
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<CompositeNode>()
+	}
+
+	fn depth(&self) -> usize {
+		self.depth
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	fn as_composite(&self) -> Option<&CompositeNode> {
+		Some(self)
+	}
+
+	fn as_composite_mut(&mut self) -> Option<&mut CompositeNode> {
+		Some(self)
 	}
-	// Overlaps head and tail.
-	var headChunkSize = cesure - sourceIndex;
-	this.head.CopyTo (sourceIndex, destination, destinationIndex, headChunkSize)
-	this.tail.CopyTo (0, destination, destinationIndex + headChunkSize, count - headChunkSize)
-}
 
-func (this CompositeNode) RotateRight () CompositeNode {
-	// See: http://en.wikipedia.org/wiki/Tree_rotation
-	var P, isType = this.head.(CompositeNode);
-	if !isType {
-		return this // Head not a composite, cannot rotate.
+	fn pool_class(&self) -> Option<PoolClass> {
+		Some(PoolClass::Composite)
 	}
-	var A = P.head
-	var B = P.tail
-	var C = this.tail
-	var tailLength = B.Length() + C.Length ()
-	return CompositeNode { A.Length () + tailLength, A, CompositeNode { tailLength, B, C } }
 }
 
-func (this CompositeNode) RotateLeft () CompositeNode {
-	// See: http://en.wikipedia.org/wiki/Tree_rotation
-	var Q, isType = this.tail.(CompositeNode)
-	if !isType {
-		return this // Tail not a composite, cannot rotate.
+impl Drop for CompositeNode {
+	fn drop(&mut self) {
+		pool::release(self);
 	}
-	var B = Q.head
-	var C = Q.tail
-	var A = this.head
-	var headLength = A.Length() + B.Length()
-	return CompositeNode { headLength +  C.Length(), CompositeNode { headLength, A, B }, C }
 }
 
-func NodeOf (node Node, offset int, length int) Node {
-	if (length <= BLOCK_SIZE) {
-		return node.SubNode (offset, offset + length);
+impl TreeLink for Shared<dyn Node> {
+	type Config = TextConfig;
+
+	fn length(&self) -> usize {
+		(**self).length()
+	}
+
+	fn depth(&self) -> usize {
+		(**self).depth()
+	}
+
+	fn children(&self) -> Option<(&Shared<dyn Node>, &Shared<dyn Node>)> {
+		self.as_composite().map(|composite| (&composite.head, &composite.tail))
+	}
+
+	fn composite(head : Shared<dyn Node>, tail : Shared<dyn Node>) -> Shared<dyn Node> {
+		pool::composite(head, tail)
+	}
+
+	fn sub_node(&self, start : usize, end : usize, config : &TextConfig) -> Shared<dyn Node> {
+		self.clone().sub_node(start, end, config)
+	}
+
+	fn merged(head : &Shared<dyn Node>, tail : &Shared<dyn Node>, config : &TextConfig) -> Option<Shared<dyn Node>> {
+		if !config.chunk_policy().merges(head.length(), tail.length()) {
+			return None;
+		}
+		let mut merged_array = vec!['\0'; head.length() + tail.length()];
+		head.copy_to(0, &mut merged_array, 0, head.length());
+		tail.copy_to(0, &mut merged_array, head.length(), tail.length());
+		Some(create_leaf_node(merged_array, config))
+	}
+
+	fn block_size(config : &TextConfig) -> usize {
+		config.block_size()
+	}
+
+	fn empty() -> Shared<dyn Node> {
+		Shared::new(WideLeafNode::new(Vec::new()))
 	}
-	// Splits on a block boundary.
-	var half = ((length + BLOCK_SIZE) >> 1) & BLOCK_MASK
-	var head = NodeOf (node, offset, half)
-	var tail = NodeOf (node, offset + half, length - half)
-	return CompositeNode { head.Length() + tail.Length(), head, tail }
 }
 
-func ConcatNodes (node1 Node, node2 Node) Node  {
-	// All Text instances are maintained balanced:
-	//   (head < tail * 2) & (tail < head * 2)
-	var length = node1.Length() + node2.Length()
-	if length <= BLOCK_SIZE { // Merges to primitive.
-		var mergedArray = make ([]rune, length)
-		node1.CopyTo (0, mergedArray, 0, node1.Length())
-		node2.CopyTo (0, mergedArray, node1.Length(), node2.Length())
-		return CreateLeafNode (mergedArray)
+/// <summary>
+/// Returns the node split into blocks of the given configuration if it is a leaf
+/// longer than a block. All the trees of texts are built through this function, so
+/// that they are always chunked: pieces and cold leaves alone stay whole, being cut
+/// on access, and inline leaves until they are concatenated.
+/// </summary>
+fn chunked(node : Shared<dyn Node>, config : &TextConfig) -> Shared<dyn Node> {
+	let length = node.length();
+	if length > config.block_size() && node.as_composite().is_none() && !node.is_piece() && !node.is_cold() {
+		return node_of(&node, 0, length, config);
 	}
-	// Returns a composite.
-	var head = node1
-	var tail = node2
-	var compositeTail, isType = tail.(CompositeNode)
-	if (head.Length() << 1) < tail.Length() && isType {
-		// head too small, returns (head + tail/2) + (tail/2)
-		if compositeTail.head.Length() > compositeTail.tail.Length() {
-			// Rotates to concatenate with smaller part.
-			compositeTail = compositeTail.RotateRight ()
+	node
+}
+
+/// <summary>Returns whether a leaf of the given length may be spliced in place, staying non-empty and within the policy.</summary>
+fn splices(length : usize, removed : usize, inserted : usize, config : &TextConfig) -> bool {
+	let remaining = length - removed;
+	(remaining > 0 || inserted > 0) && config.chunk_policy().merges(remaining, inserted)
+}
+
+/// <summary>
+/// Returns the leaf a splice of <code>removed</code> characters at <code>start</code>
+/// goes to, the one before a leaf boundary for insertions, or None if the range
+/// spans several leaves.
+/// </summary>
+fn splice_leaf(root : &dyn Node, mut start : usize, removed : usize) -> Option<&dyn Node> {
+	let mut node = root;
+	while let Some(composite) = node.as_composite() {
+		let cesure = composite.head.length();
+		if start + removed <= cesure {
+			node = &*composite.head;
+		} else if start >= cesure {
+			start -= cesure;
+			node = &*composite.tail;
+		} else {
+			return None;
 		}
-		head = ConcatNodes (head, compositeTail.head)
-		tail = compositeTail.tail
+	}
+	Some(node)
+}
+
+/// <summary>
+/// Replaces <code>removed</code> characters at <code>start</code> with the given ones
+/// in place, provided they lie within a single leaf (see <code>splice_leaf</code>) and
+/// every node down to it is uniquely owned, updating the cached lengths and metrics on
+/// the way back. If <code>hot</code> is set, the leaf is first materialized as a gap
+/// buffer so that further edits at the same point are cheap.
+/// </summary>
+/// <returns>false if the tree was left unchanged.</returns>
+fn splice_in_place(node : &mut Shared<dyn Node>, start : usize, removed : usize, data : &[char], config : &TextConfig, hot : bool) -> bool {
+	if hot && node.as_composite().is_none() && !node.is_gap_buffer() && splices(node.length(), removed, data.len(), config) {
+		*node = Shared::new(GapLeafNode::new(&**node));
+	}
+	let node = match Shared::get_mut(node) {
+		Some(node) => node,
+		None => return false
+	};
+	let composite = match node.as_composite_mut() {
+		Some(composite) => composite,
+		None => return node.splice_chars(start, removed, data, config)
+	};
+	let cesure = composite.head.length();
+	let spliced = if start + removed <= cesure {
+		splice_in_place(&mut composite.head, start, removed, data, config, hot)
+	} else if start >= cesure {
+		splice_in_place(&mut composite.tail, start - cesure, removed, data, config, hot)
 	} else {
-		var compositeHead, isType2 = head.(CompositeNode)
-		if (tail.Length() << 1) < head.Length() && isType2 {
-			// tail too small, returns (head/2) + (head/2 concat tail)
-			if (compositeHead.tail.Length() > compositeHead.head.Length()) {
-				// Rotates to concatenate with smaller part.
-				compositeHead = compositeHead.RotateLeft ()
-			}
-			tail = ConcatNodes (compositeHead.tail, tail)
-			head = compositeHead.head
-		}
+		false // Overlaps head and tail.
+	};
+	if !spliced {
+		return false;
 	}
-	return CompositeNode { head.Length() + tail.Length(), head, tail }
+	composite.count = composite.count - removed + data.len();
+	composite.metrics = composite.head.metrics().combine(composite.tail.metrics());
+	true
 }
 
-func CreateLeafNode (str []rune) Node {
-//			byte [] bytes = ToBytesIfPossible (str);
-//			if (bytes != null)
-//				return new Leaf8BitNode (bytes);
-	return WideLeafNode { str}
+fn create_leaf_node(data : Vec<char>, config : &TextConfig) -> Shared<dyn Node> {
+	if config.chunk_policy().prefer_ascii_leaves() && data.iter().all(char::is_ascii) {
+		return pool::leaf(PoolClass::Ascii, data, |data| Shared::new(Leaf8BitNode::new(&data)));
+	}
+	pool::leaf(PoolClass::Wide, data, |data| Shared::new(WideLeafNode::new(data)))
 }
 
-type ImmutableText struct {
-	root Node
+/// <summary>The root of a text: a tree of nodes, or a short text stored inline.</summary>
+#[derive(Clone)]
+enum Root {
+	Inline(InlineLeaf),
+	Node(Shared<dyn Node>)
 }
 
-func (this ImmutableText) Length() int {
-	return this.root.Length()
+impl Root {
+	/// <summary>Returns the root as a shared node, moving an inline text to the heap.</summary>
+	fn to_node(&self) -> Shared<dyn Node> {
+		match self {
+			Root::Inline(leaf) => Shared::new(leaf.clone()),
+			Root::Node(node) => node.clone()
+		}
+	}
+}
+
+impl Deref for Root {
+	type Target = dyn Node;
+
+	fn deref(&self) -> &Self::Target {
+		match self {
+			Root::Inline(leaf) => leaf,
+			Root::Node(node) => &**node
+		}
+	}
 }
 
-func (this ImmutableText) GetCharAt(index int) rune {
-	var leaf = this.FindLeaf(index, 0)
-	return (*leaf.leafNode).GetCharAt(index - leaf.offset)
+#[derive(Clone)]
+pub struct ImmutableText {
+	root : Root,
+	config : TextConfig,
+	cache : LeafCache
 }
 
-type InnerLeaf struct {
-	leafNode *Node
-	offset int
+struct InnerLeaf<'a> {
+	leaf_node : &'a dyn Node,
+	offset : usize
 }
 
-func (this ImmutableText) EnsureChunked() ImmutableText {
-	var len = this.Length()
-	var composite, isComposite = this.root.(CompositeNode)
-	if len > BLOCK_SIZE && !isComposite {
-		return ImmutableText { NodeOf (composite, 0, len) }
+impl ImmutableText {
+	pub fn length(&self) -> usize {
+		self.root.length()
 	}
-	return this
-}
 
-func (this ImmutableText) FindLeaf(index int, offset int) InnerLeaf {
-	var node = this.root
-	for {
-		if index >= node.Length() {
-			return InnerLeaf { nil, -1 }
+	pub fn get_char_at(&self, index : usize) -> char {
+		match self.char_at_checked(index) {
+			Some(c) => c,
+			None => panic!("index out of range: {} (length {})", index, self.length())
 		}
+	}
 
-		var composite, isComposite = node.(CompositeNode)
-		if isComposite {
-			if (index < composite.head.Length()) {
-				node = composite.head
-			} else {
-				offset += composite.head.Length()
-				index -= composite.head.Length()
-				node = composite.tail
+	/// <summary>Returns the root, cut again into the blocks of the given configuration if they are smaller than those of this text.</summary>
+	fn chunked_root(&self, config : &TextConfig) -> Shared<dyn Node> {
+		let length = self.length();
+		if self.config.block_size() > config.block_size() && length > config.block_size() && !self.root.is_piece() && !self.root.is_cold() {
+			return node_of(&self.root.to_node(), 0, length, config);
+		}
+		chunked(self.root.to_node(), config)
+	}
+
+	/// <summary>Returns a text with the given root, chunked, and the configuration of this text.</summary>
+	fn with_root(&self, root : Shared<dyn Node>) -> ImmutableText {
+		ImmutableText { root : Root::Node(chunked(root, &self.config)), config : self.config, cache : LeafCache::default() }
+	}
+
+	/// <summary>Returns a text stored inline with the configuration of this text.</summary>
+	fn with_inline(&self, leaf : InlineLeaf) -> ImmutableText {
+		ImmutableText { root : Root::Inline(leaf), config : self.config, cache : LeafCache::default() }
+	}
+
+	/// <summary>Returns an empty text with the configuration of this text.</summary>
+	fn empty(&self) -> ImmutableText {
+		self.with_inline(InlineLeaf::default())
+	}
+
+	fn find_leaf(&self, mut index : usize, mut offset : usize) -> Option<InnerLeaf<'_>> {
+		// An inline leaf moves with the text: only leaves on the heap are cached.
+		let mut link = match &self.root {
+			Root::Inline(leaf) => {
+				return (index < leaf.length()).then_some(InnerLeaf { leaf_node : leaf, offset });
+			}
+			Root::Node(root) => root
+		};
+		loop {
+			if index >= link.length() {
+				return None;
+			}
+			match link.as_composite() {
+				Some(composite) => {
+					if index < composite.head.length() {
+						link = &composite.head;
+					} else {
+						offset += composite.head.length();
+						index -= composite.head.length();
+						link = &composite.tail;
+					}
+				}
+				None => {
+					self.cache.set(link, offset);
+					return Some(InnerLeaf { leaf_node : &**link, offset });
+				}
 			}
-			continue
 		}
+	}
 
-		return InnerLeaf { &node, offset }
+	/// <summary>
+	/// Concatenates the specified text to the end of this text.
+	/// This method is very fast (faster even than
+	/// <code>StringBuffer.append(String)</code>) and still returns
+	/// a text instance with an internal binary tree of minimal depth!
+	/// </summary>
+	/// <param name="that">that the text that is concatenated.</param>
+	/// <returns><code>this + that</code>, with the configuration of this text.</returns>
+	pub fn concat(&self, that : &ImmutableText) -> ImmutableText {
+		let text = self.join_uncoalesced(that).coalesce_at(self.length());
+		#[cfg(feature = "shadow")]
+		shadow::check("concat", &text, &(self.to_string() + &that.to_string()));
+		text
 	}
-}
 
-/// <summary>
-/// Concatenates the specified text to the end of this text.
-/// This method is very fast (faster even than
-/// <code>StringBuffer.append(String)</code>) and still returns
-/// a text instance with an internal binary tree of minimal depth!
-/// </summary>
-/// <param name="that">that the text that is concatenated.</param>
-/// <returns><code>this + that</code></returns>
-func (this ImmutableText) Concat(that ImmutableText) ImmutableText {
-	if that.Length() == 0 {
-		return this
+	/// <summary>Concatenates without coalescing the leaves meeting at the seam.</summary>
+	fn join_uncoalesced(&self, that : &ImmutableText) -> ImmutableText {
+		let text = self.join_nodes(that);
+		if text.root.depth() > MAX_DEPTH {
+			return self.with_root(balanced_node(&collect_leaves(&text.root.to_node())));
+		}
+		text
+	}
+
+	/// <summary>Concatenates without coalescing nor limiting the depth of the result.</summary>
+	fn join_nodes(&self, that : &ImmutableText) -> ImmutableText {
+		if that.length() == 0 {
+			return self.clone();
+		}
+		if self.length() == 0 {
+			return ImmutableText { root : that.root.clone(), config : self.config, cache : that.cache.clone() };
+		}
+		if let Some(leaf) = InlineLeaf::of_nodes(&*self.root, &*that.root) {
+			return self.with_inline(leaf);
+		}
+		let root = concat_nodes(chunked(self.root.to_node(), &self.config), that.chunked_root(&self.config), &self.config);
+		debug_assert_eq!(root.length(), self.length() + that.length());
+		self.with_root(root)
+	}
+
+	/// <summary>
+	/// Returns a portion of this text.
+	/// </summary>
+	/// <returns>the sub-text starting at the specified start position and ending just before the specified end position.</returns>
+	pub fn get_text(&self, start : usize, count : usize) -> ImmutableText {
+		if let Err(error) = self.check_range(start, count) {
+			panic!("{}", error);
+		}
+		let text = self.slice_text(start, count);
+		#[cfg(feature = "shadow")]
+		shadow::check("get_text", &text, &self.to_string().chars().skip(start).take(count).collect::<String>());
+		text
+	}
+
+	fn slice_text(&self, start : usize, count : usize) -> ImmutableText {
+		let end = start + count;
+		if start == 0 && end == self.length() {
+			return self.clone();
+		}
+		if start == end {
+			return self.empty();
+		}
+		if count <= INLINE_CAPACITY {
+			let mut data = ['\0'; INLINE_CAPACITY];
+			self.root.copy_to(start, &mut data, 0, count);
+			if let Some(leaf) = InlineLeaf::new(&data[..count]) {
+				return self.with_inline(leaf);
+			}
+		}
+		let root = self.root.to_node().sub_node(start, end, &self.config);
+		debug_assert_eq!(root.length(), count);
+		self.with_root(root)
+	}
+
+	/// <summary>
+	/// Concatenates the specified text to the end of this text, consuming it. When
+	/// the nodes down to the rightmost leaf are not shared with any other text and
+	/// the leaf has room, the characters are appended to it in place instead of new
+	/// nodes being created, which makes appending in a loop cheap.
+	/// </summary>
+	/// <returns><code>this + that</code>, with the configuration of this text.</returns>
+	pub fn append(mut self, that : &ImmutableText) -> ImmutableText {
+		#[cfg(feature = "shadow")]
+		let expected = self.to_string() + &that.to_string();
+		let mut spliced = false;
+		self.cache.clear();
+		if let Root::Node(root) = &mut self.root {
+			if that.length() <= self.config.chunk_policy().merge_threshold() {
+				let mut data = vec!['\0'; that.length()];
+				that.root.copy_to(0, &mut data, 0, that.length());
+				let length = root.length();
+				spliced = splice_in_place(root, length, 0, &data, &self.config, false);
+			}
+		}
+		let text = if spliced { self } else { self.concat(that) };
+		#[cfg(feature = "shadow")]
+		shadow::check("append", &text, &expected);
+		text
+	}
+
+	/// <summary>
+	/// Appends the string to this text, consuming it, like <code>String::push_str</code>.
+	/// Short strings go in place into the rightmost leaf when it is uniquely owned and
+	/// has room, as with <code>append</code>, without a text being created for them.
+	/// </summary>
+	pub fn with_pushed_str(mut self, text : &str) -> ImmutableText {
+		let data : Vec<char> = text.chars().collect();
+		if data.len() <= self.config.chunk_policy().merge_threshold() && self.insert_in_place(self.length(), &data) {
+			return self;
+		}
+		self.concat(&create_immutable_text(text))
+	}
+
+	/// <summary>Appends the character to this text, consuming it, like <code>String::push</code>.</summary>
+	pub fn with_pushed_char(self, c : char) -> ImmutableText {
+		let length = self.length();
+		self.with_inserted_char(length, c)
 	}
-	if this.Length() == 0 {
-		return that
+
+	/// <summary>
+	/// Inserts the character at <code>index</code>, consuming this text, like
+	/// <code>String::insert</code>. The character goes in place into its leaf when the
+	/// nodes down to it are uniquely owned and the leaf has room.
+	/// </summary>
+	pub fn with_inserted_char(mut self, index : usize, c : char) -> ImmutableText {
+		if let Err(error) = self.check_index(index) {
+			panic!("{}", error);
+		}
+		if self.insert_in_place(index, &[c]) {
+			return self;
+		}
+		self.insert_string(index, c.encode_utf8(&mut [0; 4]))
+	}
+
+	/// <summary>Inserts the characters at <code>index</code> in place, see <code>splice_in_place</code>.</summary>
+	/// <returns>false if the text was left unchanged.</returns>
+	fn insert_in_place(&mut self, index : usize, data : &[char]) -> bool {
+		#[cfg(feature = "shadow")]
+		let expected = shadow::splice(&self.to_string(), index, 0, &data.iter().collect::<String>());
+		let spliced = match &mut self.root {
+			Root::Node(root) => splice_in_place(root, index, 0, data, &self.config, false),
+			Root::Inline(_) => false
+		};
+		if spliced {
+			self.cache.clear();
+			#[cfg(feature = "shadow")]
+			shadow::check("insert_in_place", self, &expected);
+		}
+		spliced
+	}
+
+	pub fn insert_text(&self, index : usize, text : &ImmutableText) -> ImmutableText {
+		if let Err(error) = self.check_index(index) {
+			panic!("{}", error);
+		}
+		let inserted = self.get_text(0, index).append(text).concat(&self.sub_text(index));
+		#[cfg(feature = "shadow")]
+		shadow::check("insert_text", &inserted, &shadow::splice(&self.to_string(), index, 0, &text.to_string()));
+		inserted
+	}
+
+	pub fn insert_string(&self, index : usize, text : &str) -> ImmutableText {
+		self.insert_text(index, &create_immutable_text(text))
+	}
+
+	/// <summary>
+	/// Returns the text without the characters between the specified indexes.
+	/// </summary>
+	/// <returns><code>subtext(0, start).concat(subtext(end))</code></returns>
+	pub fn remove_text(&self, start : usize, count : usize) -> ImmutableText {
+		if let Err(error) = self.check_range(start, count) {
+			panic!("{}", error);
+		}
+		if count == 0 {
+			return self.clone();
+		}
+		let end = start + count;
+		let text = self.get_text(0, start).concat(&self.sub_text(end));
+		#[cfg(feature = "shadow")]
+		shadow::check("remove_text", &text, &shadow::splice(&self.to_string(), start, count, ""));
+		text
+	}
+
+	pub fn sub_text(&self, start : usize) -> ImmutableText {
+		self.get_text(start, self.length() - start)
+	}
+
+	/// <summary>
+	/// Returns the first <code>length</code> characters of this text, or the whole text
+	/// if it is not longer, like <code>String::truncate</code>.
+	/// </summary>
+	pub fn truncate(&self, length : usize) -> ImmutableText {
+		if length >= self.length() {
+			return self.clone();
+		}
+		self.get_text(0, length)
+	}
+
+	/// <summary>
+	/// Splits this text at the specified index, like <code>String::split_off</code>.
+	/// Both parts share the leaves of this text, split along a single path of the tree.
+	/// </summary>
+	/// <returns>the characters before <code>at</code> and the characters from <code>at</code>.</returns>
+	pub fn split_off(&self, at : usize) -> (ImmutableText, ImmutableText) {
+		if let Err(error) = self.check_index(at) {
+			panic!("{}", error);
+		}
+		(self.get_text(0, at), self.sub_text(at))
+	}
+
+	/// <summary>Returns an iterator over the characters of this text.</summary>
+	pub fn chars(&self) -> Chars<'_> {
+		Chars::new(&*self.root, 0, self.length())
+	}
+
+	/// <summary>Returns an iterator over the characters between <code>start</code> and <code>end</code>.</summary>
+	pub fn chars_range(&self, start : usize, end : usize) -> Chars<'_> {
+		if let Err(error) = self.check_bounds(start, end) {
+			panic!("{}", error);
+		}
+		Chars::new(&*self.root, start, end)
 	}
-	return ImmutableText { ConcatNodes (this.EnsureChunked().root, that.EnsureChunked().root) }
 }
 
-/// <summary>
-/// Returns a portion of this text.
-// </summary>
-/// <returns>the sub-text starting at the specified start position and ending just before the specified end position.</returns>
-func (this ImmutableText) GetText(start int, count int) ImmutableText {
-	var end = start + count
-//	if ((start < 0) || (start > end) || (end > Length)) {
-//		throw new IndexOutOfRangeException (" start :" + start + " end :" + end + " needs to be between 0 <= " + Length)
-//	}
-	if start == 0 && end == this.Length() {
-		return this
+pub fn create_immutable_text(text : &str) -> ImmutableText {
+	TextConfig::new().create(text)
+}
+
+impl Default for ImmutableText {
+	fn default() -> ImmutableText {
+		TextConfig::new().create("")
 	}
-	if start == end {
-		return ImmutableText { WideLeafNode {make([]rune, 0)}}
+}
+
+impl PartialEq for ImmutableText {
+	fn eq(&self, other : &ImmutableText) -> bool {
+		let same_root = match (&self.root, &other.root) {
+			(Root::Node(node), Root::Node(other_node)) => Shared::ptr_eq(node, other_node),
+			_ => false
+		};
+		same_root || (self.length() == other.length() && self.chars().eq(other.chars()))
+	}
+}
+
+impl Eq for ImmutableText {}
+
+impl PartialOrd for ImmutableText {
+	fn partial_cmp(&self, other : &ImmutableText) -> Option<cmp::Ordering> {
+		Some(self.cmp(other))
 	}
-	return ImmutableText { this.root.SubNode (start, end) }
 }
 
-func (this ImmutableText) InsertText(index int, text ImmutableText) ImmutableText {
-	return this.GetText (0, index).Concat (text).Concat (this.SubText (index))
+impl Ord for ImmutableText {
+	fn cmp(&self, other : &ImmutableText) -> cmp::Ordering {
+		self.chars().cmp(other.chars())
+	}
 }
 
-func (this ImmutableText) InsertString(index int, text string) ImmutableText {
-	return this.InsertText (index, CreateImmutableText (text))
+impl fmt::Debug for ImmutableText {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&self.to_string(), f)
+	}
 }
 
-func CreateImmutableText (text string) ImmutableText {
-	return ImmutableText { WideLeafNode { []rune(text) } }
+impl fmt::Display for ImmutableText {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mut chars = vec!['\0'; self.length()];
+		self.root.copy_to(0, &mut chars, 0, self.length());
+		f.write_str(&chars.into_iter().collect::<String>())
+	}
 }
 
 /// <summary>
-/// Returns the text without the characters between the specified indexes.
+/// Iterates over the characters of a leaf between two offsets. The characters of a
+/// leaf decoded on access, such as a cold leaf, are decoded once for the whole walk.
 /// </summary>
-/// <returns><code>subtext(0, start).concat(subtext(end))</code></returns>
-func (this ImmutableText) RemoveText(start int, count int) ImmutableText {
-	if count == 0 {
-		return this
+pub(crate) struct LeafChars<'a> {
+	leaf : &'a dyn Node,
+	decoded : Option<Arc<[char]>>,
+	index : usize,
+	end : usize
+}
+
+impl<'a> LeafChars<'a> {
+	pub(crate) fn new(leaf : &'a dyn Node, start : usize, end : usize) -> LeafChars<'a> {
+		LeafChars { leaf, decoded : leaf.decoded(), index : start, end }
 	}
-	var end = start + count
-//	if (end > Length)
-//		throw new IndexOutOfRangeException ();
-	return this.EnsureChunked ().GetText (0, start).Concat (this.SubText (end))
 }
 
-func (this ImmutableText) SubText(start int) ImmutableText {
-	return this.GetText (start, this.Length() - start)
+impl Iterator for LeafChars<'_> {
+	type Item = char;
+
+	fn next(&mut self) -> Option<char> {
+		if self.index >= self.end {
+			return None;
+		}
+		let c = match &self.decoded {
+			Some(chars) => chars[self.index],
+			None => self.leaf.get_char_at(self.index)
+		};
+		self.index += 1;
+		Some(c)
+	}
 }
 
-func (this ImmutableText) ToString() string {
-	var runes = make([]rune, this.Length())
-	this.root.CopyTo(0, runes, 0, this.Length())
-	return string(runes)
+/// <summary>
+/// Iterates over the characters of a text, walking the leaves from left to right
+/// with an explicit stack of the subtrees still to visit.
+/// </summary>
+pub struct Chars<'a> {
+	stack : Vec<&'a dyn Node>,
+	leaf : LeafChars<'a>,
+	remaining : usize
 }
-*/
 
-fn main() {
+impl<'a> Chars<'a> {
+	fn new(root : &'a dyn Node, start : usize, end : usize) -> Chars<'a> {
+		let mut stack = Vec::new();
+		let mut node = root;
+		let mut index = start;
+		while let Some(composite) = node.as_composite() {
+			let head_length = composite.head.length();
+			if index < head_length {
+				stack.push(&*composite.tail);
+				node = &*composite.head;
+			} else {
+				index -= head_length;
+				node = &*composite.tail;
+			}
+		}
+		Chars { stack, leaf : LeafChars::new(node, index, node.length()), remaining : end - start }
+	}
+}
 
-  println!("Hello world");
+impl Iterator for Chars<'_> {
+	type Item = char;
 
-  /*
-	for j := 0; j < 100; j++ {
-		var myText = CreateImmutableText("hello")
-		for i := 0; i < 100000; i++ {
-			myText = myText.InsertString(i, "1")
+	fn next(&mut self) -> Option<char> {
+		if self.remaining == 0 {
+			return None;
 		}
-		for i := 0; i < 100000; i++ {
-			myText = myText.RemoveText(0, 1)
+		loop {
+			if let Some(c) = self.leaf.next() {
+				self.remaining -= 1;
+				return Some(c);
+			}
+			// Descends to the leftmost leaf of the next pending subtree.
+			let mut node = self.stack.pop()?;
+			while let Some(composite) = node.as_composite() {
+				self.stack.push(&*composite.tail);
+				node = &*composite.head;
+			}
+			self.leaf = LeafChars::new(node, 0, node.length());
 		}
 	}
-  */
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.remaining, Some(self.remaining))
+	}
 }
@@ -0,0 +1,193 @@
+use std::ptr;
+
+use super::{ImmutableText, Node};
+
+/// <summary>
+/// Walks the leaves of a tree in one direction, with an explicit stack of the subtrees
+/// still to visit and the number of characters of the first one already consumed.
+/// </summary>
+struct Cursor<'a> {
+	stack : Vec<&'a dyn Node>,
+	skip : usize,
+	reverse : bool
+}
+
+impl<'a> Cursor<'a> {
+	/// <summary>Replaces the composite on top of the stack with its children, dropping those already consumed.</summary>
+	fn expand(&mut self) {
+		let composite = self.stack.pop().and_then(|node| node.as_composite()).unwrap();
+		let (first, second) = if self.reverse { (&*composite.tail, &*composite.head) } else { (&*composite.head, &*composite.tail) };
+		self.stack.push(second);
+		if self.skip >= first.length() {
+			self.skip -= first.length();
+		} else {
+			self.stack.push(first);
+		}
+	}
+
+	/// <summary>Returns the next <code>count</code> characters of the leaf on top of the stack, in the walking order.</summary>
+	fn leaf_chars(&self, count : usize) -> Vec<char> {
+		let leaf = self.stack[self.stack.len() - 1];
+		let start = if self.reverse { leaf.length() - self.skip - count } else { self.skip };
+		let mut data = vec!['\0'; count];
+		leaf.copy_to(start, &mut data, 0, count);
+		if self.reverse {
+			data.reverse();
+		}
+		data
+	}
+
+	fn advance(&mut self, count : usize) {
+		self.skip += count;
+		if self.skip == self.stack[self.stack.len() - 1].length() {
+			self.stack.pop();
+			self.skip = 0;
+		}
+	}
+}
+
+/// <summary>
+/// Returns the number of characters the trees have in common from their start, or from
+/// their end if <code>reverse</code> is set. Leaves are compared chunk by chunk, and the
+/// subtrees both trees share at the same position are skipped whole.
+/// </summary>
+fn common_length(a : &dyn Node, b : &dyn Node, reverse : bool) -> usize {
+	let mut left = Cursor { stack : vec![a], skip : 0, reverse };
+	let mut right = Cursor { stack : vec![b], skip : 0, reverse };
+	let mut common = 0;
+	loop {
+		let (x, y) = match (left.stack.last(), right.stack.last()) {
+			(Some(&x), Some(&y)) => (x, y),
+			_ => return common
+		};
+		if left.skip == 0 && right.skip == 0 && ptr::addr_eq(x, y) {
+			common += x.length();
+			left.stack.pop();
+			right.stack.pop();
+			continue;
+		}
+		// Descends the longer side first, so that subtrees of the same size meet.
+		if x.as_composite().is_some() && (x.length() >= y.length() || y.as_composite().is_none()) {
+			left.expand();
+			continue;
+		}
+		if y.as_composite().is_some() {
+			right.expand();
+			continue;
+		}
+		let count = (x.length() - left.skip).min(y.length() - right.skip);
+		if let Some(i) = left.leaf_chars(count).iter().zip(right.leaf_chars(count)).position(|(a, b)| *a != b) {
+			return common + i;
+		}
+		common += count;
+		left.advance(count);
+		right.advance(count);
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the length of the longest common prefix of this text and the specified one.
+	/// The subtrees the texts share, as versions of one document do, are skipped without
+	/// their characters being read, so only the edited parts cost.
+	/// </summary>
+	pub fn common_prefix_len(&self, that : &ImmutableText) -> usize {
+		common_length(&*self.root, &*that.root, false)
+	}
+
+	/// <summary>Returns the length of the longest common suffix of this text and the specified one, see <code>common_prefix_len</code>.</summary>
+	pub fn common_suffix_len(&self, that : &ImmutableText) -> usize {
+		common_length(&*self.root, &*that.root, true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::metrics::LineMetrics;
+	use super::super::{create_immutable_text, create_leaf_node, CompositeNode, Shared, TextConfig};
+	use super::*;
+
+	/// <summary>A leaf whose characters cannot be read, to check that shared subtrees are skipped.</summary>
+	struct Unreadable {
+		metrics : LineMetrics
+	}
+
+	impl Node for Unreadable {
+		fn length(&self) -> usize {
+			100
+		}
+
+		fn sub_node(self: Shared<Self>, _start: usize, _end: usize, _config : &TextConfig) -> Shared<dyn Node> {
+			unreachable!("unreadable leaf sliced")
+		}
+
+		fn get_char_at(&self, _offset : usize) -> char {
+			unreachable!("unreadable leaf read")
+		}
+
+		fn copy_to(&self, _source_index : usize, _destination : &mut [char], _destination_index : usize, _count : usize) {
+			unreachable!("unreadable leaf read")
+		}
+
+		fn metrics(&self) -> &LineMetrics {
+			&self.metrics
+		}
+
+		fn allocated_bytes(&self) -> usize {
+			0
+		}
+	}
+
+	/// <summary>Returns the lengths of the common prefix and suffix of the strings, in characters.</summary>
+	fn affixes(a : &str, b : &str) -> (usize, usize) {
+		let prefix = a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count();
+		let suffix = a.chars().rev().zip(b.chars().rev()).take_while(|(x, y)| x == y).count();
+		(prefix, suffix)
+	}
+
+	fn check(a : &ImmutableText, b : &ImmutableText) {
+		let expected = affixes(&a.to_string(), &b.to_string());
+		assert_eq!((a.common_prefix_len(b), a.common_suffix_len(b)), expected);
+		assert_eq!((b.common_prefix_len(a), b.common_suffix_len(a)), expected);
+	}
+
+	#[test]
+	fn matches_the_affixes_of_the_strings() {
+		let content = "line one\r\nligne deux é\r\n日本語 🦀\n".repeat(50);
+		let text = create_immutable_text(&content);
+		let small = TextConfig::new().with_block_size(16).create(&content);
+		let empty = create_immutable_text("");
+		for other in [
+			text.clone(),
+			small.clone(),
+			text.insert_string(700, "edit"),
+			text.remove_text(0, 1),
+			text.remove_text(text.length() - 1, 1),
+			text.insert_string(9, "\r").remove_text(1000, 30),
+			small.insert_string(17, "x"),
+			text.get_text(0, 333),
+			text.sub_text(333),
+			empty.clone(),
+			create_immutable_text("line two")
+		] {
+			check(&text, &other);
+			check(&small, &other);
+			check(&empty, &other);
+		}
+	}
+
+	#[test]
+	fn skips_the_subtrees_both_texts_share() {
+		let shared : Shared<dyn Node> = Shared::new(Unreadable { metrics : LineMetrics::of_chars(&['x'; 100]) });
+		let config = TextConfig::new();
+		let text = |head : Shared<dyn Node>, tail : Shared<dyn Node>| create_immutable_text("").with_root(Shared::new(CompositeNode::new(head, tail)));
+		let leaf = |content : &str| create_leaf_node(content.chars().collect(), &config);
+		let a = text(shared.clone(), leaf("ab\r\n"));
+		let b = text(shared.clone(), leaf("ab\n"));
+		assert_eq!(a.common_prefix_len(&b), 102);
+		let a = text(leaf("é\r\nab"), shared.clone());
+		let b = text(leaf("\r\nab"), shared.clone());
+		assert_eq!(a.common_suffix_len(&b), 104);
+		assert_eq!(a.common_prefix_len(&b), 0);
+	}
+}
@@ -0,0 +1,71 @@
+use std::alloc::Allocator;
+use std::mem;
+
+use super::metrics::LineMetrics;
+use super::{chunked, ImmutableText, LeafCache, Node, Root, Shared, TextConfig};
+
+/// <summary>
+/// A leaf whose characters live in a caller-provided allocator. Sub-leaves cut from
+/// it, as when the text is chunked, stay in the same allocator.
+/// </summary>
+struct AllocLeafNode<A : Allocator + Clone + Send + Sync + 'static> {
+	data : Vec<char, A>,
+	metrics : LineMetrics
+}
+
+impl<A : Allocator + Clone + Send + Sync + 'static> AllocLeafNode<A> {
+	fn new(data : Vec<char, A>) -> AllocLeafNode<A> {
+		AllocLeafNode {
+			metrics : LineMetrics::of_chars(&data),
+			data
+		}
+	}
+}
+
+impl<A : Allocator + Clone + Send + Sync + 'static> Node for AllocLeafNode<A> {
+	fn length(&self) -> usize {
+		self.data.len()
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, _config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		let mut data = Vec::with_capacity_in(end - start, self.data.allocator().clone());
+		data.extend_from_slice(&self.data[start..end]);
+		Shared::new(AllocLeafNode::new(data))
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		self.data[offset]
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		destination[destination_index..destination_index + count].copy_from_slice(&self.data[source_index..source_index + count]);
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<AllocLeafNode<A>>() + self.data.capacity() * mem::size_of::<char>()
+	}
+}
+
+/// <summary>
+/// Creates a text whose characters are stored in the given allocator: the leaves it
+/// is chunked into, and the parts of them later versions keep, stay there.
+/// </summary>
+/// <remarks>
+/// Only the characters of this text are placed in the allocator. Composites, the
+/// characters inserted by later edits and the leaves edits merge or copy out (on
+/// concatenation, <code>compact</code>, <code>canonicalize</code>...) use the
+/// global allocator.
+/// </remarks>
+pub fn create_immutable_text_in<A : Allocator + Clone + Send + Sync + 'static>(text : &str, alloc : A) -> ImmutableText {
+	let mut data = Vec::new_in(alloc);
+	data.extend(text.chars());
+	let config = TextConfig::new();
+	ImmutableText { root : Root::Node(chunked(Shared::new(AllocLeafNode::new(data)), &config)), config, cache : LeafCache::default() }
+}
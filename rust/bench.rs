@@ -0,0 +1,130 @@
+//! Replays a trace of editor operations against ImmutableText and reports the
+//! throughput and latency percentiles of every kind of operation.
+//!
+//! A trace holds one operation per line, texts escaped with \n, \t and \\:
+//!
+//!     insert <index> <text>
+//!     remove <start> <count>
+//!     find <pattern>
+//!
+//! Without a trace file, the loop of the original speed test is replayed:
+//! 100000 single character inserts then as many removals at the start.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+
+use immutable_text::{create_immutable_text, ImmutableText};
+
+enum Operation {
+	Insert(usize, String),
+	Remove(usize, usize),
+	Find(String)
+}
+
+impl Operation {
+	fn kind(&self) -> &'static str {
+		match self {
+			Operation::Insert(..) => "insert",
+			Operation::Remove(..) => "remove",
+			Operation::Find(_) => "find"
+		}
+	}
+
+	fn apply(&self, text : ImmutableText) -> ImmutableText {
+		match self {
+			Operation::Insert(index, inserted) => text.insert_string(*index, inserted),
+			Operation::Remove(start, count) => text.remove_text(*start, *count),
+			Operation::Find(pattern) => {
+				text.count_matches(pattern);
+				text
+			}
+		}
+	}
+}
+
+fn unescape(text : &str) -> String {
+	let mut unescaped = String::new();
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			unescaped.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('n') => unescaped.push('\n'),
+			Some('t') => unescaped.push('\t'),
+			Some(other) => unescaped.push(other),
+			None => unescaped.push('\\')
+		}
+	}
+	unescaped
+}
+
+fn parse_trace(trace : &str) -> Result<Vec<Operation>, String> {
+	let mut operations = Vec::new();
+	for (number, line) in trace.lines().enumerate() {
+		if line.trim().is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let index = |field : &str| field.parse::<usize>().map_err(|_| format!("line {}: expected an index: {}", number + 1, field));
+		let (name, arguments) = line.split_once(' ').unwrap_or((line, ""));
+		let (first, rest) = arguments.split_once(' ').unwrap_or((arguments, ""));
+		let operation = match name {
+			"insert" => Operation::Insert(index(first)?, unescape(rest)),
+			"remove" => Operation::Remove(index(first)?, index(rest)?),
+			"find" => Operation::Find(unescape(arguments)),
+			_ => return Err(format!("line {}: unknown operation: {}", number + 1, line))
+		};
+		operations.push(operation);
+	}
+	Ok(operations)
+}
+
+/// <summary>Returns the operations of the speed test kept in the comment of <code>main</code>.</summary>
+fn default_trace() -> Vec<Operation> {
+	let mut operations : Vec<Operation> = (0..100000).map(|i| Operation::Insert(i, String::from("1"))).collect();
+	operations.extend((0..100000).map(|_| Operation::Remove(0, 1)));
+	operations
+}
+
+fn percentile(sorted : &[Duration], percent : usize) -> Duration {
+	sorted[((sorted.len() - 1) * percent) / 100]
+}
+
+fn main() {
+	let operations = match env::args().nth(1) {
+		Some(path) => {
+			let trace = fs::read_to_string(&path).unwrap_or_else(|e| {
+				eprintln!("cannot read {}: {}", path, e);
+				process::exit(1);
+			});
+			parse_trace(&trace).unwrap_or_else(|e| {
+				eprintln!("{}: {}", path, e);
+				process::exit(1);
+			})
+		}
+		None => default_trace()
+	};
+
+	let mut latencies : BTreeMap<&str, Vec<Duration>> = BTreeMap::new();
+	let mut text = create_immutable_text("hello");
+	let start = Instant::now();
+	for operation in &operations {
+		let before = Instant::now();
+		text = operation.apply(text);
+		latencies.entry(operation.kind()).or_default().push(before.elapsed());
+	}
+	let total = start.elapsed();
+
+	println!("{} operations in {:?}, {:.0} ops/s, final length {}", operations.len(), total, operations.len() as f64 / total.as_secs_f64(), text.length());
+	println!("{:<8} {:>8} {:>12} {:>10} {:>10} {:>10} {:>10}", "op", "count", "ops/s", "p50", "p90", "p99", "max");
+	for (kind, mut samples) in latencies {
+		samples.sort();
+		let elapsed : Duration = samples.iter().sum();
+		println!("{:<8} {:>8} {:>12.0} {:>10?} {:>10?} {:>10?} {:>10?}", kind, samples.len(), samples.len() as f64 / elapsed.as_secs_f64(),
+			percentile(&samples, 50), percentile(&samples, 90), percentile(&samples, 99), samples[samples.len() - 1]);
+	}
+}
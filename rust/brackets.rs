@@ -0,0 +1,110 @@
+use super::ImmutableText;
+
+/// <summary>Holds the bracket pairs matched by default.</summary>
+const BRACKET_PAIRS : [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the offset of the bracket matching the one at <code>offset</code>, among
+	/// parentheses, square brackets and braces, scanning forward from an opening
+	/// bracket or backward from a closing one and skipping nested pairs.
+	/// </summary>
+	/// <returns>None if the character is not a bracket or has no match.</returns>
+	pub fn find_matching_bracket(&self, offset : usize) -> Option<usize> {
+		self.find_matching_bracket_with(offset, &BRACKET_PAIRS, |_| false)
+	}
+
+	/// <summary>
+	/// Returns the offset of the bracket matching the one at <code>offset</code> among the
+	/// given pairs, like <code>find_matching_bracket</code>. Brackets at the offsets for
+	/// which <code>skip</code> returns true, such as those within strings or comments,
+	/// are ignored.
+	/// </summary>
+	pub fn find_matching_bracket_with<F>(&self, offset : usize, pairs : &[(char, char)], mut skip : F) -> Option<usize> where F : FnMut(usize) -> bool {
+		let c = self.get(offset)?;
+		if let Some(&(open, close)) = pairs.iter().find(|&&(open, _)| open == c) {
+			let mut depth = 0;
+			for (i, c) in self.chars_range(offset + 1, self.length()).enumerate() {
+				let i = offset + 1 + i;
+				if (c != open && c != close) || skip(i) {
+					continue;
+				}
+				if c == open {
+					depth += 1;
+				} else if depth == 0 {
+					return Some(i);
+				} else {
+					depth -= 1;
+				}
+			}
+			return None;
+		}
+		let &(open, close) = pairs.iter().find(|&&(_, close)| close == c)?;
+		let mut depth = 0;
+		for i in (0..offset).rev() {
+			let c = self.get_char_at(i);
+			if (c != open && c != close) || skip(i) {
+				continue;
+			}
+			if c == close {
+				depth += 1;
+			} else if depth == 0 {
+				return Some(i);
+			} else {
+				depth -= 1;
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn matches_nested_brackets_both_ways() {
+		let text = create_immutable_text("f(a[1], {b: (c)}) [");
+		for (open, close) in [(1, 16), (3, 5), (8, 15), (12, 14)] {
+			assert_eq!(text.find_matching_bracket(open), Some(close));
+			assert_eq!(text.find_matching_bracket(close), Some(open));
+		}
+		assert_eq!(text.find_matching_bracket(0), None);
+		assert_eq!(text.find_matching_bracket(18), None);
+		assert_eq!(text.find_matching_bracket(text.length()), None);
+	}
+
+	#[test]
+	fn leaves_unmatched_brackets_unmatched() {
+		let text = create_immutable_text("(a]) ((b) ]");
+		assert_eq!(text.find_matching_bracket(0), Some(3));
+		assert_eq!(text.find_matching_bracket(2), None);
+		assert_eq!(text.find_matching_bracket(5), None);
+		assert_eq!(text.find_matching_bracket(10), None);
+		assert_eq!(create_immutable_text(")(").find_matching_bracket(0), None);
+		assert_eq!(create_immutable_text(")(").find_matching_bracket(1), None);
+	}
+
+	#[test]
+	fn matches_the_given_pairs_skipping_offsets() {
+		let text = create_immutable_text("<a \"<\"> (\")\")");
+		let pairs = [('<', '>'), ('(', ')')];
+		let quoted = |i : usize| text.to_string()[..i].matches('"').count() % 2 == 1;
+		assert_eq!(text.find_matching_bracket_with(0, &pairs, quoted), Some(6));
+		assert_eq!(text.find_matching_bracket_with(6, &pairs, quoted), Some(0));
+		assert_eq!(text.find_matching_bracket_with(8, &pairs, quoted), Some(12));
+		assert_eq!(text.find_matching_bracket_with(12, &pairs, quoted), Some(8));
+		assert_eq!(text.find_matching_bracket_with(8, &pairs, |_| false), Some(10));
+		assert_eq!(text.find_matching_bracket(0), None);
+	}
+
+	#[test]
+	fn matches_brackets_across_leaves() {
+		let source = format!("{{{}}}", "(x) [y] ".repeat(20));
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		assert!(text.tree_stats().leaf_count > 4);
+		assert_eq!(text.find_matching_bracket(0), Some(source.len() - 1));
+		assert_eq!(text.find_matching_bracket(source.len() - 1), Some(0));
+		assert_eq!(text.find_matching_bracket(85), Some(87));
+	}
+}
@@ -0,0 +1,184 @@
+use std::borrow::Borrow;
+
+use super::rebalance::merge_leaves;
+use super::{balanced_node, collect_leaves, create_immutable_text, create_leaf_node, ImmutableText, Node, Shared, TextConfig};
+
+impl TextConfig {
+	/// <summary>
+	/// Creates a text with this configuration from the chunks, in order. The chunks
+	/// are cut into full blocks whatever their sizes, and the tree is built bottom-up
+	/// over the leaves at minimal depth, so that loaders and deserializers need not
+	/// concatenate the chunks first.
+	/// </summary>
+	pub fn create_from_chunks<I>(&self, chunks : I) -> ImmutableText where I : IntoIterator, I::Item : AsRef<str> {
+		let mut pending = Vec::with_capacity(self.block_size());
+		let mut leaves = Vec::new();
+		for chunk in chunks {
+			for c in chunk.as_ref().chars() {
+				pending.push(c);
+				if pending.len() == self.block_size() {
+					leaves.push(create_leaf_node(std::mem::replace(&mut pending, Vec::with_capacity(self.block_size())), self));
+				}
+			}
+		}
+		if leaves.is_empty() {
+			return self.create(&pending.into_iter().collect::<String>());
+		}
+		if !pending.is_empty() {
+			leaves.push(create_leaf_node(pending, self));
+		}
+		self.create("").with_root(balanced_node(&leaves))
+	}
+
+	/// <summary>
+	/// Creates a text with this configuration over the leaves, in one bottom-up pass:
+	/// runs of adjacent leaves the chunk policy merges are merged, the others shared,
+	/// and the tree built above them has minimal depth.
+	/// </summary>
+	fn text_of_leaves<I : IntoIterator<Item = Shared<dyn Node>>>(&self, leaves : I) -> ImmutableText {
+		let policy = self.chunk_policy();
+		let mut merged = Vec::new();
+		let mut run : Vec<Shared<dyn Node>> = Vec::new();
+		let mut run_length = 0;
+		for leaf in leaves {
+			if leaf.length() == 0 {
+				continue;
+			}
+			if !run.is_empty() && !policy.merges(run_length, leaf.length()) {
+				merged.push(merge_leaves(&run, run_length, self));
+				run.clear();
+				run_length = 0;
+			}
+			run_length += leaf.length();
+			run.push(leaf);
+		}
+		if !run.is_empty() {
+			merged.push(merge_leaves(&run, run_length, self));
+		}
+		let empty = self.create("");
+		if merged.is_empty() {
+			return empty;
+		}
+		empty.with_root(balanced_node(&merged))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Creates a text with the default configuration from the chunks, see <code>TextConfig::create_from_chunks</code>.</summary>
+	pub fn from_chunks<I>(chunks : I) -> ImmutableText where I : IntoIterator, I::Item : AsRef<str> {
+		TextConfig::new().create_from_chunks(chunks)
+	}
+
+	/// <summary>
+	/// Concatenates the fragments in one bottom-up pass over their leaves, which stay
+	/// shared, instead of one concatenation per fragment, building a tree of minimal
+	/// depth. The result has the configuration of the first fragment.
+	/// </summary>
+	pub fn concat_all<I>(fragments : I) -> ImmutableText where I : IntoIterator, I::Item : Borrow<ImmutableText> {
+		let mut fragments = fragments.into_iter().peekable();
+		let config = fragments.peek().map_or(TextConfig::new(), |first| first.borrow().config);
+		let leaves = fragments.flat_map(|fragment| collect_leaves(&fragment.borrow().chunked_root(&config)));
+		config.text_of_leaves(leaves)
+	}
+
+	/// <summary>Concatenates the fragments with the separator between each two, like <code>concat_all</code>.</summary>
+	pub fn join<I>(fragments : I, separator : &str) -> ImmutableText where I : IntoIterator, I::Item : Borrow<ImmutableText> {
+		let separator = create_immutable_text(separator);
+		let mut joined = Vec::new();
+		for (i, fragment) in fragments.into_iter().enumerate() {
+			if i > 0 {
+				joined.push(separator.clone());
+			}
+			joined.push(fragment.borrow().clone());
+		}
+		ImmutableText::concat_all(&joined)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// <summary>Returns the depth of a binary tree of minimal depth over the leaves.</summary>
+	fn minimal_depth(leaf_count : usize) -> usize {
+		leaf_count.next_power_of_two().trailing_zeros() as usize
+	}
+
+	fn fragments(count : usize) -> Vec<ImmutableText> {
+		let config = TextConfig::new().with_block_size(16);
+		(0..count).map(|i| config.create(&format!("fragment {} of the text, ", i).repeat(i % 3 + 1))).collect()
+	}
+
+	#[test]
+	fn concatenates_at_minimal_depth() {
+		let fragments = fragments(200);
+		let expected : String = fragments.iter().map(ImmutableText::to_string).collect();
+		let all = ImmutableText::concat_all(&fragments);
+		let sequential = fragments.iter().fold(TextConfig::new().with_block_size(16).create(""), |text, fragment| text.concat(fragment));
+		assert_eq!(all.to_string(), expected);
+		assert_eq!(sequential.to_string(), expected);
+		assert_eq!(all.validate(), Ok(()));
+		let stats = all.tree_stats();
+		assert_eq!(stats.depth, minimal_depth(stats.leaf_count));
+		assert!(stats.depth <= sequential.tree_stats().depth);
+		assert_eq!(all.config(), fragments[0].config());
+	}
+
+	#[test]
+	fn concatenates_skipping_empty_fragments() {
+		let config = TextConfig::new().with_block_size(16);
+		let empty = config.create("");
+		let texts = [empty.clone(), create_immutable_text("ab"), empty.clone(), empty.clone(), create_immutable_text("cd")];
+		let all = ImmutableText::concat_all(&texts);
+		assert_eq!(all.to_string(), "abcd");
+		assert_eq!(all.config(), config);
+		assert_eq!(all.tree_stats().leaf_count, 1);
+		assert_eq!(ImmutableText::concat_all([&empty, &empty]).to_string(), "");
+		assert_eq!(ImmutableText::concat_all(Vec::<ImmutableText>::new()).config(), TextConfig::new());
+		assert_eq!(ImmutableText::concat_all(Vec::<ImmutableText>::new()).length(), 0);
+	}
+
+	#[test]
+	fn joins_with_a_separator() {
+		let texts : Vec<ImmutableText> = ["a", "", "b", "c"].iter().map(|s| create_immutable_text(s)).collect();
+		assert_eq!(ImmutableText::join(&texts, ", ").to_string(), "a, , b, c");
+		assert_eq!(ImmutableText::join(&texts, "").to_string(), "abc");
+		assert_eq!(ImmutableText::join(&texts[..1], ", ").to_string(), "a");
+		assert_eq!(ImmutableText::join(Vec::<ImmutableText>::new(), ", ").to_string(), "");
+		let fragments = fragments(100);
+		let strings : Vec<String> = fragments.iter().map(ImmutableText::to_string).collect();
+		let joined = ImmutableText::join(&fragments, "\n");
+		assert_eq!(joined.to_string(), strings.join("\n"));
+		let stats = joined.tree_stats();
+		assert_eq!(stats.depth, minimal_depth(stats.leaf_count));
+	}
+
+	#[test]
+	fn creates_from_chunks_skipping_empty_ones() {
+		assert_eq!(ImmutableText::from_chunks(Vec::<&str>::new()).to_string(), "");
+		assert_eq!(ImmutableText::from_chunks(["", "", ""]).length(), 0);
+		assert_eq!(ImmutableText::from_chunks(["", "ab", "", "c", ""]).to_string(), "abc");
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create_from_chunks(["", "0123456789abcdef", "", "0123456789abcdef", ""]);
+		assert_eq!(text.tree_stats().leaf_count, 2);
+		assert_eq!(text.config(), config);
+	}
+
+	#[test]
+	fn creates_from_chunks_of_multi_byte_characters() {
+		// Chunk and block boundaries fall between characters of one to four bytes.
+		let chunks : Vec<String> = (0..100).map(|i| "a\u{e9}\u{20ac}😀".chars().cycle().skip(i).take(i % 7).collect()).collect();
+		let expected : String = chunks.concat();
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create_from_chunks(&chunks);
+		assert_eq!(text.to_string(), expected);
+		assert_eq!(text.length(), expected.chars().count());
+		assert_eq!(text.validate(), Ok(()));
+		let stats = text.tree_stats();
+		assert_eq!(stats.leaf_count, expected.chars().count().div_ceil(16));
+		assert_eq!(stats.depth, minimal_depth(stats.leaf_count));
+		for offset in [0, 15, 16, 17, 100, text.length() - 1] {
+			assert_eq!(text.get_char_at(offset), expected.chars().nth(offset).unwrap());
+		}
+	}
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::io;
+
+use super::{Rope, RopeChunks};
+
+/// <summary>
+/// An immutable binary blob over the balanced tree of blocks, for hex editors and
+/// binary patching: slicing, concatenation and splicing share the unchanged blocks.
+/// </summary>
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ImmutableBytes {
+	rope : Rope<u8>
+}
+
+impl ImmutableBytes {
+	pub fn new() -> ImmutableBytes {
+		ImmutableBytes::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.rope.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rope.is_empty()
+	}
+
+	pub fn get(&self, index : usize) -> Option<u8> {
+		self.rope.get(index).copied()
+	}
+
+	/// <summary>Concatenates the specified bytes to the end of these bytes.</summary>
+	pub fn concat(&self, that : &ImmutableBytes) -> ImmutableBytes {
+		ImmutableBytes { rope : self.rope.concat(&that.rope) }
+	}
+
+	/// <summary>Returns the <code>count</code> bytes starting at <code>start</code>.</summary>
+	pub fn slice(&self, start : usize, count : usize) -> ImmutableBytes {
+		ImmutableBytes { rope : self.rope.slice(start, count) }
+	}
+
+	/// <summary>Returns these bytes with the <code>removed</code> bytes at <code>start</code> replaced by the given ones.</summary>
+	pub fn splice(&self, start : usize, removed : usize, bytes : &[u8]) -> ImmutableBytes {
+		let rope = self.rope.remove(start, removed);
+		if bytes.is_empty() {
+			return ImmutableBytes { rope };
+		}
+		ImmutableBytes { rope : rope.insert(start, &Rope::from_slice(bytes)) }
+	}
+
+	pub fn insert(&self, index : usize, bytes : &[u8]) -> ImmutableBytes {
+		self.splice(index, 0, bytes)
+	}
+
+	pub fn remove(&self, start : usize, count : usize) -> ImmutableBytes {
+		self.splice(start, count, &[])
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+		self.rope.iter().copied()
+	}
+
+	/// <summary>Returns an iterator over the blocks of bytes, in order.</summary>
+	pub fn chunks(&self) -> RopeChunks<'_, u8> {
+		self.rope.chunks()
+	}
+
+	pub fn to_vec(&self) -> Vec<u8> {
+		self.rope.to_vec()
+	}
+
+	/// <summary>Writes the bytes block by block, without gathering them first.</summary>
+	pub fn write_to<W : io::Write>(&self, mut writer : W) -> io::Result<()> {
+		for chunk in self.chunks() {
+			writer.write_all(chunk)?;
+		}
+		Ok(())
+	}
+}
+
+impl From<&[u8]> for ImmutableBytes {
+	fn from(bytes : &[u8]) -> ImmutableBytes {
+		ImmutableBytes { rope : Rope::from_slice(bytes) }
+	}
+}
+
+impl From<Vec<u8>> for ImmutableBytes {
+	fn from(bytes : Vec<u8>) -> ImmutableBytes {
+		ImmutableBytes::from(&bytes[..])
+	}
+}
+
+impl fmt::Debug for ImmutableBytes {
+	/// <summary>Formats the bytes in hexadecimal, in groups of sixteen.</summary>
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("[")?;
+		for (i, b) in self.iter().enumerate() {
+			if i > 0 {
+				f.write_str(if i % 16 == 0 { "\n " } else { " " })?;
+			}
+			write!(f, "{:02x}", b)?;
+		}
+		f.write_str("]")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pattern(length : usize) -> Vec<u8> {
+		(0..length).map(|i| (i * 7 % 256) as u8).collect()
+	}
+
+	#[test]
+	fn splices_across_blocks() {
+		let source = pattern(300);
+		let bytes = ImmutableBytes::from(source.clone());
+		assert_eq!(bytes.chunks().count(), 5);
+		for (start, removed, inserted) in [(0, 0, &b"ab"[..]), (60, 10, b"xyz"), (63, 2, b""), (100, 150, &[0xff; 70][..]), (299, 1, b"!"), (300, 0, b"end")] {
+			let mut expected = source.clone();
+			expected.splice(start..start + removed, inserted.iter().copied());
+			let spliced = bytes.splice(start, removed, inserted);
+			assert_eq!(spliced.to_vec(), expected);
+			assert_eq!(spliced.len(), expected.len());
+		}
+		assert_eq!(bytes.insert(64, b"in").get(65), Some(b'n'));
+		assert!(bytes.remove(0, 300).is_empty());
+		assert_eq!(bytes.to_vec(), source);
+	}
+
+	#[test]
+	fn slices_and_concatenates() {
+		let source = pattern(300);
+		let bytes = ImmutableBytes::from(&source[..]);
+		assert_eq!(bytes.slice(50, 100).to_vec(), &source[50..150]);
+		assert_eq!(bytes.slice(0, 0).len(), 0);
+		assert_eq!(bytes.slice(299, 1).to_vec(), vec![source[299]]);
+		assert_eq!(bytes.slice(0, 100).concat(&bytes.slice(100, 200)), bytes);
+		assert_eq!(ImmutableBytes::new().concat(&bytes).iter().collect::<Vec<u8>>(), source);
+		assert_eq!(bytes.get(300), None);
+	}
+
+	#[test]
+	fn writes_every_block() {
+		let source = pattern(1000);
+		let bytes = ImmutableBytes::from(source.clone()).splice(500, 1, b"spliced");
+		let mut written = Vec::new();
+		bytes.write_to(&mut written).unwrap();
+		assert_eq!(written, bytes.to_vec());
+		assert_eq!(written.len(), 1006);
+		let mut empty = Vec::new();
+		ImmutableBytes::new().write_to(&mut empty).unwrap();
+		assert!(empty.is_empty());
+	}
+
+	#[test]
+	fn formats_sixteen_hex_bytes_per_line() {
+		assert_eq!(format!("{:?}", ImmutableBytes::new()), "[]");
+		assert_eq!(format!("{:?}", ImmutableBytes::from(&b"\x00\x0fA\xff"[..])), "[00 0f 41 ff]");
+		let bytes = ImmutableBytes::from((0..18).collect::<Vec<u8>>());
+		assert_eq!(format!("{:?}", bytes), "[00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n 10 11]");
+	}
+}
@@ -0,0 +1,262 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{ImmutableText, TextConfig};
+
+/// <summary>Holds the first line of the manifests, naming the version of the format.</summary>
+const FORMAT : &str = "checkpoint-store 1";
+
+/// <summary>Holds the bounds of the length of a block, in characters.</summary>
+const MIN_BLOCK : usize = 1 << 10;
+const MAX_BLOCK : usize = 1 << 16;
+
+/// <summary>Holds the mask of the rolling hash cutting blocks of 4096 characters on average past the minimum.</summary>
+const BOUNDARY_MASK : u64 = (1 << 12) - 1;
+
+/// <summary>Returns the gear table of the rolling hash: one pseudo-random number per byte value, by SplitMix64.</summary>
+const fn gear_table() -> [u64; 256] {
+	let mut table = [0; 256];
+	let mut state : u64 = 0;
+	let mut i = 0;
+	while i < 256 {
+		state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		table[i] = z ^ (z >> 31);
+		i += 1;
+	}
+	table
+}
+
+const GEAR : [u64; 256] = gear_table();
+
+/// <summary>Returns the FNV-1a hash of the bytes, stable across platforms and versions.</summary>
+fn fnv1a(bytes : &[u8]) -> u64 {
+	bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+/// <summary>
+/// A store of snapshots of documents on disk, where the snapshots share their identical
+/// blocks, so that keeping frequent checkpoints of a large file only costs the blocks
+/// that changed in between. Blocks are cut where a rolling hash of the content meets a
+/// condition, so that an edit only changes the blocks around it, and are stored once
+/// under the name of their hash in <code>blocks</code>; a snapshot is a manifest listing its
+/// blocks in <code>snapshots</code>.
+/// </summary>
+pub struct CheckpointStore {
+	directory : PathBuf
+}
+
+impl CheckpointStore {
+	/// <summary>Opens the store in the directory, created if need be.</summary>
+	pub fn open<P : AsRef<Path>>(directory : P) -> io::Result<CheckpointStore> {
+		let directory = directory.as_ref().to_path_buf();
+		fs::create_dir_all(directory.join("blocks"))?;
+		fs::create_dir_all(directory.join("snapshots"))?;
+		Ok(CheckpointStore { directory })
+	}
+
+	/// <summary>Saves the text as the named snapshot, replacing any snapshot of the same name.</summary>
+	/// <returns>the number of blocks written, those not already stored.</returns>
+	pub fn save(&self, name : &str, text : &ImmutableText) -> io::Result<usize> {
+		let mut manifest = format!("{}\n", FORMAT);
+		let mut written = 0;
+		let mut block = String::new();
+		let mut length = 0;
+		let mut hash : u64 = 0;
+		let mut chars = text.chars().peekable();
+		while let Some(c) = chars.next() {
+			block.push(c);
+			length += 1;
+			hash = (hash << 1).wrapping_add(GEAR[(c as u32 & 0xFF) as usize]);
+			let boundary = length >= MIN_BLOCK && hash & BOUNDARY_MASK == 0;
+			if boundary || length == MAX_BLOCK || chars.peek().is_none() {
+				let block_name = self.write_block(&block, &mut written)?;
+				manifest.push_str(&block_name);
+				manifest.push('\n');
+				block.clear();
+				length = 0;
+				hash = 0;
+			}
+		}
+		self.write_file(&self.directory.join("snapshots").join(name), manifest.as_bytes())?;
+		Ok(written)
+	}
+
+	/// <summary>Stores the block under the name of its hash unless it is already stored, and returns the name.</summary>
+	fn write_block(&self, block : &str, written : &mut usize) -> io::Result<String> {
+		let name = format!("{:016x}-{}", fnv1a(block.as_bytes()), block.len());
+		let path = self.directory.join("blocks").join(&name);
+		match fs::read(&path) {
+			Ok(stored) if stored == block.as_bytes() => return Ok(name),
+			Ok(_) => return Err(io::Error::other(format!("checkpoint store: hash collision on block {}", name))),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e)
+		}
+		self.write_file(&path, block.as_bytes())?;
+		*written += 1;
+		Ok(name)
+	}
+
+	/// <summary>Writes the file through a temporary one renamed over it, so that it is never seen half written.</summary>
+	fn write_file(&self, path : &Path, content : &[u8]) -> io::Result<()> {
+		let temporary = path.with_extension("tmp");
+		let mut file = File::create(&temporary)?;
+		file.write_all(content)?;
+		file.sync_all()?;
+		fs::rename(&temporary, path)
+	}
+
+	/// <summary>Loads the named snapshot.</summary>
+	pub fn load(&self, name : &str) -> io::Result<ImmutableText> {
+		let manifest = fs::read_to_string(self.directory.join("snapshots").join(name))?;
+		let mut lines = manifest.lines();
+		if lines.next() != Some(FORMAT) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!("checkpoint store: unknown format of snapshot {}", name)));
+		}
+		let config = TextConfig::new();
+		let mut text = config.create("");
+		for block in lines {
+			text = text.concat(&config.create(&fs::read_to_string(self.directory.join("blocks").join(block))?));
+		}
+		Ok(text)
+	}
+
+	/// <summary>Returns the names of the snapshots, sorted.</summary>
+	pub fn snapshots(&self) -> io::Result<Vec<String>> {
+		let mut names = Vec::new();
+		for entry in fs::read_dir(self.directory.join("snapshots"))? {
+			let name = entry?.file_name().to_string_lossy().into_owned();
+			if !name.ends_with(".tmp") {
+				names.push(name);
+			}
+		}
+		names.sort();
+		Ok(names)
+	}
+
+	/// <summary>Removes the named snapshot; its blocks stay until <code>collect_garbage</code>.</summary>
+	pub fn remove(&self, name : &str) -> io::Result<()> {
+		fs::remove_file(self.directory.join("snapshots").join(name))
+	}
+
+	/// <summary>Deletes the blocks no snapshot lists anymore.</summary>
+	/// <returns>the number of blocks deleted.</returns>
+	pub fn collect_garbage(&self) -> io::Result<usize> {
+		let mut listed = std::collections::HashSet::new();
+		for name in self.snapshots()? {
+			let manifest = fs::read_to_string(self.directory.join("snapshots").join(name))?;
+			listed.extend(manifest.lines().skip(1).map(str::to_string));
+		}
+		let mut deleted = 0;
+		for entry in fs::read_dir(self.directory.join("blocks"))? {
+			let entry = entry?;
+			if !listed.contains(&*entry.file_name().to_string_lossy()) {
+				fs::remove_file(entry.path())?;
+				deleted += 1;
+			}
+		}
+		Ok(deleted)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns an empty directory of its own for the test.</summary>
+	fn directory(name : &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("immutable-text-checkpoint-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&directory);
+		directory
+	}
+
+	/// <summary>Returns a document of pseudo-random lines, so that the rolling hash cuts blocks of every size.</summary>
+	fn document(lines : usize) -> String {
+		let mut state : u64 = 7;
+		let mut content = String::new();
+		for _ in 0..lines {
+			for _ in 0..40 {
+				state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+				content.push(match state >> 58 {
+					0 => 'é',
+					1 => '日',
+					2 => '🦀',
+					3 => ' ',
+					letter => (b'a' + (letter % 26) as u8) as char
+				});
+			}
+			content.push_str(if state & 1 == 0 { "\r\n" } else { "\n" });
+		}
+		content
+	}
+
+	/// <summary>Returns the lengths in characters of the blocks of the snapshot, in order.</summary>
+	fn block_lengths(directory : &Path, name : &str) -> Vec<usize> {
+		let manifest = fs::read_to_string(directory.join("snapshots").join(name)).unwrap();
+		manifest.lines().skip(1).map(|block| fs::read_to_string(directory.join("blocks").join(block)).unwrap().chars().count()).collect()
+	}
+
+	#[test]
+	fn loads_what_was_saved() {
+		let directory = directory("round-trip");
+		let store = CheckpointStore::open(&directory).unwrap();
+		let large = document(2000);
+		for (name, content) in [("empty", ""), ("crlf", "one\r\ntwo\r\n"), ("large", large.as_str())] {
+			store.save(name, &create_immutable_text(content)).unwrap();
+			let loaded = store.load(name).unwrap();
+			assert_eq!(loaded.to_string(), content);
+			assert!(loaded.validate().is_ok());
+		}
+		assert_eq!(store.snapshots().unwrap(), vec!["crlf", "empty", "large"]);
+		// Blocks are cut within the bounds, the last one alone shorter.
+		let lengths = block_lengths(&directory, "large");
+		assert!(lengths.len() > 10);
+		assert!(lengths[..lengths.len() - 1].iter().all(|&length| (MIN_BLOCK..=MAX_BLOCK).contains(&length)));
+		assert_eq!(lengths.iter().sum::<usize>(), large.chars().count());
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn edits_only_write_the_blocks_around_them() {
+		let directory = directory("dedup");
+		let store = CheckpointStore::open(&directory).unwrap();
+		let text = create_immutable_text(&document(2000));
+		let written = store.save("v1", &text).unwrap();
+		assert_eq!(store.save("v1 again", &text).unwrap(), 0);
+		let edited = text.insert_string(text.length() / 2, "inserted\r\n").remove_text(100, 3);
+		let rewritten = store.save("v2", &edited).unwrap();
+		assert!(rewritten > 0 && rewritten <= 4, "{} of {} blocks rewritten", rewritten, written);
+		assert_eq!(store.load("v2").unwrap().to_string(), edited.to_string());
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn collects_the_blocks_of_removed_snapshots() {
+		let directory = directory("garbage");
+		let store = CheckpointStore::open(&directory).unwrap();
+		let text = create_immutable_text(&document(2000));
+		store.save("kept", &text).unwrap();
+		let edited = text.insert_string(10, "only in the removed snapshot");
+		let unique = store.save("removed", &edited).unwrap();
+		assert_eq!(store.collect_garbage().unwrap(), 0);
+		store.remove("removed").unwrap();
+		assert_eq!(store.collect_garbage().unwrap(), unique);
+		assert_eq!(store.load("kept").unwrap().to_string(), text.to_string());
+		assert_eq!(store.snapshots().unwrap(), vec!["kept"]);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn refuses_missing_and_foreign_snapshots() {
+		let directory = directory("errors");
+		let store = CheckpointStore::open(&directory).unwrap();
+		assert_eq!(store.load("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+		fs::write(directory.join("snapshots").join("foreign"), "another format\n").unwrap();
+		assert_eq!(store.load("foreign").unwrap_err().kind(), io::ErrorKind::InvalidData);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+}
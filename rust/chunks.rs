@@ -0,0 +1,157 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::{ImmutableText, Node, TextError};
+
+/// <summary>
+/// Returns the characters of the leaf between <code>start</code> and <code>end</code>, borrowed
+/// from the leaf when it stores them as UTF-8 (ASCII leaves, pieces, inline texts),
+/// or else decoded into a string.
+/// </summary>
+fn leaf_str(leaf : &dyn Node, start : usize, end : usize) -> Cow<'_, str> {
+	match leaf.as_utf8() {
+		Some(text) if text.len() == leaf.length() => Cow::Borrowed(&text[start..end]),
+		Some(text) => {
+			let mut offsets = text.char_indices().map(|(offset, _)| offset).chain(Some(text.len()));
+			let byte_start = offsets.nth(start).unwrap();
+			let byte_end = if end == start { byte_start } else { offsets.nth(end - start - 1).unwrap() };
+			Cow::Borrowed(&text[byte_start..byte_end])
+		}
+		None => {
+			let mut data = vec!['\0'; end - start];
+			leaf.copy_to(start, &mut data, 0, end - start);
+			Cow::Owned(data.into_iter().collect())
+		}
+	}
+}
+
+/// <summary>
+/// Iterates over the chunks of a range of a text, leaf by leaf, the first and last
+/// ones clipped to the range. See <code>chunk_at</code> for when chunks are borrowed.
+/// </summary>
+pub struct ChunksIn<'a> {
+	stack : Vec<&'a dyn Node>,
+	/// <summary>The characters of the next leaf before the range.</summary>
+	skip : usize,
+	remaining : usize
+}
+
+impl<'a> Iterator for ChunksIn<'a> {
+	type Item = Cow<'a, str>;
+
+	fn next(&mut self) -> Option<Cow<'a, str>> {
+		while self.remaining > 0 {
+			let mut node = self.stack.pop()?;
+			while let Some(composite) = node.as_composite() {
+				self.stack.push(&*composite.tail);
+				node = &*composite.head;
+			}
+			let start = self.skip;
+			if start >= node.length() {
+				self.skip -= node.length();
+				continue;
+			}
+			let end = node.length().min(start + self.remaining);
+			self.skip = 0;
+			self.remaining -= end - start;
+			return Some(leaf_str(node, start, end));
+		}
+		None
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the contiguous chunk holding the character at <code>offset</code> and the range
+	/// of the text it covers, or None past the end. Chunks are borrowed from the leaves
+	/// that store UTF-8, while those storing wide characters are decoded into owned
+	/// strings. Leaves are wide by default, even for ASCII text: a text created with
+	/// <code>ChunkPolicy::with_prefer_ascii_leaves</code> stores its ASCII leaves as bytes,
+	/// whose chunks are borrowed.
+	/// </summary>
+	pub fn chunk_at(&self, offset : usize) -> Option<(Cow<'_, str>, Range<usize>)> {
+		let leaf = self.find_leaf(offset, 0)?;
+		let length = leaf.leaf_node.length();
+		Some((leaf_str(leaf.leaf_node, 0, length), leaf.offset..leaf.offset + length))
+	}
+
+	/// <summary>
+	/// Returns an iterator over the chunks overlapping the range, clipped to it, so that
+	/// renderers and highlighters can be fed exactly the visible region.
+	/// </summary>
+	pub fn chunks_in(&self, range : Range<usize>) -> ChunksIn<'_> {
+		if range.start > range.end || range.end > self.length() {
+			panic!("{}", TextError::InvalidRange { start : range.start, end : range.end, len : self.length() });
+		}
+		let mut stack = Vec::new();
+		let mut node : &dyn Node = &*self.root;
+		let mut skip = range.start;
+		while let Some(composite) = node.as_composite() {
+			if skip < composite.head.length() {
+				stack.push(&*composite.tail);
+				node = &*composite.head;
+			} else {
+				skip -= composite.head.length();
+				node = &*composite.tail;
+			}
+		}
+		stack.push(node);
+		ChunksIn { stack, skip, remaining : range.end - range.start }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, ChunkPolicy, TextConfig};
+	use super::*;
+
+	fn ascii_config() -> TextConfig {
+		let config = TextConfig::new().with_block_size(16);
+		config.with_chunk_policy(config.chunk_policy().with_prefer_ascii_leaves(true))
+	}
+
+	#[test]
+	fn chunks_in_clips_the_leaves_to_the_range() {
+		let content = "0123456789abcdef".repeat(3) + "été 😀";
+		for text in [TextConfig::new().with_block_size(16).create(&content), ascii_config().create(&content)] {
+			let chunks : Vec<String> = text.chunks_in(10..text.length()).map(Cow::into_owned).collect();
+			assert_eq!(chunks, vec!["abcdef", "0123456789abcdef", "0123456789abcdef", "été 😀"]);
+			let chunks : Vec<String> = text.chunks_in(3..5).map(Cow::into_owned).collect();
+			assert_eq!(chunks, vec!["34"]);
+			let chunks : Vec<String> = text.chunks_in(14..18).map(Cow::into_owned).collect();
+			assert_eq!(chunks, vec!["ef", "01"]);
+			assert_eq!(text.chunks_in(16..16).count(), 0);
+			assert_eq!(text.chunks_in(0..text.length()).collect::<String>(), content);
+		}
+	}
+
+	#[test]
+	fn only_ascii_leaves_are_borrowed() {
+		let content = "0123456789abcdef".repeat(2) + "été 😀";
+		let text = ascii_config().create(&content);
+		let borrowed : Vec<bool> = text.chunks_in(0..text.length()).map(|chunk| matches!(chunk, Cow::Borrowed(_))).collect();
+		assert_eq!(borrowed, vec![true, true, false]);
+		let text = TextConfig::new().with_block_size(16).create(&content);
+		assert!(text.chunks_in(0..text.length()).all(|chunk| matches!(chunk, Cow::Owned(_))));
+		// Short texts are stored inline as UTF-8.
+		assert!(matches!(create_immutable_text("été").chunk_at(0), Some((Cow::Borrowed("été"), _))));
+		assert!(!ChunkPolicy::new().prefer_ascii_leaves());
+	}
+
+	#[test]
+	fn chunk_at_returns_the_leaf_and_its_range() {
+		let content = "0123456789abcdef".repeat(2) + "été 😀";
+		let text = ascii_config().create(&content);
+		let (chunk, range) = text.chunk_at(20).unwrap();
+		assert_eq!((chunk.as_ref(), range), ("0123456789abcdef", 16..32));
+		let (chunk, range) = text.chunk_at(text.length() - 1).unwrap();
+		assert_eq!((chunk.as_ref(), range), ("été 😀", 32..37));
+		assert!(text.chunk_at(text.length()).is_none());
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds: 3..40")]
+	fn chunks_in_rejects_ranges_past_the_end() {
+		create_immutable_text("0123456789").chunks_in(3..40);
+	}
+}
@@ -0,0 +1,189 @@
+use std::ops::Range;
+
+use super::column::advance;
+use super::ImmutableText;
+
+/// <summary>The characters ending the lines of copied text.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+	Lf,
+	CrLf
+}
+
+impl LineEnding {
+	/// <summary>Returns the line ending of the platform: CR LF on Windows, LF elsewhere.</summary>
+	pub const fn native() -> LineEnding {
+		if cfg!(windows) { LineEnding::CrLf } else { LineEnding::Lf }
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LineEnding::Lf => "\n",
+			LineEnding::CrLf => "\r\n"
+		}
+	}
+}
+
+/// <summary>What becomes of the newline ending the copied text.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingNewline {
+	Keep,
+	/// <summary>Removes it, if any.</summary>
+	Strip,
+	/// <summary>Adds one to a text not empty that has none.</summary>
+	Ensure
+}
+
+/// <summary>Holds how text copied for the clipboard is converted.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipboardOptions {
+	line_ending : LineEnding,
+	trailing_newline : TrailingNewline,
+	tab_width : Option<usize>
+}
+
+impl ClipboardOptions {
+	/// <summary>Returns the default options: native line endings, the trailing newline kept, tabs kept.</summary>
+	pub const fn new() -> ClipboardOptions {
+		ClipboardOptions { line_ending : LineEnding::native(), trailing_newline : TrailingNewline::Keep, tab_width : None }
+	}
+
+	pub fn with_line_ending(mut self, line_ending : LineEnding) -> ClipboardOptions {
+		self.line_ending = line_ending;
+		self
+	}
+
+	pub fn with_trailing_newline(mut self, trailing_newline : TrailingNewline) -> ClipboardOptions {
+		self.trailing_newline = trailing_newline;
+		self
+	}
+
+	/// <summary>Sets the width of the tab stops the tabs are expanded to with spaces, or None to keep the tabs.</summary>
+	/// <param name="tab_width">a positive width, if any.</param>
+	pub fn with_expanded_tabs(mut self, tab_width : Option<usize>) -> ClipboardOptions {
+		assert!(tab_width != Some(0), "tab width must be positive");
+		self.tab_width = tab_width;
+		self
+	}
+
+	pub fn line_ending(&self) -> LineEnding {
+		self.line_ending
+	}
+
+	pub fn trailing_newline(&self) -> TrailingNewline {
+		self.trailing_newline
+	}
+
+	pub fn tab_width(&self) -> Option<usize> {
+		self.tab_width
+	}
+}
+
+impl Default for ClipboardOptions {
+	fn default() -> ClipboardOptions {
+		ClipboardOptions::new()
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the characters of the range as a string ready for the clipboard of the
+	/// platform: LF and CR LF line endings converted to the one of the options, the
+	/// trailing newline handled as they say and, if asked, tabs expanded to the spaces
+	/// reaching the same visual columns as in the text.
+	/// </summary>
+	pub fn copy_range_for_clipboard(&self, range : Range<usize>, options : &ClipboardOptions) -> String {
+		if let Err(error) = self.check_bounds(range.start, range.end) {
+			panic!("{}", error);
+		}
+		let newline = options.line_ending.as_str();
+		let mut copied = String::with_capacity(range.len());
+		// The visual column, counted from the start of the line even when the range starts within it.
+		let mut column = match options.tab_width {
+			Some(tab_width) => self.chars_range(self.line_start(self.line_of_offset(range.start)), range.start).fold(0, |column, c| advance(column, c, tab_width)),
+			None => 0
+		};
+		let mut chars = self.chars_range(range.start, range.end).peekable();
+		while let Some(c) = chars.next() {
+			match c {
+				'\r' if chars.peek() == Some(&'\n') => {}
+				'\n' => {
+					copied.push_str(newline);
+					column = 0;
+				}
+				'\t' if options.tab_width.is_some() => {
+					let next = advance(column, c, options.tab_width.unwrap());
+					copied.push_str(&" ".repeat(next - column));
+					column = next;
+				}
+				_ => {
+					copied.push(c);
+					if let Some(tab_width) = options.tab_width {
+						column = advance(column, c, tab_width);
+					}
+				}
+			}
+		}
+		match options.trailing_newline {
+			TrailingNewline::Keep => {}
+			TrailingNewline::Strip => {
+				if copied.ends_with(newline) {
+					copied.truncate(copied.len() - newline.len());
+				}
+			}
+			TrailingNewline::Ensure => {
+				if !copied.is_empty() && !copied.ends_with(newline) {
+					copied.push_str(newline);
+				}
+			}
+		}
+		copied
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	#[test]
+	fn line_endings_are_converted() {
+		let text = create_immutable_text("a\nb\r\nc\rd\n");
+		let lf = ClipboardOptions::new().with_line_ending(LineEnding::Lf);
+		let crlf = ClipboardOptions::new().with_line_ending(LineEnding::CrLf);
+		assert_eq!(text.copy_range_for_clipboard(0..text.length(), &lf), "a\nb\nc\rd\n");
+		assert_eq!(text.copy_range_for_clipboard(0..text.length(), &crlf), "a\r\nb\r\nc\rd\r\n");
+		// A range ending between CR and LF keeps the CR.
+		assert_eq!(text.copy_range_for_clipboard(2..4, &lf), "b\r");
+	}
+
+	#[test]
+	fn trailing_newlines_are_stripped_or_ensured() {
+		let text = create_immutable_text("a\r\nb\r\n");
+		let crlf = ClipboardOptions::new().with_line_ending(LineEnding::CrLf);
+		let strip = crlf.with_trailing_newline(TrailingNewline::Strip);
+		let ensure = crlf.with_trailing_newline(TrailingNewline::Ensure);
+		assert_eq!(text.copy_range_for_clipboard(0..6, &strip), "a\r\nb");
+		assert_eq!(text.copy_range_for_clipboard(0..4, &strip), "a\r\nb");
+		assert_eq!(text.copy_range_for_clipboard(0..4, &ensure), "a\r\nb\r\n");
+		assert_eq!(text.copy_range_for_clipboard(0..6, &ensure), "a\r\nb\r\n");
+		assert_eq!(text.copy_range_for_clipboard(0..0, &ensure), "");
+	}
+
+	#[test]
+	fn tabs_expand_to_the_columns_of_the_text() {
+		let text = create_immutable_text("ab\tc\n\td\te");
+		let options = ClipboardOptions::new().with_line_ending(LineEnding::Lf).with_expanded_tabs(Some(4));
+		assert_eq!(text.copy_range_for_clipboard(0..text.length(), &options), "ab  c\n    d   e");
+		// The range starts mid-line: the tab still stops at column 4 of the line.
+		assert_eq!(text.copy_range_for_clipboard(1..4, &options), "b  c");
+		assert_eq!(text.copy_range_for_clipboard(2..3, &options), "  ");
+		assert_eq!(text.copy_range_for_clipboard(6..8, &options), "d   ");
+	}
+
+	#[test]
+	#[should_panic(expected = "tab width must be positive")]
+	fn a_tab_width_of_zero_is_rejected() {
+		ClipboardOptions::new().with_expanded_tabs(Some(0));
+	}
+}
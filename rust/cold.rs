@@ -0,0 +1,195 @@
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::metrics::LineMetrics;
+use super::{balanced_node, create_leaf_node, node_of, ImmutableText, Node, Root, Shared, TextConfig};
+
+/// <summary>Holds the number of characters compressed together in a cold leaf.</summary>
+const COLD_BLOCK : usize = 1 << 16;
+
+/// <summary>Holds the number of decompressed cold leaves kept by the hot cache.</summary>
+const HOT_LEAVES : usize = 8;
+
+static NEXT_ID : AtomicU64 = AtomicU64::new(0);
+
+/// <summary>
+/// The most recently decompressed cold leaves, most recent first, shared by all the
+/// texts. Leaves are identified by a serial number rather than their address, which
+/// a new leaf may reuse.
+/// </summary>
+static HOT : Mutex<Vec<(u64, Arc<[char]>)>> = Mutex::new(Vec::new());
+
+/// <summary>
+/// A leaf holding a large block of characters compressed with LZ4, decompressed on
+/// access. Like pieces, cold leaves are never chunked: cutting one decompresses the
+/// range into ordinary leaves.
+/// </summary>
+struct ColdLeafNode {
+	id : u64,
+	compressed : Box<[u8]>,
+	length : usize,
+	metrics : LineMetrics
+}
+
+impl ColdLeafNode {
+	fn new(data : &[char]) -> ColdLeafNode {
+		let text : String = data.iter().collect();
+		ColdLeafNode {
+			id : NEXT_ID.fetch_add(1, Ordering::Relaxed),
+			compressed : lz4_flex::compress_prepend_size(text.as_bytes()).into_boxed_slice(),
+			length : data.len(),
+			metrics : LineMetrics::of_chars(data)
+		}
+	}
+
+	/// <summary>Returns the characters of this leaf, from the hot cache or decompressed into it.</summary>
+	fn chars(&self) -> Arc<[char]> {
+		let mut hot = HOT.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some(position) = hot.iter().position(|(id, _)| *id == self.id) {
+			let entry = hot.remove(position);
+			hot.insert(0, entry);
+			return hot[0].1.clone();
+		}
+		let bytes = lz4_flex::decompress_size_prepended(&self.compressed).expect("corrupted cold leaf");
+		let chars : Arc<[char]> = String::from_utf8(bytes).expect("corrupted cold leaf").chars().collect();
+		hot.truncate(HOT_LEAVES - 1);
+		hot.insert(0, (self.id, chars.clone()));
+		chars
+	}
+}
+
+impl Node for ColdLeafNode {
+	fn length(&self) -> usize {
+		self.length
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		if end - start > config.block_size() {
+			return node_of(&(self as Shared<dyn Node>), start, end - start, config);
+		}
+		create_leaf_node(self.chars()[start..end].to_vec(), config)
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		self.chars()[offset]
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		destination[destination_index..destination_index + count].copy_from_slice(&self.chars()[source_index..source_index + count]);
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	/// <summary>Returns the size of the compressed block: decompressed copies belong to the hot cache.</summary>
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<ColdLeafNode>() + self.compressed.len()
+	}
+
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		self.chars()[start..].iter().position(|&other| other == c).map(|i| start + i)
+	}
+
+	fn decoded(&self) -> Option<Arc<[char]>> {
+		Some(self.chars())
+	}
+
+	fn utf8_offset(&self, index : usize) -> usize {
+		self.chars()[..index].iter().map(|&c| c.len_utf8()).sum()
+	}
+
+	fn char_at_utf8(&self, byte_offset : usize) -> (usize, usize) {
+		let mut offset = 0;
+		for (index, &c) in self.chars().iter().enumerate() {
+			if offset + c.len_utf8() > byte_offset {
+				return (index, offset);
+			}
+			offset += c.len_utf8();
+		}
+		unreachable!("byte offset past the end of the leaf")
+	}
+
+	fn is_cold(&self) -> bool {
+		true
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the same text with its characters compressed in large blocks, which
+	/// are decompressed on access through a small cache shared by all the texts. This
+	/// bounds the memory of viewers holding many large documents rarely edited: the
+	/// parts of a compressed text that are edited come back as ordinary leaves.
+	/// </summary>
+	pub fn compress(&self) -> ImmutableText {
+		if self.length() == 0 || matches!(self.root, Root::Inline(_)) {
+			return self.clone();
+		}
+		let mut blocks : Vec<Shared<dyn Node>> = Vec::new();
+		let mut data = vec!['\0'; COLD_BLOCK];
+		let mut start = 0;
+		while start < self.length() {
+			let count = COLD_BLOCK.min(self.length() - start);
+			self.root.copy_to(start, &mut data, 0, count);
+			blocks.push(Shared::new(ColdLeafNode::new(&data[..count])));
+			start += count;
+		}
+		self.with_root(balanced_node(&blocks))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns a text of several cold blocks, with CRLF and multi-byte characters across their boundaries.</summary>
+	fn sample() -> String {
+		"ligne accentuée, 日本語 🦀\r\n".repeat(COLD_BLOCK / 10)
+	}
+
+	#[test]
+	fn reads_like_the_uncompressed_text() {
+		let expected = sample();
+		let text = create_immutable_text(&expected);
+		let cold = text.compress();
+		assert!(cold.length() > 2 * COLD_BLOCK);
+		assert_eq!(cold.to_string(), expected);
+		assert!(cold.chars().eq(expected.chars()));
+		assert_eq!(cold.stats(), text.stats());
+		assert_eq!(cold.line_count(), text.line_count());
+		for line in [0, 1, cold.line_count() / 2, cold.line_count() - 1] {
+			assert_eq!(cold.line_start(line), text.line_start(line));
+			assert_eq!(cold.line_end(line), text.line_end(line));
+		}
+		for index in [0, 1, COLD_BLOCK - 1, COLD_BLOCK, COLD_BLOCK + 1, cold.length()] {
+			assert_eq!(cold.line_of_offset(index), text.line_of_offset(index));
+			let byte_offset = cold.try_byte_offset(index).unwrap();
+			assert_eq!(byte_offset, expected.char_indices().map(|(offset, _)| offset).chain([expected.len()]).nth(index).unwrap());
+			assert_eq!(cold.try_char_index(byte_offset), Ok(index));
+		}
+		assert_eq!(cold.get_text(COLD_BLOCK - 5, 10).to_string(), text.get_text(COLD_BLOCK - 5, 10).to_string());
+		assert!(cold.validate().is_ok());
+	}
+
+	#[test]
+	fn edits_decompress_only_what_they_touch() {
+		let expected = sample();
+		let cold = create_immutable_text(&expected).compress();
+		let edited = cold.insert_string(COLD_BLOCK, "\r\n").remove_text(0, 3);
+		let split = expected.char_indices().nth(COLD_BLOCK).unwrap().0;
+		assert_eq!(edited.to_string(), format!("{}\r\n{}", &expected[3..split], &expected[split..]));
+		assert!(edited.validate().is_ok());
+	}
+
+	#[test]
+	fn empty_and_short_texts_stay_as_they_are() {
+		assert_eq!(create_immutable_text("").compress().to_string(), "");
+		assert_eq!(create_immutable_text("a\r\n").compress().to_string(), "a\r\n");
+	}
+}
@@ -0,0 +1,168 @@
+use std::ops::Range;
+
+use super::metrics::char_width;
+use super::ImmutableText;
+
+/// <summary>The edit of one line of a column operation: replaces <code>remove</code> characters at <code>start</code>.</summary>
+struct LineEdit {
+	start : usize,
+	remove : usize,
+	insert : String,
+	cursor : usize
+}
+
+/// <summary>Returns the visual column after a character starting at <code>column</code>, expanding tabs.</summary>
+pub(crate) fn advance(column : usize, c : char, tab_width : usize) -> usize {
+	if c == '\t' {
+		return (column / tab_width + 1) * tab_width;
+	}
+	column + char_width(c)
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Inserts the text at a visual column on each of the given lines, as for a
+	/// block selection: short lines are padded with spaces and a tab spanning the
+	/// column is split into spaces. A wide character spanning the column is not
+	/// split: the text is inserted after it.
+	/// </summary>
+	/// <param name="tab_width">the width of the tab stops, which must be positive.</param>
+	/// <returns>the new text and, for each line, the offset just after the inserted text.</returns>
+	pub fn insert_column(&self, lines : Range<usize>, column : usize, text : &str, tab_width : usize) -> (ImmutableText, Vec<usize>) {
+		self.edit_columns(lines, tab_width, |line_start, chars| {
+			let mut visual = 0;
+			for (i, &c) in chars.iter().enumerate() {
+				if visual >= column {
+					return LineEdit { start : line_start + i, remove : 0, insert : text.to_string(), cursor : text.chars().count() };
+				}
+				let next = advance(visual, c, tab_width);
+				if c == '\t' && next > column {
+					let before = " ".repeat(column - visual);
+					let after = " ".repeat(next - column);
+					let cursor = before.len() + text.chars().count();
+					return LineEdit { start : line_start + i, remove : 1, insert : before + text + &after, cursor };
+				}
+				visual = next;
+			}
+			let padding = " ".repeat(column.saturating_sub(visual));
+			let cursor = padding.len() + text.chars().count();
+			LineEdit { start : line_start + chars.len(), remove : 0, insert : padding + text, cursor }
+		})
+	}
+
+	/// <summary>
+	/// Deletes the characters between the given visual columns on each of the given
+	/// lines, as for a block selection. Tabs crossing a column boundary are replaced
+	/// with the spaces lying outside the deleted columns. A wide character is deleted
+	/// whole when it starts within the columns, even if it ends past them, and kept
+	/// when it starts before them.
+	/// </summary>
+	/// <param name="tab_width">the width of the tab stops, which must be positive.</param>
+	/// <returns>the new text and, for each line, the offset where the deletion happened.</returns>
+	pub fn delete_column(&self, lines : Range<usize>, columns : Range<usize>, tab_width : usize) -> (ImmutableText, Vec<usize>) {
+		self.edit_columns(lines, tab_width, |line_start, chars| {
+			let mut visual = 0;
+			let mut first = None;
+			let mut last = 0;
+			let mut before = String::new();
+			let mut after = String::new();
+			for (i, &c) in chars.iter().enumerate() {
+				let next = advance(visual, c, tab_width);
+				if visual >= columns.end {
+					break;
+				}
+				let overlaps = (visual >= columns.start) || (c == '\t' && next > columns.start);
+				if overlaps {
+					if first.is_none() {
+						first = Some(i);
+						if visual < columns.start {
+							before = " ".repeat(columns.start - visual);
+						}
+					}
+					last = i + 1;
+					after = if c == '\t' && next > columns.end { " ".repeat(next - columns.end) } else { String::new() };
+				}
+				visual = next;
+			}
+			match first {
+				Some(first) => {
+					let cursor = before.len();
+					LineEdit { start : line_start + first, remove : last - first, insert : before + &after, cursor }
+				}
+				None => LineEdit { start : line_start + chars.len(), remove : 0, insert : String::new(), cursor : 0 }
+			}
+		})
+	}
+
+	/// <summary>Applies one edit per line, top to bottom, tracking how earlier edits shifted the offsets.</summary>
+	fn edit_columns<F>(&self, lines : Range<usize>, tab_width : usize, mut edit_line : F) -> (ImmutableText, Vec<usize>) where F : FnMut(usize, &[char]) -> LineEdit {
+		assert!(tab_width > 0, "tab width must be positive");
+		let mut result = self.clone();
+		let mut cursors = Vec::with_capacity(lines.len());
+		let mut shift : isize = 0;
+		for line in lines {
+			let start = self.line_start(line);
+			let chars : Vec<char> = self.chars_range(start, self.line_end(line)).collect();
+			let edit = edit_line(start, &chars);
+			let position = (edit.start as isize + shift) as usize;
+			result = result.remove_text(position, edit.remove).insert_string(position, &edit.insert);
+			cursors.push(position + edit.cursor);
+			shift += edit.insert.chars().count() as isize - edit.remove as isize;
+		}
+		(result, cursors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+
+	#[test]
+	fn insert_column_pads_short_lines() {
+		let text = create_immutable_text("abcdef\nab\n\nabcd");
+		let (result, cursors) = text.insert_column(0..4, 4, "|", 4);
+		assert_eq!(result.to_string(), "abcd|ef\nab  |\n    |\nabcd|");
+		assert_eq!(cursors, vec![5, 13, 19, 25]);
+	}
+
+	#[test]
+	fn insert_column_splits_a_tab_spanning_the_column() {
+		let text = create_immutable_text("a\tb\n\tc");
+		let (result, cursors) = text.insert_column(0..2, 2, "|", 4);
+		assert_eq!(result.to_string(), "a |  b\n  |  c");
+		assert_eq!(cursors, vec![3, 10]);
+		// A tab ending at the column is kept whole.
+		let (result, _) = text.insert_column(0..2, 4, "|", 4);
+		assert_eq!(result.to_string(), "a\t|b\n\t|c");
+	}
+
+	#[test]
+	fn insert_column_goes_after_a_wide_character_spanning_the_column() {
+		let text = create_immutable_text("a中b\nabcd");
+		let (result, _) = text.insert_column(0..2, 2, "|", 4);
+		assert_eq!(result.to_string(), "a中|b\nab|cd");
+	}
+
+	#[test]
+	fn delete_column_keeps_the_spaces_of_split_tabs() {
+		let text = create_immutable_text("a\tb\nabcdef\nab");
+		let (result, cursors) = text.delete_column(0..3, 2..3, 4);
+		assert_eq!(result.to_string(), "a  b\nabdef\nab");
+		assert_eq!(cursors, vec![2, 7, 13]);
+		let (result, _) = text.delete_column(0..1, 2..5, 4);
+		assert_eq!(result.to_string(), "a \nabcdef\nab");
+	}
+
+	#[test]
+	fn delete_column_deletes_wide_characters_starting_within_the_columns() {
+		let text = create_immutable_text("ab中cd\na中bcd");
+		let (result, _) = text.delete_column(0..2, 2..3, 4);
+		assert_eq!(result.to_string(), "abcd\na中bcd");
+	}
+
+	#[test]
+	#[should_panic(expected = "tab width must be positive")]
+	fn a_tab_width_of_zero_is_rejected() {
+		create_immutable_text("a\tb").insert_column(0..1, 1, "|", 0);
+	}
+}
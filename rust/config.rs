@@ -0,0 +1,162 @@
+#[cfg(feature = "shadow")]
+use super::shadow;
+use super::{chunked, create_leaf_node, ImmutableText, InlineLeaf, LeafCache, Root, BLOCK_SIZE};
+
+/// <summary>
+/// Holds how a text is cut into leaves. Small leaves make edits cheaper (less
+/// copying), large ones save node overhead and speed up scans.
+/// </summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkPolicy {
+	min_leaf_size : usize,
+	max_leaf_size : usize,
+	merge_threshold : usize,
+	prefer_ascii_leaves : bool
+}
+
+impl ChunkPolicy {
+	/// <summary>
+	/// Returns the default policy: leaves of up to 64 characters, merged whenever
+	/// they fit, and leaves under 8 characters merged with their neighbours.
+	/// </summary>
+	pub const fn new() -> ChunkPolicy {
+		ChunkPolicy {
+			min_leaf_size : BLOCK_SIZE / 8,
+			max_leaf_size : BLOCK_SIZE,
+			merge_threshold : BLOCK_SIZE,
+			prefer_ascii_leaves : false
+		}
+	}
+
+	/// <summary>
+	/// Sets the length below which a leaf is merged with its neighbour whenever
+	/// the result fits in a leaf, even above the merge threshold. Concatenations
+	/// check the leaves on both sides of the seam, see <code>coalesce_at</code>.
+	/// </summary>
+	pub fn with_min_leaf_size(mut self, min_leaf_size : usize) -> ChunkPolicy {
+		self.min_leaf_size = min_leaf_size;
+		self
+	}
+
+	/// <summary>Sets the maximum number of characters of a leaf.</summary>
+	/// <param name="max_leaf_size">a power of two.</param>
+	pub fn with_max_leaf_size(mut self, max_leaf_size : usize) -> ChunkPolicy {
+		self.max_leaf_size = max_leaf_size;
+		self
+	}
+
+	/// <summary>
+	/// Sets the length up to which two adjacent nodes being concatenated are
+	/// copied into a single leaf. Lowering it trades memory for edit throughput.
+	/// </summary>
+	pub fn with_merge_threshold(mut self, merge_threshold : usize) -> ChunkPolicy {
+		self.merge_threshold = merge_threshold;
+		self
+	}
+
+	/// <summary>Sets whether leaves holding only ASCII characters are stored one byte per character.</summary>
+	pub fn with_prefer_ascii_leaves(mut self, prefer_ascii_leaves : bool) -> ChunkPolicy {
+		self.prefer_ascii_leaves = prefer_ascii_leaves;
+		self
+	}
+
+	pub fn min_leaf_size(&self) -> usize {
+		self.min_leaf_size
+	}
+
+	pub fn max_leaf_size(&self) -> usize {
+		self.max_leaf_size
+	}
+
+	pub fn merge_threshold(&self) -> usize {
+		self.merge_threshold
+	}
+
+	pub fn prefer_ascii_leaves(&self) -> bool {
+		self.prefer_ascii_leaves
+	}
+
+	/// <summary>Returns whether two adjacent nodes of the given lengths are to be merged into a leaf.</summary>
+	pub(crate) fn merges(&self, head_length : usize, tail_length : usize) -> bool {
+		let length = head_length + tail_length;
+		length <= self.merge_threshold || (length <= self.max_leaf_size && head_length.min(tail_length) < self.min_leaf_size)
+	}
+}
+
+impl Default for ChunkPolicy {
+	fn default() -> ChunkPolicy {
+		ChunkPolicy::new()
+	}
+}
+
+/// <summary>
+/// Holds the tuning parameters of a text. Every text derived from another one
+/// (by concatenation, insertion, removal...) keeps the configuration of the
+/// text it was derived from.
+/// </summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextConfig {
+	chunk_policy : ChunkPolicy
+}
+
+impl TextConfig {
+	/// <summary>Returns the default configuration, see <code>ChunkPolicy::new</code>.</summary>
+	pub const fn new() -> TextConfig {
+		TextConfig { chunk_policy : ChunkPolicy::new() }
+	}
+
+	/// <summary>
+	/// Sets the maximum number of characters of a leaf, which is also the merge
+	/// threshold. Small blocks make edits cheaper (less copying), large ones save
+	/// memory and speed up scans.
+	/// </summary>
+	/// <param name="block_size">a power of two.</param>
+	pub fn with_block_size(self, block_size : usize) -> TextConfig {
+		let policy = self.chunk_policy.with_max_leaf_size(block_size).with_merge_threshold(block_size);
+		self.with_chunk_policy(policy.with_min_leaf_size(policy.min_leaf_size().min(block_size)))
+	}
+
+	/// <summary>Sets how the text is cut into leaves.</summary>
+	pub fn with_chunk_policy(mut self, chunk_policy : ChunkPolicy) -> TextConfig {
+		let max_leaf_size = chunk_policy.max_leaf_size;
+		assert!(max_leaf_size.is_power_of_two(), "block size must be a power of two: {}", max_leaf_size);
+		assert!(chunk_policy.merge_threshold <= max_leaf_size, "merge threshold above the maximum leaf size: {:?}", chunk_policy);
+		assert!(chunk_policy.min_leaf_size <= max_leaf_size, "minimum leaf size above the maximum leaf size: {:?}", chunk_policy);
+		self.chunk_policy = chunk_policy;
+		self
+	}
+
+	/// <summary>Returns the maximum number of characters of a leaf.</summary>
+	pub fn block_size(&self) -> usize {
+		self.chunk_policy.max_leaf_size
+	}
+
+	pub fn chunk_policy(&self) -> &ChunkPolicy {
+		&self.chunk_policy
+	}
+
+	/// <summary>Creates a text with this configuration.</summary>
+	pub fn create(&self, text : &str) -> ImmutableText {
+		let root = match InlineLeaf::of_str(text) {
+			Some(leaf) => Root::Inline(leaf),
+			None => Root::Node(chunked(create_leaf_node(text.chars().collect(), self), self))
+		};
+		let created = ImmutableText { root, config : *self, cache : LeafCache::default() };
+		#[cfg(feature = "shadow")]
+		shadow::check("create", &created, text);
+		created
+	}
+}
+
+impl Default for TextConfig {
+	fn default() -> TextConfig {
+		TextConfig::new()
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Returns the configuration of this text.</summary>
+	pub fn config(&self) -> TextConfig {
+		self.config
+	}
+}
@@ -0,0 +1,115 @@
+use std::ops::Range;
+
+use super::ImmutableText;
+
+/// <summary>
+/// The region an edit invalidated, for the consumers that cache what they derive from
+/// a text, like highlighters, layout and parsers. It is normalized: the characters the
+/// edit replaced with the same ones, at either end, are left out.
+/// </summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Damage {
+	/// <summary>The range of the replaced characters, in the old text.</summary>
+	pub old_range : Range<usize>,
+	/// <summary>The range of the characters replacing them, in the new text.</summary>
+	pub new_range : Range<usize>,
+	/// <summary>The lines of the old text holding the replaced characters.</summary>
+	pub old_lines : Range<usize>,
+	/// <summary>The lines of the new text holding the characters replacing them.</summary>
+	pub new_lines : Range<usize>
+}
+
+impl Damage {
+	/// <summary>Returns true if the edit changed nothing.</summary>
+	pub fn is_empty(&self) -> bool {
+		self.old_range.is_empty() && self.new_range.is_empty()
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Replaces the <code>removed</code> characters at <code>start</code> with the text.</summary>
+	/// <returns>the new text and the region the edit damaged.</returns>
+	pub fn splice_with_damage(&self, start : usize, removed : usize, text : &str) -> (ImmutableText, Damage) {
+		if let Err(error) = self.check_range(start, removed) {
+			panic!("{}", error);
+		}
+		let inserted : Vec<char> = text.chars().collect();
+		let prefix = self.chars_range(start, start + removed).zip(inserted.iter()).take_while(|(a, &b)| *a == b).count();
+		// The suffix is looked for in the characters of the removed range that the prefix and the inserted text leave.
+		let end = start + removed;
+		let window : Vec<char> = self.chars_range(end - (removed - prefix).min(inserted.len() - prefix), end).collect();
+		let suffix = window.iter().rev().zip(inserted[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+		let old_range = start + prefix..end - suffix;
+		let new_range = start + prefix..start + inserted.len() - suffix;
+		let changed : String = inserted[prefix..inserted.len() - suffix].iter().collect();
+		let spliced = match self.try_splice(old_range.start, old_range.len(), &self.config.create(&changed)) {
+			Ok(spliced) => spliced,
+			Err(error) => panic!("{}", error)
+		};
+		let damage = Damage {
+			old_lines : self.line_of_offset(old_range.start)..self.line_of_offset(old_range.end) + 1,
+			new_lines : spliced.line_of_offset(new_range.start)..spliced.line_of_offset(new_range.end) + 1,
+			old_range,
+			new_range
+		};
+		(spliced, damage)
+	}
+
+	/// <summary>Inserts the text at <code>index</code>.</summary>
+	/// <returns>the new text and the region the edit damaged.</returns>
+	pub fn insert_with_damage(&self, index : usize, text : &str) -> (ImmutableText, Damage) {
+		self.splice_with_damage(index, 0, text)
+	}
+
+	/// <summary>Removes the <code>count</code> characters at <code>start</code>.</summary>
+	/// <returns>the new text and the region the edit damaged.</returns>
+	pub fn remove_with_damage(&self, start : usize, count : usize) -> (ImmutableText, Damage) {
+		self.splice_with_damage(start, count, "")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn damage_leaves_out_the_unchanged_ends() {
+		let text = create_immutable_text("hello world");
+		let (spliced, damage) = text.splice_with_damage(0, 11, "hello there world");
+		assert_eq!(spliced.to_string(), "hello there world");
+		assert_eq!((damage.old_range, damage.new_range), (6..6, 6..12));
+		let (_, damage) = text.splice_with_damage(2, 3, "llo");
+		assert!(damage.is_empty());
+		assert_eq!(damage.old_range, 5..5);
+		// The prefix and the suffix may not overlap: "aa" replaced with "a" removes one of them.
+		let (spliced, damage) = create_immutable_text("aa").splice_with_damage(0, 2, "a");
+		assert_eq!(spliced.to_string(), "a");
+		assert_eq!((damage.old_range, damage.new_range), (1..2, 1..1));
+	}
+
+	#[test]
+	fn damage_spans_the_lines_of_the_edit() {
+		let text = create_immutable_text("one\nab\ntwo");
+		let (spliced, damage) = text.splice_with_damage(3, 3, "XYZ");
+		assert_eq!(spliced.to_string(), "oneXYZ\ntwo");
+		assert_eq!((damage.old_range, damage.new_range), (3..6, 3..6));
+		assert_eq!((damage.old_lines, damage.new_lines), (0..2, 0..1));
+		let (spliced, damage) = spliced.insert_with_damage(6, "\n\n");
+		assert_eq!(spliced.to_string(), "oneXYZ\n\n\ntwo");
+		assert_eq!((damage.old_lines, damage.new_lines), (0..1, 0..3));
+		let (_, damage) = spliced.remove_with_damage(7, 2);
+		assert_eq!((damage.old_range, damage.new_range), (7..9, 7..7));
+		assert_eq!((damage.old_lines, damage.new_lines), (1..4, 1..2));
+	}
+
+	#[test]
+	fn splices_keep_the_configuration() {
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create(&"0123456789".repeat(10));
+		let (spliced, damage) = text.splice_with_damage(5, 50, &"x".repeat(40));
+		assert_eq!(spliced.config(), config);
+		assert_eq!(spliced.to_string(), "01234".to_string() + &"x".repeat(40) + &"5678901234".repeat(4) + "56789");
+		assert_eq!((damage.old_range, damage.new_range), (5..55, 5..45));
+		assert!(spliced.tree_stats().leaf_count > 1);
+	}
+}
@@ -0,0 +1,265 @@
+use std::mem;
+use std::ops::Range;
+
+use super::{create_immutable_text, ImmutableText};
+
+/// <summary>Which side of the text inserted at an offset the offset maps to.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bias {
+	Before,
+	After
+}
+
+/// <summary>One replacement: <code>removed</code> characters at <code>start</code> replaced with <code>inserted</code>.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+	pub start : usize,
+	pub removed : usize,
+	pub inserted : String
+}
+
+/// <summary>
+/// The change between two versions of a text, as replacements in the coordinates
+/// of the old text. Edits are sorted and do not overlap.
+/// </summary>
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+	edits : Vec<Edit>
+}
+
+impl Delta {
+	pub fn new() -> Delta {
+		Delta::default()
+	}
+
+	/// <summary>Appends a replacement, which must start after the end of the previous one.</summary>
+	pub fn push(&mut self, start : usize, removed : usize, inserted : &str) {
+		if let Some(last) = self.edits.last() {
+			assert!(start >= last.start + last.removed, "edits must be sorted and must not overlap");
+		}
+		if removed == 0 && inserted.is_empty() {
+			return;
+		}
+		self.edits.push(Edit { start, removed, inserted : inserted.to_string() });
+	}
+
+	/// <summary>
+	/// Returns the delta turning <code>old</code> into <code>new</code> as a single replacement
+	/// of everything between their common prefix and common suffix, or an empty delta
+	/// if they are equal.
+	/// </summary>
+	pub fn between(old : &ImmutableText, new : &ImmutableText) -> Delta {
+		let (prefix, suffix) = common_affixes(old, new);
+		let mut delta = Delta::new();
+		let inserted : String = new.chars_range(prefix, new.length() - suffix).collect();
+		delta.push(prefix, old.length() - suffix - prefix, &inserted);
+		delta
+	}
+
+	pub fn edits(&self) -> &[Edit] {
+		&self.edits
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.edits.is_empty()
+	}
+
+	/// <summary>
+	/// Maps an offset of the old text to the new one, so that the positions held outside
+	/// of the text can follow an edit. An offset at an insertion, or within a replaced
+	/// range, maps before or after the inserted text as the bias says.
+	/// </summary>
+	pub fn map_offset(&self, offset : usize, bias : Bias) -> usize {
+		let mut shift = 0isize;
+		for edit in &self.edits {
+			let inserted = edit.inserted.chars().count();
+			if offset < edit.start || (offset == edit.start && edit.removed == 0 && bias == Bias::Before) {
+				break;
+			}
+			if offset < edit.start + edit.removed {
+				return (edit.start as isize + shift) as usize + if bias == Bias::After { inserted } else { 0 };
+			}
+			shift += inserted as isize - edit.removed as isize;
+		}
+		(offset as isize + shift) as usize
+	}
+
+	/// <summary>
+	/// Maps a range of the old text to the new one, keeping the text inserted at its
+	/// edges outside of it. A range whose characters were all removed becomes empty.
+	/// </summary>
+	pub fn map_range(&self, range : Range<usize>) -> Range<usize> {
+		let start = self.map_offset(range.start, Bias::After);
+		start..self.map_offset(range.end, Bias::Before).max(start)
+	}
+
+	/// <summary>Applies the delta to the text it was computed against, sharing the unchanged parts.</summary>
+	pub fn apply(&self, text : &ImmutableText) -> ImmutableText {
+		let mut result = text.empty();
+		let mut copied = 0;
+		for edit in &self.edits {
+			result = result.concat(&text.get_text(copied, edit.start - copied)).concat(&create_immutable_text(&edit.inserted));
+			copied = edit.start + edit.removed;
+		}
+		result.concat(&text.sub_text(copied))
+	}
+
+	/// <summary>
+	/// Returns the single delta doing this one then <code>next</code>, whose edits are in
+	/// the coordinates of the text this one applies to, so that consecutive steps can be
+	/// kept as one.
+	/// </summary>
+	pub fn compose(&self, next : &Delta) -> Delta {
+		let mut first = steps_of(self).into_iter().peekable();
+		let mut second = steps_of(next).into_iter().peekable();
+		let mut composed = Delta::new();
+		let mut offset = 0;
+		let mut pending : Option<Edit> = None;
+		loop {
+			let step = match (first.peek_mut(), second.peek_mut()) {
+				(_, Some(Step::Insert(inserted))) => {
+					let step = Step::Insert(mem::take(inserted));
+					second.next();
+					step
+				}
+				(Some(Step::Delete(count)), _) => {
+					let step = Step::Delete(*count);
+					first.next();
+					step
+				}
+				(None, None) => break,
+				(a, b) => {
+					// Both consume characters of the intermediate text; the missing side retains the rest.
+					let a_length = a.as_ref().map_or(usize::MAX, |step| step.length());
+					let b_length = b.as_ref().map_or(usize::MAX, |step| step.length());
+					let count = a_length.min(b_length);
+					let step = match (a, b) {
+						(Some(Step::Insert(inserted)), b) => {
+							let split = inserted.char_indices().nth(count).map_or(inserted.len(), |(index, _)| index);
+							let head : String = inserted.drain(..split).collect();
+							if matches!(b, Some(Step::Delete(_))) { None } else { Some(Step::Insert(head)) }
+						}
+						(_, Some(Step::Delete(_))) => Some(Step::Delete(count)),
+						_ => Some(Step::Retain(count))
+					};
+					if count == a_length { first.next(); } else if let Some(a) = first.peek_mut() { a.shorten(count); }
+					if count == b_length { second.next(); } else if let Some(b) = second.peek_mut() { b.shorten(count); }
+					match step {
+						Some(step) => step,
+						None => continue
+					}
+				}
+			};
+			match step {
+				Step::Retain(count) => {
+					if let Some(edit) = pending.take() {
+						composed.push(edit.start, edit.removed, &edit.inserted);
+					}
+					offset += count;
+				}
+				Step::Delete(count) => {
+					pending.get_or_insert_with(|| Edit { start : offset, removed : 0, inserted : String::new() }).removed += count;
+					offset += count;
+				}
+				Step::Insert(inserted) => pending.get_or_insert_with(|| Edit { start : offset, removed : 0, inserted : String::new() }).inserted.push_str(&inserted)
+			}
+		}
+		if let Some(edit) = pending {
+			composed.push(edit.start, edit.removed, &edit.inserted);
+		}
+		composed
+	}
+
+	/// <summary>Returns the heap bytes the edits of this delta hold.</summary>
+	pub(crate) fn heap_bytes(&self) -> usize {
+		self.edits.capacity() * mem::size_of::<Edit>() + self.edits.iter().map(|edit| edit.inserted.capacity()).sum::<usize>()
+	}
+}
+
+/// <summary>One step of a delta walking its old text: characters kept, removed or inserted.</summary>
+enum Step {
+	Retain(usize),
+	Delete(usize),
+	Insert(String)
+}
+
+impl Step {
+	/// <summary>Returns the number of characters of the text between the two deltas composed the step covers.</summary>
+	fn length(&self) -> usize {
+		match self {
+			Step::Retain(count) | Step::Delete(count) => *count,
+			Step::Insert(inserted) => inserted.chars().count()
+		}
+	}
+
+	/// <summary>Removes the first <code>count</code> characters of a retain or a delete, the inserted ones being drained.</summary>
+	fn shorten(&mut self, count : usize) {
+		if let Step::Retain(length) | Step::Delete(length) = self {
+			*length -= count;
+		}
+	}
+}
+
+/// <summary>Returns the steps of the delta, up to the end of its last edit; the rest of the text is retained.</summary>
+fn steps_of(delta : &Delta) -> Vec<Step> {
+	let mut steps = Vec::new();
+	let mut offset = 0;
+	for edit in &delta.edits {
+		if edit.start > offset {
+			steps.push(Step::Retain(edit.start - offset));
+		}
+		if edit.removed > 0 {
+			steps.push(Step::Delete(edit.removed));
+		}
+		if !edit.inserted.is_empty() {
+			steps.push(Step::Insert(edit.inserted.clone()));
+		}
+		offset = edit.start + edit.removed;
+	}
+	steps
+}
+
+/// <summary>Returns the lengths of the common prefix and of the common suffix of the texts, which do not overlap.</summary>
+pub(crate) fn common_affixes(old : &ImmutableText, new : &ImmutableText) -> (usize, usize) {
+	let prefix = old.common_prefix_len(new);
+	let suffix = old.common_suffix_len(new).min(old.length() - prefix).min(new.length() - prefix);
+	(prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	fn delta(edits : &[(usize, usize, &str)]) -> Delta {
+		let mut delta = Delta::new();
+		for &(start, removed, inserted) in edits {
+			delta.push(start, removed, inserted);
+		}
+		delta
+	}
+
+	#[test]
+	fn compose_matches_applying_in_turn() {
+		let text = create_immutable_text("hello world\r\nsecond line");
+		let cases = [
+			(delta(&[(0, 5, "bye")]), delta(&[(2, 2, "")])),
+			(delta(&[(5, 0, ", dear")]), delta(&[(0, 1, "J"), (7, 4, "friend")])),
+			(delta(&[(11, 2, "\n")]), delta(&[(11, 1, "\r\n"), (20, 0, "!")])),
+			(delta(&[(0, 0, "é∂")]), delta(&[(1, 1, "")])),
+			(delta(&[(3, 8, "")]), delta(&[])),
+			(delta(&[]), delta(&[(24, 0, "end")]))
+		];
+		for (first, second) in cases {
+			let expected = second.apply(&first.apply(&text));
+			assert_eq!(first.compose(&second).apply(&text).to_string(), expected.to_string());
+		}
+	}
+
+	#[test]
+	fn compose_merges_typing_into_one_edit() {
+		let typed = delta(&[(3, 0, "a")]).compose(&delta(&[(4, 0, "b")])).compose(&delta(&[(5, 0, "c")]));
+		assert_eq!(typed, delta(&[(3, 0, "abc")]));
+		assert_eq!(delta(&[(0, 0, "abc")]).compose(&delta(&[(0, 3, "")])), Delta::new());
+	}
+}
@@ -0,0 +1,374 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::mem;
+
+use super::metrics::LineMetrics;
+use super::{ImmutableText, LeafChars, Node, Root, Shared};
+
+/// <summary>Shape and memory statistics of the node tree of a text.</summary>
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TreeStats {
+	pub depth : usize,
+	/// <summary>Number of distinct nodes, leaves included; a node reachable twice is counted once.</summary>
+	pub node_count : usize,
+	pub leaf_count : usize,
+	/// <summary>Average leaf length relative to the block size; above 1 for texts that are not chunked.</summary>
+	pub average_leaf_fill : f64,
+	/// <summary>Heap bytes of the distinct nodes, including their reference counts.</summary>
+	pub allocated_bytes : usize
+}
+
+/// <summary>Heap bytes used by the tree of a text, split by ownership.</summary>
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+	/// <summary>Bytes of nodes only this text can reach: freed when it is dropped.</summary>
+	pub owned_bytes : usize,
+	/// <summary>Bytes of nodes also referenced elsewhere, by other versions or clones.</summary>
+	pub shared_bytes : usize
+}
+
+impl MemoryUsage {
+	pub fn total_bytes(&self) -> usize {
+		self.owned_bytes + self.shared_bytes
+	}
+}
+
+/// <summary>An invariant of the node tree found broken by <code>validate</code>, with the offset of the node.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvariantViolation {
+	/// <summary>A composite caches a length other than the sum of its children.</summary>
+	LengthMismatch { offset : usize, cached : usize, actual : usize },
+	/// <summary>A composite caches a depth other than one more than its deepest child.</summary>
+	DepthMismatch { offset : usize, cached : usize, actual : usize },
+	/// <summary>The cached line metrics of a node disagree with its content.</summary>
+	MetricsMismatch { offset : usize },
+	/// <summary>A leaf below a composite, other than a piece or a cold leaf, holds more than the block size characters.</summary>
+	OversizedLeaf { offset : usize, length : usize },
+	/// <summary>The depths of the head and tail of a composite differ by more than one.</summary>
+	Unbalanced { offset : usize, head_depth : usize, tail_depth : usize }
+}
+
+/// <summary>Returns an identity for a node, used to count shared nodes once.</summary>
+fn node_id(node : &Shared<dyn Node>) -> usize {
+	Shared::as_ptr(node) as *const () as usize
+}
+
+/// <summary>Heap bytes of the reference counts in front of every node allocation.</summary>
+const RC_HEADER_BYTES : usize = 2 * mem::size_of::<usize>();
+
+impl ImmutableText {
+	/// <summary>Returns the depth, node and leaf counts, leaf fill and allocated bytes of the tree.</summary>
+	pub fn tree_stats(&self) -> TreeStats {
+		let root = match &self.root {
+			Root::Inline(leaf) => {
+				// A single leaf living in the text value itself.
+				return TreeStats {
+					depth : 0,
+					node_count : 1,
+					leaf_count : 1,
+					average_leaf_fill : leaf.length() as f64 / self.config.block_size() as f64,
+					allocated_bytes : 0
+				};
+			}
+			Root::Node(root) => root
+		};
+		let mut visited = HashSet::new();
+		let mut stack = vec![root.clone()];
+		let mut node_count = 0;
+		let mut leaf_count = 0;
+		let mut leaf_chars = 0;
+		let mut allocated_bytes = 0;
+		while let Some(node) = stack.pop() {
+			if !visited.insert(node_id(&node)) {
+				continue;
+			}
+			node_count += 1;
+			allocated_bytes += node.allocated_bytes() + RC_HEADER_BYTES;
+			match node.as_composite() {
+				Some(composite) => {
+					stack.push(composite.head.clone());
+					stack.push(composite.tail.clone());
+				}
+				None => {
+					leaf_count += 1;
+					leaf_chars += node.length();
+				}
+			}
+		}
+		TreeStats {
+			depth : self.root.depth(),
+			node_count,
+			leaf_count,
+			average_leaf_fill : leaf_chars as f64 / (leaf_count * self.config.block_size()) as f64,
+			allocated_bytes
+		}
+	}
+
+	/// <summary>
+	/// Returns the heap bytes used by the tree, distinguishing nodes uniquely owned by
+	/// this text from nodes shared with other holders. A node is owned when it and all
+	/// its ancestors have a single reference; a node reachable twice within this same
+	/// text counts as shared.
+	/// </summary>
+	pub fn memory_usage(&self) -> MemoryUsage {
+		let mut usage = MemoryUsage::default();
+		let root = match &self.root {
+			Root::Inline(_) => return usage,
+			Root::Node(root) => root
+		};
+		let mut visited = HashSet::new();
+		let mut stack = vec![(root.clone(), true)];
+		while let Some((node, parent_owned)) = stack.pop() {
+			if !visited.insert(node_id(&node)) {
+				continue;
+			}
+			// The references held by the stack and this binding are not ownership.
+			let owned = parent_owned && Shared::strong_count(&node) <= 2;
+			let bytes = node.allocated_bytes() + RC_HEADER_BYTES;
+			if owned {
+				usage.owned_bytes += bytes;
+			} else {
+				usage.shared_bytes += bytes;
+			}
+			if let Some(composite) = node.as_composite() {
+				stack.push((composite.head.clone(), owned));
+				stack.push((composite.tail.clone(), owned));
+			}
+		}
+		usage
+	}
+
+	/// <summary>Emits the node tree as a Graphviz digraph, see <code>versions_to_dot</code>.</summary>
+	pub fn to_dot(&self) -> String {
+		versions_to_dot(std::slice::from_ref(self))
+	}
+
+	/// <summary>
+	/// Checks the invariants the operations rely on: cached lengths, depths and line
+	/// metrics match the children, leaves below a composite, pieces aside, respect the
+	/// block size, and every composite is balanced, the depths of its head and tail
+	/// differing by at most one. Every operation keeps a valid text valid.
+	/// </summary>
+	pub fn validate(&self) -> Result<(), InvariantViolation> {
+		validate_node(&*self.root, self.config.block_size(), true)
+	}
+
+	/// <summary>Checks the invariants of <code>validate</code> but the balance, which trees built by hand may break.</summary>
+	#[cfg(feature = "shadow")]
+	pub(crate) fn validate_structure(&self) -> Result<(), InvariantViolation> {
+		validate_node(&*self.root, self.config.block_size(), false)
+	}
+}
+
+/// <summary>Escapes a leaf preview for a DOT label.</summary>
+fn dot_escape(text : &str) -> String {
+	let mut escaped = String::new();
+	for c in text.chars() {
+		match c {
+			'"' | '\\' => {
+				escaped.push('\\');
+				escaped.push(c);
+			}
+			'\n' => escaped.push_str("\\\\n"),
+			'\t' => escaped.push_str("\\\\t"),
+			_ if c.is_control() => escaped.push('?'),
+			_ => escaped.push(c)
+		}
+	}
+	escaped
+}
+
+/// <summary>
+/// Emits the node DAG of several texts as one Graphviz digraph. Every node is drawn
+/// once; nodes reachable from more than one parent or root are filled, which shows
+/// the structure shared between versions.
+/// </summary>
+pub fn versions_to_dot(versions : &[ImmutableText]) -> String {
+	// Counts the references to every node first.
+	let mut references : HashMap<usize, usize> = HashMap::new();
+	// Inline texts are given a node kept alive here so that no two share an address.
+	let roots : Vec<Shared<dyn Node>> = versions.iter().map(|text| text.root.to_node()).collect();
+	let mut stack = roots.clone();
+	while let Some(node) = stack.pop() {
+		let count = references.entry(node_id(&node)).or_insert(0);
+		*count += 1;
+		if *count > 1 {
+			continue;
+		}
+		if let Some(composite) = node.as_composite() {
+			stack.push(composite.head.clone());
+			stack.push(composite.tail.clone());
+		}
+	}
+
+	let mut dot = String::from("digraph ImmutableText {\n\tnode [shape=box, fontname=monospace];\n");
+	let mut emitted = HashSet::new();
+	for (version, root) in roots.iter().enumerate() {
+		let _ = writeln!(dot, "\tv{} [shape=ellipse, label=\"version {}\"];", version, version);
+		let _ = writeln!(dot, "\tv{} -> n{};", version, node_id(root));
+		let mut stack = vec![root.clone()];
+		while let Some(node) = stack.pop() {
+			let id = node_id(&node);
+			if !emitted.insert(id) {
+				continue;
+			}
+			let fill = if references[&id] > 1 { ", style=filled, fillcolor=lightblue" } else { "" };
+			match node.as_composite() {
+				Some(composite) => {
+					let _ = writeln!(dot, "\tn{} [shape=ellipse, label=\"{} (depth {})\"{}];", id, node.length(), node.depth(), fill);
+					let _ = writeln!(dot, "\tn{} -> n{} [label=\"head\"];", id, node_id(&composite.head));
+					let _ = writeln!(dot, "\tn{} -> n{} [label=\"tail\"];", id, node_id(&composite.tail));
+					stack.push(composite.tail.clone());
+					stack.push(composite.head.clone());
+				}
+				None => {
+					let preview : String = (0..node.length().min(16)).map(|i| node.get_char_at(i)).collect();
+					let ellipsis = if node.length() > 16 { "..." } else { "" };
+					let _ = writeln!(dot, "\tn{} [label=\"{}: {}{}\"{}];", id, node.length(), dot_escape(&preview), ellipsis, fill);
+				}
+			}
+		}
+	}
+	dot.push_str("}\n");
+	dot
+}
+
+/// <summary>
+/// Counts the heap bytes of the distinct nodes of a changing set of versions, each node
+/// shared between them once: the memory they retain together. Every node counts the
+/// versions and composites of the set reaching it, so that adding or removing a version
+/// only walks the nodes it does not share with the others.
+/// </summary>
+#[derive(Default)]
+pub(crate) struct NodeCensus {
+	references : HashMap<usize, usize>,
+	bytes : usize
+}
+
+impl NodeCensus {
+	pub(crate) fn bytes(&self) -> usize {
+		self.bytes
+	}
+
+	pub(crate) fn add(&mut self, text : &ImmutableText) {
+		let Root::Node(root) = &text.root else {
+			return;
+		};
+		let mut stack = vec![root.clone()];
+		while let Some(node) = stack.pop() {
+			let references = self.references.entry(node_id(&node)).or_insert(0);
+			*references += 1;
+			if *references > 1 {
+				continue; // Its children are already counted.
+			}
+			self.bytes += node.allocated_bytes() + RC_HEADER_BYTES;
+			if let Some(composite) = node.as_composite() {
+				stack.push(composite.head.clone());
+				stack.push(composite.tail.clone());
+			}
+		}
+	}
+
+	/// <summary>Removes a version added before, which must still be alive.</summary>
+	pub(crate) fn remove(&mut self, text : &ImmutableText) {
+		let Root::Node(root) = &text.root else {
+			return;
+		};
+		let mut stack = vec![root.clone()];
+		while let Some(node) = stack.pop() {
+			let id = node_id(&node);
+			let references = self.references.get_mut(&id).expect("the version was not added");
+			*references -= 1;
+			if *references > 0 {
+				continue;
+			}
+			self.references.remove(&id);
+			self.bytes -= node.allocated_bytes() + RC_HEADER_BYTES;
+			if let Some(composite) = node.as_composite() {
+				stack.push(composite.head.clone());
+				stack.push(composite.tail.clone());
+			}
+		}
+	}
+}
+
+/// <summary>Validates a subtree with an explicit stack, stopping at the first violation.</summary>
+fn validate_node(root : &dyn Node, block_size : usize, balanced : bool) -> Result<(), InvariantViolation> {
+	let mut stack : Vec<(&dyn Node, usize)> = vec![(root, 0)];
+	while let Some((node, offset)) = stack.pop() {
+		let composite = match node.as_composite() {
+			Some(composite) => composite,
+			None => {
+				if node.length() > block_size && root.as_composite().is_some() && !node.is_piece() && !node.is_cold() {
+					return Err(InvariantViolation::OversizedLeaf { offset, length : node.length() });
+				}
+				let chars : Vec<char> = LeafChars::new(node, 0, node.length()).collect();
+				if *node.metrics() != LineMetrics::of_chars(&chars) {
+					return Err(InvariantViolation::MetricsMismatch { offset });
+				}
+				continue;
+			}
+		};
+		let head = composite.head.length();
+		let tail = composite.tail.length();
+		if composite.length() != head + tail {
+			return Err(InvariantViolation::LengthMismatch { offset, cached : composite.length(), actual : head + tail });
+		}
+		let depth = 1 + composite.head.depth().max(composite.tail.depth());
+		if composite.depth() != depth {
+			return Err(InvariantViolation::DepthMismatch { offset, cached : composite.depth(), actual : depth });
+		}
+		if *composite.metrics() != composite.head.metrics().combine(composite.tail.metrics()) {
+			return Err(InvariantViolation::MetricsMismatch { offset });
+		}
+		let (head_depth, tail_depth) = (composite.head.depth(), composite.tail.depth());
+		if balanced && head_depth.abs_diff(tail_depth) > 1 {
+			return Err(InvariantViolation::Unbalanced { offset, head_depth, tail_depth });
+		}
+		stack.push((&*composite.tail, offset + head));
+		stack.push((&*composite.head, offset));
+	}
+	Ok(())
+}
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, create_leaf_node, CompositeNode, TextConfig};
+	use super::*;
+
+	#[test]
+	fn edits_keep_texts_balanced() {
+		// A xorshift generator, so that the edits are the same on every run.
+		let mut seed = 0x2545F4914F6CDD1Du64;
+		let mut random = move |bound : usize| {
+			seed ^= seed << 13;
+			seed ^= seed >> 7;
+			seed ^= seed << 17;
+			(seed % bound as u64) as usize
+		};
+		for _ in 0..20 {
+			let mut text = create_immutable_text(&"line\r\n".repeat(random(300)));
+			for _ in 0..40 {
+				let start = random(text.length() + 1);
+				let count = random(text.length() - start + 1);
+				text = match random(4) {
+					0 => text.insert_string(start, &"é".repeat(random(100))),
+					1 => text.remove_text(start, count),
+					2 => text.get_text(start, count).concat(&text),
+					_ => text.concat(&create_immutable_text(&"x".repeat(random(200))))
+				};
+				assert_eq!(text.validate(), Ok(()));
+			}
+		}
+	}
+
+	#[test]
+	fn reports_unbalanced_composites() {
+		let config = TextConfig::new();
+		let leaf = |s : &str| create_leaf_node(s.chars().collect(), &config);
+		let comb = [leaf("b"), leaf("c")].into_iter().fold(leaf("a"), |head, leaf| Shared::new(CompositeNode::new(head, leaf)) as Shared<dyn Node>);
+		let text = config.create("").with_root(Shared::new(CompositeNode::new(comb, leaf("d"))));
+		assert_eq!(text.validate(), Err(InvariantViolation::Unbalanced { offset : 0, head_depth : 2, tail_depth : 0 }));
+		assert_eq!(text.rebalance().validate(), Ok(()));
+		assert_eq!(create_immutable_text("").validate(), Ok(()));
+	}
+}
@@ -0,0 +1,232 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use super::delta::common_affixes;
+use super::search::is_word_char;
+use super::{Delta, ImmutableText};
+
+/// <summary>Holds the number of differing lines past which a diff gives up aligning them.</summary>
+const MAX_DIFF_COST : usize = 1 << 10;
+
+/// <summary>
+/// Aligns the sequences with Myers' O(ND) algorithm, keeping only the diagonals of each
+/// step, so that memory stays quadratic in the number of differences rather than in
+/// the lengths.
+/// </summary>
+/// <returns>the pairs of differing ranges of <code>a</code> and <code>b</code> in order, or None past <code>max_cost</code> differences.</returns>
+pub(crate) fn diff_ranges<T : PartialEq>(a : &[T], b : &[T], max_cost : usize) -> Option<Vec<(Range<usize>, Range<usize>)>> {
+	let (n, m) = (a.len() as isize, b.len() as isize);
+	// The furthest x reached on every diagonal k = x - y, indexed by k + d.
+	let mut trace : Vec<Vec<isize>> = Vec::new();
+	let mut v = vec![0isize; 3];
+	'search: for d in 0..=(n + m) {
+		if d as usize > max_cost {
+			return None;
+		}
+		let previous = v;
+		let reached = |k : isize| previous[(k + d) as usize];
+		v = vec![0; 2 * d as usize + 3];
+		for k in (-d..=d).step_by(2) {
+			let mut x = if k == -d || (k != d && reached(k - 1) < reached(k + 1)) { reached(k + 1) } else { reached(k - 1) + 1 };
+			let mut y = x - k;
+			while x < n && y < m && a[x as usize] == b[y as usize] {
+				x += 1;
+				y += 1;
+			}
+			v[(k + d + 1) as usize] = x;
+			if x >= n && y >= m {
+				trace.push(previous);
+				break 'search;
+			}
+		}
+		trace.push(previous);
+	}
+	// Walks the steps back, collecting the matching pairs.
+	let mut matches = Vec::new();
+	let (mut x, mut y) = (n, m);
+	for d in (0..trace.len() as isize).rev() {
+		let reached = |k : isize| trace[d as usize][(k + d) as usize];
+		let k = x - y;
+		let previous_k = if k == -d || (k != d && reached(k - 1) < reached(k + 1)) { k + 1 } else { k - 1 };
+		let previous_x = if d == 0 { 0 } else { reached(previous_k) };
+		let previous_y = previous_x - previous_k;
+		while x > previous_x && y > previous_y.max(0) {
+			x -= 1;
+			y -= 1;
+			matches.push((x as usize, y as usize));
+		}
+		x = previous_x;
+		y = previous_y;
+	}
+	let mut ranges = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	for (x, y) in matches.into_iter().rev().chain(std::iter::once((a.len(), b.len()))) {
+		if x > i || y > j {
+			ranges.push((i..x, j..y));
+		}
+		i = x + 1;
+		j = y + 1;
+	}
+	Some(ranges)
+}
+
+/// <summary>The units a diff is refined to.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Granularity {
+	/// <summary>Runs of word characters, runs of whitespace, and any other character alone.</summary>
+	Word,
+	Char
+}
+
+/// <summary>Splits the text into the units of the granularity, which concatenate back to it.</summary>
+fn tokens(text : &str, granularity : Granularity) -> Vec<&str> {
+	let mut tokens = Vec::new();
+	let mut start = 0;
+	let mut previous : Option<char> = None;
+	for (offset, c) in text.char_indices() {
+		let joins = match (granularity, previous) {
+			(Granularity::Word, Some(p)) => (is_word_char(p) && is_word_char(c)) || (p.is_whitespace() && c.is_whitespace() && p != '\n' && c != '\n'),
+			_ => false
+		};
+		if !joins && offset > start {
+			tokens.push(&text[start..offset]);
+			start = offset;
+		}
+		previous = Some(c);
+	}
+	if start < text.len() {
+		tokens.push(&text[start..]);
+	}
+	tokens
+}
+
+/// <summary>Returns the offset of every token, in characters from <code>start</code>, and the offset after the last one.</summary>
+fn token_offsets(tokens : &[&str], start : usize) -> Vec<usize> {
+	let mut offsets = Vec::with_capacity(tokens.len() + 1);
+	let mut offset = start;
+	for token in tokens {
+		offsets.push(offset);
+		offset += token.chars().count();
+	}
+	offsets.push(offset);
+	offsets
+}
+
+impl Delta {
+	/// <summary>
+	/// Returns the delta turning <code>old</code> into <code>new</code> as one replacement per run
+	/// of differing lines, so that the positions in the unchanged lines between two
+	/// changes map through the delta. Past a thousand differing lines, the lines between
+	/// the common prefix and suffix are replaced as a whole, like <code>between</code>.
+	/// </summary>
+	pub fn between_lines(old : &ImmutableText, new : &ImmutableText) -> Delta {
+		let (prefix, suffix) = common_affixes(old, new);
+		let old_middle : String = old.chars_range(prefix, old.length() - suffix).collect();
+		let new_middle : String = new.chars_range(prefix, new.length() - suffix).collect();
+		let old_lines : Vec<&str> = old_middle.split_inclusive('\n').collect();
+		let new_lines : Vec<&str> = new_middle.split_inclusive('\n').collect();
+		let mut delta = Delta::new();
+		let Some(ranges) = diff_ranges(&old_lines, &new_lines, MAX_DIFF_COST) else {
+			delta.push(prefix, old.length() - suffix - prefix, &new_middle);
+			return delta;
+		};
+		let starts = token_offsets(&old_lines, prefix);
+		for (old_range, new_range) in ranges {
+			delta.push(starts[old_range.start], starts[old_range.end] - starts[old_range.start], &new_lines[new_range].concat());
+		}
+		delta
+	}
+
+	/// <summary>
+	/// Refines the delta computed against <code>old</code>, typically by <code>between_lines</code>,
+	/// by diffing the text each edit removes with the text it inserts, so that changed lines
+	/// become the words or characters that changed within them, as diff gutters and review
+	/// tools highlight them. An edit too different to align is kept whole.
+	/// </summary>
+	pub fn refine(&self, old : &ImmutableText, granularity : Granularity) -> Delta {
+		let mut refined = Delta::new();
+		for edit in self.edits() {
+			let removed : String = old.chars_range(edit.start, edit.start + edit.removed).collect();
+			let old_tokens = tokens(&removed, granularity);
+			let new_tokens = tokens(&edit.inserted, granularity);
+			let Some(ranges) = diff_ranges(&old_tokens, &new_tokens, MAX_DIFF_COST) else {
+				refined.push(edit.start, edit.removed, &edit.inserted);
+				continue;
+			};
+			let starts = token_offsets(&old_tokens, edit.start);
+			for (old_range, new_range) in ranges {
+				refined.push(starts[old_range.start], starts[old_range.end] - starts[old_range.start], &new_tokens[new_range].concat());
+			}
+		}
+		refined
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Reloads the text from the file after it was modified by another program, as the
+	/// lines that changed applied to this text: the unchanged lines stay shared and the
+	/// returned delta lets the positions held on this text, like markers, and the undo
+	/// history follow the external modification instead of being reset.
+	/// </summary>
+	/// <returns>the reloaded text and the delta from this text to it.</returns>
+	pub fn reload_from_disk<P : AsRef<Path>>(&self, path : P) -> io::Result<(ImmutableText, Delta)> {
+		let content = fs::read_to_string(path)?;
+		let delta = Delta::between_lines(self, &self.config.open(content));
+		Ok((delta.apply(self), delta))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	fn edits(delta : &Delta) -> Vec<(usize, usize, &str)> {
+		delta.edits().iter().map(|edit| (edit.start, edit.removed, edit.inserted.as_str())).collect()
+	}
+
+	/// <summary>Returns the line delta between the texts refined to the granularity, checking that it still turns one into the other.</summary>
+	fn refine(old : &str, new : &str, granularity : Granularity) -> Delta {
+		let (old, new) = (create_immutable_text(old), create_immutable_text(new));
+		let refined = Delta::between_lines(&old, &new).refine(&old, granularity);
+		assert_eq!(refined.apply(&old).to_string(), new.to_string());
+		refined
+	}
+
+	#[test]
+	fn refines_changed_lines_to_words() {
+		let refined = refine("let x = 1;\nfoo(a, b);\nend\n", "let y = 1;\nfoo(a, c);\nend\n", Granularity::Word);
+		assert_eq!(edits(&refined), vec![(4, 1, "y"), (18, 1, "c")]);
+		let refined = refine("the quick brown fox\n", "the quick red fox jumps\n", Granularity::Word);
+		assert_eq!(edits(&refined), vec![(10, 5, "red"), (19, 0, " jumps")]);
+		let refined = refine("x = foo(a)  + bar(b)\n", "y = foo(a) + baz(b)\n", Granularity::Word);
+		assert_eq!(edits(&refined), vec![(0, 1, "y"), (10, 2, " "), (14, 3, "baz")]);
+	}
+
+	#[test]
+	fn refines_changed_lines_to_characters() {
+		let refined = refine("the quick brown fox\n", "the quick red fox\n", Granularity::Char);
+		assert_eq!(edits(&refined), vec![(10, 1, ""), (12, 3, "ed")]);
+		let refined = refine("caf\u{e9} 😀\n", "cafe 😀!\n", Granularity::Char);
+		assert_eq!(edits(&refined), vec![(3, 1, "e"), (6, 0, "!")]);
+	}
+
+	#[test]
+	fn refines_insertions_and_deletions_of_whole_lines() {
+		let refined = refine("one\nthree\n", "one\n2\nthree\n", Granularity::Word);
+		assert_eq!(edits(&refined), vec![(4, 0, "2\n")]);
+		let refined = refine("one\n2\nthree\n", "one\nthree\n", Granularity::Char);
+		assert_eq!(edits(&refined), vec![(4, 2, "")]);
+		assert!(refine("same\n", "same\n", Granularity::Word).is_empty());
+	}
+
+	#[test]
+	fn keeps_edits_too_different_to_align() {
+		let (old, new) = ("a ".repeat(600), "b ".repeat(600));
+		let refined = refine(&old, &new, Granularity::Word);
+		assert_eq!(edits(&refined), vec![(0, 1199, &new[..1199])]);
+	}
+}
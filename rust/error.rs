@@ -0,0 +1,255 @@
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::str;
+
+use super::{ImmutableText, Node, TextConfig, MAX_DEPTH};
+
+/// <summary>
+/// The reason a fallible operation on a text was refused, returned by all the try_
+/// operations and by the loaders, within a <code>LoadError</code>. The operations without
+/// try_ panic with its message instead, like the indexing of slices.
+/// </summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextError {
+	/// <summary>An index past the end of the text.</summary>
+	OutOfBounds { index : usize, len : usize },
+	/// <summary>A range reaching past the end of the text.</summary>
+	InvalidRange { start : usize, end : usize, len : usize },
+	/// <summary>A byte offset within the UTF-8 encoding of a character.</summary>
+	NotCharBoundary { byte_offset : usize },
+	/// <summary>Bytes that are not UTF-8, valid up to the given offset.</summary>
+	InvalidUtf8 { valid_up_to : usize },
+	/// <summary>A tree that would be deeper than the maximum depth.</summary>
+	DepthExceeded { depth : usize, max : usize }
+}
+
+impl fmt::Display for TextError {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			TextError::OutOfBounds { index, len } => write!(f, "index out of range: {} (length {})", index, len),
+			TextError::InvalidRange { start, end, len } => write!(f, "range out of bounds: {}..{} (length {})", start, end, len),
+			TextError::NotCharBoundary { byte_offset } => write!(f, "byte offset {} is not a char boundary", byte_offset),
+			TextError::InvalidUtf8 { valid_up_to } => write!(f, "invalid utf-8 after byte {}", valid_up_to),
+			TextError::DepthExceeded { depth, max } => write!(f, "tree depth {} exceeds the maximum {}", depth, max)
+		}
+	}
+}
+
+impl error::Error for TextError {}
+
+impl From<str::Utf8Error> for TextError {
+	fn from(error : str::Utf8Error) -> TextError {
+		TextError::InvalidUtf8 { valid_up_to : error.valid_up_to() }
+	}
+}
+
+impl TextConfig {
+	/// <summary>Creates a text with this configuration from UTF-8 bytes, or returns an error if they are not UTF-8.</summary>
+	pub fn try_create_from_utf8(&self, bytes : &[u8]) -> Result<ImmutableText, TextError> {
+		Ok(self.create(str::from_utf8(bytes)?))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Checks that <code>index</code> is at most the length, so that text can be inserted there.</summary>
+	pub(crate) fn check_index(&self, index : usize) -> Result<(), TextError> {
+		if index > self.length() {
+			return Err(TextError::OutOfBounds { index, len : self.length() });
+		}
+		Ok(())
+	}
+
+	/// <summary>Checks that the <code>count</code> characters at <code>start</code> lie within the text.</summary>
+	pub(crate) fn check_range(&self, start : usize, count : usize) -> Result<(), TextError> {
+		match start.checked_add(count) {
+			Some(end) if end <= self.length() => Ok(()),
+			_ => Err(TextError::InvalidRange { start, end : start.saturating_add(count), len : self.length() })
+		}
+	}
+
+	/// <summary>Checks that the characters between <code>start</code> and <code>end</code> lie within the text, <code>start</code> not past <code>end</code>.</summary>
+	pub(crate) fn check_bounds(&self, start : usize, end : usize) -> Result<(), TextError> {
+		if start > end || end > self.length() {
+			return Err(TextError::InvalidRange { start, end, len : self.length() });
+		}
+		Ok(())
+	}
+
+	/// <summary>Returns the <code>count</code> characters at <code>start</code>, or an error instead of panicking on a bad range.</summary>
+	pub fn try_get_text(&self, start : usize, count : usize) -> Result<ImmutableText, TextError> {
+		self.check_range(start, count)?;
+		Ok(self.get_text(start, count))
+	}
+
+	/// <summary>Inserts the text at <code>index</code>, or returns an error instead of panicking past the end.</summary>
+	pub fn try_insert(&self, index : usize, text : &ImmutableText) -> Result<ImmutableText, TextError> {
+		self.check_index(index)?;
+		Ok(self.insert_text(index, text))
+	}
+
+	/// <summary>Removes the <code>count</code> characters at <code>start</code>, or returns an error instead of panicking on a bad range.</summary>
+	pub fn try_remove(&self, start : usize, count : usize) -> Result<ImmutableText, TextError> {
+		self.check_range(start, count)?;
+		Ok(self.remove_text(start, count))
+	}
+
+	/// <summary>Replaces the <code>removed</code> characters at <code>start</code> with the text, or returns an error on a bad range.</summary>
+	pub fn try_splice(&self, start : usize, removed : usize, text : &ImmutableText) -> Result<ImmutableText, TextError> {
+		self.check_range(start, removed)?;
+		Ok(self.remove_text(start, removed).insert_text(start, text))
+	}
+
+	/// <summary>
+	/// Concatenates the specified text like <code>concat</code>, but returns an error
+	/// instead of rebuilding the tree when the result would exceed the maximum depth,
+	/// so that callers can choose when to pay for <code>rebalance</code>.
+	/// </summary>
+	pub fn try_concat(&self, that : &ImmutableText) -> Result<ImmutableText, TextError> {
+		let text = self.join_nodes(that);
+		if text.root.depth() > MAX_DEPTH {
+			return Err(TextError::DepthExceeded { depth : text.root.depth(), max : MAX_DEPTH });
+		}
+		Ok(text.coalesce_at(self.length()))
+	}
+
+	/// <summary>Returns the length of the UTF-8 encoding of this text, in O(1).</summary>
+	fn utf8_len(&self) -> usize {
+		self.root.metrics().bytes
+	}
+
+	/// <summary>
+	/// Returns the index of the character whose UTF-8 encoding holds the byte at
+	/// <code>byte_offset</code>, which must be within the encoding of the text, and the
+	/// offset it starts at, walking down by the byte counts of the nodes in O(log n).
+	/// </summary>
+	fn char_at_utf8(&self, mut byte_offset : usize) -> (usize, usize) {
+		let mut node : &dyn Node = &*self.root;
+		let (mut index, mut offset) = (0, 0);
+		while let Some(composite) = node.as_composite() {
+			let head_bytes = composite.head.metrics().bytes;
+			if byte_offset < head_bytes {
+				node = &*composite.head;
+			} else {
+				byte_offset -= head_bytes;
+				index += composite.head.length();
+				offset += head_bytes;
+				node = &*composite.tail;
+			}
+		}
+		let (leaf_index, leaf_offset) = node.char_at_utf8(byte_offset);
+		(index + leaf_index, offset + leaf_offset)
+	}
+
+	/// <summary>Returns the index of the character starting at the byte offset of the UTF-8 encoding of this text.</summary>
+	pub fn try_char_index(&self, byte_offset : usize) -> Result<usize, TextError> {
+		match byte_offset.cmp(&self.utf8_len()) {
+			Ordering::Greater => Err(TextError::OutOfBounds { index : byte_offset, len : self.utf8_len() }),
+			Ordering::Equal => Ok(self.length()),
+			Ordering::Less => match self.char_at_utf8(byte_offset) {
+				(index, offset) if offset == byte_offset => Ok(index),
+				_ => Err(TextError::NotCharBoundary { byte_offset })
+			}
+		}
+	}
+
+	/// <summary>Returns the offset in the UTF-8 encoding of this text of the character at <code>index</code>, in O(log n).</summary>
+	pub fn try_byte_offset(&self, mut index : usize) -> Result<usize, TextError> {
+		self.check_index(index)?;
+		if index == self.length() {
+			return Ok(self.utf8_len());
+		}
+		let mut node : &dyn Node = &*self.root;
+		let mut offset = 0;
+		while let Some(composite) = node.as_composite() {
+			let head_length = composite.head.length();
+			if index < head_length {
+				node = &*composite.head;
+			} else {
+				index -= head_length;
+				offset += composite.head.metrics().bytes;
+				node = &*composite.tail;
+			}
+		}
+		Ok(offset + node.utf8_offset(index))
+	}
+
+	/// <summary>
+	/// Returns the byte offsets of the boundaries of the character whose UTF-8 encoding
+	/// holds the byte at <code>byte_offset</code>, equal if it is a boundary; the length
+	/// of the encoding twice past its end.
+	/// </summary>
+	fn char_boundaries_around(&self, byte_offset : usize) -> (usize, usize) {
+		if byte_offset >= self.utf8_len() {
+			return (self.utf8_len(), self.utf8_len());
+		}
+		match self.char_at_utf8(byte_offset) {
+			(_, offset) if offset == byte_offset => (offset, offset),
+			(index, offset) => (offset, offset + self.get_char_at(index).len_utf8())
+		}
+	}
+
+	/// <summary>Returns whether the byte offset of the UTF-8 encoding of this text starts a character or is its end, like <code>str::is_char_boundary</code>.</summary>
+	pub fn is_char_boundary(&self, byte_offset : usize) -> bool {
+		let (floor, ceil) = self.char_boundaries_around(byte_offset);
+		floor == byte_offset && ceil == byte_offset
+	}
+
+	/// <summary>
+	/// Returns the largest character boundary at or before the byte offset, so that
+	/// byte offsets from a regex engine or a parser can be snapped safely; the length
+	/// of the encoding past its end.
+	/// </summary>
+	pub fn floor_char_boundary(&self, byte_offset : usize) -> usize {
+		self.char_boundaries_around(byte_offset).0
+	}
+
+	/// <summary>Returns the smallest character boundary at or after the byte offset; the length of the encoding past its end.</summary>
+	pub fn ceil_char_boundary(&self, byte_offset : usize) -> usize {
+		self.char_boundaries_around(byte_offset).1
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, open_immutable_text};
+	use super::*;
+
+	/// <summary>Checks every byte offset of the text, and one past its end, against <code>str</code>.</summary>
+	fn check_against_str(text : &ImmutableText, expected : &str) {
+		for byte_offset in 0..=expected.len() + 1 {
+			let boundary = expected.is_char_boundary(byte_offset) && byte_offset <= expected.len();
+			assert_eq!(text.is_char_boundary(byte_offset), boundary, "at {}", byte_offset);
+			let floor = (0..=byte_offset.min(expected.len())).rev().find(|&i| expected.is_char_boundary(i)).unwrap();
+			let ceil = (byte_offset.min(expected.len())..=expected.len()).find(|&i| expected.is_char_boundary(i)).unwrap();
+			assert_eq!((text.floor_char_boundary(byte_offset), text.ceil_char_boundary(byte_offset)), (floor, ceil), "at {}", byte_offset);
+			let index = match byte_offset {
+				_ if byte_offset > expected.len() => Err(TextError::OutOfBounds { index : byte_offset, len : expected.len() }),
+				_ if boundary => Ok(expected[..byte_offset].chars().count()),
+				_ => Err(TextError::NotCharBoundary { byte_offset })
+			};
+			assert_eq!(text.try_char_index(byte_offset), index, "at {}", byte_offset);
+		}
+		for (index, (byte_offset, _)) in expected.char_indices().chain([(expected.len(), ' ')]).enumerate() {
+			assert_eq!(text.try_byte_offset(index), Ok(byte_offset));
+		}
+		assert_eq!(text.try_byte_offset(text.length() + 1), Err(TextError::OutOfBounds { index : text.length() + 1, len : text.length() }));
+	}
+
+	#[test]
+	fn byte_offsets_match_str() {
+		let mixed = "ascii, é, 日本語, 🦀\r\n".repeat(20);
+		for expected in ["", "a", "é", "line\r\n", mixed.as_str()] {
+			check_against_str(&create_immutable_text(expected), expected);
+			check_against_str(&open_immutable_text(expected.to_string()), expected);
+		}
+	}
+
+	#[test]
+	fn byte_offsets_across_edited_leaves() {
+		// Leaves of several kinds and sizes, with multi-byte characters at their boundaries.
+		let opened = open_immutable_text("ωmega\r\n".repeat(50));
+		let text = opened.insert_string(63, "🦀🦀").remove_text(130, 7).concat(&create_immutable_text(&"x".repeat(70)));
+		check_against_str(&text, &text.to_string());
+	}
+}
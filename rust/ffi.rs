@@ -0,0 +1,105 @@
+//! A C API over opaque handles, to embed the rope from C, C++ and any language
+//! with a C foreign function interface. A handle is a reference-counted pointer
+//! to an immutable text: every function returning a handle returns a new
+//! reference, which the caller releases with <code>immutable_text_release</code>.
+//!
+//! Every handle passed in must have been returned by this API and not released
+//! yet, and every buffer must be valid for the given number of bytes. Strings are
+//! UTF-8 and offsets count characters. The functions return a null handle, or -1,
+//! on invalid UTF-8 or on a range outside the text instead of panicking across
+//! the boundary.
+#![allow(clippy::missing_safety_doc)]
+
+use std::slice;
+use std::str;
+
+use super::{create_immutable_text, ImmutableText, Shared};
+
+fn into_handle(text : ImmutableText) -> *const ImmutableText {
+	Shared::into_raw(Shared::new(text))
+}
+
+unsafe fn as_str<'a>(data : *const u8, len : usize) -> Option<&'a str> {
+	if len == 0 {
+		return Some("");
+	}
+	str::from_utf8(slice::from_raw_parts(data, len)).ok()
+}
+
+/// <summary>Creates a text from <code>len</code> bytes of UTF-8, or returns null if they are not UTF-8.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_create(data : *const u8, len : usize) -> *const ImmutableText {
+	match as_str(data, len) {
+		Some(text) => into_handle(create_immutable_text(text)),
+		None => std::ptr::null()
+	}
+}
+
+/// <summary>Adds a reference to the text, to be released separately.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_retain(text : *const ImmutableText) {
+	Shared::increment_strong_count(text);
+}
+
+/// <summary>Releases a reference to the text, freeing it with the last one. Null is ignored.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_release(text : *const ImmutableText) {
+	if !text.is_null() {
+		drop(Shared::from_raw(text));
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_length(text : *const ImmutableText) -> usize {
+	(*text).length()
+}
+
+/// <summary>Returns the <code>count</code> characters at <code>start</code>, or null if they do not lie within the text.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_slice(text : *const ImmutableText, start : usize, count : usize) -> *const ImmutableText {
+	match (*text).try_get_text(start, count) {
+		Ok(slice) => into_handle(slice),
+		Err(_) => std::ptr::null()
+	}
+}
+
+/// <summary>Inserts <code>len</code> bytes of UTF-8 at <code>index</code>, or returns null on invalid UTF-8 or an index past the end.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_insert(text : *const ImmutableText, index : usize, data : *const u8, len : usize) -> *const ImmutableText {
+	let inserted = match as_str(data, len) {
+		Some(inserted) => create_immutable_text(inserted),
+		None => return std::ptr::null()
+	};
+	match (*text).try_insert(index, &inserted) {
+		Ok(result) => into_handle(result),
+		Err(_) => std::ptr::null()
+	}
+}
+
+/// <summary>Removes the <code>count</code> characters at <code>start</code>, or returns null if they do not lie within the text.</summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_remove(text : *const ImmutableText, start : usize, count : usize) -> *const ImmutableText {
+	match (*text).try_remove(start, count) {
+		Ok(result) => into_handle(result),
+		Err(_) => std::ptr::null()
+	}
+}
+
+/// <summary>
+/// Copies the UTF-8 encoding of the <code>count</code> characters at <code>start</code> to the
+/// buffer if it holds <code>capacity</code> bytes or more. Returns the length of the encoding
+/// either way, so that a call with a null buffer measures it, or -1 if the characters do
+/// not lie within the text.
+/// </summary>
+#[no_mangle]
+pub unsafe extern "C" fn immutable_text_copy_out(text : *const ImmutableText, start : usize, count : usize, buffer : *mut u8, capacity : usize) -> isize {
+	let text = &*text;
+	if text.check_range(start, count).is_err() {
+		return -1;
+	}
+	let encoded : String = text.chars_range(start, start + count).collect();
+	if !buffer.is_null() && encoded.len() <= capacity {
+		slice::from_raw_parts_mut(buffer, encoded.len()).copy_from_slice(encoded.as_bytes());
+	}
+	encoded.len() as isize
+}
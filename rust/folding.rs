@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+use super::{Delta, ImmutableText};
+
+/// <summary>A foldable block of lines: the line introducing it and the last line indented deeper, with its nesting level.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FoldingRange {
+	pub start_line : usize,
+	pub end_line : usize,
+	pub level : usize
+}
+
+/// <summary>
+/// The folding ranges of a text given by the indentation of its lines, for editors
+/// knowing nothing of the language. The indentation of every line is kept, so that
+/// after an edit only the lines it touched are measured again.
+/// </summary>
+pub struct IndentFolding {
+	tab_width : usize,
+	/// <summary>The indentation width of every line, None for blank lines.</summary>
+	indents : Vec<Option<usize>>
+}
+
+/// <summary>Closes the innermost open range at <code>end_line</code>, keeping it if it holds more than its first line.</summary>
+fn close(open : &mut Vec<(usize, usize)>, end_line : usize, ranges : &mut Vec<FoldingRange>) {
+	let (start_line, _) = open.pop().unwrap();
+	if end_line > start_line {
+		ranges.push(FoldingRange { start_line, end_line, level : open.len() });
+	}
+}
+
+/// <summary>Returns the indentation widths of the lines in the range, in one pass over their characters.</summary>
+fn measure(text : &ImmutableText, lines : Range<usize>, tab_width : usize) -> Vec<Option<usize>> {
+	let mut indents = Vec::with_capacity(lines.len());
+	let mut width = 0;
+	let mut indent = None;
+	for c in text.chars_range(text.line_start(lines.start), text.line_end(lines.end - 1)) {
+		match c {
+			'\n' => {
+				indents.push(indent);
+				width = 0;
+				indent = None;
+			}
+			' ' if indent.is_none() => width += 1,
+			'\t' if indent.is_none() => width += tab_width - width % tab_width,
+			c if indent.is_none() && !c.is_whitespace() => indent = Some(width),
+			_ => {}
+		}
+	}
+	indents.push(indent);
+	indents
+}
+
+impl IndentFolding {
+	pub fn new(text : &ImmutableText, tab_width : usize) -> IndentFolding {
+		IndentFolding { tab_width, indents : measure(text, 0..text.line_count(), tab_width) }
+	}
+
+	/// <summary>
+	/// Brings the indentation up to date with <code>new</code>, the text the delta turns
+	/// <code>old</code> into, measuring only the lines between the first and last edits.
+	/// </summary>
+	pub fn update(&mut self, old : &ImmutableText, new : &ImmutableText, delta : &Delta) {
+		let (first, last) = match (delta.edits().first(), delta.edits().last()) {
+			(Some(first), Some(last)) => (first, last),
+			_ => return
+		};
+		let first_line = old.line_of_offset(first.start);
+		let old_end = last.start + last.removed;
+		let last_line = old.line_of_offset(old_end);
+		let new_last_line = new.line_of_offset(old_end + new.length() - old.length());
+		self.indents.splice(first_line..last_line + 1, measure(new, first_line..new_last_line + 1, self.tab_width));
+	}
+
+	/// <summary>
+	/// Returns the folding ranges in the order of their first lines: every non-blank line
+	/// followed by lines indented deeper starts a range ending at the last of them.
+	/// </summary>
+	pub fn ranges(&self) -> Vec<FoldingRange> {
+		let mut ranges = Vec::new();
+		// The lines that may start a range still open, with their indentation.
+		let mut open : Vec<(usize, usize)> = Vec::new();
+		let mut last_non_blank = 0;
+		for (line, indent) in self.indents.iter().enumerate() {
+			let indent = match indent {
+				Some(indent) => *indent,
+				None => continue
+			};
+			while open.last().is_some_and(|&(_, open_indent)| open_indent >= indent) {
+				close(&mut open, last_non_blank, &mut ranges);
+			}
+			open.push((line, indent));
+			last_non_blank = line;
+		}
+		while !open.is_empty() {
+			close(&mut open, last_non_blank, &mut ranges);
+		}
+		ranges.sort_by_key(|range| range.start_line);
+		ranges
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Returns the folding ranges given by the indentation of the lines, see <code>IndentFolding</code>.</summary>
+	pub fn folding_ranges(&self, tab_width : usize) -> Vec<FoldingRange> {
+		IndentFolding::new(self, tab_width).ranges()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	/// <summary>Checks that updating the folding of the text with the delta measures it as it would from scratch.</summary>
+	fn check_update(old : &ImmutableText, delta : &Delta) {
+		let new = delta.apply(old);
+		let mut folding = IndentFolding::new(old, 4);
+		folding.update(old, &new, delta);
+		let fresh = IndentFolding::new(&new, 4);
+		assert_eq!(folding.indents, fresh.indents, "{:?} -> {:?}", old.to_string(), new.to_string());
+		assert_eq!(folding.ranges(), fresh.ranges());
+	}
+
+	fn edit(start : usize, removed : usize, inserted : &str) -> Delta {
+		let mut delta = Delta::new();
+		delta.push(start, removed, inserted);
+		delta
+	}
+
+	#[test]
+	fn folds_by_indentation() {
+		let text = create_immutable_text("a\n\tb\n\t\tc\n\n\td\ne\n  f\n");
+		assert_eq!(text.folding_ranges(4), vec![
+			FoldingRange { start_line : 0, end_line : 4, level : 0 },
+			FoldingRange { start_line : 1, end_line : 2, level : 1 },
+			FoldingRange { start_line : 5, end_line : 6, level : 0 }
+		]);
+		assert!(create_immutable_text("a\nb\n\n").folding_ranges(4).is_empty());
+	}
+
+	#[test]
+	fn updates_like_a_fresh_folding() {
+		let source = "fn a() {\n\tif b {\n\t\tc();\n\t}\n}\n\nfn d() {\n    e();\n}\n";
+		let text = create_immutable_text(source);
+		// Edits within a line.
+		check_update(&text, &edit(10, 2, "while"));
+		check_update(&text, &edit(18, 0, "    "));
+		check_update(&text, &edit(17, 1, ""));
+		// Inserts adding lines.
+		check_update(&text, &edit(9, 0, "\tlet x = 1;\n\t\tlet y = 2;\n"));
+		check_update(&text, &edit(source.len(), 0, "\nfn f() {\n\tg();\n}"));
+		check_update(&text, &edit(0, 0, "\n\n  // comment\n"));
+		// Deletes removing lines.
+		check_update(&text, &edit(9, 17, ""));
+		check_update(&text, &edit(31, source.len() - 31, ""));
+		check_update(&text, &edit(0, source.len(), ""));
+		check_update(&text, &edit(2, 40, "\n\t\t"));
+		// Several edits in one delta.
+		let mut delta = Delta::new();
+		delta.push(9, 0, "\t\n");
+		delta.push(20, 5, "");
+		delta.push(37, 1, "\n\n\t\tx\n");
+		check_update(&text, &delta);
+		// An empty delta changes nothing.
+		check_update(&text, &Delta::new());
+	}
+
+	#[test]
+	fn updates_across_leaves() {
+		let source : String = (0..60).map(|i| format!("{}line {}\n", "\t".repeat(i % 4), i)).collect();
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let line_start = text.line_start(30);
+		check_update(&text, &edit(line_start, 0, "\t\t\tnew\n\t\t\t\tdeeper\n"));
+		check_update(&text, &edit(line_start, text.line_start(45) - line_start, ""));
+		check_update(&text, &edit(line_start + 2, text.line_start(33) - line_start, "\n"));
+	}
+}
@@ -0,0 +1,50 @@
+//! Applies arbitrary edit sequences to an arbitrary text and cross-checks every
+//! step against a plain vector of characters, validating the tree as it goes.
+
+#![no_main]
+
+use immutable_text::ImmutableText;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input : (ImmutableText, Vec<(u8, usize, usize, String)>)| {
+	let (mut text, edits) = input;
+	let mut model : Vec<char> = text.chars().collect();
+	for (operation, first, second, inserted) in edits {
+		// Folds the raw offsets into the current bounds.
+		let start = first % (model.len() + 1);
+		let count = second % (model.len() - start + 1);
+		match operation % 5 {
+			0 => {
+				text = text.insert_string(start, &inserted);
+				model.splice(start..start, inserted.chars());
+			}
+			1 => {
+				text = text.remove_text(start, count);
+				model.drain(start..start + count);
+			}
+			2 => {
+				text = text.get_text(start, count);
+				model = model[start..start + count].to_vec();
+			}
+			3 => {
+				let other = text.config().create(&inserted);
+				text = text.concat(&other);
+				model.extend(inserted.chars());
+			}
+			_ => {
+				let copied = text.get_text(start, count);
+				text = text.append(&copied);
+				model.extend_from_within(start..start + count);
+			}
+		}
+		assert_eq!(text.length(), model.len());
+		if let Err(violation) = text.validate() {
+			panic!("invariant broken after {:?}: {:?}", operation % 5, violation);
+		}
+		assert!(text.chars().eq(model.iter().copied()));
+		if !model.is_empty() {
+			assert_eq!(text.get_char_at(start.min(model.len() - 1)), model[start.min(model.len() - 1)]);
+		}
+	}
+	assert_eq!(text.line_count(), model.iter().filter(|&&c| c == '\n').count() + 1);
+});
@@ -0,0 +1,29 @@
+use arbitrary::{Arbitrary, Unstructured};
+
+use super::{ImmutableText, TextConfig};
+
+impl<'a> Arbitrary<'a> for ImmutableText {
+	/// <summary>
+	/// Builds a text of an arbitrary shape: created whole or opened by pieces, with an
+	/// arbitrary block size, then edited by a sequence of inserts and removals so that
+	/// its tree holds the composites, small leaves and slices real edits produce.
+	/// </summary>
+	fn arbitrary(u : &mut Unstructured<'a>) -> arbitrary::Result<ImmutableText> {
+		let config = match u.int_in_range(0u32..=8)? {
+			0 => TextConfig::new(),
+			shift => TextConfig::new().with_block_size(1 << shift)
+		};
+		let content = String::arbitrary(u)?;
+		let mut text = if bool::arbitrary(u)? { config.open(content) } else { config.create(&content) };
+		for _ in 0..u.int_in_range(0u8..=16)? {
+			let start = u.int_in_range(0..=text.length())?;
+			if bool::arbitrary(u)? {
+				text = text.insert_text(start, &config.create(&String::arbitrary(u)?));
+			} else {
+				let count = u.int_in_range(0..=text.length() - start)?;
+				text = text.remove_text(start, count);
+			}
+		}
+		Ok(text)
+	}
+}
@@ -0,0 +1,253 @@
+use super::ImmutableText;
+
+// Scoring follows fzf's v1 algorithm: a greedy forward scan finds where the
+// pattern ends, a backward scan tightens where it starts, and the window in
+// between is scored with gap penalties and word-boundary bonuses.
+const SCORE_MATCH : i32 = 16;
+const SCORE_GAP_START : i32 = -3;
+const SCORE_GAP_EXTENSION : i32 = -1;
+const BONUS_BOUNDARY : i32 = SCORE_MATCH / 2;
+const BONUS_NON_WORD : i32 = SCORE_MATCH / 2;
+const BONUS_CAMEL_123 : i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE : i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER : i32 = 2;
+
+/// <summary>A fuzzy match: its score and the text offsets of the matched characters.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyMatch {
+	pub score : i32,
+	pub positions : Vec<usize>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+	NonWord,
+	Lower,
+	Upper,
+	Letter,
+	Number
+}
+
+fn char_class(c : char) -> CharClass {
+	if c.is_lowercase() {
+		CharClass::Lower
+	} else if c.is_uppercase() {
+		CharClass::Upper
+	} else if c.is_numeric() {
+		CharClass::Number
+	} else if c.is_alphabetic() {
+		CharClass::Letter
+	} else {
+		CharClass::NonWord
+	}
+}
+
+fn bonus_for(prev : CharClass, class : CharClass) -> i32 {
+	if prev == CharClass::NonWord && class != CharClass::NonWord {
+		// Word boundary
+		return BONUS_BOUNDARY;
+	}
+	if (prev == CharClass::Lower && class == CharClass::Upper) || (prev != CharClass::Number && class == CharClass::Number) {
+		// camelCase letter123
+		return BONUS_CAMEL_123;
+	}
+	if class == CharClass::NonWord {
+		return BONUS_NON_WORD;
+	}
+	0
+}
+
+struct Pattern {
+	chars : Vec<char>,
+	case_sensitive : bool
+}
+
+impl Pattern {
+	/// <summary>Smart case: the pattern is matched case-insensitively unless it contains an upper case letter.</summary>
+	fn new(pattern : &str) -> Pattern {
+		let case_sensitive = pattern.chars().any(char::is_uppercase);
+		let chars = pattern.chars().map(|c| if case_sensitive { c } else { fold(c) }).collect();
+		Pattern { chars, case_sensitive }
+	}
+
+	fn matches(&self, index : usize, c : char) -> bool {
+		let c = if self.case_sensitive { c } else { fold(c) };
+		self.chars[index] == c
+	}
+}
+
+fn fold(c : char) -> char {
+	c.to_lowercase().next().unwrap_or(c)
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Fuzzy matches the pattern against the whole text, fzf style.
+	/// </summary>
+	/// <returns>the scored match, or None if the characters of the pattern do not occur in order.</returns>
+	pub fn fuzzy_match(&self, pattern : &str) -> Option<FuzzyMatch> {
+		let pattern = Pattern::new(pattern);
+		let mut pattern_index = 0;
+		let mut end = 0;
+		for (offset, c) in self.chars().enumerate() {
+			if pattern_index < pattern.chars.len() && pattern.matches(pattern_index, c) {
+				pattern_index += 1;
+				end = offset + 1;
+			}
+		}
+		if pattern_index < pattern.chars.len() {
+			return None;
+		}
+		Some(self.score_window(&pattern, 0, end))
+	}
+
+	/// <summary>
+	/// Fuzzy matches the pattern against every line of the text.
+	/// </summary>
+	/// <returns>the matching line numbers with their matches, best score first.</returns>
+	pub fn fuzzy_match_lines(&self, pattern : &str) -> Vec<(usize, FuzzyMatch)> {
+		let pattern = Pattern::new(pattern);
+		let mut result = Vec::new();
+		let mut line = 0;
+		let mut line_start = 0;
+		let mut pattern_index = 0;
+		let mut end = 0;
+		for (offset, c) in self.chars().enumerate() {
+			if c == '\n' {
+				if pattern_index == pattern.chars.len() {
+					result.push((line, self.score_window(&pattern, line_start, end)));
+				}
+				line += 1;
+				line_start = offset + 1;
+				pattern_index = 0;
+				end = line_start;
+			} else if pattern_index < pattern.chars.len() && pattern.matches(pattern_index, c) {
+				pattern_index += 1;
+				end = offset + 1;
+			}
+		}
+		if pattern_index == pattern.chars.len() {
+			result.push((line, self.score_window(&pattern, line_start, end)));
+		}
+		result.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.0.cmp(&b.0)));
+		result
+	}
+
+	/// <summary>
+	/// Tightens the window <code>[start, end)</code> whose last character completes a greedy
+	/// match of the pattern by scanning backwards, then scores it.
+	/// </summary>
+	fn score_window(&self, pattern : &Pattern, start : usize, end : usize) -> FuzzyMatch {
+		let mut match_start = end;
+		let mut pattern_index = pattern.chars.len();
+		while pattern_index > 0 {
+			match_start -= 1;
+			if pattern.matches(pattern_index - 1, self.get_char_at(match_start)) {
+				pattern_index -= 1;
+			}
+		}
+
+		let mut prev_class = if match_start > start {
+			char_class(self.get_char_at(match_start - 1))
+		} else {
+			CharClass::NonWord
+		};
+		let mut score = 0;
+		let mut positions = Vec::with_capacity(pattern.chars.len());
+		let mut in_gap = false;
+		let mut consecutive = 0;
+		let mut first_bonus = 0;
+		for (offset, c) in (match_start..end).zip(self.chars_range(match_start, end)) {
+			let class = char_class(c);
+			if pattern_index < pattern.chars.len() && pattern.matches(pattern_index, c) {
+				positions.push(offset);
+				score += SCORE_MATCH;
+				let mut bonus = bonus_for(prev_class, class);
+				if consecutive == 0 {
+					first_bonus = bonus;
+				} else {
+					// Break consecutive chunk
+					if bonus == BONUS_BOUNDARY {
+						first_bonus = bonus;
+					}
+					bonus = bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+				}
+				if pattern_index == 0 {
+					score += bonus * BONUS_FIRST_CHAR_MULTIPLIER;
+				} else {
+					score += bonus;
+				}
+				in_gap = false;
+				consecutive += 1;
+				pattern_index += 1;
+			} else {
+				score += if in_gap { SCORE_GAP_EXTENSION } else { SCORE_GAP_START };
+				in_gap = true;
+				consecutive = 0;
+				first_bonus = 0;
+			}
+			prev_class = class;
+		}
+		FuzzyMatch { score, positions }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn positions_are_the_tightest_window() {
+		let text = create_immutable_text("a_b_abc");
+		let found = text.fuzzy_match("abc").unwrap();
+		assert_eq!(found.positions, vec![4, 5, 6]);
+		assert_eq!(text.fuzzy_match("abd"), None);
+		assert_eq!(text.fuzzy_match("cba"), None);
+	}
+
+	#[test]
+	fn word_starts_and_consecutive_runs_score_higher() {
+		let text = create_immutable_text("foo_bar\nfxoxo\nxfoo\nfoobar");
+		let lines = text.fuzzy_match_lines("foo");
+		let order : Vec<usize> = lines.iter().map(|(line, _)| *line).collect();
+		assert_eq!(order, vec![0, 3, 1, 2]);
+		assert_eq!(lines[0].1.score, lines[1].1.score);
+		assert!(lines[1].1.score > lines[2].1.score);
+		assert!(lines[2].1.score > lines[3].1.score);
+		assert_eq!(lines[2].1.positions, vec![8, 10, 12]);
+		assert_eq!(lines[3].1.positions, vec![15, 16, 17]);
+	}
+
+	#[test]
+	fn camel_case_humps_earn_a_bonus() {
+		let camel = create_immutable_text("getFileName").fuzzy_match("gfn").unwrap();
+		let flat = create_immutable_text("getfilename").fuzzy_match("gfn").unwrap();
+		assert_eq!(camel.positions, vec![0, 3, 7]);
+		assert!(camel.score > flat.score);
+	}
+
+	#[test]
+	fn upper_case_in_the_pattern_makes_it_case_sensitive() {
+		let text = create_immutable_text("ReadMe");
+		assert_eq!(text.fuzzy_match("rm").unwrap().positions, vec![0, 4]);
+		assert_eq!(text.fuzzy_match("RM").unwrap().positions, vec![0, 4]);
+		assert_eq!(text.fuzzy_match("Rm"), None);
+		assert_eq!(create_immutable_text("ÉTÉ").fuzzy_match("été").unwrap().positions, vec![0, 1, 2]);
+	}
+
+	#[test]
+	fn matches_cross_leaves() {
+		let content = "x".repeat(30) + "alpha" + &"y".repeat(30) + "beta";
+		let text = TextConfig::new().with_block_size(16).create(&content);
+		assert!(text.tree_stats().leaf_count > 1);
+		let found = text.fuzzy_match("alphab").unwrap();
+		assert_eq!(found.positions, vec![30, 31, 32, 33, 34, 65]);
+		assert_eq!(found, create_immutable_text(&content).fuzzy_match("alphab").unwrap());
+
+		let lines = content.clone() + "\n" + &content;
+		let text = TextConfig::new().with_block_size(16).create(&lines);
+		let found = text.fuzzy_match_lines("pha");
+		assert_eq!(found.len(), 2);
+		assert_eq!(found[1].1.positions, vec![102, 103, 104]);
+	}
+}
@@ -0,0 +1,278 @@
+// The remainders are taken by hand, u32::is_multiple_of being too recent for older compilers.
+#![allow(clippy::manual_is_multiple_of)]
+
+use std::ops::Range;
+
+use super::{balanced_node, chunked, collect_leaves, create_leaf_node, ImmutableText, Node, Shared, TextConfig};
+
+/// <summary>
+/// Grapheme cluster break classes of UAX #29. Membership is approximated with the
+/// ranges of the scripts and emoji most found in source code and prose; spacing
+/// marks are treated as extending characters.
+/// </summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GraphemeClass {
+	Cr,
+	Lf,
+	Control,
+	Extend,
+	Zwj,
+	RegionalIndicator,
+	L,
+	V,
+	T,
+	Lv,
+	Lvt,
+	ExtendedPictographic,
+	Other
+}
+
+const EXTEND : [(u32, u32); 27] = [
+	(0x0300, 0x036F), (0x0483, 0x0489), (0x0591, 0x05BD), (0x05BF, 0x05BF), (0x05C1, 0x05C2),
+	(0x05C4, 0x05C5), (0x05C7, 0x05C7), (0x0610, 0x061A), (0x064B, 0x065F), (0x0670, 0x0670),
+	(0x06D6, 0x06DC), (0x06DF, 0x06E4), (0x06E7, 0x06E8), (0x06EA, 0x06ED), (0x0900, 0x0903),
+	(0x093A, 0x094F), (0x0951, 0x0957), (0x0962, 0x0963), (0x0E31, 0x0E31), (0x0E34, 0x0E3A),
+	(0x0E47, 0x0E4E), (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF), (0x200C, 0x200C), (0x20D0, 0x20FF),
+	(0xFE00, 0xFE0F), (0xFE20, 0xFE2F)
+];
+
+const EXTEND_SUPPLEMENTARY : [(u32, u32); 3] = [
+	(0x1F3FB, 0x1F3FF), (0xE0020, 0xE007F), (0xE0100, 0xE01EF)
+];
+
+const EXTENDED_PICTOGRAPHIC : [(u32, u32); 19] = [
+	(0x00A9, 0x00A9), (0x00AE, 0x00AE), (0x203C, 0x203C), (0x2049, 0x2049), (0x2122, 0x2122),
+	(0x2139, 0x2139), (0x2194, 0x21AA), (0x231A, 0x23FF), (0x24C2, 0x24C2), (0x25AA, 0x25FE),
+	(0x2600, 0x27BF), (0x2934, 0x2935), (0x2B05, 0x2B55), (0x3030, 0x3030), (0x303D, 0x303D),
+	(0x3297, 0x3297), (0x3299, 0x3299), (0x1F000, 0x1F1E5), (0x1F200, 0x1FAFF)
+];
+
+fn in_ranges(c : u32, ranges : &[(u32, u32)]) -> bool {
+	ranges.iter().any(|&(low, high)| low <= c && c <= high)
+}
+
+fn grapheme_class(c : char) -> GraphemeClass {
+	let code = c as u32;
+	match code {
+		0x0D => GraphemeClass::Cr,
+		0x0A => GraphemeClass::Lf,
+		0x200D => GraphemeClass::Zwj,
+		0x1F1E6..=0x1F1FF => GraphemeClass::RegionalIndicator,
+		0x1100..=0x115F | 0xA960..=0xA97C => GraphemeClass::L,
+		0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => GraphemeClass::V,
+		0x11A8..=0x11FF | 0xD7CB..=0xD7FB => GraphemeClass::T,
+		0xAC00..=0xD7A3 if (code - 0xAC00) % 28 == 0 => GraphemeClass::Lv,
+		0xAC00..=0xD7A3 => GraphemeClass::Lvt,
+		_ if c.is_control() || code == 0x2028 || code == 0x2029 => GraphemeClass::Control,
+		_ if in_ranges(code, &EXTEND) || in_ranges(code, &EXTEND_SUPPLEMENTARY) => GraphemeClass::Extend,
+		_ if in_ranges(code, &EXTENDED_PICTOGRAPHIC) => GraphemeClass::ExtendedPictographic,
+		_ => GraphemeClass::Other
+	}
+}
+
+/// <summary>Returns whether the character extends the one before it, as a combining mark does.</summary>
+pub(crate) fn is_extending(c : char) -> bool {
+	matches!(grapheme_class(c), GraphemeClass::Extend | GraphemeClass::Zwj)
+}
+
+/// <summary>Finds grapheme cluster boundaries in a stream of characters, left to right.</summary>
+pub(crate) struct GraphemeBreaker {
+	previous : Option<GraphemeClass>,
+	/// <summary>Number of consecutive regional indicators just seen.</summary>
+	regional_indicators : usize,
+	/// <summary>Whether the current run is an extended pictographic followed by extending characters.</summary>
+	in_emoji : bool,
+	/// <summary>Whether the previous character is a ZWJ that follows such a run.</summary>
+	emoji_zwj : bool
+}
+
+impl GraphemeBreaker {
+	pub(crate) fn new() -> GraphemeBreaker {
+		GraphemeBreaker { previous : None, regional_indicators : 0, in_emoji : false, emoji_zwj : false }
+	}
+
+	/// <summary>Feeds the next character; returns true if a cluster boundary comes before it.</summary>
+	pub(crate) fn is_boundary_before(&mut self, c : char) -> bool {
+		use self::GraphemeClass::*;
+		let class = grapheme_class(c);
+		let boundary = match (self.previous, class) {
+			(None, _) => true,
+			(Some(Cr), Lf) => false,
+			(Some(Cr), _) | (Some(Lf), _) | (Some(Control), _) => true,
+			(_, Cr) | (_, Lf) | (_, Control) => true,
+			(Some(L), L) | (Some(L), V) | (Some(L), Lv) | (Some(L), Lvt) => false,
+			(Some(Lv), V) | (Some(Lv), T) | (Some(V), V) | (Some(V), T) => false,
+			(Some(Lvt), T) | (Some(T), T) => false,
+			(_, Extend) | (_, Zwj) => false,
+			(Some(Zwj), ExtendedPictographic) if self.emoji_zwj => false,
+			(Some(RegionalIndicator), RegionalIndicator) => self.regional_indicators % 2 == 0,
+			_ => true
+		};
+		self.emoji_zwj = class == Zwj && self.in_emoji;
+		self.in_emoji = match class {
+			ExtendedPictographic => true,
+			Extend => self.in_emoji,
+			_ => false
+		};
+		self.regional_indicators = if class == RegionalIndicator { self.regional_indicators + 1 } else { 0 };
+		self.previous = Some(class);
+		boundary
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the text reversed by grapheme cluster: combining sequences, emoji
+	/// sequences, flags and CR LF keep their inner order.
+	/// </summary>
+	/// <remarks>
+	/// The clusters are gathered a block at a time into a buffer reused for every
+	/// block, each block becoming a leaf, so that no copy of the whole text is made.
+	/// </remarks>
+	pub fn reverse(&self) -> ImmutableText {
+		if self.length() == 0 {
+			return self.clone();
+		}
+		let mut breaker = GraphemeBreaker::new();
+		let mut leaves = Vec::new();
+		// The characters of the block being filled and the offsets its clusters start at.
+		let mut block = Vec::with_capacity(self.config.block_size());
+		let mut clusters : Vec<usize> = Vec::new();
+		for c in self.chars() {
+			if breaker.is_boundary_before(c) {
+				cut_block(&mut leaves, &mut block, &mut clusters, &self.config);
+				clusters.push(block.len());
+			}
+			block.push(c);
+		}
+		cut_block(&mut leaves, &mut block, &mut clusters, &self.config);
+		push_reversed(&mut leaves, &block, &clusters, &self.config);
+		leaves.reverse();
+		self.with_root(balanced_node(&leaves))
+	}
+
+	/// <summary>
+	/// Returns the range of the grapheme cluster holding the character at <code>offset</code>.
+	/// Clusters always break after a line feed, so only the line of the offset is scanned.
+	/// </summary>
+	fn grapheme_range(&self, offset : usize) -> Range<usize> {
+		let line_start = self.line_start(self.line_of_offset(offset));
+		let mut breaker = GraphemeBreaker::new();
+		let mut start = line_start;
+		for (i, c) in self.chars_range(line_start, self.length()).enumerate() {
+			let index = line_start + i;
+			if breaker.is_boundary_before(c) && index > line_start {
+				if index > offset {
+					return start..index;
+				}
+				start = index;
+			}
+		}
+		start..self.length()
+	}
+
+	/// <summary>
+	/// Removes the grapheme cluster starting before <code>offset</code>, as backspace does:
+	/// a combining sequence, an emoji sequence, a flag or CR LF goes as a whole, even
+	/// if <code>offset</code> falls within it.
+	/// </summary>
+	/// <returns>the new text and the removed cluster, empty at the start of the text.</returns>
+	pub fn remove_grapheme_before(&self, offset : usize) -> (ImmutableText, String) {
+		if let Err(error) = self.check_index(offset) {
+			panic!("{}", error);
+		}
+		if offset == 0 {
+			return (self.clone(), String::new());
+		}
+		self.remove_grapheme(self.grapheme_range(offset - 1))
+	}
+
+	/// <summary>Removes the grapheme cluster at <code>offset</code>, as delete does, see <code>remove_grapheme_before</code>.</summary>
+	/// <returns>the new text and the removed cluster, empty at the end of the text.</returns>
+	pub fn remove_grapheme_at(&self, offset : usize) -> (ImmutableText, String) {
+		if let Err(error) = self.check_index(offset) {
+			panic!("{}", error);
+		}
+		if offset == self.length() {
+			return (self.clone(), String::new());
+		}
+		self.remove_grapheme(self.grapheme_range(offset))
+	}
+
+	fn remove_grapheme(&self, range : Range<usize>) -> (ImmutableText, String) {
+		let removed = self.chars_range(range.start, range.end).collect();
+		(self.remove_text(range.start, range.end - range.start), removed)
+	}
+}
+
+/// <summary>
+/// Cuts a block that overflowed with the cluster just completed: the clusters before
+/// it go to the leaves, and it starts the next block. Blocks are only cut between
+/// clusters, so that no cluster is split over two leaves.
+/// </summary>
+fn cut_block(leaves : &mut Vec<Shared<dyn Node>>, block : &mut Vec<char>, clusters : &mut Vec<usize>, config : &TextConfig) {
+	if block.len() <= config.block_size() || clusters.len() < 2 {
+		return;
+	}
+	let last = clusters.pop().unwrap();
+	push_reversed(leaves, &block[..last], clusters, config);
+	block.drain(..last);
+	clusters.clear();
+	clusters.push(0);
+}
+
+/// <summary>
+/// Pushes the leaves holding the clusters of the block, starting at the given offsets,
+/// in reverse order, for the leaves of the reversed text to be reversed in turn.
+/// </summary>
+fn push_reversed(leaves : &mut Vec<Shared<dyn Node>>, block : &[char], clusters : &[usize], config : &TextConfig) {
+	let mut reversed = Vec::with_capacity(block.len());
+	let mut end = block.len();
+	for &start in clusters.iter().rev() {
+		reversed.extend_from_slice(&block[start..end]);
+		end = start;
+	}
+	// A single cluster longer than a block is cut like any leaf.
+	leaves.extend(collect_leaves(&chunked(create_leaf_node(reversed, config), config)).into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns the string reversed by grapheme cluster, splitting it with the breaker.</summary>
+	fn reversed(text : &str) -> String {
+		let mut breaker = GraphemeBreaker::new();
+		let mut clusters : Vec<String> = Vec::new();
+		for c in text.chars() {
+			if breaker.is_boundary_before(c) || clusters.is_empty() {
+				clusters.push(String::new());
+			}
+			clusters.last_mut().unwrap().push(c);
+		}
+		clusters.into_iter().rev().collect()
+	}
+
+	#[test]
+	fn reverses_by_cluster_across_blocks() {
+		let config = TextConfig::new().with_block_size(16);
+		let long_cluster = format!("a{}", "\u{301}".repeat(40));
+		for expected in ["", "a", "\r\n", "ab\r\ncd", "e\u{301}🇫🇷🇩🇪👩\u{200D}💻\r\n각", long_cluster.as_str()] {
+			let expected = expected.repeat(7);
+			for text in [create_immutable_text(&expected), config.create(&expected)] {
+				let reverse = text.reverse();
+				assert_eq!(reverse.to_string(), reversed(&expected));
+				assert_eq!(reverse.config(), text.config());
+				assert!(reverse.validate().is_ok());
+				assert_eq!(reverse.reverse().to_string(), expected);
+			}
+		}
+	}
+
+	#[test]
+	fn keeps_crlf_and_flags_whole() {
+		assert_eq!(create_immutable_text("ab\r\n🇫🇷🇩🇪").reverse().to_string(), "🇩🇪🇫🇷\r\nba");
+		assert_eq!(create_immutable_text("가각").reverse().to_string(), "각가");
+	}
+}
@@ -0,0 +1,534 @@
+use std::collections::BTreeSet;
+
+use super::diagnostics::NodeCensus;
+use super::{Delta, ImmutableText};
+
+/// <summary>How an edit was made, which decides whether it extends the previous undo step.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditKind {
+	/// <summary>A character typed at the caret.</summary>
+	Typing,
+	/// <summary>An update of the text an input method is composing.</summary>
+	Composition,
+	/// <summary>Any other edit, a step of its own.</summary>
+	Other
+}
+
+/// <summary>Holds which consecutive edits are merged into a single undo step.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoalescePolicy {
+	merge_typing : bool,
+	merge_composition : bool,
+	max_typing_run : usize
+}
+
+impl CoalescePolicy {
+	/// <summary>
+	/// Returns the default policy: characters typed one after the other are merged
+	/// in runs of up to 64, and the updates of an input method composition are merged.
+	/// </summary>
+	pub const fn new() -> CoalescePolicy {
+		CoalescePolicy { merge_typing : true, merge_composition : true, max_typing_run : 64 }
+	}
+
+	/// <summary>Sets whether single characters typed at the end of the previous ones are merged.</summary>
+	pub fn with_merge_typing(mut self, merge_typing : bool) -> CoalescePolicy {
+		self.merge_typing = merge_typing;
+		self
+	}
+
+	/// <summary>Sets whether the consecutive updates of a composition are merged.</summary>
+	pub fn with_merge_composition(mut self, merge_composition : bool) -> CoalescePolicy {
+		self.merge_composition = merge_composition;
+		self
+	}
+
+	/// <summary>Sets the number of typed characters after which a new step starts.</summary>
+	pub fn with_max_typing_run(mut self, max_typing_run : usize) -> CoalescePolicy {
+		self.max_typing_run = max_typing_run;
+		self
+	}
+
+	pub fn merge_typing(&self) -> bool {
+		self.merge_typing
+	}
+
+	pub fn merge_composition(&self) -> bool {
+		self.merge_composition
+	}
+
+	pub fn max_typing_run(&self) -> usize {
+		self.max_typing_run
+	}
+}
+
+impl Default for CoalescePolicy {
+	fn default() -> CoalescePolicy {
+		CoalescePolicy::new()
+	}
+}
+
+/// <summary>How the history frees memory once it retains more than its budget.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+	/// <summary>Drops the oldest revisions: they can no longer be restored.</summary>
+	Drop,
+	/// <summary>
+	/// Drops the oldest revisions but the first one, merging the steps around each into
+	/// one, so that the original text can still be restored; it is dropped last.
+	/// </summary>
+	Squash
+}
+
+/// <summary>
+/// A revision of the text: a node of the undo tree, whose parent is the revision it
+/// was edited from. Revisions share their unchanged nodes.
+/// </summary>
+struct Revision {
+	parent : Option<usize>,
+	children : Vec<usize>,
+	/// <summary>The child redo moves to: the last one created or left by undo.</summary>
+	redo : Option<usize>,
+	/// <summary>The text, or None once the revision was evicted.</summary>
+	text : Option<ImmutableText>,
+	/// <summary>
+	/// The delta from the parent to this revision, to rebuild it once evicted, or None
+	/// once evicted itself: it is then composed into the deltas of its children.
+	/// </summary>
+	delta : Option<Delta>,
+	kind : EditKind,
+	/// <summary>The offset after the last character typed, if the step ends with one.</summary>
+	caret : Option<usize>,
+	/// <summary>The number of characters typed, for a typing step.</summary>
+	typed : usize
+}
+
+/// <summary>
+/// The undo history of a document, kept as a tree like Vim's undotree: editing after
+/// undoing starts a new branch instead of discarding the steps undone, and any revision
+/// can be restored. Every revision keeps its text, so moving between them is O(1), and
+/// consecutive edits are merged into one step as the coalescing policy allows, so that
+/// undoing matches what the user did rather than each keystroke.
+/// </summary>
+/// <remarks>Revisions are numbered in the order they were created, from 0 for the initial text.</remarks>
+pub struct History {
+	revisions : Vec<Revision>,
+	current : usize,
+	policy : CoalescePolicy,
+	/// <summary>Whether the current revision may still be extended by the next edit.</summary>
+	open : bool,
+	budget : Option<(usize, Eviction)>,
+	/// <summary>The revisions whose text is kept, oldest first.</summary>
+	kept : BTreeSet<usize>,
+	/// <summary>The nodes of the texts kept.</summary>
+	census : NodeCensus,
+	/// <summary>The heap bytes of the deltas kept.</summary>
+	delta_bytes : usize
+}
+
+impl History {
+	/// <summary>Creates an empty history of the text, with the default coalescing policy.</summary>
+	pub fn new(text : ImmutableText) -> History {
+		History::with_policy(text, CoalescePolicy::new())
+	}
+
+	pub fn with_policy(text : ImmutableText, policy : CoalescePolicy) -> History {
+		let mut census = NodeCensus::default();
+		census.add(&text);
+		let initial = Revision { parent : None, children : Vec::new(), redo : None, text : Some(text), delta : Some(Delta::new()), kind : EditKind::Other, caret : None, typed : 0 };
+		History { revisions : vec![initial], current : 0, policy, open : false, budget : None, kept : BTreeSet::from([0]), census, delta_bytes : 0 }
+	}
+
+	/// <summary>
+	/// Sets the number of bytes the revisions kept by the history may retain together,
+	/// past which the oldest ones are evicted after each new step.
+	/// </summary>
+	pub fn with_budget(mut self, bytes : usize, eviction : Eviction) -> History {
+		self.budget = Some((bytes, eviction));
+		self.enforce_budget();
+		self
+	}
+
+	/// <summary>
+	/// Returns the heap bytes of the nodes of the revisions the history keeps, the
+	/// current one included, and of the deltas kept to rebuild the others. Revisions share
+	/// most of their nodes, and a node is counted once however many revisions reach it,
+	/// so this is what the history actually retains.
+	/// </summary>
+	pub fn retained_bytes(&self) -> usize {
+		self.census.bytes() + self.delta_bytes
+	}
+
+	/// <summary>Evicts the oldest revisions until the history fits its budget, or only the current one is left.</summary>
+	fn enforce_budget(&mut self) {
+		let Some((budget, eviction)) = self.budget else {
+			return;
+		};
+		while self.retained_bytes() > budget {
+			let mut candidates = self.kept.iter().copied().filter(|&i| i != self.current);
+			let oldest = match eviction {
+				Eviction::Squash => candidates.clone().find(|&i| i != 0).or_else(|| candidates.next()),
+				Eviction::Drop => candidates.next()
+			};
+			match oldest {
+				Some(i) => self.evict(i),
+				None => break
+			}
+		}
+	}
+
+	/// <summary>
+	/// Drops the text of the revision and composes its delta into the deltas of its
+	/// children, so that they can still be rebuilt from its ancestors while it cannot.
+	/// </summary>
+	fn evict(&mut self, revision : usize) {
+		self.kept.remove(&revision);
+		if let Some(text) = self.revisions[revision].text.take() {
+			self.census.remove(&text);
+		}
+		let Some(delta) = self.revisions[revision].delta.take() else {
+			return;
+		};
+		self.delta_bytes -= delta.heap_bytes();
+		if self.revisions[revision].parent.is_none() {
+			return; // Nothing is left to rebuild its children from.
+		}
+		for child in self.revisions[revision].children.clone() {
+			if let Some(after) = self.revisions[child].delta.as_mut() {
+				let composed = delta.compose(after);
+				self.delta_bytes = self.delta_bytes - after.heap_bytes() + composed.heap_bytes();
+				*after = composed;
+			}
+		}
+	}
+
+	/// <summary>Returns the current version of the text.</summary>
+	pub fn text(&self) -> &ImmutableText {
+		self.revisions[self.current].text.as_ref().unwrap()
+	}
+
+	pub fn policy(&self) -> &CoalescePolicy {
+		&self.policy
+	}
+
+	/// <summary>Applies the delta to the current text and records it, merged into the current revision if the policy allows.</summary>
+	/// <returns>the new current text.</returns>
+	pub fn apply(&mut self, delta : &Delta, kind : EditKind) -> &ImmutableText {
+		if delta.is_empty() {
+			return self.text();
+		}
+		let after = delta.apply(self.text());
+		let typed = match delta.edits() {
+			[edit] if edit.removed == 0 && edit.inserted.chars().count() == 1 => Some(edit.start),
+			_ => None
+		};
+		let id = self.revisions.len();
+		let last = &mut self.revisions[self.current];
+		// Only a leaf of the tree may be extended: the revisions after it would not follow.
+		let merged = self.open && last.parent.is_some() && last.children.is_empty() && last.kind == kind && match kind {
+			EditKind::Typing => typed.is_some() && typed == last.caret && self.policy.merge_typing && last.typed < self.policy.max_typing_run,
+			EditKind::Composition => self.policy.merge_composition,
+			EditKind::Other => false
+		};
+		self.census.add(&after);
+		if merged {
+			if let Some(before) = last.text.replace(after) {
+				self.census.remove(&before);
+			}
+			last.caret = typed.map(|start| start + 1);
+			last.typed += 1;
+			if let Some(last_delta) = last.delta.as_mut() {
+				let composed = last_delta.compose(delta);
+				self.delta_bytes = self.delta_bytes - last_delta.heap_bytes() + composed.heap_bytes();
+				*last_delta = composed;
+			}
+		} else {
+			last.children.push(id);
+			last.redo = Some(id);
+			self.delta_bytes += delta.heap_bytes();
+			self.revisions.push(Revision { parent : Some(self.current), children : Vec::new(), redo : None, text : Some(after), delta : Some(delta.clone()), kind, caret : typed.map(|start| start + 1), typed : 1 });
+			self.kept.insert(id);
+			self.current = id;
+			self.enforce_budget();
+		}
+		self.open = true;
+		self.text()
+	}
+
+	/// <summary>
+	/// Ends the current step, so that the next edit starts a new one whatever its kind,
+	/// for example when the caret moves or a composition is committed.
+	/// </summary>
+	pub fn seal(&mut self) {
+		self.open = false;
+	}
+
+	/// <summary>Returns the closest ancestor of the current revision that was not evicted.</summary>
+	fn undo_target(&self) -> Option<usize> {
+		let mut revision = self.revisions[self.current].parent;
+		while let Some(i) = revision {
+			if self.revisions[i].text.is_some() {
+				return Some(i);
+			}
+			revision = self.revisions[i].parent;
+		}
+		None
+	}
+
+	/// <summary>Returns the revision redo moves to: the first one not evicted along the redo children.</summary>
+	fn redo_target(&self) -> Option<usize> {
+		let mut revision = self.revisions[self.current].redo;
+		while let Some(i) = revision {
+			if self.revisions[i].text.is_some() {
+				return Some(i);
+			}
+			revision = self.revisions[i].redo;
+		}
+		None
+	}
+
+	pub fn can_undo(&self) -> bool {
+		self.undo_target().is_some()
+	}
+
+	pub fn can_redo(&self) -> bool {
+		self.redo_target().is_some()
+	}
+
+	/// <summary>Moves to the parent of the current revision, or to its closest ancestor not evicted.</summary>
+	/// <returns>the new current text, or None if there is nothing to undo.</returns>
+	pub fn undo(&mut self) -> Option<&ImmutableText> {
+		let target = self.undo_target()?;
+		self.jump_to(target)
+	}
+
+	/// <summary>Moves to the child of the current revision that was last created or undone.</summary>
+	/// <returns>the new current text, or None if there is nothing to redo.</returns>
+	pub fn redo(&mut self) -> Option<&ImmutableText> {
+		let target = self.redo_target()?;
+		self.jump_to(target)
+	}
+
+	/// <summary>
+	/// Restores the given revision, on any branch. The ancestors of the revision will
+	/// redo towards it.
+	/// </summary>
+	/// <returns>its text, or None if there is no such revision or it was evicted.</returns>
+	pub fn jump_to(&mut self, revision : usize) -> Option<&ImmutableText> {
+		self.revisions.get(revision)?.text.as_ref()?;
+		let mut child = revision;
+		while let Some(parent) = self.revisions[child].parent {
+			self.revisions[parent].redo = Some(child);
+			child = parent;
+		}
+		self.current = revision;
+		self.open = false;
+		Some(self.text())
+	}
+
+	/// <summary>Returns the number of the current revision.</summary>
+	pub fn current_revision(&self) -> usize {
+		self.current
+	}
+
+	/// <summary>Returns the number of revisions created, evicted ones included.</summary>
+	pub fn revision_count(&self) -> usize {
+		self.revisions.len()
+	}
+
+	/// <summary>Returns the revision the given one was edited from, None for the initial revision.</summary>
+	pub fn parent(&self, revision : usize) -> Option<usize> {
+		self.revisions[revision].parent
+	}
+
+	/// <summary>Returns the branches starting at the given revision, the revisions edited from it, oldest first.</summary>
+	pub fn children(&self, revision : usize) -> &[usize] {
+		&self.revisions[revision].children
+	}
+
+	/// <summary>Returns the revisions no other was edited from, the tips of the branches, oldest first.</summary>
+	pub fn leaves(&self) -> Vec<usize> {
+		(0..self.revisions.len()).filter(|&i| self.revisions[i].children.is_empty()).collect()
+	}
+
+	/// <summary>
+	/// Returns the text of the revision, as kept or, for a revision edited from evicted
+	/// ones, rebuilt from its closest ancestor still kept by applying the deltas since,
+	/// without moving to it. The first revision is evicted last, so with
+	/// <code>Eviction::Squash</code> every revision not evicted itself can be rebuilt until it is.
+	/// </summary>
+	/// <returns>the text, or None if there is no such revision, it was evicted, or there is no ancestor to rebuild it from.</returns>
+	pub fn text_at(&self, revision : usize) -> Option<ImmutableText> {
+		self.revisions.get(revision)?.delta.as_ref()?;
+		let mut path = Vec::new();
+		let mut ancestor = revision;
+		let mut text = loop {
+			let node = self.revisions.get(ancestor)?;
+			if let Some(text) = &node.text {
+				break text.clone();
+			}
+			path.push(ancestor);
+			ancestor = node.parent?;
+		};
+		// The deltas of the evicted ancestors are composed into those of their children.
+		for delta in path.iter().rev().filter_map(|&i| self.revisions[i].delta.as_ref()) {
+			text = delta.apply(&text);
+		}
+		Some(text)
+	}
+
+	/// <summary>Returns true if the text of the revision is still kept, so that it can be restored.</summary>
+	pub fn is_available(&self, revision : usize) -> bool {
+		self.revisions.get(revision).is_some_and(|revision| revision.text.is_some())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	fn replace(start : usize, removed : usize, inserted : &str) -> Delta {
+		let mut delta = Delta::new();
+		delta.push(start, removed, inserted);
+		delta
+	}
+
+	/// <summary>Returns a history of ten steps, each replacing one line of a text large enough to span several leaves.</summary>
+	fn edited(eviction : Option<Eviction>) -> (History, Vec<String>) {
+		let initial : String = (0..200).map(|i| format!("line {}\r\n", i)).collect();
+		let mut history = History::new(create_immutable_text(&initial));
+		let mut versions = vec![initial];
+		for step in 0..10 {
+			let start = step * 97;
+			history.apply(&replace(start, 4, &format!("edit {}", step)), EditKind::Other);
+			versions.push(history.text().to_string());
+		}
+		if let Some(eviction) = eviction {
+			// Just below what the history retains, so that one revision has to go.
+			let budget = history.retained_bytes() - 1;
+			history = history.with_budget(budget, eviction);
+		}
+		(history, versions)
+	}
+
+	#[test]
+	fn squash_keeps_the_first_revision_and_rebuilds_the_others() {
+		let (history, versions) = edited(Some(Eviction::Squash));
+		assert!(history.is_available(0));
+		assert!(!history.is_available(1));
+		assert!(history.is_available(10));
+		let evicted : Vec<usize> = (1..10).filter(|&i| !history.is_available(i)).collect();
+		for (i, version) in versions.iter().enumerate() {
+			match history.text_at(i) {
+				Some(text) => assert_eq!(&text.to_string(), version),
+				None => assert!(evicted.contains(&i))
+			}
+		}
+	}
+
+	#[test]
+	fn drop_evicts_the_first_revision_first() {
+		let (history, versions) = edited(Some(Eviction::Drop));
+		assert!(!history.is_available(0));
+		assert_eq!(history.text_at(0), None);
+		assert_eq!(history.text_at(10).unwrap().to_string(), versions[10]);
+	}
+
+	#[test]
+	fn retained_bytes_follow_evictions() {
+		let (unbounded, _) = edited(None);
+		let (history, _) = edited(Some(Eviction::Squash));
+		assert!(history.retained_bytes() < unbounded.retained_bytes());
+		// The deltas composed into the children are counted: a budget of nothing keeps only the current text.
+		let (history, versions) = edited(None);
+		let history = history.with_budget(0, Eviction::Squash);
+		assert_eq!((0..=10).filter(|&i| history.is_available(i)).collect::<Vec<_>>(), vec![10]);
+		assert_eq!(history.text().to_string(), versions[10]);
+		assert!(!history.can_undo());
+	}
+
+	#[test]
+	fn empty_history_fits_any_budget() {
+		let history = History::new(create_immutable_text("")).with_budget(0, Eviction::Drop);
+		assert_eq!(history.retained_bytes(), 0);
+		assert_eq!(history.text().length(), 0);
+	}
+
+	/// <summary>Types the characters one at a time from the offset, as a user would.</summary>
+	fn type_at(history : &mut History, start : usize, typed : &str) {
+		for (i, c) in typed.chars().enumerate() {
+			history.apply(&replace(start + i, 0, &c.to_string()), EditKind::Typing);
+		}
+	}
+
+	#[test]
+	fn typing_merges_into_one_step() {
+		let mut history = History::new(create_immutable_text("ab\r\n"));
+		type_at(&mut history, 2, "typed\r\né");
+		assert_eq!(history.text().to_string(), "abtyped\r\né\r\n");
+		assert_eq!(history.revision_count(), 2);
+		assert_eq!(history.undo().unwrap().to_string(), "ab\r\n");
+		assert!(!history.can_undo());
+		assert_eq!(history.redo().unwrap().to_string(), "abtyped\r\né\r\n");
+		// An empty delta records nothing.
+		history.apply(&Delta::new(), EditKind::Other);
+		assert_eq!(history.revision_count(), 2);
+	}
+
+	#[test]
+	fn typing_runs_break_on_moves_seals_and_their_length() {
+		let policy = CoalescePolicy::new().with_max_typing_run(3);
+		let mut history = History::with_policy(create_immutable_text(""), policy);
+		type_at(&mut history, 0, "abcde");
+		// Runs of three: "abc" and "de".
+		assert_eq!(history.revision_count(), 3);
+		// Typing away from the caret starts a step.
+		type_at(&mut history, 0, "x");
+		assert_eq!(history.revision_count(), 4);
+		history.seal();
+		type_at(&mut history, 1, "y");
+		assert_eq!(history.revision_count(), 5);
+		let undone : Vec<String> = (0..4).map(|_| history.undo().unwrap().to_string()).collect();
+		assert_eq!(undone, vec!["xabcde", "abcde", "abc", ""]);
+	}
+
+	#[test]
+	fn only_typing_and_compositions_merge() {
+		let mut history = History::with_policy(create_immutable_text("text"), CoalescePolicy::new().with_merge_typing(false));
+		type_at(&mut history, 4, "ab");
+		assert_eq!(history.revision_count(), 3);
+		// Each update replaces the text composed so far.
+		let mut previous = 0;
+		for composed in ["k", "ka", "か"] {
+			history.apply(&replace(6, previous, composed), EditKind::Composition);
+			previous = composed.chars().count();
+		}
+		assert_eq!(history.text().to_string(), "textabか");
+		assert_eq!(history.revision_count(), 4);
+		// A typed character after a composition is a step of its own, and so is any other edit.
+		type_at(&mut history, 7, "!");
+		history.apply(&replace(0, 1, "T"), EditKind::Other);
+		history.apply(&replace(1, 1, "E"), EditKind::Other);
+		assert_eq!(history.revision_count(), 7);
+		assert_eq!(history.undo().unwrap().to_string(), "Textabか!");
+	}
+
+	#[test]
+	fn edits_after_undo_start_a_branch() {
+		let mut history = History::new(create_immutable_text("base"));
+		type_at(&mut history, 4, " one");
+		history.undo();
+		type_at(&mut history, 4, " two");
+		assert_eq!(history.leaves(), vec![1, 2]);
+		assert_eq!(history.children(0), &[1, 2]);
+		assert_eq!(history.parent(2), Some(0));
+		// The undone branch is not extended, and redo follows the branch last taken.
+		history.undo();
+		assert_eq!(history.redo().unwrap().to_string(), "base two");
+		assert_eq!(history.jump_to(1).unwrap().to_string(), "base one");
+		history.undo();
+		assert_eq!(history.redo().unwrap().to_string(), "base one");
+		assert!(history.jump_to(3).is_none());
+	}
+}
@@ -0,0 +1,145 @@
+use super::ImmutableText;
+
+/// <summary>
+/// A search as you type: the occurrences of the needle are kept for every prefix of
+/// it, so that typing a character only checks the character following each previous
+/// occurrence, and deleting one goes back to the occurrences already known, instead
+/// of the document being scanned again on every keystroke.
+/// </summary>
+pub struct IncrementalSearch {
+	text : ImmutableText,
+	needle : Vec<char>,
+	/// <summary>The starts of the occurrences of every prefix of the needle, longest last.</summary>
+	levels : Vec<Vec<usize>>
+}
+
+impl IncrementalSearch {
+	pub fn new(text : &ImmutableText) -> IncrementalSearch {
+		IncrementalSearch { text : text.clone(), needle : Vec::new(), levels : Vec::new() }
+	}
+
+	/// <summary>Appends a character to the needle, narrowing the occurrences.</summary>
+	pub fn push(&mut self, c : char) {
+		let starts = match self.levels.last() {
+			Some(starts) => {
+				let length = self.needle.len();
+				starts.iter().copied().filter(|&start| self.text.get(start + length) == Some(c)).collect()
+			}
+			None => {
+				let mut starts = Vec::new();
+				let mut offset = 0;
+				while let Some(start) = self.text.find_char(c, offset) {
+					starts.push(start);
+					offset = start + 1;
+				}
+				starts
+			}
+		};
+		self.needle.push(c);
+		self.levels.push(starts);
+	}
+
+	/// <summary>Removes the last character of the needle, going back to its previous occurrences.</summary>
+	pub fn pop(&mut self) -> Option<char> {
+		self.levels.pop();
+		self.needle.pop()
+	}
+
+	pub fn needle(&self) -> String {
+		self.needle.iter().collect()
+	}
+
+	/// <summary>Returns the starts of all the occurrences of the needle, overlapping ones included, in order.</summary>
+	pub fn matches(&self) -> &[usize] {
+		match self.levels.last() {
+			Some(starts) => starts,
+			None => &[]
+		}
+	}
+
+	/// <summary>Returns the start of the first occurrence at or after <code>offset</code>, wrapping around to the first one.</summary>
+	pub fn next_match(&self, offset : usize) -> Option<usize> {
+		let matches = self.matches();
+		let index = matches.partition_point(|&start| start < offset);
+		matches.get(index).or(matches.first()).copied()
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Returns a search as you type over this text, with an empty needle.</summary>
+	pub fn incremental_search(&self) -> IncrementalSearch {
+		IncrementalSearch::new(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn narrows_and_widens_as_the_needle_changes() {
+		let text = create_immutable_text("abc abd abc xbc");
+		let mut search = text.incremental_search();
+		assert!(search.matches().is_empty());
+		assert_eq!(search.pop(), None);
+		search.push('a');
+		assert_eq!(search.matches(), &[0, 4, 8]);
+		search.push('b');
+		assert_eq!(search.matches(), &[0, 4, 8]);
+		search.push('c');
+		assert_eq!(search.matches(), &[0, 8]);
+		assert_eq!(search.needle(), "abc");
+		assert_eq!(search.pop(), Some('c'));
+		search.push('d');
+		assert_eq!(search.matches(), &[4]);
+		search.push('!');
+		assert!(search.matches().is_empty());
+		assert_eq!(search.pop(), Some('!'));
+		assert_eq!(search.pop(), Some('d'));
+		assert_eq!(search.pop(), Some('b'));
+		assert_eq!(search.matches(), &[0, 4, 8]);
+		assert_eq!(search.pop(), Some('a'));
+		assert!(search.matches().is_empty());
+		assert_eq!(search.needle(), "");
+	}
+
+	#[test]
+	fn finds_overlapping_matches() {
+		let text = create_immutable_text("aaaa");
+		let mut search = text.incremental_search();
+		search.push('a');
+		search.push('a');
+		assert_eq!(search.matches(), &[0, 1, 2]);
+		search.push('a');
+		assert_eq!(search.matches(), &[0, 1]);
+		search.push('a');
+		search.push('a');
+		assert!(search.matches().is_empty());
+	}
+
+	#[test]
+	fn finds_the_next_match_wrapping_around() {
+		let text = create_immutable_text("ab ab ab");
+		let mut search = text.incremental_search();
+		assert_eq!(search.next_match(0), None);
+		search.push('a');
+		search.push('b');
+		assert_eq!(search.next_match(0), Some(0));
+		assert_eq!(search.next_match(1), Some(3));
+		assert_eq!(search.next_match(3), Some(3));
+		assert_eq!(search.next_match(7), Some(0));
+		assert_eq!(search.next_match(100), Some(0));
+	}
+
+	#[test]
+	fn searches_across_leaves() {
+		let source = "needle in a haystack, ".repeat(10);
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let mut search = text.incremental_search();
+		for c in "hay".chars() {
+			search.push(c);
+		}
+		let expected : Vec<usize> = source.match_indices("hay").map(|(start, _)| start).collect();
+		assert_eq!(search.matches(), &expected[..]);
+	}
+}
@@ -0,0 +1,255 @@
+use std::ops::Range;
+
+use super::{Delta, ImmutableText};
+
+/// <summary>Whether indentation is made of tabs or of spaces.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentStyle {
+	Tabs,
+	Spaces
+}
+
+/// <summary>The indentation detected in a text, with a confidence between 0 and 1.</summary>
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Indentation {
+	pub style : IndentStyle,
+	pub width : usize,
+	pub confidence : f32
+}
+
+/// <summary>Leading whitespace of one line.</summary>
+#[derive(Clone, Copy, Default)]
+struct LineIndent {
+	tabs : usize,
+	spaces : usize,
+	blank : bool
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Guesses the indentation of the text from the leading whitespace of its lines:
+	/// the style is the one most lines start with, the width the most common
+	/// difference in leading spaces between consecutive non-blank lines.
+	/// </summary>
+	/// <returns>the detected indentation; four spaces with zero confidence if no line is indented.</returns>
+	pub fn detect_indentation(&self) -> Indentation {
+		let mut tab_lines = 0;
+		let mut space_lines = 0;
+		let mut deltas = [0usize; 9];
+		let mut previous_spaces = 0;
+		self.for_each_line_indent(|indent| {
+			if indent.blank {
+				return;
+			}
+			if indent.tabs > 0 {
+				tab_lines += 1;
+			} else if indent.spaces > 0 {
+				space_lines += 1;
+			}
+			if indent.tabs == 0 {
+				let delta = indent.spaces.abs_diff(previous_spaces);
+				if (2..deltas.len()).contains(&delta) {
+					deltas[delta] += 1;
+				}
+				previous_spaces = indent.spaces;
+			}
+		});
+
+		let width_votes : usize = deltas.iter().sum();
+		let (width, votes) = deltas.iter().enumerate().rev().max_by_key(|&(_, &votes)| votes).map(|(width, &votes)| (width, votes)).unwrap_or((4, 0));
+		let (width, width_confidence) = if width_votes == 0 { (4, 0.0) } else { (width, votes as f32 / width_votes as f32) };
+		let indented = tab_lines + space_lines;
+		if indented == 0 {
+			return Indentation { style : IndentStyle::Spaces, width : 4, confidence : 0.0 };
+		}
+		if tab_lines > space_lines {
+			return Indentation { style : IndentStyle::Tabs, width, confidence : tab_lines as f32 / indented as f32 };
+		}
+		Indentation { style : IndentStyle::Spaces, width, confidence : space_lines as f32 / indented as f32 * width_confidence }
+	}
+
+	/// <summary>Calls the function with the leading whitespace of every line, in one pass over the text.</summary>
+	fn for_each_line_indent<F>(&self, mut f : F) where F : FnMut(LineIndent) {
+		let mut indent = LineIndent { blank : true, ..LineIndent::default() };
+		let mut leading = true;
+		for c in self.chars() {
+			if c == '\n' {
+				f(indent);
+				indent = LineIndent { blank : true, ..LineIndent::default() };
+				leading = true;
+			} else if leading && c == '\t' {
+				indent.tabs += 1;
+			} else if leading && c == ' ' {
+				indent.spaces += 1;
+			} else if c != '\r' {
+				leading = false;
+				indent.blank = false;
+			}
+		}
+		f(indent);
+	}
+
+	/// <summary>Prefixes every non-empty line of the range with the indentation unit.</summary>
+	/// <returns>the new text and the delta from this text to it.</returns>
+	pub fn indent_lines(&self, lines : Range<usize>, unit : &str) -> (ImmutableText, Delta) {
+		let mut delta = Delta::new();
+		for line in lines {
+			let start = self.line_start(line);
+			if self.line_end(line) > start {
+				delta.push(start, 0, unit);
+			}
+		}
+		(delta.apply(self), delta)
+	}
+
+	/// <summary>
+	/// Removes one level of indentation from every line of the range: the unit itself,
+	/// else a leading tab, else as many leading spaces as the unit has characters.
+	/// </summary>
+	/// <returns>the new text and the delta from this text to it.</returns>
+	pub fn dedent_lines(&self, lines : Range<usize>, unit : &str) -> (ImmutableText, Delta) {
+		let unit : Vec<char> = unit.chars().collect();
+		let mut delta = Delta::new();
+		for line in lines {
+			let start = self.line_start(line);
+			let end = self.line_end(line);
+			let prefix : Vec<char> = self.chars_range(start, end).take(unit.len()).collect();
+			let removed = if prefix == unit {
+				unit.len()
+			} else if prefix.first() == Some(&'\t') {
+				1
+			} else {
+				prefix.iter().take_while(|&&c| c == ' ').count()
+			};
+			delta.push(start, removed, "");
+		}
+		(delta.apply(self), delta)
+	}
+
+	/// <summary>Returns the tabs and spaces a line starts with.</summary>
+	pub fn leading_whitespace(&self, line : usize) -> String {
+		let start = self.line_start(line);
+		self.chars_range(start, self.line_end(line)).take_while(|&c| c == ' ' || c == '\t').collect()
+	}
+
+	/// <summary>
+	/// Returns the indentation for a new line typed after the given one: the leading
+	/// whitespace of that line, or of the nearest non-blank line above it if it is
+	/// blank, so that auto-indent keeps the level across empty lines.
+	/// </summary>
+	pub fn indent_for_line(&self, line : usize) -> String {
+		for line in (0..=line).rev() {
+			let start = self.line_start(line);
+			if self.chars_range(start, self.line_end(line)).any(|c| !c.is_whitespace()) {
+				return self.leading_whitespace(line);
+			}
+		}
+		String::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	#[test]
+	fn detects_tabs() {
+		let indentation = create_immutable_text("fn a() {\n\tb();\n\tif c {\n\t\td();\n\t}\n}\n").detect_indentation();
+		assert_eq!(indentation, Indentation { style : IndentStyle::Tabs, width : 4, confidence : 1.0 });
+	}
+
+	#[test]
+	fn detects_the_width_of_spaces() {
+		let two = create_immutable_text("a:\n  b:\n    c\n  d\ne\n").detect_indentation();
+		assert_eq!(two, Indentation { style : IndentStyle::Spaces, width : 2, confidence : 1.0 });
+		let four = create_immutable_text("a:\r\n    b:\r\n        c\r\n\r\n    d\r\ne\r\n").detect_indentation();
+		assert_eq!(four, Indentation { style : IndentStyle::Spaces, width : 4, confidence : 1.0 });
+		// Alignment spaces lower the confidence without changing the width.
+		let aligned = create_immutable_text("a\n    b\n        c\n    d\ne\n  f\n").detect_indentation();
+		assert_eq!(aligned, Indentation { style : IndentStyle::Spaces, width : 4, confidence : 0.8 });
+		// Ties go to the smaller width.
+		let tied = create_immutable_text("a\n  b\nc\n    d\n").detect_indentation();
+		assert_eq!(tied.width, 2);
+	}
+
+	#[test]
+	fn detects_the_majority_of_mixed_indentation() {
+		let tabs = create_immutable_text("a\n\tb\n\tc\n\td\n    e\n").detect_indentation();
+		assert_eq!(tabs.style, IndentStyle::Tabs);
+		assert_eq!(tabs.confidence, 0.75);
+		let spaces = create_immutable_text("a\n\tb\nc\n  d\n  e\n").detect_indentation();
+		assert_eq!(spaces.style, IndentStyle::Spaces);
+		assert_eq!(spaces.width, 2);
+		assert!(spaces.confidence > 0.5 && spaces.confidence < 1.0);
+	}
+
+	#[test]
+	fn detects_nothing_without_indented_lines() {
+		let none = Indentation { style : IndentStyle::Spaces, width : 4, confidence : 0.0 };
+		assert_eq!(create_immutable_text("").detect_indentation(), none);
+		assert_eq!(create_immutable_text("\n  \n\t\n \t \n").detect_indentation(), none);
+		assert_eq!(create_immutable_text("a\nb\nc").detect_indentation(), none);
+	}
+
+	#[test]
+	fn indents_non_empty_lines() {
+		let text = create_immutable_text("a\n\n  b\nc");
+		let (indented, delta) = text.indent_lines(0..3, "\t");
+		assert_eq!(indented.to_string(), "\ta\n\n\t  b\nc");
+		assert_eq!(delta.apply(&text).to_string(), indented.to_string());
+		assert_eq!(text.indent_lines(3..4, "    ").0.to_string(), "a\n\n  b\n    c");
+		assert!(text.indent_lines(1..2, "\t").1.is_empty());
+	}
+
+	#[test]
+	fn dedents_one_level() {
+		let text = create_immutable_text("    a\n\tb\n      c\n\t    d\n e\nf\n");
+		let (dedented, delta) = text.dedent_lines(0..7, "    ");
+		assert_eq!(dedented.to_string(), "a\nb\n  c\n    d\ne\nf\n");
+		assert_eq!(delta.apply(&text).to_string(), dedented.to_string());
+		let (dedented, _) = text.dedent_lines(0..4, "\t");
+		assert_eq!(dedented.to_string(), "   a\nb\n     c\n    d\n e\nf\n");
+	}
+
+	#[test]
+	fn dedents_lines_shorter_than_the_indent() {
+		let text = create_immutable_text("  \n x\n\n  \t\n   ");
+		let (dedented, _) = text.dedent_lines(0..5, "    ");
+		assert_eq!(dedented.to_string(), "\nx\n\n\t\n");
+		assert_eq!(create_immutable_text("ab").dedent_lines(0..1, "    ").0.to_string(), "ab");
+	}
+
+	#[test]
+	fn indenting_shares_the_other_lines() {
+		let text = TextConfig::new().with_block_size(16).create(&(0..100).map(|i| format!("line {}\n", i)).collect::<String>());
+		let (indented, _) = text.indent_lines(50..52, "\t");
+		let (dedented, _) = indented.dedent_lines(50..52, "\t");
+		assert_eq!(dedented.to_string(), text.to_string());
+		let usage = indented.memory_usage();
+		assert!(usage.shared_bytes > 5 * usage.owned_bytes, "{:?}", usage);
+	}
+
+	#[test]
+	fn returns_the_leading_whitespace_of_a_line() {
+		let text = create_immutable_text("a\n \t b\n\t\t\n  \r\n");
+		assert_eq!(text.leading_whitespace(0), "");
+		assert_eq!(text.leading_whitespace(1), " \t ");
+		assert_eq!(text.leading_whitespace(2), "\t\t");
+		assert_eq!(text.leading_whitespace(3), "  ");
+		assert_eq!(text.leading_whitespace(4), "");
+	}
+
+	#[test]
+	fn indents_new_lines_like_the_nearest_non_blank_line() {
+		let text = create_immutable_text("\n\nfn a() {\n\tif b {\n\t\tc();\n\n   \n\t}\n");
+		assert_eq!(text.indent_for_line(0), "");
+		assert_eq!(text.indent_for_line(1), "");
+		assert_eq!(text.indent_for_line(3), "\t");
+		assert_eq!(text.indent_for_line(4), "\t\t");
+		assert_eq!(text.indent_for_line(5), "\t\t");
+		assert_eq!(text.indent_for_line(6), "\t\t");
+		assert_eq!(text.indent_for_line(7), "\t");
+		assert_eq!(text.indent_for_line(8), "\t");
+	}
+}
@@ -0,0 +1,112 @@
+use std::str;
+
+use super::metrics::LineMetrics;
+use super::{create_leaf_node, Node, Shared, TextConfig};
+
+/// <summary>Holds the number of UTF-8 bytes a text can hold without a heap allocation.</summary>
+pub(crate) const INLINE_CAPACITY : usize = 23;
+
+/// <summary>
+/// A short text stored in the <code>ImmutableText</code> value itself, as UTF-8.
+/// Splitting and slicing create huge numbers of short strings; keeping them inline
+/// saves both the allocation and the reference counting.
+/// </summary>
+#[derive(Clone, Default)]
+pub(crate) struct InlineLeaf {
+	length : u8,
+	byte_length : u8,
+	bytes : [u8; INLINE_CAPACITY],
+	metrics : LineMetrics
+}
+
+impl InlineLeaf {
+	/// <summary>Returns the characters stored inline, or None if they do not fit.</summary>
+	pub(crate) fn new(data : &[char]) -> Option<InlineLeaf> {
+		let mut leaf = InlineLeaf::default();
+		for &c in data {
+			let end = leaf.byte_length as usize + c.len_utf8();
+			if end > INLINE_CAPACITY {
+				return None;
+			}
+			c.encode_utf8(&mut leaf.bytes[leaf.byte_length as usize..end]);
+			leaf.byte_length = end as u8;
+		}
+		leaf.length = data.len() as u8;
+		leaf.metrics = LineMetrics::of_chars(data);
+		Some(leaf)
+	}
+
+	pub(crate) fn of_str(text : &str) -> Option<InlineLeaf> {
+		if text.len() > INLINE_CAPACITY {
+			return None;
+		}
+		let mut data = ['\0'; INLINE_CAPACITY];
+		let mut length = 0;
+		for c in text.chars() {
+			data[length] = c;
+			length += 1;
+		}
+		InlineLeaf::new(&data[..length])
+	}
+
+	/// <summary>Returns the concatenated content of the given nodes stored inline, or None if it does not fit.</summary>
+	pub(crate) fn of_nodes(head : &dyn Node, tail : &dyn Node) -> Option<InlineLeaf> {
+		let length = head.length() + tail.length();
+		if length > INLINE_CAPACITY {
+			return None;
+		}
+		let mut data = ['\0'; INLINE_CAPACITY];
+		head.copy_to(0, &mut data, 0, head.length());
+		tail.copy_to(0, &mut data, head.length(), tail.length());
+		InlineLeaf::new(&data[..length])
+	}
+
+	fn as_str(&self) -> &str {
+		// Only whole characters are ever encoded into the buffer.
+		unsafe { str::from_utf8_unchecked(&self.bytes[..self.byte_length as usize]) }
+	}
+}
+
+impl Node for InlineLeaf {
+	fn length(&self) -> usize {
+		self.length as usize
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		create_leaf_node(self.as_str().chars().skip(start).take(end - start).collect(), config)
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		match self.as_str().chars().nth(offset) {
+			Some(c) => c,
+			None => panic!("index out of range: {} (length {})", offset, self.length)
+		}
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		let target = &mut destination[destination_index..destination_index + count];
+		for (target, c) in target.iter_mut().zip(self.as_str().chars().skip(source_index)) {
+			*target = c;
+		}
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		self.as_str().chars().skip(start).position(|x| x == c).map(|i| start + i)
+	}
+
+	fn as_utf8(&self) -> Option<&str> {
+		Some(self.as_str())
+	}
+
+	/// <summary>Returns 0: the characters live in the text value, not on the heap.</summary>
+	fn allocated_bytes(&self) -> usize {
+		0
+	}
+}
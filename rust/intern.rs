@@ -0,0 +1,147 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use super::{balanced_node, collect_leaves, ImmutableText, LeafChars, Node, Shared, Weak};
+
+/// <summary>Holds the number of entries below which the interner is not purged as it grows.</summary>
+const PURGE_THRESHOLD : usize = 64;
+
+/// <summary>Returns the hash of the characters of the leaf.</summary>
+fn content_hash(leaf : &dyn Node) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	leaf.length().hash(&mut hasher);
+	for c in LeafChars::new(leaf, 0, leaf.length()) {
+		c.hash(&mut hasher);
+	}
+	hasher.finish()
+}
+
+/// <summary>Returns true if the leaves hold the same characters.</summary>
+fn same_content(leaf : &dyn Node, other : &dyn Node) -> bool {
+	leaf.length() == other.length() && LeafChars::new(leaf, 0, leaf.length()).eq(LeafChars::new(other, 0, other.length()))
+}
+
+/// <summary>
+/// A content-addressed cache of leaves. Texts passed through the same interner
+/// share their identical blocks (blank lines, license headers, indentation runs),
+/// which saves memory when many similar documents are held at once.
+/// </summary>
+/// <remarks>
+/// The interner only holds weak references: it never keeps a leaf alive. The entries
+/// of dropped leaves are purged as it grows, whenever their number has doubled.
+/// </remarks>
+#[derive(Default)]
+pub struct LeafInterner {
+	/// <summary>The leaves by the hash of their characters, those whose hashes collide in the same bucket.</summary>
+	leaves : HashMap<u64, Vec<Weak<dyn Node>>>,
+	len : usize,
+	/// <summary>The number of entries left by the last purge.</summary>
+	purged_len : usize
+}
+
+impl LeafInterner {
+	pub fn new() -> LeafInterner {
+		LeafInterner::default()
+	}
+
+	/// <summary>
+	/// Returns the same text with every leaf replaced by an identical leaf already
+	/// interned, if any, over a tree of minimal depth. New leaves are interned.
+	/// </summary>
+	pub fn intern(&mut self, text : &ImmutableText) -> ImmutableText {
+		let leaves : Vec<Shared<dyn Node>> = collect_leaves(&text.root.to_node()).into_iter().map(|leaf| self.intern_leaf(leaf)).collect();
+		text.with_root(balanced_node(&leaves))
+	}
+
+	fn intern_leaf(&mut self, leaf : Shared<dyn Node>) -> Shared<dyn Node> {
+		let bucket = self.leaves.entry(content_hash(&*leaf)).or_default();
+		if let Some(interned) = bucket.iter().filter_map(Weak::upgrade).find(|interned| same_content(&**interned, &*leaf)) {
+			return interned;
+		}
+		let before = bucket.len();
+		bucket.retain(|interned| interned.strong_count() > 0);
+		bucket.push(Shared::downgrade(&leaf));
+		self.len = self.len + bucket.len() - before;
+		if self.len >= 2 * self.purged_len.max(PURGE_THRESHOLD) {
+			self.purge();
+		}
+		leaf
+	}
+
+	/// <summary>Returns the number of entries, including those whose leaf has been dropped since the last purge.</summary>
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// <summary>Forgets the entries whose leaf is no longer used by any text.</summary>
+	pub fn purge(&mut self) {
+		self.leaves.retain(|_, bucket| {
+			bucket.retain(|leaf| leaf.strong_count() > 0);
+			!bucket.is_empty()
+		});
+		self.len = self.leaves.values().map(Vec::len).sum();
+		self.purged_len = self.len;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::TextConfig;
+	use super::*;
+
+	/// <summary>Returns a text of 16-character leaves, one per block.</summary>
+	fn blocks(blocks : &[&str]) -> ImmutableText {
+		assert!(blocks.iter().all(|block| block.chars().count() == 16));
+		TextConfig::new().with_block_size(16).create(&blocks.concat())
+	}
+
+	fn leaves(text : &ImmutableText) -> Vec<Shared<dyn Node>> {
+		collect_leaves(&text.root.to_node())
+	}
+
+	#[test]
+	fn documents_share_their_identical_blocks() {
+		let header = "// Licensed MIT\n";
+		let blank = "                ";
+		let first = blocks(&[header, "fn first() {}\n\n\n", blank, "été 😀 and more!\n"]);
+		let second = blocks(&[header, "fn second() {}\n\n", blank, "été 😀 and more!\n"]);
+		let mut interner = LeafInterner::new();
+		let first = interner.intern(&first);
+		let second = interner.intern(&second);
+		assert_eq!(second.to_string(), [header, "fn second() {}\n\n", blank, "été 😀 and more!\n"].concat());
+		let (first, second) = (leaves(&first), leaves(&second));
+		assert_eq!(second.len(), 4);
+		let shared : Vec<bool> = first.iter().zip(&second).map(|(a, b)| Shared::ptr_eq(a, b)).collect();
+		assert_eq!(shared, vec![true, false, true, true]);
+		assert_eq!(interner.len(), 5);
+	}
+
+	#[test]
+	fn purge_forgets_the_dropped_leaves() {
+		let mut interner = LeafInterner::new();
+		let kept = interner.intern(&blocks(&["kept kept kept\n\n", "the end is near\n"]));
+		let dropped = interner.intern(&blocks(&["dropped dropped\n", "the end is near\n"]));
+		assert_eq!(interner.len(), 3);
+		drop(dropped);
+		interner.purge();
+		assert_eq!(interner.len(), 2);
+		assert!(!interner.is_empty());
+		drop(kept);
+		interner.purge();
+		assert!(interner.is_empty());
+	}
+
+	#[test]
+	fn dropped_leaves_are_purged_as_the_interner_grows() {
+		let mut interner = LeafInterner::new();
+		for i in 0..100 * PURGE_THRESHOLD {
+			interner.intern(&blocks(&[&format!("{:>16}", i)]));
+		}
+		assert!(interner.len() < 2 * PURGE_THRESHOLD);
+	}
+}
@@ -0,0 +1,223 @@
+use std::io::{self, BufRead, Write};
+
+use super::{Delta, ImmutableText};
+
+/// <summary>
+/// An append-only log of the deltas applied to a document, from which the document
+/// can be rebuilt after a crash, or any of its past versions for a local history.
+/// Each delta is a record: a line <code>delta &lt;edits&gt;</code>, then for every edit a line
+/// <code>&lt;start&gt; &lt;removed&gt; &lt;bytes&gt;</code> followed by the inserted UTF-8 and a newline.
+/// </summary>
+pub struct Journal<W : Write> {
+	writer : W,
+	records : usize,
+	/// <summary>Whether an append failed, possibly after writing part of its record.</summary>
+	failed : bool
+}
+
+impl<W : Write> Journal<W> {
+	pub fn new(writer : W) -> Journal<W> {
+		Journal { writer, records : 0, failed : false }
+	}
+
+	/// <summary>Appends the delta as one record and flushes it, so that it survives a crash once this returns.</summary>
+	/// <remarks>
+	/// Once an append failed, the log may end with part of its record, which reading
+	/// takes for the end of the log: the journal refuses further appends rather than
+	/// write records that would never be read back.
+	/// </remarks>
+	pub fn append(&mut self, delta : &Delta) -> io::Result<()> {
+		if self.failed {
+			return Err(io::Error::other("journal: an earlier append failed"));
+		}
+		let mut record = format!("delta {}\n", delta.edits().len()).into_bytes();
+		for edit in delta.edits() {
+			record.extend_from_slice(format!("{} {} {}\n", edit.start, edit.removed, edit.inserted.len()).as_bytes());
+			record.extend_from_slice(edit.inserted.as_bytes());
+			record.push(b'\n');
+		}
+		if let Err(e) = self.writer.write_all(&record).and_then(|_| self.writer.flush()) {
+			self.failed = true;
+			return Err(e);
+		}
+		self.records += 1;
+		Ok(())
+	}
+
+	/// <summary>Returns whether an append failed, after which the journal refuses new records.</summary>
+	pub fn has_failed(&self) -> bool {
+		self.failed
+	}
+
+	/// <summary>Returns the number of records appended through this journal.</summary>
+	pub fn records(&self) -> usize {
+		self.records
+	}
+
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+}
+
+fn invalid(message : &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("journal: {}", message))
+}
+
+/// <summary>Reads a header line of numbers after the given keyword, or None at the end of the log or of a torn record.</summary>
+fn read_header<R : BufRead>(reader : &mut R, keyword : &str, fields : usize) -> io::Result<Option<Vec<usize>>> {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+		return Ok(None);
+	}
+	let mut words = line.trim_end().split(' ');
+	if !keyword.is_empty() && words.next() != Some(keyword) {
+		return Err(invalid("record expected"));
+	}
+	let numbers = words.map(|word| word.parse().map_err(|_| invalid("number expected"))).collect::<io::Result<Vec<usize>>>()?;
+	if numbers.len() != fields {
+		return Err(invalid("wrong number of fields"));
+	}
+	Ok(Some(numbers))
+}
+
+/// <summary>
+/// Reads the next record, or returns None at the end of the log. A record cut short,
+/// as the last one written when a crash happened, ends the log too.
+/// </summary>
+fn read_record<R : BufRead>(reader : &mut R) -> io::Result<Option<Delta>> {
+	let edits = match read_header(reader, "delta", 1)? {
+		Some(header) => header[0],
+		None => return Ok(None)
+	};
+	let mut delta = Delta::new();
+	let mut end = 0;
+	for _ in 0..edits {
+		let (start, removed, bytes) = match read_header(reader, "", 3)? {
+			Some(header) => (header[0], header[1], header[2]),
+			None => return Ok(None)
+		};
+		let mut inserted = vec![0; bytes + 1];
+		match reader.read_exact(&mut inserted) {
+			Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+			result => result?
+		}
+		if inserted.pop() != Some(b'\n') {
+			return Err(invalid("newline expected after the inserted text"));
+		}
+		if start < end {
+			return Err(invalid("edits out of order"));
+		}
+		let inserted = String::from_utf8(inserted).map_err(|_| invalid("inserted text not UTF-8"))?;
+		delta.push(start, removed, &inserted);
+		end = start + removed;
+	}
+	Ok(Some(delta))
+}
+
+/// <summary>Reads all the complete records of a journal, in the order they were appended.</summary>
+pub fn read_journal<R : BufRead>(mut journal : R) -> io::Result<Vec<Delta>> {
+	let mut deltas = Vec::new();
+	while let Some(delta) = read_record(&mut journal)? {
+		deltas.push(delta);
+	}
+	Ok(deltas)
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Applies the deltas of a journal in order to this text, the version the journal
+	/// started from, returning the document as of its last complete record.
+	/// </summary>
+	pub fn replay<R : BufRead>(&self, journal : R) -> io::Result<ImmutableText> {
+		let mut text = self.clone();
+		for delta in read_journal(journal)? {
+			if let Some(last) = delta.edits().last() {
+				if last.start + last.removed > text.length() {
+					return Err(invalid("edit past the end of the text"));
+				}
+			}
+			text = delta.apply(&text);
+		}
+		Ok(text)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	fn replace(start : usize, removed : usize, inserted : &str) -> Delta {
+		let mut delta = Delta::new();
+		delta.push(start, removed, inserted);
+		delta
+	}
+
+	/// <summary>A writer failing once it has accepted <code>capacity</code> bytes.</summary>
+	struct Failing {
+		written : Vec<u8>,
+		capacity : usize
+	}
+
+	impl Write for Failing {
+		fn write(&mut self, bytes : &[u8]) -> io::Result<usize> {
+			let count = bytes.len().min(self.capacity - self.written.len());
+			if count == 0 {
+				return Err(io::Error::other("disk full"));
+			}
+			self.written.extend_from_slice(&bytes[..count]);
+			Ok(count)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn replays_records_with_line_breaks_in_inserted_text() {
+		let mut journal = Journal::new(Vec::new());
+		journal.append(&replace(0, 0, "a\r\nb\n")).unwrap();
+		journal.append(&Delta::new()).unwrap();
+		journal.append(&replace(1, 2, "é")).unwrap();
+		assert_eq!(journal.records(), 3);
+		let log = journal.into_inner();
+		assert_eq!(read_journal(&log[..]).unwrap().len(), 3);
+		assert_eq!(create_immutable_text("").replay(&log[..]).unwrap().to_string(), "aéb\n");
+	}
+
+	#[test]
+	fn torn_record_ends_the_log() {
+		let mut journal = Journal::new(Vec::new());
+		journal.append(&replace(0, 0, "kept")).unwrap();
+		let complete = journal.get_ref().len();
+		journal.append(&replace(4, 0, " torn")).unwrap();
+		let log = journal.into_inner();
+		for end in complete..log.len() {
+			assert_eq!(read_journal(&log[..end]).unwrap(), vec![replace(0, 0, "kept")]);
+		}
+	}
+
+	#[test]
+	fn refuses_appends_after_a_failed_one() {
+		let mut journal = Journal::new(Failing { written : Vec::new(), capacity : 24 });
+		journal.append(&replace(0, 0, "ok")).unwrap();
+		assert!(journal.append(&replace(2, 0, "too long to fit")).is_err());
+		assert!(journal.has_failed());
+		assert!(journal.append(&replace(0, 0, "")).is_err());
+		assert!(journal.append(&replace(0, 1, "")).is_err());
+		assert_eq!(journal.records(), 1);
+		assert_eq!(read_journal(&journal.into_inner().written[..]).unwrap(), vec![replace(0, 0, "ok")]);
+	}
+
+	#[test]
+	fn rejects_edits_past_the_end() {
+		let mut journal = Journal::new(Vec::new());
+		journal.append(&replace(3, 1, "")).unwrap();
+		assert!(create_immutable_text("abc").replay(&journal.into_inner()[..]).is_err());
+	}
+}
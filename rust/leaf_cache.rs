@@ -0,0 +1,105 @@
+use std::sync::{Mutex, PoisonError};
+
+use super::{Node, Shared, Weak};
+
+/// <summary>
+/// Remembers the leaf last found by <code>find_leaf</code> with its offset, so that reading
+/// characters near the previous one, the usual pattern of lexers and renderers, skips
+/// the walk from the root. The cache is shared by the threads reading the same text:
+/// a thread finding it locked simply walks the tree.
+/// </summary>
+#[derive(Default)]
+pub(crate) struct LeafCache {
+	cached : Mutex<Option<CachedLeaf>>
+}
+
+/// <summary>
+/// A leaf of the tree of the text holding the cache, held weakly so that the cache
+/// neither keeps it alive nor counts as one more owner of it.
+/// </summary>
+#[derive(Clone)]
+struct CachedLeaf {
+	leaf : Weak<dyn Node>,
+	offset : usize
+}
+
+impl LeafCache {
+	/// <summary>Returns the cached leaf and its offset if it holds the character at <code>index</code>.</summary>
+	pub(crate) fn get(&self, index : usize) -> Option<(Shared<dyn Node>, usize)> {
+		let cached = self.cached.try_lock().ok()?;
+		let cached = cached.as_ref()?;
+		let leaf = cached.leaf.upgrade()?;
+		if index < cached.offset || index - cached.offset >= leaf.length() {
+			return None;
+		}
+		Some((leaf, cached.offset))
+	}
+
+	/// <summary>Caches a leaf of the tree found at <code>offset</code>, unless another thread holds the cache.</summary>
+	pub(crate) fn set(&self, leaf : &Shared<dyn Node>, offset : usize) {
+		if let Ok(mut cached) = self.cached.try_lock() {
+			*cached = Some(CachedLeaf { leaf : Shared::downgrade(leaf), offset });
+		}
+	}
+
+	/// <summary>
+	/// Forgets the cached leaf, before the nodes of the text are modified in place: a weak
+	/// reference to a leaf keeps <code>Shared::get_mut</code> from handing it out.
+	/// </summary>
+	pub(crate) fn clear(&mut self) {
+		*self.cached.get_mut().unwrap_or_else(PoisonError::into_inner) = None;
+	}
+}
+
+impl Clone for LeafCache {
+	/// <summary>Copies the cached leaf, which the clone of the text shares.</summary>
+	fn clone(&self) -> LeafCache {
+		let cached = self.cached.try_lock().ok().and_then(|cached| cached.clone());
+		LeafCache { cached : Mutex::new(cached) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_leaf_node, TextConfig};
+	use super::*;
+
+	#[test]
+	fn forgets_dropped_leaves() {
+		let config = TextConfig::new();
+		let cache = LeafCache::default();
+		let leaf = create_leaf_node("abc".chars().collect(), &config);
+		cache.set(&leaf, 10);
+		assert_eq!(cache.get(11).map(|(leaf, offset)| (leaf.get_char_at(11 - offset), offset)), Some(('b', 10)));
+		assert!(cache.get(9).is_none());
+		assert!(cache.get(13).is_none());
+		drop(leaf);
+		assert!(cache.get(11).is_none());
+	}
+
+	#[test]
+	fn reads_edits_made_in_place_after_a_cached_read() {
+		let source = "abcdefghijklmnop".repeat(8);
+		let mut text = TextConfig::new().with_block_size(16).create(&source).thaw();
+		let mut expected = source.clone();
+		for (i, offset) in [20, 21, 40, 20, 90].into_iter().enumerate() {
+			assert_eq!(text.get_char_at(offset), expected.chars().nth(offset).unwrap());
+			text.insert_str(offset - 2, &i.to_string());
+			expected.insert_str(offset - 2, &i.to_string());
+			assert_eq!(text.get_char_at(offset), expected.chars().nth(offset).unwrap());
+			text.remove(offset + 1, 1);
+			expected.remove(offset + 1);
+			for offset in offset - 3..offset + 3 {
+				assert_eq!(text.get_char_at(offset), expected.chars().nth(offset).unwrap());
+			}
+		}
+		assert_eq!(text.snapshot().to_string(), expected);
+	}
+
+	#[test]
+	fn cached_leaves_stay_owned() {
+		let text = TextConfig::new().with_block_size(16).create(&"abcdefghijklmnop".repeat(8));
+		assert_eq!(text.get_char_at(20), 'e');
+		assert_eq!(text.memory_usage().shared_bytes, 0);
+	}
+}
@@ -0,0 +1,340 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use super::{create_immutable_text, Delta, ImmutableText};
+
+impl ImmutableText {
+	/// <summary>
+	/// Splits the text into its lines, without their terminators. Lines are sub-texts
+	/// sharing the leaves of this text; the empty line after a final newline is left out.
+	/// </summary>
+	/// <returns>the lines and whether the text ends with a newline.</returns>
+	fn line_texts(&self) -> (Vec<ImmutableText>, bool) {
+		let mut count = self.line_count();
+		let trailing_newline = count > 1 && self.line_start(count - 1) == self.length();
+		if trailing_newline {
+			count -= 1;
+		}
+		let lines = (0..count).map(|line| {
+			let start = self.line_start(line);
+			self.get_text(start, self.line_end(line) - start)
+		}).collect();
+		(lines, trailing_newline)
+	}
+
+	/// <summary>
+	/// Joins lines back with newlines, the reverse of <code>line_texts</code>, in one pass
+	/// over their leaves with <code>ImmutableText::join</code>.
+	/// </summary>
+	fn join_line_texts(&self, lines : &[ImmutableText], trailing_newline : bool) -> ImmutableText {
+		let empty = self.empty();
+		ImmutableText::join(lines.iter().chain(trailing_newline.then_some(&empty)), "\n")
+	}
+
+	/// <summary>Returns the text with its lines sorted (stably) by the given comparator.</summary>
+	pub fn sort_lines<F>(&self, mut cmp : F) -> ImmutableText where F : FnMut(&ImmutableText, &ImmutableText) -> Ordering {
+		let (mut lines, trailing_newline) = self.line_texts();
+		lines.sort_by(|a, b| cmp(a, b));
+		self.join_line_texts(&lines, trailing_newline)
+	}
+
+	/// <summary>Returns the text with consecutive duplicate lines removed, like <code>uniq</code>.</summary>
+	pub fn dedup_lines(&self) -> ImmutableText {
+		let (mut lines, trailing_newline) = self.line_texts();
+		lines.dedup();
+		self.join_line_texts(&lines, trailing_newline)
+	}
+
+	/// <summary>Returns the text with its lines in reverse order.</summary>
+	pub fn reverse_lines(&self) -> ImmutableText {
+		let (mut lines, trailing_newline) = self.line_texts();
+		lines.reverse();
+		self.join_line_texts(&lines, trailing_newline)
+	}
+
+	/// <summary>
+	/// Applies the function to every line (without its terminator). Lines for which
+	/// it returns the content unchanged are not rebuilt: runs of them are shared with
+	/// this text. The empty line after a final newline is not visited.
+	/// </summary>
+	pub fn map_lines<F>(&self, mut f : F) -> ImmutableText where F : FnMut(&str) -> Cow<'_, str> {
+		let mut count = self.line_count();
+		if count > 1 && self.line_start(count - 1) == self.length() {
+			count -= 1;
+		}
+		let mut result = self.empty();
+		let mut unchanged_from = 0;
+		for line in 0..count {
+			let start = self.line_start(line);
+			let end = self.line_end(line);
+			let content : String = self.chars_range(start, end).collect();
+			let mapped = f(&content);
+			if *mapped != *content {
+				result = result.concat(&self.get_text(unchanged_from, start - unchanged_from)).concat(&create_immutable_text(&mapped));
+				unchanged_from = end;
+			}
+		}
+		result.concat(&self.sub_text(unchanged_from))
+	}
+
+	/// <summary>
+	/// Returns the text with a new line inserted before line <code>line</code>, or
+	/// appended after the last line when <code>line</code> equals the line count.
+	/// </summary>
+	pub fn insert_line(&self, line : usize, text : &str) -> ImmutableText {
+		let count = self.line_count();
+		assert!(line <= count, "line out of range: {} (line count {})", line, count);
+		if line == count {
+			return self.insert_string(self.length(), &format!("\n{}", text));
+		}
+		self.insert_string(self.line_start(line), &format!("{}\n", text))
+	}
+
+	/// <summary>Returns the text without the given lines and their newlines.</summary>
+	pub fn remove_lines(&self, lines : Range<usize>) -> ImmutableText {
+		let count = self.line_count();
+		assert!(lines.start <= lines.end && lines.end <= count, "line range out of range: {:?} (line count {})", lines, count);
+		if lines.start == lines.end {
+			return self.clone();
+		}
+		if lines.end < count {
+			let start = self.line_start(lines.start);
+			return self.remove_text(start, self.line_start(lines.end) - start);
+		}
+		if lines.start == 0 {
+			return self.empty();
+		}
+		// Removes up to the end, including the newline ending the line before.
+		let start = self.line_end(lines.start - 1);
+		self.remove_text(start, self.length() - start)
+	}
+
+	/// <summary>Returns the text with the content of the given line replaced, keeping its newline.</summary>
+	pub fn replace_line(&self, line : usize, text : &str) -> ImmutableText {
+		let start = self.line_start(line);
+		let end = self.line_end(line);
+		self.remove_text(start, end - start).insert_string(start, text)
+	}
+
+	/// <summary>
+	/// Joins the lines of the range into one, replacing each newline with the separator.
+	/// With <code>collapse_whitespace</code> the whitespace around each newline is removed
+	/// too and blank lines are dropped without adding a separator, like Vim's <code>J</code>.
+	/// </summary>
+	pub fn join_lines(&self, lines : Range<usize>, separator : &str, collapse_whitespace : bool) -> ImmutableText {
+		assert!(lines.end <= self.line_count(), "line range out of range: {:?} (line count {})", lines, self.line_count());
+		let mut delta = Delta::new();
+		if lines.len() < 2 {
+			return self.clone();
+		}
+		if !collapse_whitespace {
+			for line in lines.start..lines.end - 1 {
+				delta.push(self.line_end(line), 1, separator);
+			}
+			return delta.apply(self);
+		}
+		// End of the joined content so far, after its trailing whitespace is trimmed.
+		let first_start = self.line_start(lines.start);
+		let mut content_end = self.trim_end_offset(first_start, self.line_end(lines.start));
+		for line in lines.start + 1..lines.end {
+			let end = self.line_end(line);
+			let mut content_start = self.line_start(line);
+			while content_start < end && is_blank(self.get_char_at(content_start)) {
+				content_start += 1;
+			}
+			if content_start == end {
+				continue;
+			}
+			delta.push(content_end, content_start - content_end, if content_end == first_start { "" } else { separator });
+			content_end = self.trim_end_offset(content_start, end);
+		}
+		let end = self.line_end(lines.end - 1);
+		if content_end < end && self.line_of_offset(content_end) != lines.end - 1 {
+			// The last lines were blank.
+			delta.push(content_end, end - content_end, "");
+		}
+		delta.apply(self)
+	}
+
+	/// <summary>
+	/// Returns the ranges of the blanks ending the lines, before the carriage return of
+	/// lines ending with CR LF, in order. Lines are scanned from their end, so only the
+	/// offending characters are read beyond the line index.
+	/// </summary>
+	pub fn trailing_whitespace_ranges(&self) -> Vec<Range<usize>> {
+		let mut ranges = Vec::new();
+		for line in 0..self.line_count() {
+			let start = self.line_start(line);
+			let mut end = self.line_end(line);
+			if end < self.length() && end > start && self.get_char_at(end - 1) == '\r' {
+				end -= 1;
+			}
+			let content_end = self.trim_end_offset(start, end);
+			if content_end < end {
+				ranges.push(content_end..end);
+			}
+		}
+		ranges
+	}
+
+	/// <summary>Removes the blanks ending the lines, leaving the other lines shared with this text.</summary>
+	/// <returns>the new text and the delta from this text to it.</returns>
+	pub fn strip_trailing_whitespace(&self) -> (ImmutableText, Delta) {
+		let mut delta = Delta::new();
+		for range in self.trailing_whitespace_ranges() {
+			delta.push(range.start, range.end - range.start, "");
+		}
+		(delta.apply(self), delta)
+	}
+
+	/// <summary>Returns the offset where the trailing blanks of <code>[start, end)</code> begin.</summary>
+	fn trim_end_offset(&self, start : usize, mut end : usize) -> usize {
+		while end > start && is_blank(self.get_char_at(end - 1)) {
+			end -= 1;
+		}
+		end
+	}
+}
+
+fn is_blank(c : char) -> bool {
+	c.is_whitespace() && c != '\n'
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	#[test]
+	fn sorts_dedups_and_reverses_lines() {
+		for ending in ["", "\n"] {
+			let text = create_immutable_text(&("pear\napple\napple\nfig\napple".to_string() + ending));
+			assert_eq!(text.sort_lines(|a, b| a.to_string().cmp(&b.to_string())).to_string(), "apple\napple\napple\nfig\npear".to_string() + ending);
+			assert_eq!(text.dedup_lines().to_string(), "pear\napple\nfig\napple".to_string() + ending);
+			assert_eq!(text.reverse_lines().to_string(), "apple\nfig\napple\napple\npear".to_string() + ending);
+		}
+		// Only the empty line after the final newline is left out: other empty lines are lines.
+		let text = create_immutable_text("b\n\na\n");
+		assert_eq!(text.sort_lines(|a, b| a.length().cmp(&b.length())).to_string(), "\nb\na\n");
+		assert_eq!(text.reverse_lines().to_string(), "a\n\nb\n");
+		assert_eq!(create_immutable_text("").reverse_lines().to_string(), "");
+		assert_eq!(create_immutable_text("\n").reverse_lines().to_string(), "\n");
+	}
+
+	#[test]
+	fn line_operations_keep_the_configuration() {
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create(&(0..50).map(|i| format!("line {:02}\n", 49 - i)).collect::<String>());
+		let sorted = text.sort_lines(|a, b| a.to_string().cmp(&b.to_string()));
+		assert_eq!(sorted.config(), config);
+		assert_eq!(sorted.to_string(), (0..50).map(|i| format!("line {:02}\n", i)).collect::<String>());
+		assert_eq!(sorted.validate(), Ok(()));
+	}
+
+	#[test]
+	fn map_lines_rebuilds_only_the_changed_lines() {
+		let text = create_immutable_text("a\n# b\nc\n");
+		let mut visited = Vec::new();
+		let mapped = text.map_lines(|line| {
+			visited.push(line.to_string());
+			match line.strip_prefix("# ") {
+				Some(uncommented) => Cow::Owned(uncommented.to_string()),
+				None => Cow::Borrowed(line)
+			}
+		});
+		assert_eq!(mapped.to_string(), "a\nb\nc\n");
+		assert_eq!(visited, vec!["a", "# b", "c"]);
+		assert_eq!(text.map_lines(|line| Cow::Owned(format!("> {}", line))).to_string(), "> a\n> # b\n> c\n");
+		assert_eq!(create_immutable_text("").map_lines(|_| Cow::Borrowed("x")).to_string(), "x");
+	}
+
+	#[test]
+	fn map_lines_shares_the_unchanged_lines() {
+		let text = TextConfig::new().with_block_size(16).create(&(0..100).map(|i| format!("line {}\n", i)).collect::<String>());
+		let mapped = text.map_lines(|line| if line == "line 50" { Cow::Borrowed("fifty") } else { Cow::Borrowed(line) });
+		assert_eq!(mapped.to_string(), text.to_string().replace("line 50\n", "fifty\n"));
+		let usage = mapped.memory_usage();
+		assert!(usage.shared_bytes > 5 * usage.owned_bytes, "{:?}", usage);
+	}
+
+	#[test]
+	fn inserts_removes_and_replaces_lines() {
+		let text = create_immutable_text("one\ntwo\nthree");
+		assert_eq!(text.insert_line(0, "zero").to_string(), "zero\none\ntwo\nthree");
+		assert_eq!(text.insert_line(2, "2.5").to_string(), "one\ntwo\n2.5\nthree");
+		assert_eq!(text.insert_line(3, "four").to_string(), "one\ntwo\nthree\nfour");
+		assert_eq!(text.remove_lines(0..1).to_string(), "two\nthree");
+		assert_eq!(text.remove_lines(1..3).to_string(), "one");
+		assert_eq!(text.remove_lines(0..3).to_string(), "");
+		assert_eq!(text.remove_lines(1..1).to_string(), text.to_string());
+		assert_eq!(text.replace_line(1, "TWO").to_string(), "one\nTWO\nthree");
+		assert_eq!(text.replace_line(2, "").to_string(), "one\ntwo\n");
+		// The empty last line after a final newline is a line too.
+		let text = create_immutable_text("a\n");
+		assert_eq!(text.insert_line(1, "b").to_string(), "a\nb\n");
+		assert_eq!(text.insert_line(2, "b").to_string(), "a\n\nb");
+		assert_eq!(text.remove_lines(1..2).to_string(), "a");
+		assert_eq!(text.replace_line(1, "b").to_string(), "a\nb");
+	}
+
+	#[test]
+	#[should_panic(expected = "line out of range: 4 (line count 3)")]
+	fn insert_line_rejects_lines_past_the_end() {
+		create_immutable_text("one\ntwo\nthree").insert_line(4, "five");
+	}
+
+	#[test]
+	fn joins_lines_with_a_separator() {
+		let text = create_immutable_text("a \nb\n\nc\nd");
+		assert_eq!(text.join_lines(0..4, ", ", false).to_string(), "a , b, , c\nd");
+		assert_eq!(text.join_lines(1..3, "", false).to_string(), "a \nb\nc\nd");
+		assert_eq!(text.join_lines(1..2, " ", false).to_string(), text.to_string());
+		assert_eq!(text.join_lines(0..0, " ", true).to_string(), text.to_string());
+	}
+
+	#[test]
+	fn joins_lines_collapsing_whitespace() {
+		let text = create_immutable_text("foo  \n   bar\n\n \t\n  baz\nqux");
+		assert_eq!(text.join_lines(0..5, " ", true).to_string(), "foo bar baz\nqux");
+		assert_eq!(text.join_lines(0..2, " ", true).to_string(), "foo bar\n\n \t\n  baz\nqux");
+		// Blank lines ending the range are removed with the newlines before them.
+		assert_eq!(text.join_lines(1..4, " ", true).to_string(), "foo  \n   bar\n  baz\nqux");
+		// A blank first line takes no separator.
+		let text = create_immutable_text("  \n\tfoo\nbar");
+		assert_eq!(text.join_lines(0..3, " ", true).to_string(), "foo bar");
+		assert_eq!(create_immutable_text("\n\n").join_lines(0..3, " ", true).to_string(), "");
+	}
+
+	#[test]
+	#[should_panic(expected = "line range out of range: 1..4 (line count 3)")]
+	fn join_lines_rejects_lines_past_the_end() {
+		create_immutable_text("a\nb\nc").join_lines(1..4, " ", false);
+	}
+
+	#[test]
+	fn finds_and_strips_trailing_whitespace() {
+		let source = "a  \r\nb\t\n \n\u{3000}c\u{3000}\r\nd \t";
+		let text = create_immutable_text(source);
+		assert_eq!(text.trailing_whitespace_ranges(), vec![1..3, 6..7, 8..9, 12..13, 16..18]);
+		let (stripped, delta) = text.strip_trailing_whitespace();
+		assert_eq!(stripped.to_string(), "a\r\nb\n\n\u{3000}c\r\nd");
+		assert_eq!(delta.apply(&text).to_string(), stripped.to_string());
+		// A lone carriage return is not a line end, so it is trailing whitespace.
+		assert_eq!(create_immutable_text("a\r").trailing_whitespace_ranges(), vec![1..2]);
+		let clean = create_immutable_text("a\r\nb\n");
+		assert!(clean.trailing_whitespace_ranges().is_empty());
+		let (unchanged, delta) = clean.strip_trailing_whitespace();
+		assert!(delta.is_empty());
+		assert_eq!(unchanged.to_string(), "a\r\nb\n");
+	}
+
+	#[test]
+	fn strips_trailing_whitespace_across_leaves() {
+		let source : String = (0..40).map(|i| format!("line {}{}\n", i, " ".repeat(i % 3))).collect();
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let expected : String = (0..40).map(|i| format!("line {}\n", i)).collect();
+		assert_eq!(text.strip_trailing_whitespace().0.to_string(), expected);
+		assert_eq!(text.trailing_whitespace_ranges().len(), 26);
+	}
+}
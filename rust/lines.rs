@@ -0,0 +1,131 @@
+use std::ops::Range;
+
+use super::{ImmutableText, LeafChars, Node};
+
+/// <summary>Returns the offset of the n-th (0-based) newline in the subtree, using the cached newline counts.</summary>
+fn newline_offset(root : &dyn Node, mut n : usize) -> usize {
+	let mut node = root;
+	let mut offset = 0;
+	while let Some(composite) = node.as_composite() {
+		let head_newlines = composite.head.metrics().newlines;
+		if n < head_newlines {
+			node = &*composite.head;
+		} else {
+			n -= head_newlines;
+			offset += composite.head.length();
+			node = &*composite.tail;
+		}
+	}
+	for (i, c) in LeafChars::new(node, 0, node.length()).enumerate() {
+		if c == '\n' {
+			if n == 0 {
+				return offset + i;
+			}
+			n -= 1;
+		}
+	}
+	unreachable!("newline counts out of sync with leaf contents")
+}
+
+impl ImmutableText {
+	/// <summary>Returns the number of lines; a text ending with a newline has an empty last line.</summary>
+	pub fn line_count(&self) -> usize {
+		self.root.metrics().newlines + 1
+	}
+
+	/// <summary>Returns the offset of the first character of the given line, in O(log n).</summary>
+	pub fn line_start(&self, line : usize) -> usize {
+		if line == 0 {
+			return 0;
+		}
+		assert!(line < self.line_count(), "line out of range: {} (line count {})", line, self.line_count());
+		newline_offset(&*self.root, line - 1) + 1
+	}
+
+	/// <summary>Returns the offset of the newline ending the given line, or the text length for the last line.</summary>
+	pub fn line_end(&self, line : usize) -> usize {
+		assert!(line < self.line_count(), "line out of range: {} (line count {})", line, self.line_count());
+		if line == self.line_count() - 1 {
+			return self.length();
+		}
+		newline_offset(&*self.root, line)
+	}
+
+	/// <summary>Returns the line containing the character at the given offset, in O(log n).</summary>
+	pub fn line_of_offset(&self, offset : usize) -> usize {
+		let mut node : &dyn Node = &*self.root;
+		let mut index = offset.min(self.length());
+		let mut line = 0;
+		while let Some(composite) = node.as_composite() {
+			if index < composite.head.length() {
+				node = &*composite.head;
+			} else {
+				line += composite.head.metrics().newlines;
+				index -= composite.head.length();
+				node = &*composite.tail;
+			}
+		}
+		line + LeafChars::new(node, 0, index.min(node.length())).filter(|&c| c == '\n').count()
+	}
+
+	/// <summary>
+	/// Returns the given lines with their newlines, as a sub-text sharing the leaves of
+	/// this text; their offsets are found through the line index, in O(log n).
+	/// </summary>
+	pub fn text_for_lines(&self, lines : Range<usize>) -> ImmutableText {
+		assert!(lines.start <= lines.end && lines.end <= self.line_count(), "line range out of range: {:?} (line count {})", lines, self.line_count());
+		if lines.start == lines.end {
+			return self.empty();
+		}
+		let start = self.line_start(lines.start);
+		let end = if lines.end == self.line_count() { self.length() } else { self.line_start(lines.end) };
+		self.get_text(start, end - start)
+	}
+
+	/// <summary>
+	/// Returns the number of characters of the given line without its newline, from the
+	/// line index. The carriage return of a CR LF line ending is counted, like by <code>line_end</code>.
+	/// </summary>
+	pub fn line_len_chars(&self, line : usize) -> usize {
+		self.line_end(line) - self.line_start(line)
+	}
+
+	/// <summary>
+	/// Returns the length of the UTF-8 encoding of the given line without its newline,
+	/// from the byte counts of the nodes, in O(log n).
+	/// </summary>
+	pub fn line_len_bytes(&self, line : usize) -> usize {
+		let start = self.try_byte_offset(self.line_start(line)).unwrap();
+		self.try_byte_offset(self.line_end(line)).unwrap() - start
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn line_lengths_exclude_the_newline() {
+		let content = "ab\nété\r\n\n😀x";
+		for text in [create_immutable_text(content), TextConfig::new().with_block_size(16).create(&content.repeat(8))] {
+			let expected = text.to_string();
+			let lines : Vec<&str> = expected.split('\n').collect();
+			assert_eq!(text.line_count(), lines.len());
+			for (line, expected) in lines.iter().enumerate() {
+				assert_eq!(text.line_len_chars(line), expected.chars().count(), "line {}", line);
+				assert_eq!(text.line_len_bytes(line), expected.len(), "line {}", line);
+			}
+		}
+		let text = create_immutable_text(content);
+		assert_eq!((text.line_len_chars(1), text.line_len_bytes(1)), (4, 6));
+		assert_eq!((text.line_len_chars(3), text.line_len_bytes(3)), (2, 5));
+	}
+
+	#[test]
+	fn the_empty_last_line_has_no_length() {
+		let text = create_immutable_text("ab\n");
+		assert_eq!((text.line_len_chars(1), text.line_len_bytes(1)), (0, 0));
+		let text = create_immutable_text("");
+		assert_eq!((text.line_len_chars(0), text.line_len_bytes(0)), (0, 0));
+	}
+}
@@ -0,0 +1,270 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::str;
+
+use super::{balanced_node, create_leaf_node, ImmutableText, LeafCache, Root, TextConfig, TextError};
+
+/// <summary>Holds the number of bytes read between two reports of progress.</summary>
+const READ_BUFFER : usize = 1 << 20;
+
+/// <summary>The progress of a load, reported after every buffer read.</summary>
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+	/// <summary>The number of bytes read so far.</summary>
+	pub bytes_read : u64,
+	/// <summary>The number of leaves built so far.</summary>
+	pub chunks_built : usize
+}
+
+/// <summary>
+/// The reason a load failed: the reader failed, or what it read is not UTF-8. It converts
+/// into an <code>io::Error</code>, of kind <code>InvalidData</code> for the content, so that
+/// loads can be chained with other I/O by <code>?</code>.
+/// </summary>
+#[derive(Debug)]
+pub enum LoadError {
+	Io(io::Error),
+	/// <summary>A <code>TextError::InvalidUtf8</code> holding the offset of the first invalid byte in the content read.</summary>
+	Text(TextError)
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LoadError::Io(error) => error.fmt(f),
+			LoadError::Text(error) => error.fmt(f)
+		}
+	}
+}
+
+impl error::Error for LoadError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match self {
+			LoadError::Io(error) => Some(error),
+			LoadError::Text(error) => Some(error)
+		}
+	}
+}
+
+impl From<io::Error> for LoadError {
+	fn from(error : io::Error) -> LoadError {
+		LoadError::Io(error)
+	}
+}
+
+impl From<TextError> for LoadError {
+	fn from(error : TextError) -> LoadError {
+		LoadError::Text(error)
+	}
+}
+
+impl From<LoadError> for io::Error {
+	fn from(error : LoadError) -> io::Error {
+		match error {
+			LoadError::Io(error) => error,
+			LoadError::Text(error) => io::Error::new(io::ErrorKind::InvalidData, error)
+		}
+	}
+}
+
+impl TextConfig {
+	/// <summary>
+	/// Creates a text with this configuration from the UTF-8 content of the reader,
+	/// building its leaves as the content streams in and calling the callback after
+	/// every megabyte read, so that opening a large file can show a progress bar.
+	/// The callback cancels the load by returning <code>ControlFlow::Break</code>.
+	/// </summary>
+	/// <returns>the text, or None if the load was cancelled; <code>TextError::InvalidUtf8</code> if the content is not UTF-8.</returns>
+	pub fn load<R, F>(&self, mut reader : R, mut progress : F) -> Result<Option<ImmutableText>, LoadError>
+		where R : Read, F : FnMut(&LoadProgress) -> ControlFlow<()> {
+		let mut buffer = vec![0; READ_BUFFER];
+		// The bytes of a character cut by the end of the previous buffer.
+		let mut carried = 0;
+		let mut pending = Vec::with_capacity(self.block_size());
+		let mut leaves = Vec::new();
+		let mut state = LoadProgress::default();
+		loop {
+			let read = match reader.read(&mut buffer[carried..]) {
+				Ok(read) => read,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e.into())
+			};
+			let filled = carried + read;
+			let valid = match str::from_utf8(&buffer[..filled]) {
+				Ok(valid) => valid,
+				// An incomplete character at the end is completed by the next read.
+				Err(e) if e.error_len().is_none() && read > 0 => str::from_utf8(&buffer[..e.valid_up_to()]).unwrap(),
+				Err(e) => return Err(TextError::InvalidUtf8 { valid_up_to : state.bytes_read as usize - carried + e.valid_up_to() }.into())
+			};
+			for c in valid.chars() {
+				pending.push(c);
+				if pending.len() == self.block_size() {
+					leaves.push(create_leaf_node(std::mem::replace(&mut pending, Vec::with_capacity(self.block_size())), self));
+				}
+			}
+			let used = valid.len();
+			buffer.copy_within(used..filled, 0);
+			carried = filled - used;
+			state.bytes_read += read as u64;
+			state.chunks_built = leaves.len();
+			if progress(&state).is_break() {
+				return Ok(None);
+			}
+			if read == 0 {
+				break;
+			}
+		}
+		if leaves.is_empty() {
+			return Ok(Some(self.create(&pending.into_iter().collect::<String>())));
+		}
+		if !pending.is_empty() {
+			leaves.push(create_leaf_node(pending, self));
+		}
+		Ok(Some(ImmutableText { root : Root::Node(balanced_node(&leaves)), config : *self, cache : LeafCache::default() }))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Creates a text with the default configuration from the reader, see <code>TextConfig::load</code>.</summary>
+	pub fn load<R, F>(reader : R, progress : F) -> Result<Option<ImmutableText>, LoadError>
+		where R : Read, F : FnMut(&LoadProgress) -> ControlFlow<()> {
+		TextConfig::new().load(reader, progress)
+	}
+
+	/// <summary>
+	/// Appends the UTF-8 content of the reader to this text, built into block-sized
+	/// leaves as it streams in, so that the tail of a growing file or the live output
+	/// of a process can be added to a text without buffering it first.
+	/// </summary>
+	/// <returns>the text with the content appended; <code>TextError::InvalidUtf8</code> if the content is not UTF-8.</returns>
+	pub fn append_from_reader<R : Read>(&self, reader : R) -> Result<ImmutableText, LoadError> {
+		let appended = self.config.load(reader, |_| ControlFlow::Continue(()))?;
+		Ok(appended.map_or_else(|| self.clone(), |appended| self.concat(&appended)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>A reader handing out its content a few bytes at a time, after one interruption.</summary>
+	struct Trickle<'a> {
+		content : &'a [u8],
+		piece : usize,
+		interrupted : bool
+	}
+
+	impl<'a> Trickle<'a> {
+		fn new(content : &'a [u8], piece : usize) -> Trickle<'a> {
+			Trickle { content, piece, interrupted : false }
+		}
+	}
+
+	impl Read for Trickle<'_> {
+		fn read(&mut self, buffer : &mut [u8]) -> io::Result<usize> {
+			if !self.interrupted {
+				self.interrupted = true;
+				return Err(io::Error::from(io::ErrorKind::Interrupted));
+			}
+			let read = self.piece.min(buffer.len()).min(self.content.len());
+			buffer[..read].copy_from_slice(&self.content[..read]);
+			self.content = &self.content[read..];
+			Ok(read)
+		}
+	}
+
+	#[test]
+	fn loads_characters_split_across_reads() {
+		let content = "a\u{e9}\u{20ac}😀".repeat(50);
+		let config = TextConfig::new().with_block_size(16);
+		for piece in 1..6 {
+			let text = config.load(Trickle::new(content.as_bytes(), piece), |_| ControlFlow::Continue(())).unwrap().unwrap();
+			assert_eq!(text.to_string(), content);
+			assert_eq!(text.config(), config);
+			assert_eq!(text.tree_stats().leaf_count, 200usize.div_ceil(16));
+		}
+	}
+
+	#[test]
+	fn reports_progress_after_every_read() {
+		let content = "0123456789".repeat(10);
+		let config = TextConfig::new().with_block_size(16);
+		let mut reports = Vec::new();
+		let text = config.load(Trickle::new(content.as_bytes(), 30), |progress| {
+			reports.push(*progress);
+			ControlFlow::Continue(())
+		}).unwrap().unwrap();
+		assert_eq!(text.to_string(), content);
+		let bytes : Vec<u64> = reports.iter().map(|progress| progress.bytes_read).collect();
+		assert_eq!(bytes, vec![30, 60, 90, 100, 100]);
+		let chunks : Vec<usize> = reports.iter().map(|progress| progress.chunks_built).collect();
+		assert_eq!(chunks, vec![1, 3, 5, 6, 6]);
+	}
+
+	#[test]
+	fn cancels_when_the_callback_breaks() {
+		let content = "0123456789".repeat(10);
+		let mut calls = 0;
+		let loaded = ImmutableText::load(Trickle::new(content.as_bytes(), 30), |progress| {
+			calls += 1;
+			if progress.bytes_read >= 60 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+		}).unwrap();
+		assert!(loaded.is_none());
+		assert_eq!(calls, 2);
+	}
+
+	#[test]
+	fn reader_errors_are_io_errors() {
+		struct Failing;
+		impl Read for Failing {
+			fn read(&mut self, _ : &mut [u8]) -> io::Result<usize> {
+				Err(io::Error::other("disk on fire"))
+			}
+		}
+		let error = ImmutableText::load(Failing, |_| ControlFlow::Continue(())).unwrap_err();
+		assert!(matches!(&error, LoadError::Io(error) if error.to_string() == "disk on fire"));
+		assert_eq!(io::Error::from(error).kind(), io::ErrorKind::Other);
+	}
+
+	#[test]
+	fn truncated_characters_are_invalid() {
+		let content = "ab😀".as_bytes();
+		let error = ImmutableText::load(Trickle::new(&content[..4], 1), |_| ControlFlow::Continue(())).unwrap_err();
+		assert!(matches!(error, LoadError::Text(TextError::InvalidUtf8 { valid_up_to : 2 })));
+		let error = ImmutableText::load(Trickle::new(b"ab\xf0\x9f\x98xyz", 2), |_| ControlFlow::Continue(())).unwrap_err();
+		assert!(matches!(error, LoadError::Text(TextError::InvalidUtf8 { valid_up_to : 2 })));
+	}
+
+	#[test]
+	fn invalid_utf8_is_a_text_error() {
+		let error = ImmutableText::load(&b"abc\xffdef"[..], |_| ControlFlow::Continue(())).unwrap_err();
+		assert!(matches!(error, LoadError::Text(TextError::InvalidUtf8 { valid_up_to : 3 })));
+		let error = io::Error::from(error);
+		assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+		assert_eq!(error.to_string(), "invalid utf-8 after byte 3");
+	}
+
+	#[test]
+	fn appends_the_content_of_a_reader() {
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create("first line\n");
+		let appended = text.append_from_reader(Trickle::new("second \u{e9}\u{20ac}😀 line\n".repeat(5).as_bytes(), 3)).unwrap();
+		assert_eq!(appended.to_string(), format!("first line\n{}", "second \u{e9}\u{20ac}😀 line\n".repeat(5)));
+		assert_eq!(appended.config(), config);
+		assert_eq!(appended.validate(), Ok(()));
+		assert_eq!(text.to_string(), "first line\n");
+		assert_eq!(text.append_from_reader(&b""[..]).unwrap().to_string(), "first line\n");
+		assert_eq!(config.create("").append_from_reader(&b"abc"[..]).unwrap().to_string(), "abc");
+	}
+
+	#[test]
+	fn appending_invalid_utf8_fails() {
+		let text = create_immutable_text("abc");
+		let error = text.append_from_reader(Trickle::new(b"de\xc3(f", 2)).unwrap_err();
+		assert!(matches!(error, LoadError::Text(TextError::InvalidUtf8 { valid_up_to : 2 })));
+		assert_eq!(text.to_string(), "abc");
+	}
+}
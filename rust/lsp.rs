@@ -0,0 +1,107 @@
+use super::{Delta, ImmutableText};
+
+/// <summary>A position as the Language Server Protocol counts it: a line and a column in UTF-16 code units, both from 0.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+	pub line : u32,
+	pub character : u32
+}
+
+/// <summary>A range between two LSP positions.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LspRange {
+	pub start : Position,
+	pub end : Position
+}
+
+/// <summary>An LSP <code>TextEdit</code>: the range of the document it replaces, and the text replacing it.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+	pub range : LspRange,
+	pub new_text : String
+}
+
+impl ImmutableText {
+	/// <summary>Returns the LSP position of the offset: its line, and the UTF-16 code units before it on the line.</summary>
+	pub fn lsp_position(&self, offset : usize) -> Position {
+		if let Err(error) = self.check_index(offset) {
+			panic!("{}", error);
+		}
+		let line = self.line_of_offset(offset);
+		let character : usize = self.chars_range(self.line_start(line), offset).map(char::len_utf16).sum();
+		Position { line : line as u32, character : character as u32 }
+	}
+}
+
+impl Delta {
+	/// <summary>
+	/// Converts the delta computed against <code>old</code> into LSP text edits, in order,
+	/// all in the coordinates of <code>old</code> and not overlapping, as a language server
+	/// returns them for a document.
+	/// </summary>
+	pub fn to_lsp_edits(&self, old : &ImmutableText) -> Vec<TextEdit> {
+		self.edits().iter().map(|edit| TextEdit {
+			range : LspRange { start : old.lsp_position(edit.start), end : old.lsp_position(edit.start + edit.removed) },
+			new_text : edit.inserted.clone()
+		}).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	fn position(line : u32, character : u32) -> Position {
+		Position { line, character }
+	}
+
+	#[test]
+	fn counts_columns_in_utf16_code_units() {
+		let text = create_immutable_text("a😀b\n😀😀x\n\u{e9}\u{20ac}");
+		let positions : Vec<Position> = (0..=text.length()).map(|offset| text.lsp_position(offset)).collect();
+		assert_eq!(positions, vec![
+			position(0, 0), position(0, 1), position(0, 3), position(0, 4),
+			position(1, 0), position(1, 2), position(1, 4), position(1, 5),
+			position(2, 0), position(2, 1), position(2, 2)
+		]);
+	}
+
+	#[test]
+	#[should_panic(expected = "index out of range: 3 (length 2)")]
+	fn rejects_offsets_past_the_end() {
+		create_immutable_text("a😀").lsp_position(3);
+	}
+
+	#[test]
+	fn converts_edits_with_astral_characters() {
+		let old = create_immutable_text("😀 one\n😀😀 two\n");
+		let mut delta = Delta::new();
+		delta.push(0, 1, "");
+		delta.push(2, 4, "1\n");
+		delta.push(9, 3, "2");
+		assert_eq!(delta.to_lsp_edits(&old), vec![
+			TextEdit { range : LspRange { start : position(0, 0), end : position(0, 2) }, new_text : String::new() },
+			TextEdit { range : LspRange { start : position(0, 3), end : position(1, 0) }, new_text : "1\n".to_string() },
+			TextEdit { range : LspRange { start : position(1, 5), end : position(1, 8) }, new_text : "2".to_string() }
+		]);
+	}
+
+	#[test]
+	fn converts_edits_at_the_end_of_the_document() {
+		let old = create_immutable_text("ab\n😀");
+		let mut delta = Delta::new();
+		delta.push(4, 0, "!");
+		assert_eq!(delta.to_lsp_edits(&old), vec![
+			TextEdit { range : LspRange { start : position(1, 2), end : position(1, 2) }, new_text : "!".to_string() }
+		]);
+		let mut delta = Delta::new();
+		delta.push(1, 3, "");
+		assert_eq!(delta.to_lsp_edits(&old)[0].range, LspRange { start : position(0, 1), end : position(1, 2) });
+		let old = create_immutable_text("ab\n");
+		let mut delta = Delta::new();
+		delta.push(3, 0, "c");
+		assert_eq!(delta.to_lsp_edits(&old)[0].range, LspRange { start : position(1, 0), end : position(1, 0) });
+		assert!(Delta::new().to_lsp_edits(&old).is_empty());
+	}
+}
@@ -0,0 +1,179 @@
+use super::ImmutableText;
+
+/// <summary>
+/// Line metrics cached on every node. A subtree sees its first and last lines
+/// only partially, so they are tracked apart from the complete lines: the
+/// metrics of a composite are computed from those of its children in O(1).
+/// </summary>
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LineMetrics {
+	pub(crate) newlines : usize,
+	pub(crate) first_chars : usize,
+	pub(crate) first_width : usize,
+	pub(crate) last_chars : usize,
+	pub(crate) last_width : usize,
+	pub(crate) max_chars : usize,
+	pub(crate) max_width : usize,
+	/// <summary>The length of the UTF-8 encoding of the characters, to find byte offsets in O(log n).</summary>
+	pub(crate) bytes : usize
+}
+
+impl LineMetrics {
+	/// <summary>
+	/// Computes the metrics of the given characters line by line: the lines are found
+	/// and measured with whole-slice scans the compiler vectorizes, and the width of a
+	/// line is only computed character by character when it is not printable ASCII.
+	/// </summary>
+	pub(crate) fn of_chars(data : &[char]) -> LineMetrics {
+		let bytes = data.iter().map(|&c| c.len_utf8()).sum();
+		LineMetrics { bytes, ..LineMetrics::of_lines(data.split(|&c| c == '\n').map(|line| (line.len(), line_width(line)))) }
+	}
+
+	/// <summary>Computes the metrics of ASCII text given as bytes, without decoding it.</summary>
+	pub(crate) fn of_ascii(data : &[u8]) -> LineMetrics {
+		// Control characters other than tabs take no column.
+		let metrics = LineMetrics::of_lines(data.split(|&b| b == b'\n').map(|line| {
+			(line.len(), line.len() - line.iter().filter(|&&b| (b < 0x20 && b != 0x09) || b == 0x7F).count())
+		}));
+		LineMetrics { bytes : data.len(), ..metrics }
+	}
+
+	/// <summary>Folds the length and width of consecutive lines, separated by newlines.</summary>
+	fn of_lines<I : Iterator<Item = (usize, usize)>>(mut lines : I) -> LineMetrics {
+		let (chars, width) = lines.next().unwrap_or_default();
+		let mut metrics = LineMetrics {
+			newlines : 0,
+			first_chars : chars,
+			first_width : width,
+			last_chars : chars,
+			last_width : width,
+			max_chars : chars,
+			max_width : width,
+			bytes : 0
+		};
+		for (chars, width) in lines {
+			metrics.newlines += 1;
+			metrics.last_chars = chars;
+			metrics.last_width = width;
+			metrics.max_chars = metrics.max_chars.max(chars);
+			metrics.max_width = metrics.max_width.max(width);
+		}
+		metrics
+	}
+
+	pub(crate) fn combine(&self, next : &LineMetrics) -> LineMetrics {
+		// The last line of this part continues on the first line of the next.
+		let joined_chars = self.last_chars + next.first_chars;
+		let joined_width = self.last_width + next.first_width;
+		LineMetrics {
+			newlines : self.newlines + next.newlines,
+			first_chars : if self.newlines == 0 { joined_chars } else { self.first_chars },
+			first_width : if self.newlines == 0 { joined_width } else { self.first_width },
+			last_chars : if next.newlines == 0 { joined_chars } else { next.last_chars },
+			last_width : if next.newlines == 0 { joined_width } else { next.last_width },
+			max_chars : self.max_chars.max(next.max_chars).max(joined_chars),
+			max_width : self.max_width.max(next.max_width).max(joined_width),
+			bytes : self.bytes + next.bytes
+		}
+	}
+}
+
+/// <summary>Returns the number of columns a line without newline occupies, see <code>char_width</code>.</summary>
+fn line_width(line : &[char]) -> usize {
+	if line.iter().all(|&c| (' '..='~').contains(&c)) {
+		return line.len();
+	}
+	line.iter().map(|&c| char_width(c)).sum()
+}
+
+/// <summary>
+/// Returns the number of terminal columns a character occupies: 0 for control
+/// and combining characters, 2 for East Asian wide characters and emoji, 1 otherwise.
+/// Tabs count as one column, their expansion depends on the column they start at.
+/// </summary>
+pub(crate) fn char_width(c : char) -> usize {
+	let c = c as u32;
+	if c == 0x09 {
+		return 1;
+	}
+	if c < 0x20 || (0x7F..0xA0).contains(&c) {
+		return 0;
+	}
+	const ZERO_WIDTH : [(u32, u32); 11] = [
+		(0x0300, 0x036F), (0x0483, 0x0489), (0x0591, 0x05BD), (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF),
+		(0x200B, 0x200F), (0x2028, 0x202E), (0x2060, 0x2064), (0x20D0, 0x20FF), (0xFE00, 0xFE0F),
+		(0xFE20, 0xFE2F)
+	];
+	const WIDE : [(u32, u32); 15] = [
+		(0x1100, 0x115F), (0x2E80, 0x303E), (0x3041, 0x33FF), (0x3400, 0x4DBF), (0x4E00, 0x9FFF),
+		(0xA000, 0xA4CF), (0xAC00, 0xD7A3), (0xF900, 0xFAFF), (0xFE30, 0xFE4F), (0xFF00, 0xFF60),
+		(0xFFE0, 0xFFE6), (0x1F300, 0x1F64F), (0x1F900, 0x1F9FF), (0x20000, 0x2FFFD), (0x30000, 0x3FFFD)
+	];
+	if c == 0xFEFF || ZERO_WIDTH.iter().any(|&(low, high)| low <= c && c <= high) {
+		return 0;
+	}
+	if WIDE.iter().any(|&(low, high)| low <= c && c <= high) {
+		return 2;
+	}
+	1
+}
+
+impl ImmutableText {
+	/// <summary>Returns the length in characters of the longest line, in O(1).</summary>
+	pub fn max_line_length(&self) -> usize {
+		self.root.metrics().max_chars
+	}
+
+	/// <summary>
+	/// Returns the display width in columns of the widest line, in O(1). A tab counts
+	/// as one column: its expansion depends on the column it starts at, which the
+	/// metrics of a subtree cannot know.
+	/// </summary>
+	pub fn max_line_width(&self) -> usize {
+		self.root.metrics().max_width
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	/// <summary>Checks the cached maxima against those measured line by line.</summary>
+	fn check_maxima(text : &ImmutableText) {
+		let content = text.to_string();
+		let lines = content.split('\n');
+		assert_eq!(text.max_line_length(), lines.clone().map(|line| line.chars().count()).max().unwrap(), "{:?}", content);
+		assert_eq!(text.max_line_width(), lines.map(|line| line.chars().map(char_width).sum::<usize>()).max().unwrap(), "{:?}", content);
+	}
+
+	#[test]
+	fn widths_of_wide_zero_width_and_tab_characters() {
+		let text = create_immutable_text("ab\n中文😀\ne\u{301}\t");
+		assert_eq!(text.max_line_length(), 3);
+		assert_eq!(text.max_line_width(), 6);
+		assert_eq!(create_immutable_text("e\u{301}\t").max_line_width(), 2);
+		assert_eq!(create_immutable_text("").max_line_length(), 0);
+	}
+
+	#[test]
+	fn maxima_after_concat_split_and_edits() {
+		let config = TextConfig::new().with_block_size(16);
+		let short = config.create(&"short line\n".repeat(20));
+		let long = config.create(&("中".repeat(30) + "\n" + &"x".repeat(40)));
+		check_maxima(&short);
+		check_maxima(&long);
+		// A line continues across the seam of a concatenation.
+		let joined = short.truncate(short.length() - 1).concat(&long);
+		assert_eq!(joined.max_line_length(), 40);
+		assert_eq!(joined.max_line_width(), 70);
+		check_maxima(&joined);
+		let (head, tail) = joined.split_off(joined.length() - 20);
+		check_maxima(&head);
+		check_maxima(&tail);
+		assert_eq!(tail.max_line_length(), 20);
+		let edited = joined.insert_string(215, "\n").remove_text(100, 30).insert_string(5, &"y".repeat(50));
+		check_maxima(&edited);
+		assert_eq!(edited.max_line_length(), 60);
+	}
+}
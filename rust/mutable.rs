@@ -0,0 +1,362 @@
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+
+use super::metrics::LineMetrics;
+#[cfg(feature = "shadow")]
+use super::shadow;
+use super::{create_leaf_node, splice_in_place, splice_leaf, splices, ImmutableText, Node, Root, Shared, TextConfig};
+
+/// <summary>
+/// A leaf kept as a gap buffer: the characters before the edit point in order, the
+/// ones after it reversed, so that consecutive edits at one point are O(1) instead
+/// of shifting the rest of the leaf.
+/// </summary>
+pub(crate) struct GapLeafNode {
+	before : Vec<char>,
+	after : Vec<char>,
+	before_metrics : LineMetrics,
+	after_metrics : LineMetrics,
+	metrics : LineMetrics
+}
+
+impl GapLeafNode {
+	/// <summary>Materializes the given leaf as a gap buffer, with the gap at its end.</summary>
+	pub(crate) fn new(leaf : &dyn Node) -> GapLeafNode {
+		let mut before = vec!['\0'; leaf.length()];
+		leaf.copy_to(0, &mut before, 0, leaf.length());
+		GapLeafNode {
+			before,
+			after : Vec::new(),
+			before_metrics : *leaf.metrics(),
+			after_metrics : LineMetrics::default(),
+			metrics : *leaf.metrics()
+		}
+	}
+
+	fn to_chars(&self) -> Vec<char> {
+		let mut chars = Vec::with_capacity(self.length());
+		chars.extend_from_slice(&self.before);
+		chars.extend(self.after.iter().rev());
+		chars
+	}
+}
+
+impl Node for GapLeafNode {
+	fn length(&self) -> usize {
+		self.before.len() + self.after.len()
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		create_leaf_node(self.to_chars()[start..end].to_vec(), config)
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		if offset < self.before.len() {
+			return self.before[offset];
+		}
+		self.after[self.length() - 1 - offset]
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		let destination = &mut destination[destination_index..destination_index + count];
+		// Copies the part before the gap as one block, then the reversed part after it.
+		let in_before = self.before.len().saturating_sub(source_index).min(count);
+		if in_before > 0 {
+			destination[..in_before].copy_from_slice(&self.before[source_index..source_index + in_before]);
+		}
+		if in_before < count {
+			let after_end = self.length() - source_index - in_before;
+			let after = &self.after[after_end - (count - in_before)..after_end];
+			for (target, &c) in destination[in_before..].iter_mut().zip(after.iter().rev()) {
+				*target = c;
+			}
+		}
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<GapLeafNode>() + (self.before.capacity() + self.after.capacity()) * mem::size_of::<char>()
+	}
+
+	fn splice_chars(&mut self, start : usize, removed : usize, data : &[char], config : &TextConfig) -> bool {
+		if !splices(self.length(), removed, data.len(), config) {
+			return false;
+		}
+		if start != self.before.len() || removed > 0 {
+			// Moves the gap to the edit point.
+			while self.before.len() > start {
+				let c = self.before.pop().unwrap();
+				self.after.push(c);
+			}
+			while self.before.len() < start {
+				let c = self.after.pop().unwrap();
+				self.before.push(c);
+			}
+			self.after.truncate(self.after.len() - removed);
+			let after : Vec<char> = self.after.iter().rev().copied().collect();
+			self.before_metrics = LineMetrics::of_chars(&self.before);
+			self.after_metrics = LineMetrics::of_chars(&after);
+		}
+		// Typing at the gap only costs the inserted characters.
+		self.before.extend_from_slice(data);
+		self.before_metrics = self.before_metrics.combine(&LineMetrics::of_chars(data));
+		self.metrics = self.before_metrics.combine(&self.after_metrics);
+		true
+	}
+
+	fn is_gap_buffer(&self) -> bool {
+		true
+	}
+}
+
+/// <summary>
+/// Turns the gap buffer leaf at <code>index</code> back into a normal leaf, if the
+/// path to it is not shared. On a leaf boundary, both adjacent leaves are candidates.
+/// </summary>
+/// <returns>true if a gap buffer was found and folded.</returns>
+fn fold_gap_leaf(node : &mut Shared<dyn Node>, index : usize, config : &TextConfig) -> bool {
+	if node.is_gap_buffer() {
+		let mut chars = vec!['\0'; node.length()];
+		node.copy_to(0, &mut chars, 0, node.length());
+		*node = create_leaf_node(chars, config);
+		return true;
+	}
+	let composite = match Shared::get_mut(node).and_then(|node| node.as_composite_mut()) {
+		Some(composite) => composite,
+		None => return false
+	};
+	let cesure = composite.head.length();
+	if index < cesure {
+		fold_gap_leaf(&mut composite.head, index, config)
+	} else if index > cesure {
+		fold_gap_leaf(&mut composite.tail, index - cesure, config)
+	} else {
+		fold_gap_leaf(&mut composite.head, index, config) || fold_gap_leaf(&mut composite.tail, 0, config)
+	}
+}
+
+/// <summary>
+/// A text edited in place, for edit-heavy phases such as bulk refactorings. The
+/// nodes it holds are owned by it alone as long as no snapshot is taken, so an edit
+/// within a leaf modifies that leaf rather than copying the path down to it. Edits
+/// that cannot be done in place fall back to those of <code>ImmutableText</code>,
+/// whose new nodes are then owned by this text alone as well.
+/// </summary>
+/// <remarks>
+/// The leaf at the edit point is kept as a gap buffer, so that typing or deleting at
+/// one location does not shift the rest of the leaf; it is folded back into a normal
+/// leaf when the edit point moves to another leaf, and on <code>freeze</code>.
+/// All the read operations of <code>ImmutableText</code> are available through <code>Deref</code>.
+/// </remarks>
+#[derive(Clone, Default)]
+pub struct MutableText {
+	text : ImmutableText,
+	cursor : Option<usize>
+}
+
+impl MutableText {
+	pub fn new() -> MutableText {
+		MutableText::default()
+	}
+
+	/// <summary>Returns the immutable text holding the same characters, in O(1) besides folding the gap buffer.</summary>
+	pub fn freeze(mut self) -> ImmutableText {
+		self.fold();
+		self.text
+	}
+
+	/// <summary>Returns a copy of the current content; the nodes become shared and are copied on the next edit to them.</summary>
+	pub fn snapshot(&self) -> ImmutableText {
+		self.text.clone()
+	}
+
+	pub fn insert_str(&mut self, index : usize, text : &str) {
+		self.replace(index, 0, text);
+	}
+
+	pub fn push_str(&mut self, text : &str) {
+		self.replace(self.text.length(), 0, text);
+	}
+
+	pub fn remove(&mut self, start : usize, count : usize) {
+		self.replace(start, count, "");
+	}
+
+	/// <summary>Replaces the <code>count</code> characters at <code>start</code> with the given text.</summary>
+	pub fn replace(&mut self, start : usize, count : usize, text : &str) {
+		if let Err(error) = self.text.check_range(start, count) {
+			panic!("{}", error);
+		}
+		if count == 0 && text.is_empty() {
+			return;
+		}
+		#[cfg(feature = "shadow")]
+		let expected = shadow::splice(&self.text.to_string(), start, count, text);
+		self.splice(start, count, text);
+		#[cfg(feature = "shadow")]
+		shadow::check("MutableText::replace", &self.text, &expected);
+	}
+
+	/// <summary>Replaces the characters in place when the nodes allow it, or through the operations of <code>ImmutableText</code>.</summary>
+	fn splice(&mut self, start : usize, count : usize, text : &str) {
+		let moved = match splice_leaf(&*self.text.root, start, count) {
+			Some(leaf) => !leaf.is_gap_buffer(),
+			None => true
+		};
+		if moved {
+			self.fold();
+		}
+		let config = self.text.config;
+		self.text.cache.clear();
+		if let Root::Node(root) = &mut self.text.root {
+			let data : Vec<char> = text.chars().collect();
+			if splice_in_place(root, start, count, &data, &config, true) {
+				self.cursor = Some(start + data.len());
+				return;
+			}
+		}
+		// Takes the text so that the old nodes are dropped, and the new ones left unshared.
+		let text_before = mem::take(&mut self.text);
+		self.text = text_before.get_text(0, start).append(&config.create(text)).concat(&text_before.sub_text(start + count));
+	}
+
+	/// <summary>Folds the gap buffer at the edit point, if any, back into a normal leaf.</summary>
+	fn fold(&mut self) {
+		self.text.cache.clear();
+		if let (Some(cursor), Root::Node(root)) = (self.cursor.take(), &mut self.text.root) {
+			fold_gap_leaf(root, cursor, &self.text.config);
+		}
+	}
+}
+
+impl Deref for MutableText {
+	type Target = ImmutableText;
+
+	fn deref(&self) -> &ImmutableText {
+		&self.text
+	}
+}
+
+impl fmt::Debug for MutableText {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&self.text, f)
+	}
+}
+
+impl fmt::Display for MutableText {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(&self.text, f)
+	}
+}
+
+impl ImmutableText {
+	/// <summary>Returns a mutable text holding the same characters, in O(1).</summary>
+	pub fn thaw(self) -> MutableText {
+		MutableText { text : self, cursor : None }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{collect_leaves, create_immutable_text};
+	use super::*;
+
+	/// <summary>Returns the number of leaves of the text kept as gap buffers.</summary>
+	fn gap_leaves(text : &ImmutableText) -> usize {
+		match &text.root {
+			Root::Node(root) => collect_leaves(root).iter().filter(|leaf| leaf.is_gap_buffer()).count(),
+			Root::Inline(_) => 0
+		}
+	}
+
+	/// <summary>Checks the text and the metrics of every leaf against the expected content.</summary>
+	fn check(text : &MutableText, expected : &str) {
+		assert_eq!(text.to_string(), expected);
+		assert!(text.validate().is_ok());
+		assert_eq!(text.line_count(), expected.split('\n').count());
+		assert_eq!(text.stats(), create_immutable_text(expected).stats());
+	}
+
+	#[test]
+	fn typing_and_deleting_at_one_point() {
+		let initial = "line one\r\nline two\r\n".repeat(20);
+		let mut expected = initial.clone();
+		let mut text = create_immutable_text(&initial).thaw();
+		let mut cursor = 30;
+		for c in "typed\r\nwords é 🦀".chars() {
+			text.insert_str(cursor, &c.to_string());
+			expected.insert(expected.char_indices().nth(cursor).unwrap().0, c);
+			cursor += 1;
+			check(&text, &expected);
+		}
+		assert_eq!(gap_leaves(&text), 1);
+		// Backspace, then delete forward, both at the gap.
+		for start in [cursor - 1, cursor - 2] {
+			text.remove(start, 1);
+			let offset = expected.char_indices().nth(start).unwrap().0;
+			expected.remove(offset);
+			check(&text, &expected);
+		}
+		text.remove(25, 2);
+		expected.replace_range(25..27, "");
+		check(&text, &expected);
+		assert_eq!(text.freeze().to_string(), expected);
+	}
+
+	#[test]
+	fn moving_to_another_leaf_folds_the_gap() {
+		let initial = "abcdefgh\n".repeat(200);
+		let mut text = create_immutable_text(&initial).thaw();
+		// Full leaves are cut by the first edit, the second goes in place.
+		for index in [5, 6] {
+			text.insert_str(index, "x");
+		}
+		assert_eq!(gap_leaves(&text), 1);
+		for _ in 0..2 {
+			text.insert_str(text.length() - 5, "y");
+		}
+		assert_eq!(gap_leaves(&text), 1);
+		text.push_str("\r\n");
+		let expected = format!("abcdexx{}yy{}\r\n", &initial[5..initial.len() - 5], &initial[initial.len() - 5..]);
+		check(&text, &expected);
+		let frozen = text.freeze();
+		assert_eq!(gap_leaves(&frozen), 0);
+		assert_eq!(frozen.to_string(), expected);
+	}
+
+	#[test]
+	fn snapshots_do_not_see_later_edits() {
+		let mut text = create_immutable_text(&"snapshot\n".repeat(100)).thaw();
+		text.insert_str(10, "before");
+		let snapshot = text.snapshot();
+		text.insert_str(16, " after");
+		text.remove(0, 3);
+		assert_eq!(snapshot.to_string(), format!("snapshot\nsbeforenapshot\n{}", "snapshot\n".repeat(98)));
+		assert_eq!(text.to_string(), format!("pshot\nsbefore afternapshot\n{}", "snapshot\n".repeat(98)));
+	}
+
+	#[test]
+	fn edits_an_empty_text() {
+		let mut text = MutableText::new();
+		text.remove(0, 0);
+		check(&text, "");
+		text.push_str("a\r\n");
+		text.insert_str(0, "é");
+		check(&text, "éa\r\n");
+		text.remove(0, 4);
+		check(&text, "");
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds: 3..5")]
+	fn rejects_ranges_past_the_end() {
+		create_immutable_text("abc\r").thaw().remove(3, 2);
+	}
+}
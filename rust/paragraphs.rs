@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use super::ImmutableText;
+
+/// <summary>
+/// Iterates over the paragraphs of a text: the runs of lines that are not blank,
+/// separated by lines holding only whitespace. See <code>ImmutableText::paragraphs</code>.
+/// </summary>
+pub struct Paragraphs<'a> {
+	text : &'a ImmutableText,
+	/// <summary>The line the next paragraph is searched from.</summary>
+	line : usize
+}
+
+impl Paragraphs<'_> {
+	fn is_blank(&self, line : usize) -> bool {
+		self.text.chars_range(self.text.line_start(line), self.text.line_end(line)).all(char::is_whitespace)
+	}
+}
+
+impl Iterator for Paragraphs<'_> {
+	type Item = (Range<usize>, ImmutableText);
+
+	fn next(&mut self) -> Option<(Range<usize>, ImmutableText)> {
+		let count = self.text.line_count();
+		while self.line < count && self.is_blank(self.line) {
+			self.line += 1;
+		}
+		if self.line == count {
+			return None;
+		}
+		let first = self.line;
+		while self.line < count && !self.is_blank(self.line) {
+			self.line += 1;
+		}
+		let start = self.text.line_start(first);
+		let mut end = self.text.line_end(self.line - 1);
+		if end < self.text.length() && self.text.get_char_at(end - 1) == '\r' {
+			end -= 1;
+		}
+		Some((start..end, self.text.get_text(start, end - start)))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns an iterator over the paragraphs, each with its range and as a sub-text
+	/// sharing the leaves of this text, for "select paragraph" or reflowing commands.
+	/// A paragraph spans its lines up to the newline or CR LF ending the last one, excluded.
+	/// </summary>
+	pub fn paragraphs(&self) -> Paragraphs<'_> {
+		Paragraphs { text : self, line : 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	fn paragraphs(source : &str) -> Vec<(usize, usize, String)> {
+		create_immutable_text(source).paragraphs().map(|(range, text)| (range.start, range.end, text.to_string())).collect()
+	}
+
+	#[test]
+	fn splits_on_blank_lines() {
+		assert_eq!(paragraphs("a\nb\n\nc"), vec![(0, 3, "a\nb".to_string()), (5, 6, "c".to_string())]);
+		assert_eq!(paragraphs("\n\n a\n\n\nb\n\n"), vec![(2, 4, " a".to_string()), (7, 8, "b".to_string())]);
+		assert!(paragraphs("").is_empty());
+		assert!(paragraphs("\n \n\t\n").is_empty());
+	}
+
+	#[test]
+	fn separates_on_whitespace_only_lines() {
+		assert_eq!(paragraphs("a\n  \t\nb\n\u{3000}\nc"), vec![(0, 1, "a".to_string()), (6, 7, "b".to_string()), (10, 11, "c".to_string())]);
+	}
+
+	#[test]
+	fn excludes_the_carriage_return_ending_a_paragraph() {
+		assert_eq!(paragraphs("a\r\nb\r\n\r\nc\r\n"), vec![(0, 4, "a\r\nb".to_string()), (8, 9, "c".to_string())]);
+		assert_eq!(paragraphs("a\r"), vec![(0, 2, "a\r".to_string())]);
+	}
+
+	#[test]
+	fn finds_paragraphs_across_leaves() {
+		let paragraph = "a line of a long paragraph\n".repeat(20);
+		let source = format!("{}\n{}", paragraph, paragraph);
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let found : Vec<_> = text.paragraphs().collect();
+		assert_eq!(found.len(), 2);
+		assert_eq!(found[1].1.to_string(), paragraph.trim_end());
+		assert_eq!(found[1].1.config(), text.config());
+		assert_eq!(found[1].0.start, paragraph.len() + 1);
+	}
+}
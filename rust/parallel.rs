@@ -0,0 +1,300 @@
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::ParallelIterator;
+
+use super::{Chars, ImmutableText, LeafChars, Node};
+
+/// <summary>Holds the number of characters below which a range is searched by a single thread.</summary>
+const SEQUENTIAL_SEARCH : usize = 1 << 16;
+
+/// <summary>Holds the number of characters below which a range is copied or encoded by a single thread.</summary>
+const SEQUENTIAL_COPY : usize = 1 << 16;
+
+/// <summary>
+/// A parallel iterator over the leaves of a text, yielding the offset of every leaf
+/// and an iterator over its characters. The tree is split at its composite nodes, so
+/// the independent subtrees are scanned by different threads.
+/// </summary>
+pub struct ParChunks<'a> {
+	node : &'a dyn Node,
+	offset : usize
+}
+
+impl<'a> ParallelIterator for ParChunks<'a> {
+	type Item = (usize, Chars<'a>);
+
+	fn drive_unindexed<C>(self, consumer : C) -> C::Result where C : UnindexedConsumer<Self::Item> {
+		bridge_unindexed(self, consumer)
+	}
+}
+
+impl<'a> UnindexedProducer for ParChunks<'a> {
+	type Item = (usize, Chars<'a>);
+
+	fn split(self) -> (ParChunks<'a>, Option<ParChunks<'a>>) {
+		match self.node.as_composite() {
+			Some(composite) => (
+				ParChunks { node : &*composite.head, offset : self.offset },
+				Some(ParChunks { node : &*composite.tail, offset : self.offset + composite.head.length() })
+			),
+			None => (self, None)
+		}
+	}
+
+	/// <summary>Walks the leaves of the subtree left to right, once rayon stops splitting it.</summary>
+	fn fold_with<F>(self, mut folder : F) -> F where F : Folder<Self::Item> {
+		let mut stack = vec![(self.node, self.offset)];
+		while let Some((node, offset)) = stack.pop() {
+			if folder.full() {
+				break;
+			}
+			match node.as_composite() {
+				Some(composite) => {
+					stack.push((&*composite.tail, offset + composite.head.length()));
+					stack.push((&*composite.head, offset));
+				}
+				None if node.length() == 0 => {}
+				None => folder = folder.consume((offset, Chars::new(node, 0, node.length())))
+			}
+		}
+		folder
+	}
+}
+
+/// <summary>Returns the start of every occurrence of the pattern in the given characters, overlapping ones included.</summary>
+fn find_overlapping(chars : &[char], pattern : &[char]) -> Vec<usize> {
+	chars.windows(pattern.len()).enumerate().filter(|(_, window)| *window == pattern).map(|(i, _)| i).collect()
+}
+
+/// <summary>
+/// Finds the occurrences of the pattern between <code>start</code> and <code>end</code> of
+/// the node, overlapping ones included. The range is split at the cesure of the composites
+/// it spans, or in halves within a large leaf, and both sides are searched concurrently;
+/// the occurrences spanning the split are then found in the window around it.
+/// </summary>
+fn find_in(node : &dyn Node, start : usize, end : usize, pattern : &[char]) -> Vec<usize> {
+	if end - start < pattern.len() {
+		return Vec::new();
+	}
+	if end - start <= SEQUENTIAL_SEARCH {
+		let chars : Vec<char> = Chars::new(node, start, end).collect();
+		return find_overlapping(&chars, pattern).into_iter().map(|i| start + i).collect();
+	}
+	let split = match node.as_composite() {
+		Some(composite) => {
+			let cesure = composite.head.length();
+			if end <= cesure {
+				return find_in(&*composite.head, start, end, pattern);
+			}
+			if start >= cesure {
+				return find_in(&*composite.tail, start - cesure, end - cesure, pattern).into_iter().map(|i| cesure + i).collect();
+			}
+			cesure
+		}
+		None => start + (end - start) / 2
+	};
+	let (mut found, tail) = rayon::join(|| find_in(node, start, split, pattern), || find_in(node, split, end, pattern));
+	// Stitches the occurrences starting before the split and ending after it.
+	let window_start = split.saturating_sub(pattern.len() - 1).max(start);
+	let window_end = (split + pattern.len() - 1).min(end);
+	let window : Vec<char> = Chars::new(node, window_start, window_end).collect();
+	found.extend(find_overlapping(&window, pattern).into_iter().map(|i| window_start + i).filter(|&i| i < split));
+	found.extend(tail);
+	found
+}
+
+/// <summary>
+/// Copies the characters of the node from <code>start</code> into the destination,
+/// which is split at the cesure of the composites it spans, or in halves within a
+/// large leaf, so that both sides are filled concurrently.
+/// </summary>
+fn copy_in(node : &dyn Node, start : usize, destination : &mut [char]) {
+	let count = destination.len();
+	if count <= SEQUENTIAL_COPY {
+		node.copy_to(start, destination, 0, count);
+		return;
+	}
+	let split = match node.as_composite() {
+		Some(composite) => {
+			let cesure = composite.head.length();
+			if start + count <= cesure {
+				return copy_in(&*composite.head, start, destination);
+			}
+			if start >= cesure {
+				return copy_in(&*composite.tail, start - cesure, destination);
+			}
+			cesure - start
+		}
+		None => count / 2
+	};
+	let (head, tail) = destination.split_at_mut(split);
+	rayon::join(|| copy_in(node, start, head), || copy_in(node, start + split, tail));
+}
+
+/// <summary>Encodes the characters of the node as UTF-8 into the destination, leaf by leaf.</summary>
+fn encode_sequential(node : &dyn Node, destination : &mut [u8]) {
+	let mut stack = vec![node];
+	let mut position = 0;
+	while let Some(node) = stack.pop() {
+		if let Some(composite) = node.as_composite() {
+			stack.push(&*composite.tail);
+			stack.push(&*composite.head);
+			continue;
+		}
+		match node.as_utf8() {
+			Some(text) => {
+				destination[position..position + text.len()].copy_from_slice(text.as_bytes());
+				position += text.len();
+			}
+			None => {
+				for c in LeafChars::new(node, 0, node.length()) {
+					position += c.encode_utf8(&mut destination[position..]).len();
+				}
+			}
+		}
+	}
+}
+
+/// <summary>
+/// Encodes the characters of the node as UTF-8 into the destination, sized by the
+/// byte metric of the node. The destination is split at the cesure of the composites,
+/// whose heads know the length of their encoding, so that both sides are encoded
+/// concurrently.
+/// </summary>
+fn encode_in(node : &dyn Node, destination : &mut [u8]) {
+	match node.as_composite() {
+		Some(composite) if node.length() > SEQUENTIAL_COPY => {
+			let (head, tail) = destination.split_at_mut(composite.head.metrics().bytes);
+			rayon::join(|| encode_in(&*composite.head, head), || encode_in(&*composite.tail, tail));
+		}
+		_ => encode_sequential(node, destination)
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns a rayon parallel iterator over the leaves of this text, for scans such
+	/// as counting, hashing or linting a large document on all cores. Leaves are
+	/// yielded with their offset in the text.
+	/// </summary>
+	pub fn par_chunks(&self) -> ParChunks<'_> {
+		ParChunks { node : &*self.root, offset : 0 }
+	}
+
+	/// <summary>
+	/// Returns the offsets of the non-overlapping occurrences of the pattern, like
+	/// <code>count_matches</code> counts them, searching the independent subtrees of
+	/// a large text concurrently.
+	/// </summary>
+	/// <returns>the offsets in increasing order; an empty pattern never matches.</returns>
+	pub fn par_find_all(&self, pattern : &str) -> Vec<usize> {
+		let pattern : Vec<char> = pattern.chars().collect();
+		if pattern.is_empty() {
+			return Vec::new();
+		}
+		let mut matches = Vec::new();
+		for start in find_in(&*self.root, 0, self.length(), &pattern) {
+			// Keeps the leftmost occurrences, skipping those overlapping the previous one.
+			if matches.last().is_none_or(|&last| start >= last + pattern.len()) {
+				matches.push(start);
+			}
+		}
+		matches
+	}
+
+	/// <summary>
+	/// Copies the characters from <code>start</code> into the destination, filling the
+	/// slices of the destination matching independent subtrees concurrently.
+	/// </summary>
+	pub fn par_copy_to(&self, start : usize, destination : &mut [char]) {
+		if let Err(error) = self.check_range(start, destination.len()) {
+			panic!("{}", error);
+		}
+		copy_in(&*self.root, start, destination);
+	}
+
+	/// <summary>
+	/// Returns the content of this text as a string, encoding its independent subtrees
+	/// concurrently in place, in a buffer allocated once.
+	/// </summary>
+	pub fn par_to_string(&self) -> String {
+		let mut bytes = vec![0; self.root.metrics().bytes];
+		encode_in(&*self.root, &mut bytes);
+		String::from_utf8(bytes).expect("leaves encode to UTF-8")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	/// <summary>Returns a document of about <code>lines</code> lines mixing ASCII and wide leaves.</summary>
+	fn document(lines : usize) -> String {
+		(0..lines).map(|i| if i % 3 == 0 { format!("ligne {} été ✓ 😀\n", i) } else { format!("line {}\n", i) }).collect()
+	}
+
+	#[test]
+	fn par_to_string_encodes_every_leaf() {
+		let content = document(20_000);
+		assert!(content.chars().count() > 2 * SEQUENTIAL_COPY);
+		let text = TextConfig::new().with_block_size(64).create(&content);
+		assert_eq!(text.par_to_string(), content);
+		let edited = text.insert_string(1000, "ü").remove_text(SEQUENTIAL_COPY, 10);
+		assert_eq!(edited.par_to_string(), edited.to_string());
+		assert_eq!(create_immutable_text("").par_to_string(), "");
+		assert_eq!(create_immutable_text("é").par_to_string(), "é");
+	}
+
+	#[test]
+	fn par_chunks_yield_the_leaves_of_chunks_in() {
+		let content = document(2_000);
+		let text = TextConfig::new().with_block_size(64).create(&content);
+		let mut expected = Vec::new();
+		let mut offset = 0;
+		for chunk in text.chunks_in(0..text.length()) {
+			let length = chunk.chars().count();
+			expected.push((offset, chunk.into_owned()));
+			offset += length;
+		}
+		assert!(expected.len() > 100);
+		let chunks : Vec<(usize, String)> = text.par_chunks().map(|(offset, chars)| (offset, chars.collect())).collect();
+		assert_eq!(chunks, expected);
+		assert_eq!(create_immutable_text("").par_chunks().count(), 0);
+	}
+
+	/// <summary>Collects the offsets of the cesures of the composites of the node.</summary>
+	fn cesures(node : &dyn Node, offset : usize, result : &mut Vec<usize>) {
+		if let Some(composite) = node.as_composite() {
+			cesures(&*composite.head, offset, result);
+			result.push(offset + composite.head.length());
+			cesures(&*composite.tail, offset + composite.head.length(), result);
+		}
+	}
+
+	#[test]
+	fn par_find_all_finds_the_matches_straddling_every_cesure() {
+		let length = 4 * SEQUENTIAL_SEARCH;
+		let text = TextConfig::new().with_block_size(64).create(&"x".repeat(length));
+		let mut splits = Vec::new();
+		cesures(&*text.root, 0, &mut splits);
+		// Self-overlapping runs across the cesures, so that the stitched windows must keep the leftmost match.
+		let mut content : Vec<char> = "x".repeat(length).chars().collect();
+		for &split in &splits {
+			content[split - 2..split + 3].copy_from_slice(&['a', 'b', 'a', 'b', 'a']);
+		}
+		let content : String = content.into_iter().collect();
+		let text = TextConfig::new().with_block_size(64).create(&content);
+		let mut after = Vec::new();
+		cesures(&*text.root, 0, &mut after);
+		assert_eq!(after, splits);
+
+		for pattern in ["aba", "bab", "xab", "bax", "a"] {
+			let found = text.par_find_all(pattern);
+			let expected : Vec<usize> = text.find_all_in(pattern, 0..text.length()).into_iter().map(|found| found.start).collect();
+			assert_eq!(found, expected, "{}", pattern);
+			assert_eq!(found.len(), text.count_matches(pattern), "{}", pattern);
+		}
+		let found = text.par_find_all("aba");
+		assert!(splits.iter().all(|&split| found.iter().any(|&start| start < split && split < start + 3)));
+	}
+}
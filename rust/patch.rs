@@ -0,0 +1,283 @@
+use std::error;
+use std::fmt;
+
+use super::{Delta, ImmutableText};
+
+/// <summary>A line of a hunk: context, removed or added, without its newline.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HunkLine {
+	Context(String),
+	Removed(String),
+	Added(String)
+}
+
+/// <summary>A hunk of a unified diff: the lines around a change, from line <code>old_start</code> of the old text (from 1).</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+	pub old_start : usize,
+	pub lines : Vec<HunkLine>
+}
+
+impl Hunk {
+	/// <summary>Returns the lines the hunk expects, its context and removed lines, without the first and last <code>fuzz</code> context lines.</summary>
+	fn old_lines(&self, fuzz : usize) -> (Vec<&str>, usize) {
+		let (leading, trailing) = self.trimmed_context(fuzz);
+		let lines = self.lines[leading..self.lines.len() - trailing].iter().filter_map(|line| match line {
+			HunkLine::Context(text) | HunkLine::Removed(text) => Some(text.as_str()),
+			HunkLine::Added(_) => None
+		}).collect();
+		(lines, leading)
+	}
+
+	/// <summary>Returns the lines replacing them, its context and added lines, with the same context lines left out.</summary>
+	fn new_lines(&self, fuzz : usize) -> Vec<&str> {
+		let (leading, trailing) = self.trimmed_context(fuzz);
+		self.lines[leading..self.lines.len() - trailing].iter().filter_map(|line| match line {
+			HunkLine::Context(text) | HunkLine::Added(text) => Some(text.as_str()),
+			HunkLine::Removed(_) => None
+		}).collect()
+	}
+
+	/// <summary>Returns the numbers of context lines to leave out at the start and at the end for the fuzz.</summary>
+	fn trimmed_context(&self, fuzz : usize) -> (usize, usize) {
+		let is_context = |line : &&HunkLine| matches!(line, HunkLine::Context(_));
+		let leading = self.lines.iter().take_while(is_context).count().min(fuzz);
+		let trailing = self.lines.iter().skip(leading).rev().take_while(is_context).count().min(fuzz);
+		(leading, trailing)
+	}
+}
+
+/// <summary>The hunks of a unified diff of one file.</summary>
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+	pub hunks : Vec<Hunk>
+}
+
+/// <summary>A line of a unified diff that could not be parsed, numbered from 1.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchParseError {
+	pub line : usize
+}
+
+impl fmt::Display for PatchParseError {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "malformed patch at line {}", self.line)
+	}
+}
+
+impl error::Error for PatchParseError {}
+
+/// <summary>Parses a range of a hunk header, <code>start[,count]</code>, the count 1 if left out.</summary>
+fn parse_range(range : &str) -> Option<(usize, usize)> {
+	let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+	Some((start.parse().ok()?, count.parse().ok()?))
+}
+
+impl Patch {
+	/// <summary>
+	/// Parses a unified diff of one file. Every hunk is read for exactly the numbers of
+	/// old and new lines its header gives, so that removed lines starting with "-- " are
+	/// not taken for file headers. The lines before the first hunk and between hunks are
+	/// skipped, as are the "No newline at end of file" markers; the headers of a next
+	/// file end the patch.
+	/// </summary>
+	/// <returns>the patch, or an error at the first line not matching the counts of its hunk.</returns>
+	pub fn parse(diff : &str) -> Result<Patch, PatchParseError> {
+		let mut hunks : Vec<Hunk> = Vec::new();
+		// The old and new lines the current hunk still has to read.
+		let mut remaining = (0, 0);
+		let mut line_count = 0;
+		// Carriage returns are kept, as part of the lines of CR LF texts.
+		for (number, line) in diff.split_terminator('\n').enumerate() {
+			let error = PatchParseError { line : number + 1 };
+			line_count = number + 1;
+			if remaining == (0, 0) {
+				if let Some(header) = line.strip_prefix("@@ -") {
+					let mut ranges = header.trim_end_matches('\r').split(' ');
+					let (old_start, old_count) = ranges.next().and_then(parse_range).ok_or(error)?;
+					let (_, new_count) = ranges.next().and_then(|range| range.strip_prefix('+')).and_then(parse_range).ok_or(error)?;
+					if ranges.next() != Some("@@") {
+						return Err(error);
+					}
+					hunks.push(Hunk { old_start, lines : Vec::new() });
+					remaining = (old_count, new_count);
+				} else if line.starts_with("--- ") && !hunks.is_empty() {
+					// The headers of the next file end this one.
+					break;
+				}
+				continue;
+			}
+			let hunk = hunks.last_mut().unwrap();
+			let (old, new) = remaining;
+			match line.chars().next() {
+				// Some tools strip the space of empty context lines.
+				Some('\r') | None if line.len() <= 1 && old > 0 && new > 0 => {
+					hunk.lines.push(HunkLine::Context(line.to_string()));
+					remaining = (old - 1, new - 1);
+				}
+				Some(' ') if old > 0 && new > 0 => {
+					hunk.lines.push(HunkLine::Context(line[1..].to_string()));
+					remaining = (old - 1, new - 1);
+				}
+				Some('-') if old > 0 => {
+					hunk.lines.push(HunkLine::Removed(line[1..].to_string()));
+					remaining = (old - 1, new);
+				}
+				Some('+') if new > 0 => {
+					hunk.lines.push(HunkLine::Added(line[1..].to_string()));
+					remaining = (old, new - 1);
+				}
+				Some('\\') => {}
+				_ => return Err(error)
+			}
+		}
+		if remaining != (0, 0) {
+			// The diff ends within a hunk.
+			return Err(PatchParseError { line : line_count + 1 });
+		}
+		Ok(Patch { hunks })
+	}
+}
+
+/// <summary>What became of a hunk of a patch.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HunkResult {
+	/// <summary>
+	/// Applied <code>offset</code> lines from where its header said, after leaving out
+	/// <code>fuzz</code> context lines at each end.
+	/// </summary>
+	Applied { offset : isize, fuzz : usize },
+	/// <summary>Its lines were found nowhere after the previous hunk.</summary>
+	Rejected
+}
+
+impl ImmutableText {
+	/// <summary>Returns the lines of the text without their newlines, the empty line after a final newline left out.</summary>
+	fn patch_lines(&self) -> Vec<String> {
+		let mut count = self.line_count();
+		if count > 1 && self.line_start(count - 1) == self.length() {
+			count -= 1;
+		}
+		(0..count).map(|line| self.chars_range(self.line_start(line), self.line_end(line)).collect()).collect()
+	}
+
+	/// <summary>
+	/// Applies the patch like GNU patch: a hunk whose lines moved is looked for at the
+	/// closest position to where its header says, shifted by the drift of the previous
+	/// hunks, and with a fuzz factor, up to <code>max_fuzz</code> context lines at each end of
+	/// a hunk may be left out to find it. The hunks found nowhere are rejected and the
+	/// others applied.
+	/// </summary>
+	/// <returns>the patched text, the delta from this text to it, and the result of every hunk.</returns>
+	pub fn apply_patch(&self, patch : &Patch, max_fuzz : usize) -> (ImmutableText, Delta, Vec<HunkResult>) {
+		let lines = self.patch_lines();
+		let ends_with_newline = self.length() > 0 && self.get_char_at(self.length() - 1) == '\n';
+		let mut delta = Delta::new();
+		let mut results = Vec::with_capacity(patch.hunks.len());
+		// The first line a hunk may touch, after the previous one applied.
+		let mut first_free = 0;
+		let mut drift = 0isize;
+		for hunk in &patch.hunks {
+			let found = (0..=max_fuzz).find_map(|fuzz| {
+				let (old, leading) = hunk.old_lines(fuzz);
+				let expected = (hunk.old_start.saturating_sub(1) as isize + drift + leading as isize).max(0) as usize;
+				let matches = |position : usize| position >= first_free && position + old.len() <= lines.len()
+					&& lines[position..position + old.len()].iter().zip(&old).all(|(line, expected)| line == expected);
+				// Tries the positions closest to the expected one first, alternating after and before it.
+				(0..=lines.len()).flat_map(|distance| [expected.checked_add(distance), expected.checked_sub(distance).filter(|_| distance > 0)])
+					.flatten().find(|&position| matches(position))
+					.map(|position| (position, old.len(), leading, fuzz))
+			});
+			let Some((position, length, leading, fuzz)) = found else {
+				results.push(HunkResult::Rejected);
+				continue;
+			};
+			let start = if position < lines.len() { self.line_start(position) } else { self.length() };
+			let reaches_end = position + length == lines.len();
+			let end = if reaches_end { self.length() } else { self.line_start(position + length) };
+			let mut inserted : String = hunk.new_lines(fuzz).iter().map(|line| format!("{}\n", line)).collect();
+			if reaches_end && !ends_with_newline && !inserted.is_empty() {
+				// The last line has no newline, and neither has the one replacing it.
+				inserted.pop();
+				if start == self.length() && start > 0 {
+					inserted.insert(0, '\n');
+				}
+			}
+			delta.push(start, end - start, &inserted);
+			let offset = position as isize - leading as isize - hunk.old_start.saturating_sub(1) as isize;
+			drift = offset;
+			first_free = position + length;
+			results.push(HunkResult::Applied { offset, fuzz });
+		}
+		(delta.apply(self), delta, results)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	#[test]
+	fn removed_lines_looking_like_headers() {
+		let diff = "--- a/schema.sql\n+++ b/schema.sql\n@@ -1,3 +1,2 @@\n select 1;\n--- a comment\n select 2;\n";
+		let patch = Patch::parse(diff).unwrap();
+		assert_eq!(patch.hunks[0].lines, vec![
+			HunkLine::Context("select 1;".to_string()),
+			HunkLine::Removed("-- a comment".to_string()),
+			HunkLine::Context("select 2;".to_string())
+		]);
+		let text = create_immutable_text("select 1;\n-- a comment\nselect 2;\n");
+		let (patched, _, results) = text.apply_patch(&patch, 0);
+		assert_eq!(patched.to_string(), "select 1;\nselect 2;\n");
+		assert_eq!(results, vec![HunkResult::Applied { offset : 0, fuzz : 0 }]);
+	}
+
+	#[test]
+	fn counts_must_match() {
+		// One line short of the header.
+		assert_eq!(Patch::parse("@@ -1,2 +1,2 @@\n a\n"), Err(PatchParseError { line : 3 }));
+		// A removed line past the old count.
+		assert_eq!(Patch::parse("@@ -1 +1 @@\n-a\n-b\n+c\n"), Err(PatchParseError { line : 3 }));
+		assert_eq!(Patch::parse("@@ -1 +1\n"), Err(PatchParseError { line : 1 }));
+		assert_eq!(Patch::parse("@@ -1 +1 @@\n*a\n"), Err(PatchParseError { line : 2 }));
+	}
+
+	#[test]
+	fn next_file_ends_the_patch() {
+		let diff = "@@ -1 +1 @@\n-a\n+b\n\\ No newline at end of file\ndiff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-c\n+d\n";
+		assert_eq!(Patch::parse(diff).unwrap().hunks.len(), 1);
+		assert_eq!(Patch::parse("").unwrap(), Patch::default());
+	}
+
+	#[test]
+	fn hunks_drift_and_fuzz() {
+		let text = create_immutable_text("0\n1\n2\na\nb\nc\n3\n4\nx\ny\nz\n");
+		// Both hunks say the lines are 2 lines above where they are.
+		let diff = "@@ -2,3 +2,3 @@\n a\n-b\n+B\n c\n@@ -7,3 +7,3 @@\n x\n-y\n+Y\n z\n";
+		let (patched, delta, results) = text.apply_patch(&Patch::parse(diff).unwrap(), 0);
+		assert_eq!(patched.to_string(), "0\n1\n2\na\nB\nc\n3\n4\nx\nY\nz\n");
+		assert_eq!(delta.apply(&text), patched);
+		assert_eq!(results, vec![HunkResult::Applied { offset : 2, fuzz : 0 }, HunkResult::Applied { offset : 2, fuzz : 0 }]);
+		// Changed context only applies with fuzz.
+		let diff = "@@ -4,3 +4,3 @@\n A\n-b\n+B\n c\n";
+		let patch = Patch::parse(diff).unwrap();
+		assert_eq!(text.apply_patch(&patch, 0).2, vec![HunkResult::Rejected]);
+		assert_eq!(text.apply_patch(&patch, 1).2, vec![HunkResult::Applied { offset : 0, fuzz : 1 }]);
+	}
+
+	#[test]
+	fn empty_text_and_missing_final_newline() {
+		let patch = Patch::parse("@@ -0,0 +1,2 @@\n+a\n+b\n").unwrap();
+		assert_eq!(create_immutable_text("").apply_patch(&patch, 0).0.to_string(), "a\nb\n");
+		let patch = Patch::parse("@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n").unwrap();
+		assert_eq!(create_immutable_text("a").apply_patch(&patch, 0).0.to_string(), "b");
+	}
+
+	#[test]
+	fn crlf_lines_keep_their_carriage_returns() {
+		let text = create_immutable_text("a\r\nb\r\n");
+		let patch = Patch::parse("@@ -2 +2 @@\n-b\r\n+c\r\n").unwrap();
+		assert_eq!(text.apply_patch(&patch, 0).0.to_string(), "a\r\nc\r\n");
+	}
+}
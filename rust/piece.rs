@@ -0,0 +1,332 @@
+use std::mem;
+
+use super::metrics::LineMetrics;
+use super::search::find_byte;
+#[cfg(feature = "shadow")]
+use super::shadow;
+use super::{create_leaf_node, ImmutableText, LeafCache, Node, Root, Shared, TextConfig};
+
+/// <summary>Holds the number of characters between two indexed byte offsets of a non-ASCII buffer.</summary>
+const OFFSET_BLOCK : usize = 32;
+
+/// <summary>Holds the number of characters whose line metrics are indexed together.</summary>
+const METRICS_BLOCK : usize = 4096;
+
+/// <summary>
+/// The original content of a file, kept as a single UTF-8 buffer shared by all the
+/// pieces referencing it. It is indexed so that any range of characters can be
+/// located in O(1) and have its line metrics computed in O(log n).
+/// </summary>
+struct PieceBuffer {
+	text : String,
+	length : usize,
+	/// <summary>The byte offset of every OFFSET_BLOCK-th character, empty for ASCII text.</summary>
+	offsets : Vec<usize>,
+	/// <summary>A segment tree over the metrics of the blocks of METRICS_BLOCK characters.</summary>
+	metrics : Vec<LineMetrics>,
+	leaves : usize
+}
+
+impl PieceBuffer {
+	fn new(text : String) -> PieceBuffer {
+		let ascii = text.is_ascii();
+		let mut offsets = Vec::new();
+		let mut block_metrics = Vec::new();
+		let mut length = 0;
+		if ascii {
+			// Measures the blocks on the bytes, without decoding them.
+			block_metrics.extend(text.as_bytes().chunks(METRICS_BLOCK).map(LineMetrics::of_ascii));
+			length = text.len();
+		} else {
+			let mut block = Vec::with_capacity(METRICS_BLOCK);
+			for (offset, c) in text.char_indices() {
+				if length % OFFSET_BLOCK == 0 {
+					offsets.push(offset);
+				}
+				block.push(c);
+				if block.len() == METRICS_BLOCK {
+					block_metrics.push(LineMetrics::of_chars(&block));
+					block.clear();
+				}
+				length += 1;
+			}
+			if !block.is_empty() {
+				block_metrics.push(LineMetrics::of_chars(&block));
+			}
+		}
+		let leaves = block_metrics.len().next_power_of_two();
+		let mut metrics = vec![LineMetrics::default(); 2 * leaves];
+		metrics[leaves..leaves + block_metrics.len()].copy_from_slice(&block_metrics);
+		for i in (1..leaves).rev() {
+			metrics[i] = metrics[2 * i].combine(&metrics[2 * i + 1]);
+		}
+		PieceBuffer { text, length, offsets, metrics, leaves }
+	}
+
+	fn byte_offset(&self, index : usize) -> usize {
+		if self.offsets.is_empty() {
+			return index; // ASCII
+		}
+		if index >= self.length {
+			return self.text.len();
+		}
+		let base = self.offsets[index / OFFSET_BLOCK];
+		match self.text[base..].char_indices().nth(index % OFFSET_BLOCK) {
+			Some((offset, _)) => base + offset,
+			None => self.text.len()
+		}
+	}
+
+	/// <summary>Returns the index of the character whose encoding holds the byte at <code>byte_offset</code>, and the offset it starts at.</summary>
+	fn char_at_byte(&self, byte_offset : usize) -> (usize, usize) {
+		if self.offsets.is_empty() {
+			return (byte_offset, byte_offset); // ASCII
+		}
+		// Starts from the last indexed character at or before the byte.
+		let block = self.offsets.partition_point(|&offset| offset <= byte_offset) - 1;
+		let base = self.offsets[block];
+		let (count, (offset, _)) = self.text[base..].char_indices().enumerate().find(|&(_, (offset, c))| base + offset + c.len_utf8() > byte_offset).unwrap();
+		(block * OFFSET_BLOCK + count, base + offset)
+	}
+
+	fn chars(&self, start : usize, end : usize) -> impl Iterator<Item = char> + '_ {
+		self.text[self.byte_offset(start)..].chars().take(end - start)
+	}
+
+	fn char_at(&self, index : usize) -> char {
+		if self.offsets.is_empty() {
+			return self.text.as_bytes()[index] as char;
+		}
+		self.chars(index, index + 1).next().unwrap()
+	}
+
+	/// <summary>Returns the index of the first occurrence of the character between <code>start</code> and <code>end</code>.</summary>
+	fn find_char(&self, c : char, start : usize, end : usize) -> Option<usize> {
+		if !c.is_ascii() {
+			return self.chars(start, end).position(|x| x == c).map(|i| start + i);
+		}
+		// An ASCII byte never occurs within the encoding of another character.
+		let byte_start = self.byte_offset(start);
+		let found = find_byte(c as u8, &self.text.as_bytes()[byte_start..self.byte_offset(end)])?;
+		if self.offsets.is_empty() {
+			return Some(start + found);
+		}
+		Some(start + self.text[byte_start..byte_start + found].chars().count())
+	}
+
+	/// <summary>Computes the line metrics of the characters between <code>start</code> and <code>end</code> from the characters.</summary>
+	fn scan_metrics(&self, start : usize, end : usize) -> LineMetrics {
+		if self.offsets.is_empty() {
+			return LineMetrics::of_ascii(&self.text.as_bytes()[start..end]);
+		}
+		LineMetrics::of_chars(&self.chars(start, end).collect::<Vec<char>>())
+	}
+
+	/// <summary>Returns the line metrics of the characters between <code>start</code> and <code>end</code>.</summary>
+	fn range_metrics(&self, start : usize, end : usize) -> LineMetrics {
+		let first_block = start.div_ceil(METRICS_BLOCK);
+		let last_block = end / METRICS_BLOCK;
+		if first_block >= last_block {
+			return self.scan_metrics(start, end);
+		}
+		let head = self.scan_metrics(start, first_block * METRICS_BLOCK);
+		let tail = self.scan_metrics(last_block * METRICS_BLOCK, end);
+		// Combines the whole blocks in order, from both ends of the segment tree.
+		let mut left = head;
+		let mut right = tail;
+		let mut l = first_block + self.leaves;
+		let mut r = last_block + self.leaves;
+		while l < r {
+			if l & 1 == 1 {
+				left = left.combine(&self.metrics[l]);
+				l += 1;
+			}
+			if r & 1 == 1 {
+				r -= 1;
+				right = self.metrics[r].combine(&right);
+			}
+			l >>= 1;
+			r >>= 1;
+		}
+		left.combine(&right)
+	}
+}
+
+/// <summary>
+/// A leaf referencing a range of a shared original buffer. Slicing a piece creates
+/// another piece without copying any character, so pieces are never chunked: an
+/// edit in a large file only allocates the inserted text and the nodes around it.
+/// </summary>
+struct PieceNode {
+	buffer : Shared<PieceBuffer>,
+	start : usize,
+	end : usize,
+	metrics : LineMetrics
+}
+
+impl Node for PieceNode {
+	fn length(&self) -> usize {
+		self.end - self.start
+	}
+
+	fn sub_node(self: Shared<Self>, start: usize, end: usize, config : &TextConfig) -> Shared<dyn Node> {
+		if start == 0 && end == self.length() {
+			return self;
+		}
+		let (start, end) = (self.start + start, self.start + end);
+		// A short view on a buffer no other piece references would keep it all alive
+		// for a few characters, once this piece is dropped: it is copied out instead.
+		if end - start <= config.block_size() && Shared::strong_count(&self.buffer) == 1 {
+			return create_leaf_node(self.buffer.chars(start, end).collect(), config);
+		}
+		Shared::new(PieceNode { metrics : self.buffer.range_metrics(start, end), buffer : self.buffer.clone(), start, end })
+	}
+
+	fn get_char_at(&self, offset : usize) -> char {
+		self.buffer.char_at(self.start + offset)
+	}
+
+	fn copy_to(&self, source_index : usize, destination : &mut [char], destination_index : usize, count : usize) {
+		let start = self.start + source_index;
+		for (target, c) in destination[destination_index..destination_index + count].iter_mut().zip(self.buffer.chars(start, start + count)) {
+			*target = c;
+		}
+	}
+
+	fn metrics(&self) -> &LineMetrics {
+		&self.metrics
+	}
+
+	/// <summary>Returns the size of the piece itself: the buffer it references is shared.</summary>
+	fn allocated_bytes(&self) -> usize {
+		mem::size_of::<PieceNode>()
+	}
+
+	fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		self.buffer.find_char(c, self.start + start, self.end).map(|i| i - self.start)
+	}
+
+	fn as_utf8(&self) -> Option<&str> {
+		Some(&self.buffer.text[self.buffer.byte_offset(self.start)..self.buffer.byte_offset(self.end)])
+	}
+
+	fn utf8_offset(&self, index : usize) -> usize {
+		self.buffer.byte_offset(self.start + index) - self.buffer.byte_offset(self.start)
+	}
+
+	fn char_at_utf8(&self, byte_offset : usize) -> (usize, usize) {
+		let base = self.buffer.byte_offset(self.start);
+		let (index, offset) = self.buffer.char_at_byte(base + byte_offset);
+		(index - self.start, offset - base)
+	}
+
+	fn retained_bytes(&self) -> usize {
+		self.buffer.text.len() - (self.buffer.byte_offset(self.end) - self.buffer.byte_offset(self.start))
+	}
+
+	fn is_piece(&self) -> bool {
+		true
+	}
+}
+
+impl TextConfig {
+	/// <summary>
+	/// Creates a text over the given content without copying it: the content stays
+	/// one buffer that the text references by pieces, and only the text inserted by
+	/// later edits is allocated. Opening a large file costs a single indexing pass.
+	/// </summary>
+	pub fn open(&self, content : String) -> ImmutableText {
+		if content.is_empty() {
+			return self.create("");
+		}
+		#[cfg(feature = "shadow")]
+		let expected = content.clone();
+		let buffer = PieceBuffer::new(content);
+		let end = buffer.length;
+		let metrics = buffer.range_metrics(0, end);
+		let opened = ImmutableText { root : Root::Node(Shared::new(PieceNode { buffer : Shared::new(buffer), start : 0, end, metrics })), config : *self, cache : LeafCache::default() };
+		#[cfg(feature = "shadow")]
+		shadow::check("open", &opened, &expected);
+		opened
+	}
+}
+
+/// <summary>Creates a text referencing the given content by pieces, see <code>TextConfig::open</code>.</summary>
+pub fn open_immutable_text(content : String) -> ImmutableText {
+	TextConfig::new().open(content)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns a file of mixed lines: ASCII, CR LF, multi-byte and long ones.</summary>
+	fn file(ascii : bool) -> String {
+		let mut content = String::new();
+		for i in 0..600 {
+			match (i % 4, ascii) {
+				(0, _) => content.push_str("plain line\n"),
+				(1, _) => content.push_str("crlf line\r\n"),
+				(2, false) => content.push_str("ligne accentuée, 日本語 🦀\n"),
+				_ => content.push_str(&"x".repeat(i % 97)),
+			}
+		}
+		content
+	}
+
+	#[test]
+	fn opens_empty_content_as_an_empty_text() {
+		let opened = open_immutable_text(String::new());
+		assert_eq!(opened.length(), 0);
+		assert!(!opened.root.is_piece());
+		assert_eq!(opened.to_string(), "");
+		assert_eq!(opened.line_count(), 1);
+		assert_eq!(opened.insert_string(0, "a\r\n").to_string(), "a\r\n");
+	}
+
+	#[test]
+	fn metrics_of_any_range_match_the_characters() {
+		for ascii in [true, false] {
+			let content = file(ascii);
+			let chars : Vec<char> = content.chars().collect();
+			let buffer = PieceBuffer::new(content.clone());
+			assert_eq!(buffer.length, chars.len());
+			// Ranges within a block, across block boundaries and over whole blocks.
+			let bounds = [0, 1, OFFSET_BLOCK - 1, OFFSET_BLOCK, METRICS_BLOCK - 1, METRICS_BLOCK, METRICS_BLOCK + 5, 2 * METRICS_BLOCK + 3, chars.len() - 1, chars.len()];
+			for &start in &bounds {
+				for &end in bounds.iter().filter(|&&end| end >= start) {
+					assert_eq!(buffer.range_metrics(start, end), LineMetrics::of_chars(&chars[start..end]), "{}..{}", start, end);
+					assert_eq!(buffer.chars(start, end).collect::<Vec<char>>(), &chars[start..end]);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn pieces_read_like_the_content() {
+		for ascii in [true, false] {
+			let content = file(ascii);
+			let opened = open_immutable_text(content.clone());
+			let created = create_immutable_text(&content);
+			assert_eq!(opened.to_string(), content);
+			assert_eq!(opened.line_count(), created.line_count());
+			assert_eq!(opened.stats(), created.stats());
+			for index in [0, 1, METRICS_BLOCK - 1, METRICS_BLOCK, opened.length() - 1] {
+				assert_eq!(opened.get_char_at(index), created.get_char_at(index));
+				assert_eq!(opened.line_of_offset(index), created.line_of_offset(index));
+				assert_eq!(opened.try_byte_offset(index), created.try_byte_offset(index));
+			}
+			// Edits keep the untouched parts as pieces of the same buffer.
+			let edited = opened.insert_string(METRICS_BLOCK, "\r\n").remove_text(10, 20);
+			assert_eq!(edited.to_string(), created.insert_string(METRICS_BLOCK, "\r\n").remove_text(10, 20).to_string());
+			assert!(edited.validate().is_ok());
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds")]
+	fn rejects_ranges_past_the_end() {
+		open_immutable_text("piece\r\n".repeat(10)).get_text(60, 30);
+	}
+}
@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::mem;
+
+use super::{CompositeNode, Node, Shared, WideLeafNode};
+
+/// <summary>The kinds of nodes the pool recycles, each on a free list of its own.</summary>
+#[derive(Clone, Copy)]
+pub(crate) enum PoolClass {
+	Composite,
+	Wide,
+	Ascii
+}
+
+/// <summary>The nodes of a thread no longer referenced, kept to be handed out again.</summary>
+struct FreeLists {
+	/// <summary>The number of nodes kept per class, 0 while pooling is disabled.</summary>
+	capacity : usize,
+	lists : [Vec<Shared<dyn Node>>; 3]
+}
+
+thread_local! {
+	static FREE_LISTS : RefCell<FreeLists> = const { RefCell::new(FreeLists { capacity : 0, lists : [Vec::new(), Vec::new(), Vec::new()] }) };
+
+	/// <summary>The empty leaf the children of recycled composites are replaced with, never uniquely owned.</summary>
+	static DETACHED : Shared<dyn Node> = Shared::new(WideLeafNode::new(Vec::new()));
+}
+
+/// <summary>
+/// A pool of the nodes of texts for heavy editing workloads. Every edit allocates a
+/// handful of composites and small leaves and drops the ones of the versions no longer
+/// held; once enabled on a thread, the composites and leaves freed there are kept on
+/// per-kind free lists and refilled by the next edits instead of going through the
+/// allocator each time. Only nodes are pooled: other allocations are left alone.
+/// </summary>
+/// <remarks>
+/// Each thread has a pool of its own, so that no lock is taken. Nodes go to the pool of
+/// the thread dropping them, whichever created them.
+/// </remarks>
+pub struct NodePool;
+
+impl NodePool {
+	/// <summary>
+	/// Sets the number of unreferenced nodes of each kind the current thread keeps for
+	/// reuse. 0, the default, disables pooling; the nodes kept beyond the new capacity
+	/// are freed.
+	/// </summary>
+	pub fn set_capacity(nodes : usize) {
+		let freed = FREE_LISTS.with(|free_lists| {
+			let mut free_lists = free_lists.borrow_mut();
+			free_lists.capacity = nodes;
+			free_lists.lists.iter_mut().flat_map(|list| list.drain(nodes.min(list.len())..)).collect::<Vec<_>>()
+		});
+		// Dropped once the free lists are released, as freeing a composite returns to them.
+		drop(freed);
+	}
+
+	/// <summary>Returns the number of nodes the pool of the current thread holds.</summary>
+	pub fn pooled_nodes() -> usize {
+		FREE_LISTS.with(|free_lists| free_lists.borrow().lists.iter().map(Vec::len).sum())
+	}
+}
+
+/// <summary>Returns a node of the class from the pool of the current thread, uniquely owned, if it holds one.</summary>
+fn take(class : PoolClass) -> Option<Shared<dyn Node>> {
+	FREE_LISTS.try_with(|free_lists| free_lists.borrow_mut().lists[class as usize].pop()).ok().flatten()
+}
+
+/// <summary>Returns a composite of the two nodes, recycled from the pool if possible.</summary>
+pub(crate) fn composite(head : Shared<dyn Node>, tail : Shared<dyn Node>) -> Shared<dyn Node> {
+	let composite = CompositeNode::new(head, tail);
+	match take(PoolClass::Composite) {
+		Some(mut node) => {
+			*Shared::get_mut(&mut node).and_then(|node| node.as_composite_mut()).unwrap() = composite;
+			node
+		}
+		None => Shared::new(composite)
+	}
+}
+
+/// <summary>Returns a leaf of the class holding the characters, recycled from the pool if possible.</summary>
+pub(crate) fn leaf<F>(class : PoolClass, data : Vec<char>, create : F) -> Shared<dyn Node> where F : FnOnce(Vec<char>) -> Shared<dyn Node> {
+	match take(class) {
+		Some(mut node) => {
+			Shared::get_mut(&mut node).unwrap().refill(data);
+			node
+		}
+		None => create(data)
+	}
+}
+
+/// <summary>
+/// Moves the children of a composite being dropped, and their descendants, to the pool
+/// of the current thread when the composite held their last reference, instead of
+/// freeing them. Composites enter the pool with their children detached.
+/// </summary>
+pub(crate) fn release(composite : &mut CompositeNode) {
+	let capacity = FREE_LISTS.try_with(|free_lists| free_lists.borrow().capacity).unwrap_or(0);
+	let detached = match DETACHED.try_with(Shared::clone) {
+		Ok(detached) if capacity > 0 => detached,
+		_ => return
+	};
+	let mut released = Vec::new();
+	let mut freed = Vec::new();
+	detach_children(composite, &detached, &mut released);
+	while let Some(mut node) = released.pop() {
+		let class = match node.pool_class() {
+			Some(class) => class,
+			None => {
+				freed.push(node);
+				continue;
+			}
+		};
+		if let Some(composite) = Shared::get_mut(&mut node).and_then(|node| node.as_composite_mut()) {
+			detach_children(composite, &detached, &mut released);
+		}
+		let rejected = FREE_LISTS.with(|free_lists| {
+			let list = &mut free_lists.borrow_mut().lists[class as usize];
+			if list.len() >= capacity {
+				return Some(node);
+			}
+			list.push(node);
+			None
+		});
+		freed.extend(rejected);
+	}
+	// Dropped once the free lists are released, as freeing a composite returns to them.
+	drop(freed);
+}
+
+/// <summary>
+/// Replaces the children of a composite with the detached leaf, so that a pooled
+/// composite keeps no other node alive, collecting those it held the last reference to.
+/// </summary>
+fn detach_children(composite : &mut CompositeNode, detached : &Shared<dyn Node>, released : &mut Vec<Shared<dyn Node>>) {
+	for child in [&mut composite.head, &mut composite.tail] {
+		let mut child = mem::replace(child, detached.clone());
+		if Shared::get_mut(&mut child).is_some() {
+			released.push(child);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns a text of several leaves, ASCII and not, rebuilt by edits so that its composites are its own.</summary>
+	fn edited() -> String {
+		let text = create_immutable_text(&"ascii line\r\n".repeat(40)).concat(&create_immutable_text(&"ligne accentuée\n".repeat(40)));
+		text.insert_string(300, "inserted").remove_text(10, 100).to_string()
+	}
+
+	#[test]
+	fn disabled_by_default() {
+		let expected = edited();
+		assert_eq!(edited(), expected);
+		assert_eq!(NodePool::pooled_nodes(), 0);
+	}
+
+	#[test]
+	fn recycles_the_nodes_of_dropped_texts() {
+		let expected = edited();
+		NodePool::set_capacity(1000);
+		assert_eq!(edited(), expected);
+		let pooled = NodePool::pooled_nodes();
+		assert!(pooled > 0);
+		// Texts built from recycled nodes are the same.
+		let text = create_immutable_text(&expected);
+		assert!(NodePool::pooled_nodes() < pooled);
+		assert_eq!(text.to_string(), expected);
+		assert_eq!(text.insert_string(0, "\r\n").sub_text(2).to_string(), expected);
+		NodePool::set_capacity(0);
+		assert_eq!(NodePool::pooled_nodes(), 0);
+		assert_eq!(text.to_string(), expected);
+	}
+
+	#[test]
+	fn keeps_at_most_its_capacity() {
+		NodePool::set_capacity(2);
+		edited();
+		assert!(NodePool::pooled_nodes() <= 6);
+		NodePool::set_capacity(1);
+		assert!(NodePool::pooled_nodes() <= 3);
+		NodePool::set_capacity(0);
+	}
+
+	#[test]
+	fn shared_nodes_stay_out_of_the_pool() {
+		NodePool::set_capacity(1000);
+		let text = create_immutable_text(&"shared\n".repeat(100));
+		let edited = text.insert_string(350, "x");
+		drop(edited);
+		let pooled = NodePool::pooled_nodes();
+		drop(text.clone());
+		assert_eq!(NodePool::pooled_nodes(), pooled);
+		assert_eq!(text.to_string(), "shared\n".repeat(100));
+		NodePool::set_capacity(0);
+	}
+}
@@ -0,0 +1,184 @@
+use std::ops::Range;
+
+use super::Delta;
+
+/// <summary>
+/// Remembers, for every character of a text, the tag of the edit that inserted it,
+/// like a revision or an author, so that collaborative and review tools can show who
+/// typed what. The tags are kept as runs, updated by the deltas of the edits in a
+/// single pass over them.
+/// </summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance<T> {
+	/// <summary>The lengths of the runs of characters sharing a tag, and their tags, in order.</summary>
+	runs : Vec<(usize, T)>
+}
+
+/// <summary>Appends a run, merged with the last one if they share the tag.</summary>
+fn push_run<T : PartialEq>(runs : &mut Vec<(usize, T)>, length : usize, tag : T) {
+	if length == 0 {
+		return;
+	}
+	match runs.last_mut() {
+		Some((last, last_tag)) if *last_tag == tag => *last += length,
+		_ => runs.push((length, tag))
+	}
+}
+
+impl<T : Clone + PartialEq> Provenance<T> {
+	/// <summary>Returns the provenance of a text of the given length whose characters all come from the origin.</summary>
+	pub fn new(length : usize, origin : T) -> Provenance<T> {
+		let mut runs = Vec::new();
+		push_run(&mut runs, length, origin);
+		Provenance { runs }
+	}
+
+	/// <summary>Returns the length of the text tracked.</summary>
+	pub fn len(&self) -> usize {
+		self.runs.iter().map(|(length, _)| length).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.runs.is_empty()
+	}
+
+	/// <summary>Updates the provenance for the edit the delta describes: the characters it inserts get the tag.</summary>
+	pub fn record(&mut self, delta : &Delta, tag : T) {
+		let mut runs = Vec::with_capacity(self.runs.len() + 2 * delta.edits().len());
+		let mut old = std::mem::take(&mut self.runs).into_iter();
+		// The part of the current old run not consumed yet.
+		let mut current : Option<(usize, T)> = old.next();
+		let mut offset = 0;
+		let mut transfer = |count : usize, keep : bool, runs : &mut Vec<(usize, T)>| {
+			let mut left = count;
+			while left > 0 {
+				let Some((length, tag)) = current.take() else {
+					panic!("delta past the end of the text tracked");
+				};
+				let taken = length.min(left);
+				if keep {
+					push_run(runs, taken, tag.clone());
+				}
+				left -= taken;
+				current = if taken < length { Some((length - taken, tag)) } else { old.next() };
+			}
+		};
+		for edit in delta.edits() {
+			transfer(edit.start - offset, true, &mut runs);
+			transfer(edit.removed, false, &mut runs);
+			push_run(&mut runs, edit.inserted.chars().count(), tag.clone());
+			offset = edit.start + edit.removed;
+		}
+		while let Some((length, tag)) = current.take() {
+			push_run(&mut runs, length, tag);
+			current = old.next();
+		}
+		self.runs = runs;
+	}
+
+	/// <summary>Returns the tag of the edit that inserted the character at the offset, or None past the end.</summary>
+	pub fn provenance_at(&self, offset : usize) -> Option<&T> {
+		let mut start = 0;
+		for (length, tag) in &self.runs {
+			if offset < start + length {
+				return Some(tag);
+			}
+			start += length;
+		}
+		None
+	}
+
+	/// <summary>Returns the ranges of characters sharing a tag, in order, adjacent ranges having different tags.</summary>
+	pub fn ranges(&self) -> impl Iterator<Item = (Range<usize>, &T)> {
+		self.runs.iter().scan(0, |start, (length, tag)| {
+			let range = *start..*start + length;
+			*start += length;
+			Some((range, tag))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// <summary>Applies the delta to the tags of every character, the plain way.</summary>
+	fn record_each(tags : &mut Vec<u32>, delta : &Delta, tag : u32) {
+		for edit in delta.edits().iter().rev() {
+			tags.splice(edit.start..edit.start + edit.removed, std::iter::repeat_n(tag, edit.inserted.chars().count()));
+		}
+	}
+
+	fn check(provenance : &Provenance<u32>, tags : &[u32]) {
+		assert_eq!(provenance.len(), tags.len());
+		assert_eq!(provenance.is_empty(), tags.is_empty());
+		for (offset, tag) in tags.iter().enumerate() {
+			assert_eq!(provenance.provenance_at(offset), Some(tag));
+		}
+		assert_eq!(provenance.provenance_at(tags.len()), None);
+		// The ranges cover the text and adjacent ones differ.
+		let ranges : Vec<(Range<usize>, &u32)> = provenance.ranges().collect();
+		assert_eq!(ranges.last().map_or(0, |(range, _)| range.end), tags.len());
+		assert!(ranges.windows(2).all(|pair| pair[0].0.end == pair[1].0.start && pair[0].1 != pair[1].1));
+	}
+
+	#[test]
+	fn tracks_the_tag_of_every_character() {
+		let mut provenance = Provenance::new(20, 0);
+		let mut tags = vec![0; 20];
+		let mut deltas = Vec::new();
+		for (start, removed, inserted) in [(0, 0, "ab"), (5, 3, "\r\n"), (21, 0, "end"), (1, 10, ""), (0, 3, "é🦀")] {
+			let mut delta = Delta::new();
+			delta.push(start, removed, inserted);
+			deltas.push(delta);
+		}
+		// A delta of several edits, in the coordinates of the text before it.
+		let mut delta = Delta::new();
+		delta.push(1, 1, "x");
+		delta.push(4, 0, "\r\n");
+		delta.push(6, 2, "");
+		deltas.push(delta);
+		for (tag, delta) in deltas.iter().enumerate() {
+			provenance.record(delta, tag as u32 + 1);
+			record_each(&mut tags, delta, tag as u32 + 1);
+			check(&provenance, &tags);
+		}
+	}
+
+	#[test]
+	fn merges_the_runs_of_a_tag() {
+		let mut provenance = Provenance::new(4, "file");
+		for (i, c) in "typed".chars().enumerate() {
+			let mut delta = Delta::new();
+			delta.push(2 + i, 0, &c.to_string());
+			provenance.record(&delta, "user");
+		}
+		let ranges : Vec<(Range<usize>, &&str)> = provenance.ranges().collect();
+		assert_eq!(ranges, vec![(0..2, &"file"), (2..7, &"user"), (7..9, &"file")]);
+		// Removing what separates two runs of a tag joins them.
+		let mut delta = Delta::new();
+		delta.push(2, 5, "");
+		provenance.record(&delta, "user");
+		assert_eq!(provenance, Provenance::new(4, "file"));
+	}
+
+	#[test]
+	fn tracks_empty_texts() {
+		let mut provenance = Provenance::new(0, 0);
+		check(&provenance, &[]);
+		provenance.record(&Delta::new(), 1);
+		check(&provenance, &[]);
+		let mut delta = Delta::new();
+		delta.push(0, 0, "\r\n");
+		provenance.record(&delta, 2);
+		check(&provenance, &[2, 2]);
+	}
+
+	#[test]
+	#[should_panic(expected = "delta past the end of the text tracked")]
+	fn rejects_deltas_past_the_end() {
+		let mut delta = Delta::new();
+		delta.push(3, 2, "");
+		Provenance::new(4, 0).record(&delta, 1);
+	}
+}
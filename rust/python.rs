@@ -0,0 +1,141 @@
+//! Python bindings built with pyo3. Indexing and slicing follow the conventions of
+//! Python strings, with negative indexes and steps, and offsets counting characters;
+//! a slice with a step of one shares the nodes of the original text instead of
+//! copying it, so that scripts can work on large buffers.
+
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+use pyo3::types::{PySlice, PyString};
+
+use super::{collect_leaves, create_immutable_text, ImmutableText, Node, Shared, TextError};
+
+impl From<TextError> for PyErr {
+	fn from(error : TextError) -> PyErr {
+		PyIndexError::new_err(error.to_string())
+	}
+}
+
+/// <summary>An immutable text, exported to Python as <code>ImmutableText</code>.</summary>
+#[pyclass(name = "ImmutableText", frozen)]
+pub struct PyText {
+	text : ImmutableText
+}
+
+/// <summary>Iterates over the characters of a text, or over its chunks, leaf by leaf.</summary>
+#[pyclass(name = "TextIterator")]
+pub struct PyTextIterator {
+	leaves : Vec<Shared<dyn Node>>,
+	leaf : usize,
+	index : usize,
+	chunks : bool
+}
+
+#[pymethods]
+impl PyText {
+	#[new]
+	#[pyo3(signature = (text = ""))]
+	fn new(text : &str) -> PyText {
+		PyText { text : create_immutable_text(text) }
+	}
+
+	fn __len__(&self) -> usize {
+		self.text.length()
+	}
+
+	fn __str__(&self) -> String {
+		self.text.to_string()
+	}
+
+	fn __repr__(&self) -> String {
+		format!("ImmutableText({:?})", self.text)
+	}
+
+	/// <summary>Returns the character at an index as a string, or the slice of a text as a text.</summary>
+	fn __getitem__(&self, key : &Bound<'_, PyAny>) -> PyResult<PyObject> {
+		let py = key.py();
+		if let Ok(slice) = key.downcast::<PySlice>() {
+			let indices = slice.indices(self.text.length() as _)?;
+			if indices.step == 1 {
+				let (start, stop) = (indices.start as usize, indices.stop.max(indices.start) as usize);
+				return Ok(Py::new(py, PyText { text : self.text.get_text(start, stop - start) })?.into_any());
+			}
+			let mut chars = String::new();
+			let mut index = indices.start;
+			while (indices.step > 0 && index < indices.stop) || (indices.step < 0 && index > indices.stop) {
+				chars.push(self.text.get_char_at(index as usize));
+				index += indices.step;
+			}
+			return Ok(Py::new(py, PyText { text : create_immutable_text(&chars) })?.into_any());
+		}
+		let index : isize = key.extract()?;
+		let resolved = if index < 0 { index + self.text.length() as isize } else { index };
+		match usize::try_from(resolved).ok().and_then(|index| self.text.get(index)) {
+			Some(c) => Ok(PyString::new(py, &c.to_string()).into_any().unbind()),
+			None => Err(PyIndexError::new_err(format!("index out of range: {} (length {})", index, self.text.length())))
+		}
+	}
+
+	fn __iter__(&self) -> PyTextIterator {
+		PyTextIterator::new(&self.text, false)
+	}
+
+	/// <summary>Returns an iterator over the leaves of the text as strings, without copying it whole.</summary>
+	fn chunks(&self) -> PyTextIterator {
+		PyTextIterator::new(&self.text, true)
+	}
+
+	fn insert(&self, index : usize, text : &str) -> PyResult<PyText> {
+		Ok(PyText { text : self.text.try_insert(index, &create_immutable_text(text))? })
+	}
+
+	fn remove(&self, start : usize, count : usize) -> PyResult<PyText> {
+		Ok(PyText { text : self.text.try_remove(start, count)? })
+	}
+
+	fn splice(&self, start : usize, removed : usize, text : &str) -> PyResult<PyText> {
+		Ok(PyText { text : self.text.try_splice(start, removed, &create_immutable_text(text))? })
+	}
+
+	fn concat(&self, other : &PyText) -> PyText {
+		PyText { text : self.text.concat(&other.text) }
+	}
+}
+
+impl PyTextIterator {
+	fn new(text : &ImmutableText, chunks : bool) -> PyTextIterator {
+		let leaves = if text.length() == 0 { Vec::new() } else { collect_leaves(&text.root.to_node()) };
+		PyTextIterator { leaves, leaf : 0, index : 0, chunks }
+	}
+}
+
+#[pymethods]
+impl PyTextIterator {
+	fn __iter__(slf : PyRef<'_, Self>) -> PyRef<'_, Self> {
+		slf
+	}
+
+	fn __next__(mut slf : PyRefMut<'_, Self>) -> Option<String> {
+		let leaf = slf.leaves.get(slf.leaf)?.clone();
+		if slf.chunks {
+			slf.leaf += 1;
+			let mut data = vec!['\0'; leaf.length()];
+			leaf.copy_to(0, &mut data, 0, leaf.length());
+			return Some(data.into_iter().collect());
+		}
+		let c = leaf.get_char_at(slf.index);
+		slf.index += 1;
+		if slf.index == leaf.length() {
+			slf.leaf += 1;
+			slf.index = 0;
+		}
+		Some(c.to_string())
+	}
+}
+
+/// <summary>Initializes the <code>immutable_text</code> Python module.</summary>
+#[pymodule]
+fn immutable_text(module : &Bound<'_, PyModule>) -> PyResult<()> {
+	module.add_class::<PyText>()?;
+	module.add_class::<PyTextIterator>()?;
+	Ok(())
+}
@@ -0,0 +1,147 @@
+
+use super::{balanced_node, chunked, collect_leaves, create_leaf_node, ImmutableText, Node, Shared, TextConfig};
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the same text over a tree of minimal depth, rebuilt on top of the
+	/// current leaves, which stay shared with this text.
+	/// </summary>
+	pub fn rebalance(&self) -> ImmutableText {
+		self.with_root(balanced_node(&collect_leaves(&self.root.to_node())))
+	}
+
+	/// <summary>
+	/// Returns the same text with runs of adjacent leaves that fit in one block merged
+	/// into single leaves, over a tree of minimal depth. Leaves not merged stay shared.
+	/// Useful after bursts of small edits have left many undersized leaves.
+	/// </summary>
+	/// <remarks>
+	/// Views keeping alive more than three times the bytes they cover of a larger
+	/// buffer, such as short pieces of a file otherwise edited away, are copied out
+	/// so that the buffer can be freed once the older versions are dropped.
+	/// </remarks>
+	pub fn compact(&self) -> ImmutableText {
+		let mut leaves : Vec<Shared<dyn Node>> = Vec::new();
+		let mut run : Vec<Shared<dyn Node>> = Vec::new();
+		let mut run_length = 0;
+		let copied_out = collect_leaves(&self.root.to_node()).into_iter().flat_map(|leaf| {
+			if leaf.retained_bytes() <= 3 * leaf.metrics().bytes {
+				return vec![leaf];
+			}
+			let mut data = vec!['\0'; leaf.length()];
+			leaf.copy_to(0, &mut data, 0, leaf.length());
+			collect_leaves(&chunked(create_leaf_node(data, &self.config), &self.config))
+		});
+		for leaf in copied_out {
+			if run_length + leaf.length() > self.config.block_size() && !run.is_empty() {
+				leaves.push(merge_leaves(&run, run_length, &self.config));
+				run.clear();
+				run_length = 0;
+			}
+			run_length += leaf.length();
+			run.push(leaf);
+		}
+		if !run.is_empty() {
+			leaves.push(merge_leaves(&run, run_length, &self.config));
+		}
+		self.with_root(balanced_node(&leaves))
+	}
+
+	/// <summary>
+	/// Returns the same text over a tree whose shape depends only on its content and
+	/// configuration: the characters are copied into full blocks, the last one partial,
+	/// under a tree of minimal depth. Texts with equal content and configuration then
+	/// have the same structure, whatever their edit history, which makes structural
+	/// comparisons, golden tests and serialized trees reproducible.
+	/// </summary>
+	pub fn canonicalize(&self) -> ImmutableText {
+		self.config.create_from_chunks(self.chunks_in(0..self.length()))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Merges the two leaves meeting at <code>seam</code> when one of them is below
+	/// the minimum leaf size of the chunk policy and both fit in a single leaf. Run
+	/// after every concatenation, it keeps bursts of tiny edits from filling the tree
+	/// with one-character leaves without ever copying more than one block.
+	/// </summary>
+	pub(crate) fn coalesce_at(&self, seam : usize) -> ImmutableText {
+		if seam == 0 || seam >= self.length() {
+			return self.clone();
+		}
+		let (before, after) = match (self.find_leaf(seam - 1, 0), self.find_leaf(seam, 0)) {
+			(Some(before), Some(after)) => (before, after),
+			_ => return self.clone()
+		};
+		if before.offset == after.offset {
+			return self.clone(); // Already inside a leaf.
+		}
+		let policy = self.config.chunk_policy();
+		let start = before.offset;
+		let end = after.offset + after.leaf_node.length();
+		if end - start > policy.max_leaf_size() || before.leaf_node.length().min(after.leaf_node.length()) >= policy.min_leaf_size() {
+			return self.clone();
+		}
+		let mut data = vec!['\0'; end - start];
+		before.leaf_node.copy_to(0, &mut data, 0, before.leaf_node.length());
+		after.leaf_node.copy_to(0, &mut data, before.leaf_node.length(), after.leaf_node.length());
+		let merged = self.with_root(create_leaf_node(data, &self.config));
+		self.get_text(0, start).join_uncoalesced(&merged).join_uncoalesced(&self.sub_text(end))
+	}
+}
+
+/// <summary>Merges adjacent leaves into one, or returns the leaf itself if there is only one.</summary>
+pub(crate) fn merge_leaves(run : &[Shared<dyn Node>], length : usize, config : &TextConfig) -> Shared<dyn Node> {
+	if run.len() == 1 {
+		return run[0].clone();
+	}
+	let mut data = vec!['\0'; length];
+	let mut offset = 0;
+	for leaf in run {
+		leaf.copy_to(0, &mut data, offset, leaf.length());
+		offset += leaf.length();
+	}
+	create_leaf_node(data, config)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	/// <summary>Returns the bytes the leaves of the text keep alive without covering them.</summary>
+	fn retained_bytes(text : &ImmutableText) -> usize {
+		collect_leaves(&text.root.to_node()).iter().map(|leaf| leaf.retained_bytes()).sum()
+	}
+
+	#[test]
+	fn compact_copies_out_views_by_the_bytes_they_cover() {
+		// Views at least a block long stay views when cut.
+		let config = TextConfig::new().with_block_size(16);
+		// Thirty characters of three bytes cover 90 of 300 bytes: kept, under three times as many.
+		let opened = config.open("日".repeat(100));
+		let wide = opened.get_text(0, 30);
+		assert_eq!(retained_bytes(&wide), 210);
+		assert_eq!(retained_bytes(&wide.compact()), 210);
+		// Thirty ASCII characters cover 30 of 300 bytes: copied out.
+		let opened = config.open("a\r\n".repeat(100));
+		let ascii = opened.get_text(0, 30);
+		assert_eq!(retained_bytes(&ascii), 270);
+		assert_eq!(retained_bytes(&ascii.compact()), 0);
+		assert_eq!(ascii.compact().to_string(), "a\r\n".repeat(10));
+	}
+
+	#[test]
+	fn compact_merges_small_leaves() {
+		let mut text = create_immutable_text("");
+		for i in 0..200 {
+			text = text.insert_string(i / 2, if i % 3 == 0 { "\r\n" } else { "é" });
+		}
+		let compacted = text.compact();
+		assert_eq!(compacted.to_string(), text.to_string());
+		assert!(collect_leaves(&compacted.root.to_node()).len() <= collect_leaves(&text.root.to_node()).len());
+		assert!(compacted.validate().is_ok());
+		assert_eq!(create_immutable_text("").compact().to_string(), "");
+	}
+}
@@ -0,0 +1,338 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use super::tree::{balanced_node, collect_leaves, concat_nodes, node_of, TreeLink};
+use super::{Shared, BLOCK_SIZE, MAX_DEPTH};
+
+type RopeLink<T> = Shared<RopeNode<T>>;
+
+/// <summary>A node of a <code>Rope</code>: a block of elements, or the concatenation of two nodes.</summary>
+enum RopeNode<T> {
+	Leaf(Vec<T>),
+	Composite {
+		count : usize,
+		depth : usize,
+		head : Shared<RopeNode<T>>,
+		tail : Shared<RopeNode<T>>
+	}
+}
+
+impl<T> RopeNode<T> {
+	fn length(&self) -> usize {
+		match self {
+			RopeNode::Leaf(data) => data.len(),
+			RopeNode::Composite { count, .. } => *count
+		}
+	}
+
+	fn depth(&self) -> usize {
+		match self {
+			RopeNode::Leaf(_) => 0,
+			RopeNode::Composite { depth, .. } => *depth
+		}
+	}
+
+	fn copy_to(&self, destination : &mut Vec<T>) where T : Clone {
+		let mut stack = vec![self];
+		while let Some(node) = stack.pop() {
+			match node {
+				RopeNode::Leaf(data) => destination.extend_from_slice(data),
+				RopeNode::Composite { head, tail, .. } => {
+					stack.push(tail);
+					stack.push(head);
+				}
+			}
+		}
+	}
+}
+
+impl<T : Clone> TreeLink for RopeLink<T> {
+	type Config = ();
+
+	fn length(&self) -> usize {
+		(**self).length()
+	}
+
+	fn depth(&self) -> usize {
+		(**self).depth()
+	}
+
+	fn children(&self) -> Option<(&RopeLink<T>, &RopeLink<T>)> {
+		match &**self {
+			RopeNode::Leaf(_) => None,
+			RopeNode::Composite { head, tail, .. } => Some((head, tail))
+		}
+	}
+
+	fn composite(head : RopeLink<T>, tail : RopeLink<T>) -> RopeLink<T> {
+		Shared::new(RopeNode::Composite {
+			count : head.length() + tail.length(),
+			depth : 1 + head.depth().max(tail.depth()),
+			head,
+			tail
+		})
+	}
+
+	fn sub_node(&self, start : usize, end : usize, config : &()) -> RopeLink<T> {
+		if start == 0 && end == self.length() {
+			return self.clone();
+		}
+		match &**self {
+			RopeNode::Leaf(data) => Shared::new(RopeNode::Leaf(data[start..end].to_vec())),
+			RopeNode::Composite { head, tail, .. } => {
+				let cesure = head.length();
+				if end <= cesure {
+					return head.sub_node(start, end, config);
+				}
+				if start >= cesure {
+					return tail.sub_node(start - cesure, end - cesure, config);
+				}
+				// Overlaps head and tail.
+				concat_nodes(head.sub_node(start, cesure, config), tail.sub_node(0, end - cesure, config), config)
+			}
+		}
+	}
+
+	fn merged(head : &RopeLink<T>, tail : &RopeLink<T>, _ : &()) -> Option<RopeLink<T>> {
+		let length = head.length() + tail.length();
+		if length > BLOCK_SIZE {
+			return None;
+		}
+		let mut merged = Vec::with_capacity(length);
+		head.copy_to(&mut merged);
+		tail.copy_to(&mut merged);
+		Some(Shared::new(RopeNode::Leaf(merged)))
+	}
+
+	fn block_size(_ : &()) -> usize {
+		BLOCK_SIZE
+	}
+
+	fn empty() -> RopeLink<T> {
+		Shared::new(RopeNode::Leaf(Vec::new()))
+	}
+}
+
+/// <summary>
+/// A persistent sequence of arbitrary elements (tokens, style runs, lines...) over
+/// the same balanced tree of blocks as <code>ImmutableText</code>, built by the same
+/// functions of the tree module: concatenation, slicing and splicing share the
+/// unchanged blocks and cost O(log n).
+/// </summary>
+pub struct Rope<T> {
+	root : Shared<RopeNode<T>>
+}
+
+impl<T : Clone> Rope<T> {
+	pub fn new() -> Rope<T> {
+		Rope { root : Shared::new(RopeNode::Leaf(Vec::new())) }
+	}
+
+	pub fn from_slice(items : &[T]) -> Rope<T> {
+		Rope { root : node_of(&Shared::new(RopeNode::Leaf(items.to_vec())), 0, items.len(), &()) }
+	}
+
+	pub fn len(&self) -> usize {
+		self.root.length()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn get(&self, mut index : usize) -> Option<&T> {
+		let mut node = &*self.root;
+		loop {
+			match node {
+				RopeNode::Leaf(data) => return data.get(index),
+				RopeNode::Composite { head, tail, .. } => {
+					if index < head.length() {
+						node = head;
+					} else {
+						index -= head.length();
+						node = tail;
+					}
+				}
+			}
+		}
+	}
+
+	/// <summary>Concatenates the specified rope to the end of this rope.</summary>
+	pub fn concat(&self, that : &Rope<T>) -> Rope<T> {
+		if that.is_empty() {
+			return self.clone();
+		}
+		if self.is_empty() {
+			return that.clone();
+		}
+		let root = concat_nodes(self.root.clone(), that.root.clone(), &());
+		if root.depth() > MAX_DEPTH {
+			// Rebuilt over the same blocks, as for texts.
+			return Rope { root : balanced_node(&collect_leaves(&root)) };
+		}
+		Rope { root }
+	}
+
+	/// <summary>Returns the <code>count</code> elements starting at <code>start</code>.</summary>
+	pub fn slice(&self, start : usize, count : usize) -> Rope<T> {
+		assert!(start + count <= self.len(), "range out of bounds: {}..{} (length {})", start, start + count, self.len());
+		Rope { root : self.root.sub_node(start, start + count, &()) }
+	}
+
+	pub fn insert(&self, index : usize, items : &Rope<T>) -> Rope<T> {
+		self.slice(0, index).concat(items).concat(&self.slice(index, self.len() - index))
+	}
+
+	pub fn remove(&self, start : usize, count : usize) -> Rope<T> {
+		let end = start + count;
+		self.slice(0, start).concat(&self.slice(end, self.len() - end))
+	}
+
+	pub fn iter(&self) -> RopeIter<'_, T> {
+		RopeIter { stack : vec![&*self.root], leaf : &[] }
+	}
+
+	/// <summary>Returns an iterator over the blocks of elements, in order.</summary>
+	pub fn chunks(&self) -> RopeChunks<'_, T> {
+		RopeChunks { stack : vec![&*self.root] }
+	}
+
+	pub fn to_vec(&self) -> Vec<T> {
+		let mut items = Vec::with_capacity(self.len());
+		self.root.copy_to(&mut items);
+		items
+	}
+}
+
+impl<T> Clone for Rope<T> {
+	fn clone(&self) -> Rope<T> {
+		Rope { root : self.root.clone() }
+	}
+}
+
+impl<T : Clone> Default for Rope<T> {
+	fn default() -> Rope<T> {
+		Rope::new()
+	}
+}
+
+impl<T : Clone> FromIterator<T> for Rope<T> {
+	fn from_iter<I : IntoIterator<Item = T>>(iter : I) -> Rope<T> {
+		Rope::from_slice(&iter.into_iter().collect::<Vec<T>>())
+	}
+}
+
+impl<T : Clone + PartialEq> PartialEq for Rope<T> {
+	fn eq(&self, other : &Rope<T>) -> bool {
+		Shared::ptr_eq(&self.root, &other.root) || (self.len() == other.len() && self.iter().eq(other.iter()))
+	}
+}
+
+impl<T : Clone + Eq> Eq for Rope<T> {}
+
+impl<T : Clone + fmt::Debug> fmt::Debug for Rope<T> {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_list().entries(self.iter()).finish()
+	}
+}
+
+/// <summary>An iterator over the elements of a rope, leaf by leaf.</summary>
+pub struct RopeIter<'a, T> {
+	stack : Vec<&'a RopeNode<T>>,
+	leaf : &'a [T]
+}
+
+impl<'a, T> Iterator for RopeIter<'a, T> {
+	type Item = &'a T;
+
+	fn next(&mut self) -> Option<&'a T> {
+		while self.leaf.is_empty() {
+			match self.stack.pop()? {
+				RopeNode::Leaf(data) => self.leaf = data,
+				RopeNode::Composite { head, tail, .. } => {
+					self.stack.push(tail);
+					self.stack.push(head);
+				}
+			}
+		}
+		let (first, rest) = self.leaf.split_first()?;
+		self.leaf = rest;
+		Some(first)
+	}
+}
+
+/// <summary>An iterator over the blocks of a rope, as slices.</summary>
+pub struct RopeChunks<'a, T> {
+	stack : Vec<&'a RopeNode<T>>
+}
+
+impl<'a, T> Iterator for RopeChunks<'a, T> {
+	type Item = &'a [T];
+
+	fn next(&mut self) -> Option<&'a [T]> {
+		loop {
+			match self.stack.pop()? {
+				RopeNode::Leaf(data) if data.is_empty() => continue,
+				RopeNode::Leaf(data) => return Some(data),
+				RopeNode::Composite { head, tail, .. } => {
+					self.stack.push(tail);
+					self.stack.push(head);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::create_immutable_text;
+	use super::*;
+
+	#[test]
+	fn edits_match_a_vector() {
+		let mut rope = Rope::new();
+		let mut expected = Vec::new();
+		for i in 0..2000u32 {
+			let index = (i as usize * 7919) % (expected.len() + 1);
+			rope = rope.insert(index, &Rope::from_slice(&[i, i + 1, i + 2]));
+			expected.splice(index..index, [i, i + 1, i + 2]);
+			if i % 3 == 0 {
+				let start = (i as usize * 31) % expected.len();
+				let count = 4.min(expected.len() - start);
+				rope = rope.remove(start, count);
+				expected.drain(start..start + count);
+			}
+		}
+		assert_eq!(rope.to_vec(), expected);
+		assert!(rope.root.depth() <= MAX_DEPTH);
+		assert_eq!(rope.slice(100, 1000).to_vec(), expected[100..1100]);
+		assert_eq!(rope.get(expected.len()), None);
+	}
+
+	#[test]
+	fn empty_rope() {
+		let rope : Rope<u8> = Rope::new();
+		assert!(rope.is_empty());
+		assert_eq!(rope.concat(&rope).len(), 0);
+		assert_eq!(rope.chunks().count(), 0);
+		assert_eq!(Rope::from_slice(b"ab").concat(&rope), Rope::from_slice(b"ab"));
+	}
+
+	#[test]
+	fn shares_the_shape_of_texts() {
+		// Both trees are chunked by the same function, on the same block boundaries.
+		let items : Vec<char> = "x".repeat(1000).chars().collect();
+		let rope = Rope::from_slice(&items);
+		let text = create_immutable_text(&"x".repeat(1000));
+		let rope_blocks : Vec<usize> = rope.chunks().map(<[char]>::len).collect();
+		let text_blocks : Vec<usize> = text.chunks_in(0..text.length()).map(|chunk| chunk.len()).collect();
+		assert_eq!(rope_blocks, text_blocks);
+		assert!(rope.root.children().is_some());
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds")]
+	fn slice_out_of_range() {
+		Rope::from_slice(&[1, 2, 3]).slice(2, 2);
+	}
+}
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::wildcard::Wildcard;
+use super::{Delta, ImmutableText, Node};
+
+/// <summary>Returns the index of the first occurrence of the byte, through memchr with the "memchr" feature.</summary>
+pub(crate) fn find_byte(needle : u8, haystack : &[u8]) -> Option<usize> {
+	#[cfg(feature = "memchr")]
+	return memchr::memchr(needle, haystack);
+	#[cfg(not(feature = "memchr"))]
+	haystack.iter().position(|&b| b == needle)
+}
+
+/// <summary>The part of a text a search is restricted to.</summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Scope {
+	Offsets(Range<usize>),
+	Lines(Range<usize>)
+}
+
+/// <summary>Holds the options of the search operations taking them, the <code>_with</code> ones and <code>replace_all_preview</code>.</summary>
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+	whole_word : bool,
+	wildcards : bool,
+	scope : Option<Scope>
+}
+
+impl SearchOptions {
+	/// <summary>Returns the default options: occurrences anywhere in the text.</summary>
+	pub const fn new() -> SearchOptions {
+		SearchOptions { whole_word : false, wildcards : false, scope : None }
+	}
+
+	/// <summary>Sets whether only the occurrences with a word boundary on both sides match, like "Match whole word".</summary>
+	pub fn with_whole_word(mut self, whole_word : bool) -> SearchOptions {
+		self.whole_word = whole_word;
+		self
+	}
+
+	/// <summary>
+	/// Sets whether the pattern is a wildcard pattern: <code>*</code> matches any run of
+	/// characters and <code>?</code> any one character, neither across lines, and <code>\</code>
+	/// escapes the next character. The shortest of the leftmost occurrences is found.
+	/// </summary>
+	pub fn with_wildcards(mut self, wildcards : bool) -> SearchOptions {
+		self.wildcards = wildcards;
+		self
+	}
+
+	/// <summary>Restricts the search to the occurrences lying entirely within the range, like "Find in selection".</summary>
+	pub fn with_range(mut self, range : Range<usize>) -> SearchOptions {
+		self.scope = Some(Scope::Offsets(range));
+		self
+	}
+
+	/// <summary>Restricts the search to the occurrences lying entirely within the lines, their newlines included.</summary>
+	pub fn with_lines(mut self, lines : Range<usize>) -> SearchOptions {
+		self.scope = Some(Scope::Lines(lines));
+		self
+	}
+
+	pub fn whole_word(&self) -> bool {
+		self.whole_word
+	}
+
+	pub fn wildcards(&self) -> bool {
+		self.wildcards
+	}
+
+	/// <summary>Returns the range of the text the search is restricted to.</summary>
+	fn bounds(&self, text : &ImmutableText) -> Range<usize> {
+		match &self.scope {
+			None => 0..text.length(),
+			Some(Scope::Offsets(range)) => {
+				if let Err(error) = text.check_bounds(range.start, range.end) {
+					panic!("{}", error);
+				}
+				range.clone()
+			}
+			Some(Scope::Lines(lines)) => {
+				let count = text.line_count();
+				assert!(lines.start <= lines.end && lines.end <= count, "line range out of range: {:?} (line count {})", lines, count);
+				let end = if lines.end < count { text.line_start(lines.end) } else { text.length() };
+				text.line_start(lines.start).min(end)..end
+			}
+		}
+	}
+}
+
+/// <summary>Returns true for the characters words are made of: letters, digits and underscores, in any script.</summary>
+pub(crate) fn is_word_char(c : char) -> bool {
+	c.is_alphanumeric() || c == '_'
+}
+
+/// <summary>Holds the number of characters scanned at once by <code>find</code>, bounding its window on large leaves.</summary>
+const SEARCH_WINDOW : usize = 4096;
+
+/// <summary>
+/// Boyer-Moore-Horspool matcher: compares the last character of the pattern first and,
+/// on a mismatch, skips as far as the last occurrence of the character read allows,
+/// so that most characters of the text are never read.
+/// </summary>
+struct Horspool {
+	pattern : Vec<char>,
+	ascii_skips : [usize; 128],
+	skips : HashMap<char, usize>
+}
+
+impl Horspool {
+	fn new(pattern : &str) -> Horspool {
+		let pattern : Vec<char> = pattern.chars().collect();
+		let length = pattern.len();
+		let mut ascii_skips = [length; 128];
+		let mut skips = HashMap::new();
+		for (i, &c) in pattern.iter().enumerate().take(length.saturating_sub(1)) {
+			if c.is_ascii() {
+				ascii_skips[c as usize] = length - 1 - i;
+			} else {
+				skips.insert(c, length - 1 - i);
+			}
+		}
+		Horspool { pattern, ascii_skips, skips }
+	}
+
+	fn skip(&self, c : char) -> usize {
+		if c.is_ascii() {
+			return self.ascii_skips[c as usize];
+		}
+		self.skips.get(&c).copied().unwrap_or(self.pattern.len())
+	}
+
+	/// <summary>Returns the index of the first occurrence of the pattern in the characters.</summary>
+	fn find_in(&self, haystack : &[char]) -> Option<usize> {
+		let length = self.pattern.len();
+		let mut position = 0;
+		while position + length <= haystack.len() {
+			let last = haystack[position + length - 1];
+			if last == self.pattern[length - 1] && haystack[position..position + length - 1] == self.pattern[..length - 1] {
+				return Some(position);
+			}
+			position += self.skip(last);
+		}
+		None
+	}
+}
+
+/// <summary>The matcher of a pattern under the options, built once for all the occurrences a search looks for.</summary>
+enum Matcher {
+	Plain(Box<Horspool>),
+	Wildcard(Wildcard)
+}
+
+impl Matcher {
+	fn new(pattern : &str, options : &SearchOptions) -> Matcher {
+		match options.wildcards {
+			true => Matcher::Wildcard(Wildcard::new(pattern)),
+			false => Matcher::Plain(Box::new(Horspool::new(pattern)))
+		}
+	}
+
+	/// <summary>Returns true if the pattern cannot match anything.</summary>
+	fn matches_nothing(&self) -> bool {
+		match self {
+			Matcher::Plain(searcher) => searcher.pattern.is_empty(),
+			Matcher::Wildcard(wildcard) => wildcard.is_empty()
+		}
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the index of the first occurrence of the pattern at or after <code>start</code>,
+	/// for "find next". The leaves are scanned with Boyer-Moore-Horspool skips through a
+	/// window carrying the end of the previous leaf, so that occurrences spanning leaves
+	/// are found without copying the text.
+	/// </summary>
+	/// <returns>the index of the occurrence, <code>start</code> for an empty pattern.</returns>
+	pub fn find(&self, pattern : &str, start : usize) -> Option<usize> {
+		if let Err(error) = self.check_index(start) {
+			panic!("{}", error);
+		}
+		self.find_between(&Horspool::new(pattern), start, self.length())
+	}
+
+	/// <summary>Returns the index of the first occurrence of the pattern between <code>start</code> and <code>end</code>, see <code>find</code>.</summary>
+	fn find_between(&self, searcher : &Horspool, start : usize, end : usize) -> Option<usize> {
+		let length = searcher.pattern.len();
+		if length == 0 {
+			return Some(start);
+		}
+		let mut window = Vec::with_capacity(SEARCH_WINDOW + length);
+		let mut window_start = start;
+		for chunk in self.chunks_in(start..end) {
+			let mut chars = chunk.chars();
+			loop {
+				let carried = window.len();
+				window.extend(chars.by_ref().take(SEARCH_WINDOW));
+				if window.len() == carried {
+					break;
+				}
+				if let Some(i) = searcher.find_in(&window) {
+					return Some(window_start + i);
+				}
+				// Keeps the characters an occurrence spanning into the next ones may start with.
+				let dropped = window.len() - (length - 1).min(window.len());
+				window.drain(..dropped);
+				window_start += dropped;
+			}
+		}
+		None
+	}
+
+	/// <summary>
+	/// Returns the latest index at or before <code>index</code> that no occurrence of the
+	/// pattern straddles. Non-overlapping occurrences matched from there are the same as
+	/// those matched from the start of the text, however the occurrences before it chain.
+	/// </summary>
+	fn match_boundary_before(&self, searcher : &Horspool, index : usize) -> usize {
+		let length = searcher.pattern.len();
+		let mut boundary = index.min(self.length());
+		// Moves back to the first occurrence straddling the boundary until none does.
+		while let Some(start) = self.find_between(searcher, boundary.saturating_sub(length - 1), (boundary + length - 1).min(self.length())).filter(|&start| start < boundary) {
+			boundary = start;
+		}
+		boundary
+	}
+
+	/// <summary>Returns true if there is a word boundary at the offset: the characters on each side are not both word characters or both not.</summary>
+	pub(crate) fn is_word_boundary(&self, offset : usize) -> bool {
+		if offset == 0 || offset == self.length() {
+			return true;
+		}
+		is_word_char(self.get_char_at(offset - 1)) != is_word_char(self.get_char_at(offset))
+	}
+
+	/// <summary>Returns true if the occurrence of the given length at the offset satisfies the options.</summary>
+	fn accepts(&self, offset : usize, length : usize, options : &SearchOptions) -> bool {
+		!options.whole_word || (self.is_word_boundary(offset) && self.is_word_boundary(offset + length))
+	}
+
+	/// <summary>
+	/// Returns the range of the first occurrence of the pattern at or after <code>start</code>
+	/// satisfying the options. With a range or lines, only that part of the text is scanned.
+	/// </summary>
+	fn find_match(&self, matcher : &Matcher, start : usize, options : &SearchOptions) -> Option<Range<usize>> {
+		if let Err(error) = self.check_index(start) {
+			panic!("{}", error);
+		}
+		let bounds = options.bounds(self);
+		let mut from = start.max(bounds.start);
+		loop {
+			if from > bounds.end {
+				return None;
+			}
+			let found = match matcher {
+				Matcher::Wildcard(wildcard) => wildcard.find_in(self.chars_range(from, bounds.end), from)?,
+				Matcher::Plain(searcher) => {
+					let found = self.find_between(searcher, from, bounds.end)?;
+					found..found + searcher.pattern.len()
+				}
+			};
+			if self.accepts(found.start, found.len(), options) {
+				return Some(found);
+			}
+			from = found.start + 1;
+		}
+	}
+
+	/// <summary>Returns the index of the first occurrence of the pattern at or after <code>start</code> satisfying the options, see <code>find</code>.</summary>
+	pub fn find_with(&self, pattern : &str, start : usize, options : &SearchOptions) -> Option<usize> {
+		self.find_match(&Matcher::new(pattern, options), start, options).map(|found| found.start)
+	}
+
+	/// <summary>Counts the non-overlapping occurrences of the pattern in this text.</summary>
+	pub fn count_matches(&self, pattern : &str) -> usize {
+		self.count_matches_with(pattern, &SearchOptions::new())
+	}
+
+	/// <summary>
+	/// Counts the non-overlapping occurrences of the pattern satisfying the options, such
+	/// as those lying entirely within a range with <code>SearchOptions::with_range</code>.
+	/// </summary>
+	/// <returns>the number of matches; an empty pattern never matches.</returns>
+	pub fn count_matches_with(&self, pattern : &str, options : &SearchOptions) -> usize {
+		self.find_iter(pattern, options).count()
+	}
+
+	/// <summary>Returns an iterator over the ranges of the non-overlapping occurrences of the pattern satisfying the options, in order.</summary>
+	pub fn find_iter<'a>(&'a self, pattern : &str, options : &'a SearchOptions) -> impl Iterator<Item = Range<usize>> + 'a {
+		let matcher = Matcher::new(pattern, options);
+		let mut from = (!matcher.matches_nothing()).then_some(0);
+		std::iter::from_fn(move || {
+			let found = self.find_match(&matcher, from?, options);
+			from = found.as_ref().map(|found| found.end);
+			found
+		})
+	}
+
+	/// <summary>
+	/// Returns the ranges of the non-overlapping occurrences of the pattern that
+	/// overlap the viewport, including those cut by its edges, to highlight all the
+	/// occurrences visible in an editor. See <code>find_all_in_with</code>.
+	/// </summary>
+	/// <returns>the ranges in order; an empty pattern never matches.</returns>
+	pub fn find_all_in(&self, pattern : &str, viewport : Range<usize>) -> Vec<Range<usize>> {
+		self.find_all_in_with(pattern, viewport, &SearchOptions::new())
+	}
+
+	/// <summary>
+	/// Returns the ranges of the occurrences satisfying the options that overlap the
+	/// viewport, the ranges <code>find_iter</code> returns. The scan starts from the last
+	/// point before the viewport that no occurrence straddles, usually within the length
+	/// of the pattern, but further back where occurrences of a self-overlapping pattern
+	/// chain up to the viewport. Wildcard occurrences are looked for from the start of
+	/// the line of the viewport.
+	/// </summary>
+	pub fn find_all_in_with(&self, pattern : &str, viewport : Range<usize>, options : &SearchOptions) -> Vec<Range<usize>> {
+		let mut ranges = Vec::new();
+		let matcher = Matcher::new(pattern, options);
+		if matcher.matches_nothing() || viewport.start >= viewport.end {
+			return ranges;
+		}
+		let mut from = match &matcher {
+			Matcher::Wildcard(_) => self.line_start(self.line_of_offset(viewport.start)),
+			Matcher::Plain(searcher) => self.match_boundary_before(searcher, viewport.start.saturating_sub(searcher.pattern.len() - 1))
+		};
+		while let Some(found) = self.find_match(&matcher, from, options) {
+			if found.start >= viewport.end {
+				break;
+			}
+			from = found.end;
+			if found.end > viewport.start {
+				ranges.push(found);
+			}
+		}
+		ranges
+	}
+
+	/// <summary>
+	/// Computes the replacement of every non-overlapping occurrence of the pattern
+	/// satisfying the options, such as those lying entirely within a selection with
+	/// <code>SearchOptions::with_range</code>, without applying it, so that an editor can
+	/// preview a "Replace All" and then apply the delta as a single edit on confirmation.
+	/// </summary>
+	/// <returns>the delta replacing the occurrences, empty if there are none or the pattern is empty.</returns>
+	pub fn replace_all_preview(&self, pattern : &str, replacement : &str, options : &SearchOptions) -> Delta {
+		let mut delta = Delta::new();
+		for found in self.find_iter(pattern, options) {
+			delta.push(found.start, found.len(), replacement);
+		}
+		delta
+	}
+
+	/// <summary>
+	/// Returns the index of the first occurrence of the character at or after
+	/// <code>start</code>. ASCII characters are searched with memchr in the leaves
+	/// stored as bytes, and newlines skip the subtrees whose metrics hold none.
+	/// </summary>
+	pub fn find_char(&self, c : char, start : usize) -> Option<usize> {
+		let mut stack : Vec<(&dyn Node, usize)> = vec![(&*self.root, 0)];
+		while let Some((node, offset)) = stack.pop() {
+			if offset + node.length() <= start || (c == '\n' && node.metrics().newlines == 0) {
+				continue;
+			}
+			match node.as_composite() {
+				Some(composite) => {
+					stack.push((&*composite.tail, offset + composite.head.length()));
+					stack.push((&*composite.head, offset));
+				}
+				None => {
+					if let Some(i) = node.find_char(c, start.saturating_sub(offset)) {
+						return Some(offset + i);
+					}
+				}
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	/// <summary>Returns the occurrences <code>find_iter</code> finds that overlap the viewport.</summary>
+	fn expected_in(text : &ImmutableText, pattern : &str, viewport : Range<usize>, options : &SearchOptions) -> Vec<Range<usize>> {
+		text.find_iter(pattern, options).filter(|found| found.start < viewport.end && found.end > viewport.start).collect()
+	}
+
+	#[test]
+	fn viewports_find_the_occurrences_of_a_full_scan() {
+		let options = SearchOptions::new();
+		let long = "ab\r\n".repeat(100) + &"a".repeat(SEARCH_WINDOW + 3);
+		for (text, pattern) in [("aaa", "aa"), ("aaaaaaa", "aaa"), ("abababab", "abab"), ("x\r\n\r\n\r\ny", "\r\n\r\n"), (long.as_str(), "aa"), (long.as_str(), "\nab")] {
+			let text = create_immutable_text(text);
+			for start in 0..text.length().min(50) {
+				for end in [start + 1, start + 2, start + 7, text.length()] {
+					assert_eq!(text.find_all_in(pattern, start..end), expected_in(&text, pattern, start..end, &options), "{:?} in {:?}", pattern, start..end);
+					assert_eq!(text.find_all_in_with(pattern, start..end, &options), expected_in(&text, pattern, start..end, &options));
+				}
+			}
+			let tail = text.length() - 3..text.length();
+			assert_eq!(text.find_all_in(pattern, tail.clone()), expected_in(&text, pattern, tail, &options));
+		}
+	}
+
+	#[test]
+	fn viewports_outside_the_text_find_nothing() {
+		let text = create_immutable_text("aaa");
+		assert_eq!(text.find_all_in("aa", 1..3), vec![0..2]);
+		assert!(text.find_all_in("aa", 2..3).is_empty());
+		assert!(text.find_all_in("aa", 3..10).is_empty());
+		assert!(text.find_all_in("", 0..3).is_empty());
+		assert!(create_immutable_text("").find_all_in("aa", 0..1).is_empty());
+	}
+
+	#[test]
+	fn counts_matches_within_a_range() {
+		let text = create_immutable_text("ab\r\nab\r\nab");
+		let within = |range : Range<usize>| SearchOptions::new().with_range(range);
+		assert_eq!(text.count_matches("ab"), 3);
+		assert_eq!(text.count_matches_with("ab", &within(0..text.length())), 3);
+		assert_eq!(text.count_matches_with("\r\n", &within(3..8)), 1);
+		assert_eq!(text.count_matches_with("ab", &within(4..4)), 0);
+		assert_eq!(text.count_matches("aba"), 0);
+		assert_eq!(create_immutable_text("aaaa").count_matches("aa"), 2);
+		assert_eq!(create_immutable_text("").count_matches_with("ab", &within(0..0)), 0);
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds: 5..2")]
+	#[allow(clippy::reversed_empty_ranges)]
+	fn count_matches_with_rejects_reversed_ranges() {
+		create_immutable_text("ab\r\nab").count_matches_with("ab", &SearchOptions::new().with_range(5..2));
+	}
+
+	#[test]
+	fn replace_all_previews_the_occurrences_within_the_range() {
+		let text = create_immutable_text("ab ab aab ab");
+		let delta = text.replace_all_preview("ab", "x", &SearchOptions::new().with_range(1..10));
+		assert_eq!(delta.apply(&text).to_string(), "ab x ax ab");
+		let delta = text.replace_all_preview("ab", "", &SearchOptions::new().with_whole_word(true));
+		assert_eq!(delta.apply(&text).to_string(), "  aab ");
+		assert!(text.replace_all_preview("", "x", &SearchOptions::new()).is_empty());
+	}
+
+	#[test]
+	#[should_panic(expected = "range out of bounds: 0..9")]
+	fn chars_range_rejects_ranges_past_the_end() {
+		create_immutable_text("ab\r\nab").chars_range(0, 9);
+	}
+
+	/// <summary>Returns the index of the first occurrence at or after the character index, with <code>str::find</code>.</summary>
+	fn str_find(text : &str, pattern : &str, start : usize) -> Option<usize> {
+		let byte_start = text.char_indices().map(|(offset, _)| offset).chain([text.len()]).nth(start)?;
+		text[byte_start..].find(pattern).map(|found| start + text[byte_start..byte_start + found].chars().count())
+	}
+
+	#[test]
+	fn finds_occurrences_across_leaves_and_windows() {
+		// Occurrences straddling leaves of 16 characters, and windows of SEARCH_WINDOW characters in larger leaves.
+		let mut content = "é".repeat(SEARCH_WINDOW - 3);
+		content.push_str("needle\r\nhay 日本 ");
+		content.push_str(&"x".repeat(SEARCH_WINDOW));
+		content.push_str("🦀needle");
+		let small = TextConfig::new().with_block_size(16);
+		for text in [create_immutable_text(&content), small.create(&content), create_immutable_text(&content).insert_string(7, "needle")] {
+			let expected = text.to_string();
+			for pattern in ["needle", "e\r\nh", "é", "日本", "x🦀n", "ne", "needle needle", "\r\n", &expected[..6]] {
+				for start in [0, 1, 7, SEARCH_WINDOW - 4, SEARCH_WINDOW, text.length() - 6, text.length()] {
+					assert_eq!(text.find(pattern, start), str_find(&expected, pattern, start), "{:?} from {}", pattern, start);
+				}
+			}
+			assert_eq!(text.find("", 5), Some(5));
+		}
+	}
+
+	#[test]
+	fn finds_nothing_in_short_or_empty_texts() {
+		let text = create_immutable_text("ab\r\n");
+		assert_eq!(text.find("ab\r\nab", 0), None);
+		assert_eq!(text.find("\n", 4), None);
+		assert_eq!(create_immutable_text("").find("a", 0), None);
+		assert_eq!(create_immutable_text("").find("", 0), Some(0));
+	}
+
+	#[test]
+	#[should_panic(expected = "index out of range: 5")]
+	fn find_rejects_starts_past_the_end() {
+		create_immutable_text("ab\r\n").find("a", 5);
+	}
+}
@@ -0,0 +1,253 @@
+use std::ops::Range;
+
+use super::{Bias, Delta};
+
+/// <summary>
+/// A selected range: the <code>anchor</code> where it started and the <code>head</code> where the
+/// caret is, which is before the anchor when selecting backwards. An empty selection
+/// is a caret.
+/// </summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Selection {
+	pub anchor : usize,
+	pub head : usize
+}
+
+impl Selection {
+	pub fn new(anchor : usize, head : usize) -> Selection {
+		Selection { anchor, head }
+	}
+
+	/// <summary>Returns an empty selection at the offset.</summary>
+	pub fn caret(offset : usize) -> Selection {
+		Selection { anchor : offset, head : offset }
+	}
+
+	pub fn start(&self) -> usize {
+		self.anchor.min(self.head)
+	}
+
+	pub fn end(&self) -> usize {
+		self.anchor.max(self.head)
+	}
+
+	pub fn range(&self) -> Range<usize> {
+		self.start()..self.end()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.anchor == self.head
+	}
+
+	/// <summary>Returns true if the head is before the anchor.</summary>
+	pub fn is_backward(&self) -> bool {
+		self.head < self.anchor
+	}
+
+	/// <summary>Returns the selection with its head moved to the offset, keeping its anchor, like shift-clicking.</summary>
+	pub fn extend_to(&self, head : usize) -> Selection {
+		Selection { anchor : self.anchor, head }
+	}
+
+	/// <summary>Returns true if the selections share characters, or are carets at the same offset or at the edge of the other.</summary>
+	pub fn overlaps(&self, other : &Selection) -> bool {
+		let (first, second) = if self.start() <= other.start() { (self, other) } else { (other, self) };
+		second.start() < first.end() || second.start() == first.end() && (first.is_empty() || second.is_empty())
+	}
+
+	/// <summary>Returns the smallest selection covering both, in the direction of this one.</summary>
+	pub fn merge(&self, other : &Selection) -> Selection {
+		let (start, end) = (self.start().min(other.start()), self.end().max(other.end()));
+		if self.is_backward() { Selection::new(end, start) } else { Selection::new(start, end) }
+	}
+
+	/// <summary>
+	/// Maps the selection through the delta, from the old text to the new one. A caret
+	/// moves after the text inserted at it, as when typing; the ends of a range keep
+	/// the text inserted at its edges outside of it.
+	/// </summary>
+	pub fn transform(&self, delta : &Delta) -> Selection {
+		if self.is_empty() {
+			return Selection::caret(delta.map_offset(self.head, Bias::After));
+		}
+		let range = delta.map_range(self.range());
+		let (start, end) = (range.start, range.end);
+		if self.is_backward() { Selection::new(end, start) } else { Selection::new(start, end) }
+	}
+}
+
+/// <summary>
+/// The selections of a multi-caret editor: sorted, none overlapping another, with one of
+/// them primary, the one the view follows. Every operation keeps this normalized,
+/// merging the selections brought to overlap.
+/// </summary>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectionSet {
+	selections : Vec<Selection>,
+	primary : usize
+}
+
+impl SelectionSet {
+	/// <summary>Returns the set of the single selection.</summary>
+	pub fn new(selection : Selection) -> SelectionSet {
+		SelectionSet { selections : vec![selection], primary : 0 }
+	}
+
+	/// <summary>Returns the set of the selections, the one at index <code>primary</code> being primary, normalized.</summary>
+	/// <remarks>Panics if <code>primary</code> is not an index of the selections, so on no selections.</remarks>
+	pub fn from_vec(selections : Vec<Selection>, primary : usize) -> SelectionSet {
+		assert!(primary < selections.len(), "primary selection out of range: {} (length {})", primary, selections.len());
+		let mut set = SelectionSet { selections, primary };
+		set.normalize();
+		set
+	}
+
+	pub fn selections(&self) -> &[Selection] {
+		&self.selections
+	}
+
+	pub fn primary(&self) -> Selection {
+		self.selections[self.primary]
+	}
+
+	pub fn primary_index(&self) -> usize {
+		self.primary
+	}
+
+	/// <summary>Returns the number of selections, at least one since a set always holds its primary selection.</summary>
+	#[allow(clippy::len_without_is_empty)]
+	pub fn len(&self) -> usize {
+		self.selections.len()
+	}
+
+	/// <summary>Adds the selection and makes it primary, merged with those it overlaps.</summary>
+	pub fn add(&mut self, selection : Selection) {
+		self.selections.push(selection);
+		self.primary = self.selections.len() - 1;
+		self.normalize();
+	}
+
+	/// <summary>Moves the head of the primary selection to the offset, keeping its anchor.</summary>
+	pub fn extend_primary_to(&mut self, head : usize) {
+		self.selections[self.primary] = self.primary().extend_to(head);
+		self.normalize();
+	}
+
+	/// <summary>Applies the function to every selection, then merges those brought to overlap.</summary>
+	pub fn map<F>(&self, f : F) -> SelectionSet where F : FnMut(&Selection) -> Selection {
+		SelectionSet::from_vec(self.selections.iter().map(f).collect(), self.primary)
+	}
+
+	/// <summary>Maps every selection through the delta, see <code>Selection::transform</code>.</summary>
+	pub fn transform(&self, delta : &Delta) -> SelectionSet {
+		self.map(|selection| selection.transform(delta))
+	}
+
+	/// <summary>Sorts the selections and merges the overlapping ones, following the primary one.</summary>
+	fn normalize(&mut self) {
+		let primary = self.selections[self.primary];
+		self.selections.sort_by_key(|selection| (selection.start(), selection.end()));
+		let mut merged : Vec<Selection> = Vec::with_capacity(self.selections.len());
+		let mut primary_index = 0;
+		for &selection in &self.selections {
+			match merged.last_mut() {
+				Some(last) if last.overlaps(&selection) => {
+					*last = if selection == primary { selection.merge(last) } else { last.merge(&selection) };
+				}
+				_ => merged.push(selection)
+			}
+			if selection == primary {
+				primary_index = merged.len() - 1;
+			}
+		}
+		self.selections = merged;
+		self.primary = primary_index;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn set(selections : &[(usize, usize)], primary : usize) -> SelectionSet {
+		SelectionSet::from_vec(selections.iter().map(|&(anchor, head)| Selection::new(anchor, head)).collect(), primary)
+	}
+
+	fn pairs(set : &SelectionSet) -> Vec<(usize, usize)> {
+		set.selections().iter().map(|selection| (selection.anchor, selection.head)).collect()
+	}
+
+	#[test]
+	fn sorts_following_the_primary_selection() {
+		let selections = set(&[(10, 12), (0, 2), (5, 5)], 0);
+		assert_eq!(pairs(&selections), vec![(0, 2), (5, 5), (10, 12)]);
+		assert_eq!(selections.primary_index(), 2);
+		assert_eq!(selections.primary(), Selection::new(10, 12));
+		assert_eq!(selections.len(), 3);
+	}
+
+	#[test]
+	fn merges_overlapping_selections() {
+		let selections = set(&[(0, 5), (3, 8), (20, 20), (20, 20), (30, 25)], 1);
+		assert_eq!(pairs(&selections), vec![(0, 8), (20, 20), (30, 25)]);
+		assert_eq!(selections.primary_index(), 0);
+		// Ranges touching each other stay apart, a caret at the edge of a range merges into it.
+		let selections = set(&[(0, 5), (5, 8), (8, 8), (12, 12), (12, 15)], 4);
+		assert_eq!(pairs(&selections), vec![(0, 5), (5, 8), (12, 15)]);
+		assert_eq!(selections.primary_index(), 2);
+	}
+
+	#[test]
+	fn keeps_the_direction_of_the_primary_selection() {
+		let selections = set(&[(0, 5), (8, 3)], 1);
+		assert_eq!(pairs(&selections), vec![(8, 0)]);
+		let selections = set(&[(0, 5), (8, 3)], 0);
+		assert_eq!(pairs(&selections), vec![(0, 8)]);
+		let selections = set(&[(5, 0), (3, 8), (7, 10)], 2);
+		assert_eq!(pairs(&selections), vec![(0, 10)]);
+		assert_eq!(selections.primary_index(), 0);
+	}
+
+	#[test]
+	fn adds_and_extends_the_primary_selection() {
+		let mut selections = SelectionSet::new(Selection::caret(4));
+		selections.add(Selection::caret(10));
+		selections.add(Selection::caret(20));
+		selections.add(Selection::new(2, 0));
+		assert_eq!(pairs(&selections), vec![(2, 0), (4, 4), (10, 10), (20, 20)]);
+		assert_eq!(selections.primary_index(), 0);
+		selections.extend_primary_to(12);
+		assert_eq!(pairs(&selections), vec![(2, 12), (20, 20)]);
+		assert_eq!(selections.primary(), Selection::new(2, 12));
+		selections.add(Selection::new(25, 15));
+		assert_eq!(pairs(&selections), vec![(2, 12), (25, 15)]);
+		assert_eq!(selections.primary_index(), 1);
+	}
+
+	#[test]
+	fn transforms_and_merges_through_a_delta() {
+		let selections = set(&[(2, 2), (5, 5), (8, 10)], 1);
+		let mut delta = Delta::new();
+		delta.push(1, 5, "");
+		let transformed = selections.transform(&delta);
+		assert_eq!(pairs(&transformed), vec![(1, 1), (3, 5)]);
+		assert_eq!(transformed.primary_index(), 0);
+		// A caret moves after the text inserted at it, a range keeps it outside.
+		let mut delta = Delta::new();
+		delta.push(2, 0, "xy");
+		delta.push(8, 0, "z");
+		delta.push(10, 0, "w");
+		assert_eq!(pairs(&selections.transform(&delta)), vec![(4, 4), (7, 7), (11, 13)]);
+		// Removing a backward range leaves a caret.
+		let mut delta = Delta::new();
+		delta.push(7, 4, "");
+		let transformed = set(&[(10, 8), (20, 20)], 0).transform(&delta);
+		assert_eq!(pairs(&transformed), vec![(7, 7), (16, 16)]);
+	}
+
+	#[test]
+	#[should_panic(expected = "primary selection out of range: 0 (length 0)")]
+	fn rejects_sets_without_selections() {
+		SelectionSet::from_vec(Vec::new(), 0);
+	}
+}
@@ -0,0 +1,31 @@
+use super::ImmutableText;
+
+/// <summary>
+/// Checks the result of an operation against the string the same operation gives
+/// on plain strings, along with the invariants of its tree, and panics on the first
+/// difference. The operations run these checks with the "shadow" feature, at the
+/// cost of copying their operands every time: this is for catching integration bugs
+/// during development, not for release builds.
+/// </summary>
+pub(crate) fn check(operation : &str, result : &ImmutableText, expected : &str) {
+	if let Err(violation) = result.validate_structure() {
+		panic!("shadow: {} broke an invariant: {:?}", operation, violation);
+	}
+	let expected_length = expected.chars().count();
+	if result.length() != expected_length {
+		panic!("shadow: {} gave {} characters, {} expected", operation, result.length(), expected_length);
+	}
+	if let Some(offset) = result.chars().zip(expected.chars()).position(|(actual, expected)| actual != expected) {
+		panic!("shadow: {} gave {:?} at offset {}, {:?} expected", operation, result.get_char_at(offset), offset, expected.chars().nth(offset).unwrap());
+	}
+	if result.line_count() != expected.matches('\n').count() + 1 {
+		panic!("shadow: {} counts {} lines, {} expected", operation, result.line_count(), expected.matches('\n').count() + 1);
+	}
+}
+
+/// <summary>Returns the string with the <code>removed</code> characters at <code>start</code> replaced by the inserted ones.</summary>
+pub(crate) fn splice(text : &str, start : usize, removed : usize, inserted : &str) -> String {
+	let mut chars : Vec<char> = text.chars().collect();
+	chars.splice(start..start + removed, inserted.chars());
+	chars.into_iter().collect()
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::iter::Rev;
+use std::ops::Range;
+
+use super::{Chars, ImmutableText};
+
+/// <summary>
+/// A borrowed range of a text, checked once when it is taken: reading from it
+/// neither copies the characters nor builds a new tree.
+/// </summary>
+#[derive(Clone, Copy)]
+pub struct TextSlice<'a> {
+	text : &'a ImmutableText,
+	start : usize,
+	end : usize
+}
+
+impl<'a> TextSlice<'a> {
+	pub fn len(&self) -> usize {
+		self.end - self.start
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	/// <summary>Returns the range of the text this slice covers.</summary>
+	pub fn range(&self) -> Range<usize> {
+		self.start..self.end
+	}
+
+	/// <summary>Returns the character at <code>index</code> of the slice, or None past its end.</summary>
+	pub fn get(&self, index : usize) -> Option<char> {
+		if index >= self.len() {
+			return None;
+		}
+		Some(self.text.get_char_at(self.start + index))
+	}
+
+	pub fn chars(&self) -> Chars<'a> {
+		self.text.chars_range(self.start, self.end)
+	}
+
+	/// <summary>Returns the characters of the slice as a text sharing the nodes of the original.</summary>
+	pub fn to_text(&self) -> ImmutableText {
+		self.text.get_text(self.start, self.len())
+	}
+}
+
+impl fmt::Debug for TextSlice<'_> {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&self.chars().collect::<String>(), f)
+	}
+}
+
+impl fmt::Display for TextSlice<'_> {
+	fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.chars().collect::<String>())
+	}
+}
+
+/// <summary>Iterates over the lines of a text with their ranges, from either end, see <code>ImmutableText::lines_with_ranges</code>.</summary>
+pub struct LinesWithRanges<'a> {
+	text : &'a ImmutableText,
+	/// <summary>The lines not yet yielded, from either end.</summary>
+	lines : Range<usize>
+}
+
+impl<'a> Iterator for LinesWithRanges<'a> {
+	type Item = (Range<usize>, TextSlice<'a>);
+
+	fn next(&mut self) -> Option<(Range<usize>, TextSlice<'a>)> {
+		let line = self.lines.next()?;
+		let slice = self.text.line(line);
+		Some((slice.range(), slice))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.lines.size_hint()
+	}
+}
+
+impl DoubleEndedIterator for LinesWithRanges<'_> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		let line = self.lines.next_back()?;
+		let slice = self.text.line(line);
+		Some((slice.range(), slice))
+	}
+}
+
+impl ExactSizeIterator for LinesWithRanges<'_> {}
+
+impl ImmutableText {
+	/// <summary>Returns the character at <code>index</code>, or None past the end, e.g. for a lexer peeking ahead.</summary>
+	pub fn get(&self, index : usize) -> Option<char> {
+		self.char_at_checked(index)
+	}
+
+	/// <summary>Returns the slice of the characters in the range, or None if it does not lie within the text.</summary>
+	pub fn get_range(&self, range : Range<usize>) -> Option<TextSlice<'_>> {
+		if range.start > range.end || range.end > self.length() {
+			return None;
+		}
+		Some(TextSlice { text : self, start : range.start, end : range.end })
+	}
+
+	/// <summary>Returns the character at <code>index</code> like <code>get_char_at</code>, or None instead of panicking past the end.</summary>
+	pub fn char_at_checked(&self, index : usize) -> Option<char> {
+		if let Some((leaf, offset)) = self.cache.get(index) {
+			return Some(leaf.get_char_at(index - offset));
+		}
+		self.find_leaf(index, 0).map(|leaf| leaf.leaf_node.get_char_at(index - leaf.offset))
+	}
+
+	/// <summary>Returns the given line without its newline, as a slice found through the line index.</summary>
+	pub fn line(&self, line : usize) -> TextSlice<'_> {
+		TextSlice { text : self, start : self.line_start(line), end : self.line_end(line) }
+	}
+
+	/// <summary>Returns the given line with its newline, if it is not the last line.</summary>
+	pub fn line_with_newline(&self, line : usize) -> TextSlice<'_> {
+		let end = if line + 1 < self.line_count() { self.line_end(line) + 1 } else { self.length() };
+		TextSlice { text : self, start : self.line_start(line), end }
+	}
+
+	/// <summary>
+	/// Returns an iterator over the lines without their newlines, each with its range
+	/// in this text, so that diagnostics or folds can be mapped to offsets directly.
+	/// </summary>
+	pub fn lines_with_ranges(&self) -> LinesWithRanges<'_> {
+		LinesWithRanges { text : self, lines : 0..self.line_count() }
+	}
+
+	/// <summary>
+	/// Returns an iterator over the lines from the last one backwards, each with its range,
+	/// so that a log viewer can render the tail first: every line is located through the
+	/// line index in O(log n), without scanning the text from the top.
+	/// </summary>
+	pub fn lines_rev(&self) -> Rev<LinesWithRanges<'_>> {
+		self.lines_with_ranges().rev()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn slices_read_the_range_they_cover() {
+		let text = TextConfig::new().with_block_size(16).create(&"0123456789\u{e9}".repeat(4));
+		let slice = text.get_range(5..30).unwrap();
+		assert_eq!((slice.len(), slice.is_empty(), slice.range()), (25, false, 5..30));
+		assert_eq!(slice.get(0), Some('5'));
+		assert_eq!(slice.get(5), Some('\u{e9}'));
+		assert_eq!(slice.get(25), None);
+		assert_eq!(slice.chars().collect::<String>(), "56789\u{e9}0123456789\u{e9}01234567");
+		assert_eq!(slice.to_string(), "56789\u{e9}0123456789\u{e9}01234567");
+		assert_eq!(format!("{:?}", text.get_range(9..12).unwrap()), "\"9\u{e9}0\"");
+		assert_eq!(slice.to_text().to_string(), slice.to_string());
+		assert_eq!(slice.to_text().config(), text.config());
+		assert!(text.get_range(44..44).unwrap().is_empty());
+		assert!(text.get_range(40..45).is_none());
+		#[allow(clippy::reversed_empty_ranges)]
+		let reversed = text.get_range(3..2);
+		assert!(reversed.is_none());
+	}
+
+	#[test]
+	fn slices_lines_with_and_without_newlines() {
+		let text = create_immutable_text("one\r\n\ntwo\n");
+		let lines : Vec<String> = (0..text.line_count()).map(|line| text.line(line).to_string()).collect();
+		assert_eq!(lines, vec!["one\r", "", "two", ""]);
+		let lines : Vec<String> = (0..text.line_count()).map(|line| text.line_with_newline(line).to_string()).collect();
+		assert_eq!(lines, vec!["one\r\n", "\n", "two\n", ""]);
+		assert_eq!(text.line(2).range(), 6..9);
+		assert_eq!(text.line_with_newline(2).range(), 6..10);
+		assert_eq!(create_immutable_text("last").line_with_newline(0).to_string(), "last");
+	}
+
+	#[test]
+	fn iterates_lines_with_their_ranges() {
+		let text = create_immutable_text("one\r\n\ntwo\nthree");
+		let lines : Vec<_> = text.lines_with_ranges().map(|(range, line)| (range, line.to_string())).collect();
+		assert_eq!(lines, vec![(0..4, "one\r".to_string()), (5..5, String::new()), (6..9, "two".to_string()), (10..15, "three".to_string())]);
+		let lines : Vec<_> = create_immutable_text("a\n").lines_with_ranges().map(|(range, line)| (range, line.to_string())).collect();
+		assert_eq!(lines, vec![(0..1, "a".to_string()), (2..2, String::new())]);
+		let lines : Vec<_> = create_immutable_text("").lines_with_ranges().map(|(range, _)| range).collect();
+		assert_eq!(lines, vec![0..0]);
+	}
+
+	#[test]
+	fn iterates_lines_from_both_ends() {
+		let source : String = (0..50).map(|i| format!("line {}\n", i)).collect();
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let mut lines = text.lines_with_ranges();
+		assert_eq!(lines.len(), 51);
+		assert_eq!(lines.next().unwrap().1.to_string(), "line 0");
+		assert_eq!(lines.next_back().unwrap().1.to_string(), "");
+		assert_eq!(lines.next_back().unwrap().1.to_string(), "line 49");
+		assert_eq!(lines.len(), 48);
+		for (range, line) in lines {
+			assert_eq!(&source[range], line.to_string());
+		}
+	}
+
+	#[test]
+	fn iterates_lines_from_the_last_one() {
+		let text = create_immutable_text("one\ntwo\r\nthree\n");
+		let lines : Vec<_> = text.lines_rev().map(|(range, line)| (range, line.to_string())).collect();
+		assert_eq!(lines, vec![(15..15, String::new()), (9..14, "three".to_string()), (4..8, "two\r".to_string()), (0..3, "one".to_string())]);
+		let source : String = (0..200).map(|i| format!("entry {}\n", i)).collect();
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		let tail : Vec<String> = text.lines_rev().skip(1).take(3).map(|(_, line)| line.to_string()).collect();
+		assert_eq!(tail, vec!["entry 199", "entry 198", "entry 197"]);
+		let mut forward : Vec<_> = text.lines_with_ranges().map(|(range, _)| range).collect();
+		forward.reverse();
+		assert_eq!(text.lines_rev().map(|(range, _)| range).collect::<Vec<_>>(), forward);
+		assert_eq!(create_immutable_text("").lines_rev().count(), 1);
+	}
+}
@@ -0,0 +1,115 @@
+use super::{ImmutableText, LeafChars, Node};
+
+/// <summary>wc-style statistics of a text.</summary>
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextStats {
+	/// <summary>Number of newline characters.</summary>
+	pub lines : usize,
+	/// <summary>Number of maximal runs of non-whitespace characters.</summary>
+	pub words : usize,
+	pub chars : usize,
+	/// <summary>Length of the UTF-8 encoding.</summary>
+	pub bytes : usize
+}
+
+/// <summary>
+/// Statistics of one subtree, plus whether it starts or ends inside a word so
+/// that the word counts of adjacent subtrees can be combined.
+/// </summary>
+#[derive(Clone, Copy, Default)]
+struct PartialStats {
+	stats : TextStats,
+	starts_in_word : bool,
+	ends_in_word : bool
+}
+
+impl PartialStats {
+	fn combine(self, next : PartialStats) -> PartialStats {
+		if self.stats.chars == 0 {
+			return next;
+		}
+		if next.stats.chars == 0 {
+			return self;
+		}
+		let joined = if self.ends_in_word && next.starts_in_word { 1 } else { 0 };
+		PartialStats {
+			stats : TextStats {
+				lines : self.stats.lines + next.stats.lines,
+				words : self.stats.words + next.stats.words - joined,
+				chars : self.stats.chars + next.stats.chars,
+				bytes : self.stats.bytes + next.stats.bytes
+			},
+			starts_in_word : self.starts_in_word,
+			ends_in_word : next.ends_in_word
+		}
+	}
+}
+
+/// <summary>Computes the statistics of a subtree; the halves of a composite are independent.</summary>
+fn node_stats(node : &dyn Node) -> PartialStats {
+	if let Some(composite) = node.as_composite() {
+		return node_stats(&*composite.head).combine(node_stats(&*composite.tail));
+	}
+	let mut result = PartialStats::default();
+	let mut in_word = false;
+	for (i, c) in LeafChars::new(node, 0, node.length()).enumerate() {
+		if c == '\n' {
+			result.stats.lines += 1;
+		}
+		if c.is_whitespace() {
+			in_word = false;
+		} else {
+			if !in_word {
+				result.stats.words += 1;
+			}
+			if i == 0 {
+				result.starts_in_word = true;
+			}
+			in_word = true;
+		}
+		result.stats.bytes += c.len_utf8();
+	}
+	result.stats.chars = node.length();
+	result.ends_in_word = in_word;
+	result
+}
+
+impl ImmutableText {
+	/// <summary>Returns the line, word, char and byte counts of this text, computed in one traversal.</summary>
+	pub fn stats(&self) -> TextStats {
+		node_stats(&*self.root).stats
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+	use super::*;
+
+	/// <summary>Returns the statistics of the string, the way <code>wc</code> counts them.</summary>
+	fn wc(text : &str) -> TextStats {
+		TextStats { lines : text.matches('\n').count(), words : text.split_whitespace().count(), chars : text.chars().count(), bytes : text.len() }
+	}
+
+	#[test]
+	fn stats_of_a_multi_leaf_tree() {
+		// 64 characters over four leaves of 16, words straddling the leaves.
+		let content = "the quick brown fox jumps over the lazy dog\r\nété 😀 naïve\t\tend!!\n";
+		assert_eq!(content.chars().count(), 64);
+		let text = TextConfig::new().with_block_size(16).create(content);
+		let tree = text.tree_stats();
+		assert_eq!((tree.leaf_count, tree.depth), (4, 2));
+		assert_eq!(text.stats(), TextStats { lines : 2, words : 13, chars : 64, bytes : 70 });
+		assert_eq!(text.stats(), wc(content));
+	}
+
+	#[test]
+	fn stats_after_edits() {
+		let text = TextConfig::new().with_block_size(16).create(&"word ".repeat(100));
+		let edited = text.insert_string(7, "\n").remove_text(250, 3).insert_string(0, "  lead");
+		assert!(edited.tree_stats().leaf_count > 10);
+		assert_eq!(edited.stats(), wc(&edited.to_string()));
+		assert_eq!(create_immutable_text("").stats(), TextStats::default());
+		assert_eq!(create_immutable_text("  \n ").stats(), TextStats { lines : 1, words : 0, chars : 4, bytes : 4 });
+	}
+}
@@ -0,0 +1,84 @@
+//! Proptest strategies generating texts of the shapes real editing produces, for
+//! property-testing code built on ImmutableText against realistic trees.
+
+use proptest::arbitrary::any;
+use proptest::collection::vec;
+use proptest::prop_oneof;
+use proptest::strategy::{Just, Strategy};
+
+use super::{create_leaf_node, CompositeNode, ImmutableText, Shared, TextConfig, MAX_DEPTH};
+
+/// <summary>Returns a strategy for characters mixing ASCII, newlines, combining marks, CJK and emoji.</summary>
+pub fn unicode_chars() -> impl Strategy<Value = char> {
+	prop_oneof![
+		proptest::char::range('a', 'z'),
+		Just(' '),
+		Just('\n'),
+		Just('\r'),
+		Just('\t'),
+		proptest::char::range('\u{300}', '\u{36F}'),
+		proptest::char::range('\u{4E00}', '\u{9FFF}'),
+		proptest::char::range('\u{1F300}', '\u{1F64F}'),
+		any::<char>()
+	]
+}
+
+/// <summary>Returns a strategy for strings of up to <code>max_length</code> characters of <code>unicode_chars</code>.</summary>
+pub fn unicode_strings(max_length : usize) -> impl Strategy<Value = String> {
+	vec(unicode_chars(), 0..max_length + 1).prop_map(|chars| chars.into_iter().collect())
+}
+
+/// <summary>Returns a strategy for texts created whole from unicode-heavy strings, inline or chunked.</summary>
+pub fn unicode_texts() -> impl Strategy<Value = ImmutableText> {
+	unicode_strings(1024).prop_map(|text| TextConfig::new().create(&text))
+}
+
+/// <summary>
+/// Returns a strategy for deep trees: texts built by concatenating many short
+/// parts one at a time, with a small block size, as typing does.
+/// </summary>
+pub fn deep_texts() -> impl Strategy<Value = ImmutableText> {
+	vec(unicode_strings(8), 1..256).prop_map(|parts| {
+		let config = TextConfig::new().with_block_size(4);
+		parts.iter().fold(config.create(""), |text, part| text.concat(&config.create(part)))
+	})
+}
+
+/// <summary>
+/// Returns a strategy for maximally unbalanced trees: leaves hanging off a comb of
+/// composites, every head holding all the preceding leaves. No operation of the
+/// library builds them and <code>validate</code> reports them unbalanced, but every
+/// operation accepts them.
+/// </summary>
+pub fn unbalanced_texts() -> impl Strategy<Value = ImmutableText> {
+	vec(vec(unicode_chars(), 1..65), 2..MAX_DEPTH).prop_map(|parts| {
+		let config = TextConfig::new();
+		let mut leaves = parts.into_iter().map(|part| create_leaf_node(part, &config));
+		let first = leaves.next().unwrap();
+		let root = leaves.fold(first, |head, leaf| Shared::new(CompositeNode::new(head, leaf)));
+		config.create("").with_root(root)
+	})
+}
+
+/// <summary>
+/// Returns a strategy for edited texts: a text created or opened by pieces, then
+/// edited by a sequence of inserts and removals at arbitrary offsets.
+/// </summary>
+pub fn edited_texts() -> impl Strategy<Value = ImmutableText> {
+	let edits = vec((any::<usize>(), any::<usize>(), unicode_strings(16)), 0..64);
+	(unicode_strings(2048), any::<bool>(), edits).prop_map(|(content, opened, edits)| {
+		let config = TextConfig::new();
+		let mut text = if opened { config.open(content) } else { config.create(&content) };
+		for (start, count, inserted) in edits {
+			let start = start % (text.length() + 1);
+			let count = count % (text.length() - start + 1);
+			text = text.remove_text(start, count).insert_string(start, &inserted);
+		}
+		text
+	})
+}
+
+/// <summary>Returns a strategy for texts of any of the shapes above.</summary>
+pub fn texts() -> impl Strategy<Value = ImmutableText> {
+	prop_oneof![unicode_texts(), deep_texts(), unbalanced_texts(), edited_texts()]
+}
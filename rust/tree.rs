@@ -0,0 +1,210 @@
+//! The balanced tree of blocks texts and ropes are built on. Both share their nodes
+//! through links implementing <code>TreeLink</code>, and all the shape of their trees
+//! (chunking, concatenation with rotations, rebuilding at minimal depth) comes from
+//! the functions of this module, which keep the depths of the head and tail of every
+//! composite within one of each other.
+
+/// <summary>
+/// A shared link to a node of a tree of blocks: a leaf holding a block of elements,
+/// or a composite concatenating two nodes.
+/// </summary>
+pub(crate) trait TreeLink : Clone {
+	/// <summary>What leaves are built with: the configuration of a text, nothing for a rope.</summary>
+	type Config : ?Sized;
+
+	fn length(&self) -> usize;
+
+	/// <summary>Returns the number of composite levels below the node, 0 for leaves.</summary>
+	fn depth(&self) -> usize;
+
+	/// <summary>Returns the head and tail of a composite, or None for leaves.</summary>
+	fn children(&self) -> Option<(&Self, &Self)>;
+
+	fn composite(head : Self, tail : Self) -> Self;
+
+	/// <summary>Returns the elements between <code>start</code> and <code>end</code> of the node, sharing it whole.</summary>
+	fn sub_node(&self, start : usize, end : usize, config : &Self::Config) -> Self;
+
+	/// <summary>Returns the single leaf holding the elements of both nodes, if the configuration merges them.</summary>
+	fn merged(head : &Self, tail : &Self, config : &Self::Config) -> Option<Self>;
+
+	/// <summary>Returns the number of elements of a full leaf, a power of two.</summary>
+	fn block_size(config : &Self::Config) -> usize;
+
+	/// <summary>Returns a leaf holding no element.</summary>
+	fn empty() -> Self;
+}
+
+/// <summary>A pending step of the iterative <code>node_of</code>.</summary>
+enum ChunkTask {
+	/// <summary>Builds the node of <code>length</code> elements at <code>offset</code>.</summary>
+	Build(usize, usize),
+	/// <summary>Joins the last two nodes built into a composite.</summary>
+	Join
+}
+
+/// <summary>Returns the <code>length</code> elements of the node at <code>offset</code> over a tree of full blocks.</summary>
+pub(crate) fn node_of<L : TreeLink>(node : &L, offset : usize, length : usize, config : &L::Config) -> L {
+	let block_size = L::block_size(config);
+	// Walks the splits with an explicit stack instead of recursing.
+	let mut tasks = vec![ChunkTask::Build(offset, length)];
+	let mut built : Vec<L> = Vec::new();
+	while let Some(task) = tasks.pop() {
+		match task {
+			ChunkTask::Build(offset, length) => {
+				if length <= block_size {
+					built.push(node.sub_node(offset, offset + length, config));
+					continue;
+				}
+				// Splits on a block boundary.
+				let half = ((length + block_size) >> 1) & !(block_size - 1);
+				tasks.push(ChunkTask::Join);
+				tasks.push(ChunkTask::Build(offset + half, length - half));
+				tasks.push(ChunkTask::Build(offset, half));
+			}
+			ChunkTask::Join => {
+				let tail = built.pop().unwrap();
+				let head = built.pop().unwrap();
+				built.push(L::composite(head, tail));
+			}
+		}
+	}
+	built.pop().unwrap()
+}
+
+/// <summary>
+/// Concatenates two trees, keeping them balanced: the depths of the head and tail of
+/// every composite differ by at most one. The shallower tree is joined to the node of
+/// the same depth on the facing edge of the deeper one, and the composites above are
+/// rebuilt on the way back, rotated where the join made them lean.
+/// See: http://en.wikipedia.org/wiki/Tree_rotation
+/// </summary>
+/// <remarks>Trees already unbalanced are concatenated all the same, without being balanced.</remarks>
+pub(crate) fn concat_nodes<L : TreeLink>(node1 : L, node2 : L, config : &L::Config) -> L {
+	if let Some(merged) = L::merged(&node1, &node2, config) { // Merges to primitive.
+		return merged;
+	}
+	if node1.depth() > node2.depth() + 1 {
+		join_right(node1, node2, config)
+	} else if node2.depth() > node1.depth() + 1 {
+		join_left(node1, node2, config)
+	} else {
+		L::composite(node1, node2)
+	}
+}
+
+/// <summary>Returns the composite of two nodes, or their merged leaf if both are leaves the configuration merges.</summary>
+fn join_leaves<L : TreeLink>(head : L, tail : L, config : &L::Config) -> L {
+	// Merging composites could leave the join shallower than its siblings.
+	let merged = (head.depth() == 0 && tail.depth() == 0).then(|| L::merged(&head, &tail, config)).flatten();
+	merged.unwrap_or_else(|| L::composite(head, tail))
+}
+
+/// <summary>Joins the shallower tail to the right edge of the deeper head.</summary>
+fn join_right<L : TreeLink>(head : L, tail : L, config : &L::Config) -> L {
+	// Descends the right edge with an explicit stack rather than recursing.
+	let mut heads = Vec::new();
+	let mut node = head;
+	while node.depth() > tail.depth() + 1 {
+		let (head, tail) = node.children().map(|(h, t)| (h.clone(), t.clone())).unwrap();
+		heads.push(head);
+		node = tail;
+	}
+	let mut result = join_leaves(node, tail, config);
+	while let Some(head) = heads.pop() {
+		if result.depth() <= head.depth() + 1 {
+			result = L::composite(head, result);
+			continue;
+		}
+		// The joined tail is two levels deeper than its head: rotates left.
+		let (middle, right) = result.children().map(|(h, t)| (h.clone(), t.clone())).unwrap();
+		result = match middle.children().filter(|_| middle.depth() > right.depth()) {
+			// Rotates the leaning middle right first.
+			Some((b, c)) => L::composite(L::composite(head, b.clone()), L::composite(c.clone(), right)),
+			None => L::composite(L::composite(head, middle), right)
+		};
+	}
+	result
+}
+
+/// <summary>Joins the shallower head to the left edge of the deeper tail, the mirror of <code>join_right</code>.</summary>
+fn join_left<L : TreeLink>(head : L, tail : L, config : &L::Config) -> L {
+	let mut tails = Vec::new();
+	let mut node = tail;
+	while node.depth() > head.depth() + 1 {
+		let (head, tail) = node.children().map(|(h, t)| (h.clone(), t.clone())).unwrap();
+		tails.push(tail);
+		node = head;
+	}
+	let mut result = join_leaves(head, node, config);
+	while let Some(tail) = tails.pop() {
+		if result.depth() <= tail.depth() + 1 {
+			result = L::composite(result, tail);
+			continue;
+		}
+		// The joined head is two levels deeper than its tail: rotates right.
+		let (left, middle) = result.children().map(|(h, t)| (h.clone(), t.clone())).unwrap();
+		result = match middle.children().filter(|_| middle.depth() > left.depth()) {
+			// Rotates the leaning middle left first.
+			Some((b, c)) => L::composite(L::composite(left, b.clone()), L::composite(c.clone(), tail)),
+			None => L::composite(left, L::composite(middle, tail))
+		};
+	}
+	result
+}
+
+/// <summary>Collects the leaves of a tree from left to right.</summary>
+pub(crate) fn collect_leaves<L : TreeLink>(root : &L) -> Vec<L> {
+	let mut leaves = Vec::new();
+	let mut stack = vec![root.clone()];
+	while let Some(node) = stack.pop() {
+		match node.children() {
+			Some((head, tail)) => {
+				stack.push(tail.clone());
+				stack.push(head.clone());
+			}
+			None => leaves.push(node.clone())
+		}
+	}
+	leaves
+}
+
+/// <summary>Builds a tree of minimal depth over the given leaves, sharing them; an empty leaf if there are none.</summary>
+pub(crate) fn balanced_node<L : TreeLink>(leaves : &[L]) -> L {
+	debug_assert!(!leaves.is_empty(), "no leaves to build a tree over");
+	if leaves.is_empty() {
+		return L::empty();
+	}
+	if leaves.len() == 1 {
+		return leaves[0].clone();
+	}
+	let half = leaves.len() / 2;
+	L::composite(balanced_node(&leaves[..half]), balanced_node(&leaves[half..]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, MAX_DEPTH};
+	use super::*;
+
+	#[test]
+	fn a_million_appends_stay_balanced() {
+		let mut text = create_immutable_text("");
+		for i in 0..1_000_000 {
+			let c = char::from(b'a' + (i % 26) as u8);
+			text = text.concat(&create_immutable_text(c.encode_utf8(&mut [0; 4])));
+		}
+		assert_eq!(text.length(), 1_000_000);
+		assert!(text.root.depth() <= MAX_DEPTH, "depth {}", text.root.depth());
+		assert_eq!(text.validate(), Ok(()));
+		assert_eq!(text.get_char_at(999_999), char::from(b'a' + (999_999 % 26) as u8));
+	}
+
+	#[test]
+	fn balanced_nodes_have_minimal_depth() {
+		let leaves : Vec<_> = (0..5).map(|i| create_immutable_text(&i.to_string()).root.to_node()).collect();
+		let node = balanced_node(&leaves);
+		assert_eq!((node.length(), TreeLink::depth(&node)), (5, 3));
+		assert_eq!(TreeLink::depth(&balanced_node(&leaves[..4])), 2);
+	}
+}
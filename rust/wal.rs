@@ -0,0 +1,187 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Delta, ImmutableText, Journal, TextConfig};
+
+/// <summary>
+/// A document persisted as a write-ahead log: every delta is appended to a journal
+/// and synced before it is applied, and every few deltas the whole text is written
+/// as a checkpoint, after which a new journal starts. A directory holds the file
+/// <code>checkpoint</code>, starting with the line <code>generation &lt;n&gt;</code>, and the
+/// journal of the deltas since, <code>journal.&lt;n&gt;</code>. Opening the directory recovers
+/// the latest consistent state: the checkpoint, then the complete records of its
+/// journal.
+/// </summary>
+pub struct WalDocument {
+	directory : PathBuf,
+	generation : u64,
+	text : ImmutableText,
+	journal : Journal<File>,
+	checkpoint_every : usize
+}
+
+fn journal_path(directory : &Path, generation : u64) -> PathBuf {
+	directory.join(format!("journal.{}", generation))
+}
+
+fn open_journal(directory : &Path, generation : u64) -> io::Result<File> {
+	OpenOptions::new().create(true).append(true).open(journal_path(directory, generation))
+}
+
+/// <summary>
+/// Makes the entries of the directory durable, such as a file just renamed in it, so
+/// that a crash cannot bring back its former content after the files depending on the
+/// change are gone.
+/// </summary>
+#[cfg(unix)]
+fn sync_directory(directory : &Path) -> io::Result<()> {
+	File::open(directory)?.sync_all()
+}
+
+/// <summary>Does nothing where directories cannot be opened as files, their entries being durable once the rename returns.</summary>
+#[cfg(not(unix))]
+fn sync_directory(_directory : &Path) -> io::Result<()> {
+	Ok(())
+}
+
+/// <summary>Reads the checkpoint of the directory, or an empty generation 0 if there is none yet.</summary>
+fn read_checkpoint(directory : &Path) -> io::Result<(u64, ImmutableText)> {
+	let mut content = String::new();
+	match File::open(directory.join("checkpoint")) {
+		Ok(file) => BufReader::new(file).read_to_string(&mut content)?,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, TextConfig::new().create(""))),
+		Err(e) => return Err(e)
+	};
+	let invalid = || io::Error::new(io::ErrorKind::InvalidData, "checkpoint: generation expected");
+	let (header, text) = content.split_once('\n').ok_or_else(invalid)?;
+	let generation = header.strip_prefix("generation ").and_then(|n| n.parse().ok()).ok_or_else(invalid)?;
+	let text = text.to_string();
+	Ok((generation, TextConfig::new().open(text)))
+}
+
+impl WalDocument {
+	/// <summary>
+	/// Opens the document persisted in the directory, created if need be, recovering
+	/// its latest consistent state. The state recovered is checkpointed at once, so
+	/// that a record torn by a crash is never followed by new ones.
+	/// </summary>
+	/// <param name="checkpoint_every">the number of deltas between two checkpoints.</param>
+	pub fn open<P : AsRef<Path>>(directory : P, checkpoint_every : usize) -> io::Result<WalDocument> {
+		let directory = directory.as_ref().to_path_buf();
+		fs::create_dir_all(&directory)?;
+		let (generation, checkpoint) = read_checkpoint(&directory)?;
+		let text = match File::open(journal_path(&directory, generation)) {
+			Ok(file) => checkpoint.replay(BufReader::new(file))?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => checkpoint,
+			Err(e) => return Err(e)
+		};
+		let journal = Journal::new(open_journal(&directory, generation)?);
+		let mut document = WalDocument { directory, generation, text, journal, checkpoint_every : checkpoint_every.max(1) };
+		document.checkpoint()?;
+		Ok(document)
+	}
+
+	pub fn text(&self) -> &ImmutableText {
+		&self.text
+	}
+
+	/// <summary>Logs the delta durably, then applies it to the document, checkpointing when it is due.</summary>
+	/// <remarks>Once logging failed, every delta is refused until the document is opened again.</remarks>
+	pub fn apply(&mut self, delta : &Delta) -> io::Result<()> {
+		if let Some(last) = delta.edits().last() {
+			self.text.check_range(last.start, last.removed).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+		}
+		self.journal.append(delta)?;
+		self.journal.get_ref().sync_data()?;
+		self.text = delta.apply(&self.text);
+		if self.journal.records() >= self.checkpoint_every {
+			self.checkpoint()?;
+		}
+		Ok(())
+	}
+
+	/// <summary>
+	/// Writes the whole text as the checkpoint of a new generation and starts its empty
+	/// journal. The checkpoint replaces the previous one by a rename, so that a crash
+	/// leaves either the old generation with its journal or the new one.
+	/// </summary>
+	pub fn checkpoint(&mut self) -> io::Result<()> {
+		let generation = self.generation + 1;
+		let temporary = self.directory.join("checkpoint.tmp");
+		let mut writer = BufWriter::new(File::create(&temporary)?);
+		writeln!(writer, "generation {}", generation)?;
+		for chunk in self.text.chunks_in(0..self.text.length()) {
+			writer.write_all(chunk.as_bytes())?;
+		}
+		writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+		fs::rename(&temporary, self.directory.join("checkpoint"))?;
+		// The old journal may only go once the new checkpoint cannot be lost.
+		sync_directory(&self.directory)?;
+		self.journal = Journal::new(open_journal(&self.directory, generation)?);
+		let _ = fs::remove_file(journal_path(&self.directory, self.generation));
+		self.generation = generation;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn replace(start : usize, removed : usize, inserted : &str) -> Delta {
+		let mut delta = Delta::new();
+		delta.push(start, removed, inserted);
+		delta
+	}
+
+	/// <summary>Returns an empty directory of its own for the test.</summary>
+	fn directory(name : &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("immutable-text-wal-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&directory);
+		directory
+	}
+
+	#[test]
+	fn recovers_the_checkpoint_and_the_journal_since() {
+		let directory = directory("recovers");
+		let mut document = WalDocument::open(&directory, 3).unwrap();
+		assert_eq!(document.text().length(), 0);
+		for (i, line) in ["one\r\n", "two\r\n", "three\r\n", "four\r\n"].iter().enumerate() {
+			document.apply(&replace(document.text().length(), 0, line)).unwrap();
+			assert_eq!(document.generation, 1 + (i as u64 + 1) / 3);
+		}
+		drop(document);
+		let document = WalDocument::open(&directory, 3).unwrap();
+		assert_eq!(document.text().to_string(), "one\r\ntwo\r\nthree\r\nfour\r\n");
+		assert!(!journal_path(&directory, 1).exists());
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn ignores_a_torn_last_record() {
+		let directory = directory("torn");
+		let mut document = WalDocument::open(&directory, 100).unwrap();
+		document.apply(&replace(0, 0, "kept")).unwrap();
+		let generation = document.generation;
+		drop(document);
+		let mut journal = OpenOptions::new().append(true).open(journal_path(&directory, generation)).unwrap();
+		journal.write_all(b"delta 1\n4 0 5\nlo").unwrap();
+		let mut document = WalDocument::open(&directory, 100).unwrap();
+		assert_eq!(document.text().to_string(), "kept");
+		// The recovered state was checkpointed: new records no longer follow the torn one.
+		document.apply(&replace(4, 0, "!")).unwrap();
+		drop(document);
+		assert_eq!(WalDocument::open(&directory, 100).unwrap().text().to_string(), "kept!");
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn refuses_edits_out_of_range() {
+		let directory = directory("range");
+		let mut document = WalDocument::open(&directory, 100).unwrap();
+		assert_eq!(document.apply(&replace(1, 0, "x")).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+		assert_eq!(document.journal.records(), 0);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+}
@@ -0,0 +1,66 @@
+//! JavaScript bindings built with wasm-bindgen, so that editors running in the
+//! browser share the rope of their native counterparts. Offsets are counted in
+//! characters, that is Unicode code points, not in the UTF-16 code units that
+//! index JavaScript strings.
+
+use wasm_bindgen::prelude::*;
+
+use super::{collect_leaves, create_immutable_text, Delta, ImmutableText};
+
+/// <summary>An immutable text, exported to JavaScript as <code>ImmutableText</code>.</summary>
+#[wasm_bindgen(js_name = ImmutableText)]
+pub struct WasmText {
+	text : ImmutableText
+}
+
+/// <summary>One replacement of a diff: <code>removed</code> characters at <code>start</code> replaced with <code>inserted</code>.</summary>
+#[wasm_bindgen(js_name = Edit, getter_with_clone)]
+pub struct WasmEdit {
+	pub start : usize,
+	pub removed : usize,
+	pub inserted : String
+}
+
+#[wasm_bindgen(js_class = ImmutableText)]
+impl WasmText {
+	#[wasm_bindgen(constructor)]
+	pub fn new(text : &str) -> WasmText {
+		WasmText { text : create_immutable_text(text) }
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn length(&self) -> usize {
+		self.text.length()
+	}
+
+	/// <summary>Returns the <code>count</code> characters at <code>start</code>, sharing the nodes of this text.</summary>
+	pub fn slice(&self, start : usize, count : usize) -> Result<WasmText, JsError> {
+		Ok(WasmText { text : self.text.try_get_text(start, count)? })
+	}
+
+	/// <summary>Returns this text with the <code>removed</code> characters at <code>start</code> replaced by the string.</summary>
+	pub fn splice(&self, start : usize, removed : usize, inserted : &str) -> Result<WasmText, JsError> {
+		Ok(WasmText { text : self.text.try_splice(start, removed, &create_immutable_text(inserted))? })
+	}
+
+	/// <summary>Returns the leaves of this text as strings, in order, to iterate over it without one large copy.</summary>
+	pub fn chunks(&self) -> Vec<String> {
+		collect_leaves(&self.text.root.to_node()).iter().map(|leaf| {
+			let mut data = vec!['\0'; leaf.length()];
+			leaf.copy_to(0, &mut data, 0, leaf.length());
+			data.into_iter().collect()
+		}).collect()
+	}
+
+	/// <summary>Returns the edits turning this text into the other one, in the coordinates of this text.</summary>
+	pub fn diff(&self, other : &WasmText) -> Vec<WasmEdit> {
+		Delta::between(&self.text, &other.text).edits().iter()
+			.map(|edit| WasmEdit { start : edit.start, removed : edit.removed, inserted : edit.inserted.clone() })
+			.collect()
+	}
+
+	#[wasm_bindgen(js_name = toString)]
+	pub fn to_js_string(&self) -> String {
+		self.text.to_string()
+	}
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+/// <summary>An element of a wildcard pattern.</summary>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+	Literal(char),
+	/// <summary><code>?</code>: any character but a newline.</summary>
+	Any,
+	/// <summary><code>*</code>: any run of characters, newlines excluded.</summary>
+	Star
+}
+
+/// <summary>
+/// A wildcard pattern, <code>*</code> matching any run of characters and <code>?</code> any one
+/// character, neither across lines, and <code>\</code> escaping the next character. It is
+/// matched by simulating its automaton over the characters as they stream in, keeping
+/// for every state the earliest start of the threads in it, so that the text is read
+/// once and never copied.
+/// </summary>
+pub(crate) struct Wildcard {
+	tokens : Vec<Token>
+}
+
+impl Wildcard {
+	pub(crate) fn new(pattern : &str) -> Wildcard {
+		let mut tokens = Vec::new();
+		let mut chars = pattern.chars();
+		while let Some(c) = chars.next() {
+			tokens.push(match c {
+				'*' if tokens.last() == Some(&Token::Star) => continue,
+				'*' => Token::Star,
+				'?' => Token::Any,
+				'\\' => Token::Literal(chars.next().unwrap_or('\\')),
+				_ => Token::Literal(c)
+			});
+		}
+		Wildcard { tokens }
+	}
+
+	/// <summary>Returns true if the pattern has nothing to match.</summary>
+	pub(crate) fn is_empty(&self) -> bool {
+		self.tokens.is_empty()
+	}
+
+	/// <summary>Activates the state with the start, and the states after the stars it reaches.</summary>
+	fn enter(&self, states : &mut [Option<usize>], mut state : usize, start : usize) {
+		loop {
+			if states[state].is_none_or(|current| start < current) {
+				states[state] = Some(start);
+			}
+			if state == self.tokens.len() || self.tokens[state] != Token::Star {
+				return;
+			}
+			state += 1;
+		}
+	}
+
+	/// <summary>
+	/// Returns the leftmost occurrence of the pattern in the characters, the shortest one
+	/// among those starting there, as a range of offsets counted from <code>start</code>.
+	/// Occurrences are never empty.
+	/// </summary>
+	pub(crate) fn find_in<I : Iterator<Item = char>>(&self, chars : I, start : usize) -> Option<Range<usize>> {
+		let accept = self.tokens.len();
+		let mut states : Vec<Option<usize>> = vec![None; accept + 1];
+		let mut next : Vec<Option<usize>> = vec![None; accept + 1];
+		let mut best : Option<Range<usize>> = None;
+		for (i, c) in chars.enumerate() {
+			let offset = start + i;
+			if best.is_none() {
+				self.enter(&mut states, 0, offset);
+			}
+			next.iter_mut().for_each(|state| *state = None);
+			for (state, token) in self.tokens.iter().enumerate() {
+				let Some(thread_start) = states[state] else {
+					continue;
+				};
+				match token {
+					Token::Literal(l) if *l == c => self.enter(&mut next, state + 1, thread_start),
+					Token::Any if c != '\n' => self.enter(&mut next, state + 1, thread_start),
+					Token::Star if c != '\n' => self.enter(&mut next, state, thread_start),
+					_ => {}
+				}
+			}
+			std::mem::swap(&mut states, &mut next);
+			if let Some(thread_start) = states[accept] {
+				if best.as_ref().is_none_or(|best| thread_start < best.start) {
+					best = Some(thread_start..offset + 1);
+				}
+				states[accept] = None;
+			}
+			// Stops once no thread starting before the best occurrence is left.
+			if let Some(found) = &best {
+				if states.iter().flatten().all(|&thread_start| thread_start >= found.start) {
+					return best;
+				}
+			}
+		}
+		best
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// <summary>Returns whether the tokens match all the characters, by backtracking.</summary>
+	fn matches(tokens : &[Token], chars : &[char]) -> bool {
+		match (tokens.first(), chars.first()) {
+			(None, _) => chars.is_empty(),
+			(Some(Token::Star), _) => matches(&tokens[1..], chars) || (chars.first().is_some_and(|&c| c != '\n') && matches(tokens, &chars[1..])),
+			(Some(_), None) => false,
+			(Some(Token::Any), Some(&c)) => c != '\n' && matches(&tokens[1..], &chars[1..]),
+			(Some(Token::Literal(l)), Some(c)) => l == c && matches(&tokens[1..], &chars[1..])
+		}
+	}
+
+	/// <summary>Returns the leftmost, then shortest, non-empty occurrence by trying every range.</summary>
+	fn find_every_range(wildcard : &Wildcard, text : &str) -> Option<Range<usize>> {
+		let chars : Vec<char> = text.chars().collect();
+		(0..chars.len()).find_map(|start| (start + 1..=chars.len()).find(|&end| matches(&wildcard.tokens, &chars[start..end])).map(|end| start..end))
+	}
+
+	#[test]
+	fn finds_the_leftmost_shortest_occurrence() {
+		let texts = ["", "a", "abc", "aab\r\nxab", "x\nab*c?d\\e", "日本語 🦀 a.b", "aXbYaZb\r\nab"];
+		let patterns = ["a", "a?b", "a*b", "*b", "a*", "*", "?", "**a**", "\\*", "\\?c", "b*\\", "*\r\n*", "日*🦀", "a*b*b"];
+		for pattern in patterns {
+			let wildcard = Wildcard::new(pattern);
+			for text in texts {
+				let offset = 10;
+				let expected = find_every_range(&wildcard, text).map(|found| found.start + offset..found.end + offset);
+				assert_eq!(wildcard.find_in(text.chars(), offset), expected, "{:?} in {:?}", pattern, text);
+			}
+		}
+	}
+
+	#[test]
+	fn parses_escapes_and_runs_of_stars() {
+		assert_eq!(Wildcard::new("a***b").tokens, vec![Token::Literal('a'), Token::Star, Token::Literal('b')]);
+		assert_eq!(Wildcard::new("\\*\\?\\\\").tokens, vec![Token::Literal('*'), Token::Literal('?'), Token::Literal('\\')]);
+		// A trailing backslash stands for itself.
+		assert_eq!(Wildcard::new("a\\").tokens, vec![Token::Literal('a'), Token::Literal('\\')]);
+		assert!(Wildcard::new("").is_empty());
+	}
+
+	#[test]
+	fn never_matches_across_lines() {
+		let wildcard = Wildcard::new("a*b");
+		assert_eq!(wildcard.find_in("a\nb a\r\nb".chars(), 0), None);
+		assert_eq!(Wildcard::new("a?b").find_in("a\nb".chars(), 0), None);
+		assert_eq!(Wildcard::new("a\r\nb").find_in("xa\r\nb".chars(), 0), Some(1..5));
+	}
+}
@@ -0,0 +1,112 @@
+use std::iter::Peekable;
+use std::ops::Range;
+
+use super::graphemes::is_extending;
+use super::search::is_word_char;
+use super::{Chars, ImmutableText};
+
+/// <summary>Returns whether the character joins two letters into one word, as in "don't".</summary>
+fn is_apostrophe(c : char) -> bool {
+	c == '\'' || c == '\u{2019}'
+}
+
+/// <summary>
+/// Iterates over the words of a text with their ranges: runs of letters, digits and
+/// underscores with their combining marks, and apostrophes between two letters.
+/// The characters are streamed, so words spanning leaves come out whole.
+/// </summary>
+pub struct Words<'a> {
+	chars : Peekable<Chars<'a>>,
+	offset : usize
+}
+
+impl Iterator for Words<'_> {
+	type Item = (Range<usize>, String);
+
+	fn next(&mut self) -> Option<(Range<usize>, String)> {
+		while !is_word_char(*self.chars.peek()?) {
+			self.chars.next();
+			self.offset += 1;
+		}
+		let start = self.offset;
+		let mut word = String::new();
+		while let Some(&c) = self.chars.peek() {
+			if is_word_char(c) || is_extending(c) {
+				word.push(c);
+				self.chars.next();
+				self.offset += 1;
+				continue;
+			}
+			// The marks a letter ends with do not keep an apostrophe from following it.
+			if !is_apostrophe(c) || !word.chars().rev().find(|&c| !is_extending(c)).is_some_and(char::is_alphabetic) {
+				break;
+			}
+			// The apostrophe is part of the word only if a letter follows it.
+			self.chars.next();
+			self.offset += 1;
+			match self.chars.peek() {
+				Some(&next) if next.is_alphabetic() => word.push(c),
+				_ => return Some((start..self.offset - 1, word))
+			}
+		}
+		Some((start..self.offset, word))
+	}
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns an iterator over the words of this text with their ranges, to feed spell
+	/// checkers or symbol extractors; everything between the words is skipped.
+	/// </summary>
+	pub fn words_with_ranges(&self) -> Words<'_> {
+		Words { chars : self.chars().peekable(), offset : 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	fn words(source : &str) -> Vec<(usize, usize, String)> {
+		create_immutable_text(source).words_with_ranges().map(|(range, word)| (range.start, range.end, word)).collect()
+	}
+
+	#[test]
+	fn splits_words_on_other_characters() {
+		assert_eq!(words("  foo_bar, 42 baz!"), vec![(2, 9, "foo_bar".to_string()), (11, 13, "42".to_string()), (14, 17, "baz".to_string())]);
+		assert!(words("").is_empty());
+		assert!(words(" -- ").is_empty());
+		assert_eq!(words("x"), vec![(0, 1, "x".to_string())]);
+	}
+
+	#[test]
+	fn keeps_apostrophes_between_letters() {
+		assert_eq!(words("don't"), vec![(0, 5, "don't".to_string())]);
+		assert_eq!(words("it\u{2019}s"), vec![(0, 4, "it\u{2019}s".to_string())]);
+		assert_eq!(words("dogs' toys"), vec![(0, 4, "dogs".to_string()), (6, 10, "toys".to_string())]);
+		assert_eq!(words("'quoted'"), vec![(1, 7, "quoted".to_string())]);
+		assert_eq!(words("rock'n'roll"), vec![(0, 11, "rock'n'roll".to_string())]);
+		assert_eq!(words("a''b"), vec![(0, 1, "a".to_string()), (3, 4, "b".to_string())]);
+		assert_eq!(words("80's"), vec![(0, 2, "80".to_string()), (3, 4, "s".to_string())]);
+	}
+
+	#[test]
+	fn keeps_combining_marks_in_words() {
+		assert_eq!(words("cafe\u{301} noe\u{308}l"), vec![(0, 5, "cafe\u{301}".to_string()), (6, 11, "noe\u{308}l".to_string())]);
+		assert_eq!(words("cafe\u{301}'s"), vec![(0, 7, "cafe\u{301}'s".to_string())]);
+		assert_eq!(words("\u{301}a"), vec![(1, 2, "a".to_string())]);
+	}
+
+	#[test]
+	fn streams_words_across_leaves() {
+		let source = "lorem ipsum dolorsitamet don't ".repeat(10);
+		let text = TextConfig::new().with_block_size(16).create(&source);
+		assert!(text.tree_stats().leaf_count > 10);
+		let expected : Vec<&str> = source.split_whitespace().collect();
+		let words : Vec<_> = text.words_with_ranges().collect();
+		assert_eq!(words.iter().map(|(_, word)| word.as_str()).collect::<Vec<_>>(), expected);
+		for (range, word) in words {
+			assert_eq!(&source[range], word);
+		}
+	}
+}
@@ -0,0 +1,149 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::graphemes::GraphemeBreaker;
+use super::metrics::char_width;
+use super::ImmutableText;
+
+/// <summary>A word of a line and its display width in columns.</summary>
+struct Word {
+	text : String,
+	width : usize
+}
+
+/// <summary>
+/// Splits a line into its indentation and its words, runs of grapheme clusters
+/// separated by whitespace, so that a line is never broken within a cluster.
+/// </summary>
+fn split_words(line : &str) -> (&str, Vec<Word>) {
+	let content = line.trim_start_matches([' ', '\t']);
+	let indent = &line[..line.len() - content.len()];
+	let mut words = Vec::new();
+	let mut word = Word { text : String::new(), width : 0 };
+	let mut breaker = GraphemeBreaker::new();
+	let mut separator = false;
+	for c in content.chars() {
+		// Whitespace only separates words when it starts a cluster of its own.
+		if breaker.is_boundary_before(c) {
+			separator = c.is_whitespace();
+			if separator && !word.text.is_empty() {
+				words.push(word);
+				word = Word { text : String::new(), width : 0 };
+			}
+		}
+		if !separator {
+			word.text.push(c);
+			word.width += char_width(c);
+		}
+	}
+	if !word.text.is_empty() {
+		words.push(word);
+	}
+	(indent, words)
+}
+
+/// <summary>
+/// Fills lines of at most <code>width</code> columns with the words, greedily, each
+/// starting with the indentation. A word wider than a line gets a line of its own.
+/// </summary>
+fn fill(indent : &str, words : &[Word], width : usize) -> String {
+	let indent_width : usize = indent.chars().map(char_width).sum();
+	let mut filled = String::from(indent);
+	let mut column = indent_width;
+	for (i, word) in words.iter().enumerate() {
+		if i > 0 && column + 1 + word.width > width {
+			filled.push('\n');
+			filled.push_str(indent);
+			column = indent_width;
+		} else if i > 0 {
+			filled.push(' ');
+			column += 1;
+		}
+		filled.push_str(&word.text);
+		column += word.width;
+	}
+	filled
+}
+
+impl ImmutableText {
+	/// <summary>
+	/// Returns the text with every line wider than <code>width</code> columns broken at
+	/// spaces into lines that fit, keeping its indentation. Lines that already fit are
+	/// shared with this text.
+	/// </summary>
+	pub fn wrap_to_width(&self, width : usize) -> ImmutableText {
+		self.map_lines(|line| {
+			let (content, terminator) = match line.strip_suffix('\r') {
+				Some(content) => (content, "\r"),
+				None => (line, "")
+			};
+			if content.chars().map(char_width).sum::<usize>() <= width {
+				return Cow::Borrowed(line);
+			}
+			let (indent, words) = split_words(content);
+			let wrapped = fill(indent, &words, width);
+			Cow::Owned(wrapped.replace('\n', &format!("{}\n", terminator)) + terminator)
+		})
+	}
+
+	/// <summary>
+	/// Returns the text with the lines of the range, a paragraph, joined and broken
+	/// again into lines of at most <code>width</code> columns, all with the indentation
+	/// of its first line. A paragraph with CR LF line endings keeps them, the one ending
+	/// its last line included.
+	/// </summary>
+	pub fn reflow_paragraph(&self, lines : Range<usize>, width : usize) -> ImmutableText {
+		if lines.is_empty() {
+			return self.clone();
+		}
+		let start = self.line_start(lines.start);
+		let end = self.line_end(lines.end - 1);
+		let paragraph : String = self.chars_range(start, end).collect();
+		let newline = if paragraph.contains("\r\n") || paragraph.ends_with('\r') { "\r\n" } else { "\n" };
+		let (content, terminator) = match paragraph.strip_suffix('\r') {
+			Some(content) => (content, "\r"),
+			None => (paragraph.as_str(), "")
+		};
+		let mut indent = None;
+		let mut words = Vec::new();
+		for line in content.split('\n') {
+			let (line_indent, line_words) = split_words(line.strip_suffix('\r').unwrap_or(line));
+			indent.get_or_insert(line_indent);
+			words.extend(line_words);
+		}
+		let reflowed = fill(indent.unwrap_or(""), &words, width).replace('\n', newline) + terminator;
+		self.get_text(0, start).concat(&self.config.create(&reflowed)).concat(&self.sub_text(end))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{create_immutable_text, TextConfig};
+
+	#[test]
+	fn reflow_keeps_crlf_line_endings() {
+		let text = create_immutable_text("title\r\n  one two\r\nthree four five\r\nsix\r\nend");
+		let reflowed = text.reflow_paragraph(1..4, 11);
+		assert_eq!(reflowed.to_string(), "title\r\n  one two\r\n  three\r\n  four five\r\n  six\r\nend");
+		assert_eq!(text.reflow_paragraph(1..5, 80).to_string(), "title\r\n  one two three four five six end");
+	}
+
+	#[test]
+	fn reflow_keeps_lf_line_endings() {
+		let text = create_immutable_text("one two\nthree\n\nfour");
+		assert_eq!(text.reflow_paragraph(0..2, 80).to_string(), "one two three\n\nfour");
+		assert_eq!(text.reflow_paragraph(0..2, 5).to_string(), "one\ntwo\nthree\n\nfour");
+		assert_eq!(text.reflow_paragraph(0..0, 5).to_string(), text.to_string());
+		assert_eq!(create_immutable_text("").reflow_paragraph(0..1, 5).to_string(), "");
+	}
+
+	#[test]
+	fn reflow_keeps_the_configuration() {
+		let config = TextConfig::new().with_block_size(16);
+		let text = config.create(&"word ".repeat(40));
+		let reflowed = text.reflow_paragraph(0..1, 12);
+		assert_eq!(reflowed.config(), config);
+		assert!(reflowed.validate().is_ok());
+		assert_eq!(reflowed.to_string(), "word word\nword word\n".repeat(10).trim_end());
+	}
+}